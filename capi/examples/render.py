@@ -0,0 +1,62 @@
+"""Render a scene file through the C API with ctypes.
+
+    cargo build --release
+    python3 examples/render.py ../examples/cornell_box.toml image.ppm
+"""
+
+import ctypes
+import json
+import sys
+import tomllib
+from pathlib import Path
+
+
+class Progress(ctypes.Structure):
+    _fields_ = [
+        ("kind", ctypes.c_int),
+        ("percent", ctypes.c_uint32),
+        ("tile_x", ctypes.c_uint32),
+        ("tile_y", ctypes.c_uint32),
+        ("tile_width", ctypes.c_uint32),
+        ("tile_height", ctypes.c_uint32),
+    ]
+
+
+RTK_PROGRESS_PERCENT = 0
+CALLBACK = ctypes.CFUNCTYPE(ctypes.c_int, ctypes.POINTER(Progress), ctypes.c_void_p)
+
+lib = ctypes.CDLL(str(Path(__file__).parent.parent / "target/release/librtk_capi.so"))
+lib.rtk_last_error.restype = ctypes.c_char_p
+
+
+def check(status):
+    if status != 0:
+        raise RuntimeError(lib.rtk_last_error().decode())
+
+
+@CALLBACK
+def on_progress(progress, _user_data):
+    if progress.contents.kind == RTK_PROGRESS_PERCENT:
+        print(f"\r{progress.contents.percent}%", end="", file=sys.stderr)
+    return 0
+
+
+def main():
+    scene_path, output_path = sys.argv[1:3]
+    scene = json.dumps(tomllib.loads(Path(scene_path).read_text())).encode()
+
+    width, height = ctypes.c_uint32(), ctypes.c_uint32()
+    check(lib.rtk_scene_dimensions(scene, ctypes.byref(width), ctypes.byref(height)))
+
+    size = width.value * height.value * 3
+    pixels = (ctypes.c_uint8 * size)()
+    check(lib.rtk_render(scene, pixels, ctypes.c_size_t(size), on_progress, None))
+    print(file=sys.stderr)
+
+    with open(output_path, "wb") as f:
+        f.write(f"P6 {width.value} {height.value} 255\n".encode())
+        f.write(bytes(pixels))
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,66 @@
+/* C bindings for rtk, a software ray-tracer. */
+
+#ifndef RTK_H
+#define RTK_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Status codes returned by every function. */
+#define RTK_OK 0
+#define RTK_ERROR_INVALID_ARGUMENT 1
+#define RTK_ERROR_SCENE 2
+#define RTK_ERROR_BUFFER_TOO_SMALL 3
+#define RTK_ERROR_CANCELLED 4
+#define RTK_ERROR_PANIC 5
+
+/* Kinds of progress updates. */
+#define RTK_PROGRESS_PERCENT 0
+#define RTK_PROGRESS_TILE 1
+#define RTK_PROGRESS_FINISHED 2
+
+typedef struct RtkProgress {
+  int kind;
+  /* For RTK_PROGRESS_PERCENT, the percentage of pixels rendered. */
+  uint32_t percent;
+  /* For RTK_PROGRESS_TILE, the rectangle of the output buffer that is now final. */
+  uint32_t tile_x;
+  uint32_t tile_y;
+  uint32_t tile_width;
+  uint32_t tile_height;
+} RtkProgress;
+
+/*
+ * Called with each progress update, from the rendering threads but one call at a time.
+ * Returning nonzero cancels the render, which then returns RTK_ERROR_CANCELLED.
+ */
+typedef int (*RtkProgressCallback)(const RtkProgress *progress, void *user_data);
+
+/*
+ * The message describing the last error on this thread, or an empty string.
+ * Valid until the next call into rtk on the same thread.
+ */
+const char *rtk_last_error(void);
+
+/*
+ * Get the dimensions of the image `scene_json` renders to.
+ * The scene is JSON with the same structure as a scene file.
+ */
+int rtk_scene_dimensions(const char *scene_json, uint32_t *width, uint32_t *height);
+
+/*
+ * Render `scene_json` into `out_buf` as tightly packed 8-bit RGB rows, which must hold at least
+ * width * height * 3 bytes. `callback` may be NULL.
+ */
+int rtk_render(const char *scene_json, uint8_t *out_buf, size_t out_len,
+               RtkProgressCallback callback, void *user_data);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RTK_H */
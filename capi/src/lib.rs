@@ -0,0 +1,247 @@
+//! A C ABI for rendering rtk scenes; see `include/rtk.h` for documentation of each function.
+//!
+//! Scenes are passed as JSON with the same structure as scene files, and are migrated from older
+//! versions of the format the same way. Errors are reported by status code, with a message
+//! available from `rtk_last_error` on the same thread.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+};
+
+use rtk::{
+    plugin::Plugins,
+    render::{ProgressEvent, Renderer},
+    scene::Scene,
+};
+
+pub const RTK_OK: c_int = 0;
+pub const RTK_ERROR_INVALID_ARGUMENT: c_int = 1;
+pub const RTK_ERROR_SCENE: c_int = 2;
+pub const RTK_ERROR_BUFFER_TOO_SMALL: c_int = 3;
+pub const RTK_ERROR_CANCELLED: c_int = 4;
+pub const RTK_ERROR_PANIC: c_int = 5;
+
+pub const RTK_PROGRESS_PERCENT: c_int = 0;
+pub const RTK_PROGRESS_TILE: c_int = 1;
+pub const RTK_PROGRESS_FINISHED: c_int = 2;
+
+/// A progress update passed to the callback of `rtk_render`.
+#[repr(C)]
+pub struct RtkProgress {
+    pub kind: c_int,
+    /// For `RTK_PROGRESS_PERCENT`, the percentage of pixels rendered.
+    pub percent: u32,
+    /// For `RTK_PROGRESS_TILE`, the rectangle of the output buffer that is now final.
+    pub tile_x: u32,
+    pub tile_y: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+}
+
+/// Called with each progress update. Returning nonzero cancels the render.
+pub type RtkProgressCallback =
+    Option<unsafe extern "C" fn(progress: *const RtkProgress, user_data: *mut c_void) -> c_int>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+struct Error {
+    code: c_int,
+    message: String,
+}
+
+impl Error {
+    fn new(code: c_int, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// The message of the last error on this thread, or an empty string. The pointer is valid until
+/// the next call into this library on the same thread.
+#[no_mangle]
+pub extern "C" fn rtk_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ptr())
+}
+
+/// Get the dimensions of the image a scene renders to, for allocating the output buffer.
+///
+/// # Safety
+/// `scene_json` must be a valid, null-terminated string, and `width` and `height` must be valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rtk_scene_dimensions(
+    scene_json: *const c_char,
+    width: *mut u32,
+    height: *mut u32,
+) -> c_int {
+    guard(|| {
+        if width.is_null() || height.is_null() {
+            return Err(Error::new(
+                RTK_ERROR_INVALID_ARGUMENT,
+                "null output pointer",
+            ));
+        }
+
+        let scene = parse_scene(scene_json)?;
+        (*width, *height) = Renderer::new(scene.create_camera()).output_dimensions();
+
+        Ok(())
+    })
+}
+
+/// Render a scene into `out_buf` as tightly packed 8-bit RGB rows.
+///
+/// # Safety
+/// `scene_json` must be a valid, null-terminated string, and `out_buf` must be valid for writes of
+/// `out_len` bytes. `callback`, if not null, is called with `user_data` from the rendering threads,
+/// one call at a time.
+#[no_mangle]
+pub unsafe extern "C" fn rtk_render(
+    scene_json: *const c_char,
+    out_buf: *mut u8,
+    out_len: usize,
+    callback: RtkProgressCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    // Pointers are not `Send`, but the caller guarantees the callback can be used from any thread.
+    struct UserData(*mut c_void);
+    unsafe impl Send for UserData {}
+
+    guard(|| {
+        if out_buf.is_null() {
+            return Err(Error::new(RTK_ERROR_INVALID_ARGUMENT, "null output buffer"));
+        }
+
+        let mut scene = parse_scene(scene_json)?;
+        let mut renderer = Renderer::new(scene.create_camera());
+        let (width, height) = renderer.output_dimensions();
+        let required = width as usize * height as usize * 3;
+        if out_len < required {
+            return Err(Error::new(
+                RTK_ERROR_BUFFER_TOO_SMALL,
+                format!("the image needs {required} bytes, but the buffer has {out_len}"),
+            ));
+        }
+
+        let mut world = scene
+            .create_world(&Plugins::new())
+            .map_err(|e| Error::new(RTK_ERROR_SCENE, e))?;
+        if scene.camera.frame.is_some() {
+            let position = scene
+                .frame_camera(&mut world)
+                .map_err(|e| Error::new(RTK_ERROR_SCENE, e))?;
+            renderer.set_position(position);
+        }
+        if let Some(distance) = scene
+            .focus_distance(&world)
+            .map_err(|e| Error::new(RTK_ERROR_SCENE, e))?
+        {
+            renderer.set_focus_distance(distance);
+        }
+        let handle = renderer.handle();
+        let user_data = UserData(user_data);
+
+        let image = renderer
+            .render(&world, true, move |event| {
+                let Some(callback) = callback else {
+                    return;
+                };
+                let mut progress = RtkProgress {
+                    kind: RTK_PROGRESS_PERCENT,
+                    percent: 0,
+                    tile_x: 0,
+                    tile_y: 0,
+                    tile_width: 0,
+                    tile_height: 0,
+                };
+                match event {
+                    ProgressEvent::Rendering(percent) => progress.percent = percent,
+                    ProgressEvent::TileFinished { x, y, pixels, .. } => {
+                        progress.kind = RTK_PROGRESS_TILE;
+                        (progress.tile_x, progress.tile_y) = (x, y);
+                        (progress.tile_width, progress.tile_height) = pixels.dimensions();
+                    }
+                    ProgressEvent::Finished => progress.kind = RTK_PROGRESS_FINISHED,
+                    _ => return,
+                }
+
+                let user_data = &user_data;
+                if callback(&progress, user_data.0) != 0 {
+                    handle.cancel();
+                }
+            })
+            .map_err(|e| Error::new(RTK_ERROR_CANCELLED, e))?;
+
+        let out = std::slice::from_raw_parts_mut(out_buf, required);
+        out.copy_from_slice(image.as_raw());
+
+        Ok(())
+    })
+}
+
+/// Run `f`, recording its error, and never unwinding into the caller.
+fn guard(f: impl FnOnce() -> Result<(), Error>) -> c_int {
+    let result = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        Err(Error::new(RTK_ERROR_PANIC, format!("panicked: {message}")))
+    });
+
+    let (code, message) = match result {
+        Ok(()) => (RTK_OK, String::new()),
+        Err(e) => (e.code, e.message),
+    };
+    LAST_ERROR.with(|error| {
+        *error.borrow_mut() = CString::new(message.replace('\0', "")).unwrap_or_default();
+    });
+
+    code
+}
+
+/// # Safety
+/// `scene_json` must be null or a valid, null-terminated string.
+unsafe fn parse_scene(scene_json: *const c_char) -> Result<Scene, Error> {
+    if scene_json.is_null() {
+        return Err(Error::new(RTK_ERROR_INVALID_ARGUMENT, "null scene"));
+    }
+
+    let source = CStr::from_ptr(scene_json)
+        .to_str()
+        .map_err(|e| Error::new(RTK_ERROR_INVALID_ARGUMENT, e))?;
+    let mut json: serde_json::Value =
+        serde_json::from_str(source).map_err(|e| Error::new(RTK_ERROR_SCENE, e))?;
+    // Scenes are migrated from older versions of the format like scene files are, by way of TOML.
+    // It has no null, so null fields are left out, which reads them as missing.
+    remove_nulls(&mut json);
+    let source = toml::to_string(&json).map_err(|e| Error::new(RTK_ERROR_SCENE, e))?;
+    let (mut scene, _) = Scene::from_toml(&source).map_err(|e| Error::new(RTK_ERROR_SCENE, e))?;
+    scene
+        .run_script()
+        .map_err(|e| Error::new(RTK_ERROR_SCENE, e))?;
+    scene
+        .validate()
+        .map_err(|e| Error::new(RTK_ERROR_SCENE, e))?;
+
+    Ok(scene)
+}
+
+/// Remove the null fields of the objects in `value`, at any depth.
+fn remove_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            fields.retain(|_, field| !field.is_null());
+            fields.values_mut().for_each(remove_nulls);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
@@ -0,0 +1,115 @@
+//! Registers a custom shape and material as plugins, and renders a scene that uses them.
+//!
+//! ```sh
+//! cargo run --release --example plugin
+//! ```
+
+use std::{ops::Range, sync::Arc};
+
+use nalgebra::Vector3;
+use rtk::{
+    object::Hit,
+    plugin::{Bsdf, Hittable, Plugins},
+    render::{Color, Ray, Renderer},
+    scene::Scene,
+};
+
+/// A flat, round disk.
+struct Disk {
+    center: Vector3<f64>,
+    normal: Vector3<f64>,
+    radius: f64,
+}
+
+impl Hittable for Disk {
+    fn hit(&self, ray: &Ray, ray_t: Range<f64>) -> Option<Hit> {
+        let denom = self.normal.dot(&ray.direction);
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.center - ray.origin).dot(&self.normal) / denom;
+        if !ray_t.contains(&t) || (ray.at(t) - self.center).magnitude() > self.radius {
+            return None;
+        }
+
+        Some(Hit::new(ray, t, &self.normal))
+    }
+}
+
+/// Glows with the color of the surface normal, a common debugging material.
+struct Normals;
+
+impl Bsdf for Normals {
+    fn scatter(&self, _ray: &Ray, _hit: &Hit) -> Option<(Color, Ray)> {
+        None
+    }
+
+    fn emit(&self, hit: &Hit) -> Color {
+        (hit.normal + Color::repeat(1.0)) / 2.0
+    }
+}
+
+/// Read a vector parameter given as an array of three numbers.
+fn vector(params: &toml::Table, name: &str) -> anyhow::Result<Vector3<f64>> {
+    let values: Vec<f64> = params
+        .get(name)
+        .and_then(|v| v.as_array())
+        .map(|a| a.iter().filter_map(|v| v.as_float()).collect())
+        .unwrap_or_default();
+    match values[..] {
+        [x, y, z] => Ok(Vector3::new(x, y, z)),
+        _ => anyhow::bail!("`{name}` must be an array of three numbers"),
+    }
+}
+
+const SCENE: &str = r#"
+[camera]
+image-dimensions = [400, 225]
+samples-per-pixel = 50
+position = [0.0, 1.0, 4.0]
+rotation = { type = "euler", roll = -0.2, pitch = 0.0, yaw = 0.0 }
+fov = 50.0
+background-color = [0.7, 0.8, 1.0]
+
+[[materials]]
+type = "diffuse"
+albedo = [0.8, 0.3, 0.3]
+
+[[objects]]
+material = 0
+shape = { type = "custom", name = "disk", params = { center = [0.0, 0.0, 0.0], normal = [0.0, 1.0, 0.0], radius = 1.5 } }
+
+[[materials]]
+type = "custom"
+name = "normals"
+
+[[objects]]
+material = 1
+shape = { type = "custom", name = "disk", params = { center = [0.0, 0.8, -0.5], normal = [0.0, 0.0, 1.0], radius = 0.6 } }
+"#;
+
+fn main() -> anyhow::Result<()> {
+    let mut plugins = Plugins::new();
+    plugins.register_shape("disk", |params| {
+        let radius = params
+            .get("radius")
+            .and_then(|v| v.as_float())
+            .ok_or_else(|| anyhow::anyhow!("`radius` must be a number"))?;
+        Ok(Arc::new(Disk {
+            center: vector(params, "center")?,
+            normal: vector(params, "normal")?.normalize(),
+            radius,
+        }))
+    });
+    plugins.register_material("normals", |_| Ok(Arc::new(Normals)));
+
+    let (scene, _) = Scene::from_toml(SCENE)?;
+    scene.validate()?;
+
+    let renderer = Renderer::new(scene.create_camera());
+    let image = renderer.render(&scene.create_world(&plugins)?, true, |_| {})?;
+    image.save("plugin.png")?;
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+//! Builds a grid of spheres in Rust, without a scene file, and renders it.
+//!
+//! ```sh
+//! cargo run --release --example procedural
+//! ```
+
+use nalgebra::{UnitQuaternion, Vector3};
+use rtk::{
+    builder::WorldBuilder,
+    light::{Falloff, Light},
+    material::Material,
+    render::{Camera, ProgressEvent, Renderer},
+};
+
+fn main() -> anyhow::Result<()> {
+    let mut builder = WorldBuilder::new();
+
+    let floor = builder.add_material(Material::diffuse(0.5, 0.5, 0.5));
+    builder.add_quad(
+        Vector3::new(-10.0, 0.0, 10.0),
+        Vector3::new(20.0, 0.0, 0.0),
+        Vector3::new(0.0, 0.0, -20.0),
+        floor,
+    );
+
+    for i in 0..5 {
+        for j in 0..3 {
+            let t = i as f64 / 4.0;
+            let material = builder.add_material(Material::diffuse(t, 0.3, 1.0 - t));
+            let center = Vector3::new(i as f64 - 2.0, 0.3, -(j as f64));
+            builder.add_sphere(center, 0.3, material);
+        }
+    }
+
+    builder.add_light(Light::Point {
+        position: Vector3::new(0.0, 4.0, 2.0),
+        color: Vector3::repeat(20.0),
+        falloff: Falloff::InverseSquare,
+    });
+
+    let camera = Camera {
+        image_width: 400,
+        image_height: 225,
+        background_color: Vector3::new(0.7, 0.8, 1.0),
+        position: Vector3::new(0.0, 1.5, 4.0),
+        rotation: UnitQuaternion::from_euler_angles(-0.3, 0.0, 0.0),
+        fov: 50.0,
+        focus_distance: 1.0,
+        defocus_angle: 0.0,
+        samples_per_pixel: 50,
+        seed: 0,
+        regularization: 0.0,
+        auto_exposure: false,
+        regions: Vec::new(),
+        shutter: None,
+        polarizer: None,
+        super_resolution: 1,
+        stereo: None,
+        adaptive: None,
+    };
+
+    let renderer = Renderer::new(camera);
+    let image = renderer.render(&builder.build(), true, |event| {
+        if let ProgressEvent::Rendering(percent) = event {
+            eprint!("\r{percent}%");
+        }
+    });
+    eprintln!();
+    image?.save("procedural.png")?;
+
+    Ok(())
+}
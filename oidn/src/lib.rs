@@ -29,16 +29,36 @@ pub struct Device {
 }
 
 impl Device {
+    /// Create a device of the default type, which is the fastest supported device of the
+    /// machine.
     pub fn new() -> Result<Self> {
-        unsafe {
-            let device = sys::oidnNewDevice(sys::OIDNDeviceType_OIDN_DEVICE_TYPE_DEFAULT);
-            sys::oidnCommitDevice(device);
+        unsafe { Self::commit(sys::oidnNewDevice(sys::OIDNDeviceType_OIDN_DEVICE_TYPE_DEFAULT)) }
+    }
 
-            let device = Self { device };
-            device.get_error()?;
+    /// Create a device that runs on the CPU, which every machine supports.
+    pub fn cpu() -> Result<Self> {
+        unsafe { Self::commit(sys::oidnNewDevice(sys::OIDNDeviceType_OIDN_DEVICE_TYPE_CPU)) }
+    }
 
-            Ok(device)
+    /// Create a device on the physical device with the ID given by `physical_devices`.
+    pub fn with_id(id: i32) -> Result<Self> {
+        unsafe { Self::commit(sys::oidnNewDeviceByID(id)) }
+    }
+
+    /// Take ownership of a newly created device and commit it.
+    unsafe fn commit(device: sys::OIDNDevice) -> Result<Self> {
+        if device.is_null() {
+            // Creating a device fails without one to report the error through.
+            return Err(Error {
+                message: "the device is not supported on this machine".to_string(),
+            });
         }
+        sys::oidnCommitDevice(device);
+
+        let device = Self { device };
+        device.get_error()?;
+
+        Ok(device)
     }
 
     pub fn get_error(&self) -> Result<()> {
@@ -73,6 +93,79 @@ impl Drop for Device {
     }
 }
 
+/// The kind of hardware a device runs on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceType {
+    Cpu,
+    /// An Intel GPU.
+    Sycl,
+    /// An NVIDIA GPU.
+    Cuda,
+    /// An AMD GPU.
+    Hip,
+    /// An Apple GPU.
+    Metal,
+    Unknown,
+}
+
+impl std::fmt::Display for DeviceType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DeviceType::Cpu => "CPU",
+            DeviceType::Sycl => "SYCL",
+            DeviceType::Cuda => "CUDA",
+            DeviceType::Hip => "HIP",
+            DeviceType::Metal => "Metal",
+            DeviceType::Unknown => "unknown",
+        })
+    }
+}
+
+/// A device of the machine that the library supports.
+#[derive(Clone, Debug)]
+pub struct PhysicalDevice {
+    /// The ID to create a device on it with, through `Device::with_id`.
+    pub id: i32,
+    pub name: String,
+    pub device_type: DeviceType,
+}
+
+/// The devices of the machine that the library supports, in order of how fast they are expected
+/// to be, which is the order the default device is picked in.
+pub fn physical_devices() -> Vec<PhysicalDevice> {
+    let type_name = CString::new("type").unwrap();
+    let name_name = CString::new("name").unwrap();
+
+    unsafe {
+        (0..sys::oidnGetNumPhysicalDevices())
+            .map(|id| {
+                let name = sys::oidnGetPhysicalDeviceString(id, name_name.as_ptr());
+                let name = if name.is_null() {
+                    String::new()
+                } else {
+                    CStr::from_ptr(name).to_string_lossy().to_string()
+                };
+                let device_type = match sys::oidnGetPhysicalDeviceInt(id, type_name.as_ptr())
+                    as sys::OIDNDeviceType
+                {
+                    sys::OIDNDeviceType_OIDN_DEVICE_TYPE_CPU => DeviceType::Cpu,
+                    sys::OIDNDeviceType_OIDN_DEVICE_TYPE_SYCL => DeviceType::Sycl,
+                    sys::OIDNDeviceType_OIDN_DEVICE_TYPE_CUDA => DeviceType::Cuda,
+                    sys::OIDNDeviceType_OIDN_DEVICE_TYPE_HIP => DeviceType::Hip,
+                    sys::OIDNDeviceType_OIDN_DEVICE_TYPE_METAL => DeviceType::Metal,
+                    _ => DeviceType::Unknown,
+                };
+
+                PhysicalDevice {
+                    id,
+                    name,
+                    device_type,
+                }
+            })
+            .collect()
+    }
+}
+
 pub struct Buffer<'a> {
     _device: &'a Device,
     buffer: sys::OIDNBuffer,
@@ -188,6 +281,17 @@ impl<'a> Filter<'a> {
         self.set_image(buffer, width, height, &color)
     }
 
+    /// Set whether the color image is in linear colors that may be brighter than white, rather
+    /// than display colors from 0 to 1.
+    pub fn set_hdr(&mut self, hdr: bool) -> Result<()> {
+        let name = CString::new("hdr").unwrap();
+        unsafe {
+            sys::oidnSetFilterBool(self.filter, name.as_ptr(), hdr);
+        }
+
+        self.device.get_error()
+    }
+
     pub fn execute(&self) -> Result<()> {
         unsafe {
             sys::oidnCommitFilter(self.filter);
@@ -0,0 +1,457 @@
+//! Bakes the light falling on a scene's objects into a lightmap, a texture laid out over their
+//! surfaces, so the path tracer can light scenes for real-time engines.
+//!
+//! Surfaces in rtk have no texture coordinates of their own, so the lightmap is laid out here: it
+//! is split into a grid of square cells, each holding a quad or two triangles of a mesh, with a
+//! gutter around each so that filtering in an engine does not bleed between them. `--obj` writes
+//! the baked surfaces with the coordinates they were given.
+
+use std::{collections::BTreeSet, fmt::Write as _, sync::Mutex};
+
+use nalgebra::Vector3;
+use rand::Rng;
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefMutIterator, ParallelIterator,
+};
+use rtk::{
+    object::Object,
+    plugin::Plugins,
+    random,
+    render::{
+        self, random_unit_vector, vector_near_zero, Color, ProgressEvent, Ray, Renderer, Stage,
+    },
+};
+
+use crate::{
+    cli::BakeArgs,
+    print_warning,
+    status::{ProgressDisplay, StatusLine},
+};
+
+/// The texels kept clear around the surface in each cell, which are filled in from its edges.
+const GUTTER: u32 = 2;
+
+/// A quad, or a triangle of a mesh, and where it lies in the lightmap.
+struct Chart {
+    /// The name of the scene object it belongs to.
+    object: String,
+    origin: Vector3<f64>,
+    /// The edges from `origin` along which the chart's two coordinates run.
+    edges: [Vector3<f64>; 2],
+    /// The unit normal of the side that is baked, or `None` if the chart has no area.
+    normal: Option<Vector3<f64>>,
+    shape: ChartShape,
+    /// The cell of the grid it lies in.
+    cell: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ChartShape {
+    Parallelogram,
+    /// The triangle in the lower left of its cell, with `origin` in the corner.
+    LowerTriangle,
+    /// The triangle in the upper right of its cell, with `origin` in the corner.
+    UpperTriangle,
+}
+
+impl Chart {
+    /// The position on the chart's surface, as fractions along its edges, of the point `(s, t)`
+    /// of the inside of its cell. `margin` is the gap between the triangles sharing a cell, as a
+    /// fraction of its inside.
+    fn surface_position(&self, (s, t): (f64, f64), margin: f64) -> (f64, f64) {
+        match self.shape {
+            ChartShape::Parallelogram => (s, t),
+            ChartShape::LowerTriangle => (s / (1.0 - margin), t / (1.0 - margin)),
+            ChartShape::UpperTriangle => ((1.0 - s) / (1.0 - margin), (1.0 - t) / (1.0 - margin)),
+        }
+    }
+
+    /// Whether a position along the chart's edges lies on its surface.
+    fn covers(&self, (a, b): (f64, f64)) -> bool {
+        match self.shape {
+            ChartShape::Parallelogram => (0.0..=1.0).contains(&a) && (0.0..=1.0).contains(&b),
+            _ => a >= 0.0 && b >= 0.0 && a + b <= 1.0,
+        }
+    }
+
+    /// The point of the cell's inside at position `(a, b)` on the chart's surface.
+    fn cell_position(&self, (a, b): (f64, f64), margin: f64) -> (f64, f64) {
+        match self.shape {
+            ChartShape::Parallelogram => (a, b),
+            ChartShape::LowerTriangle => (a * (1.0 - margin), b * (1.0 - margin)),
+            ChartShape::UpperTriangle => (1.0 - a * (1.0 - margin), 1.0 - b * (1.0 - margin)),
+        }
+    }
+
+    /// Bring a position along the chart's edges that may lie just outside it back onto it.
+    fn clamp(&self, (a, b): (f64, f64)) -> (f64, f64) {
+        let (a, b) = (a.max(0.0), b.max(0.0));
+        match self.shape {
+            ChartShape::Parallelogram => (a.min(1.0), b.min(1.0)),
+            _ if a + b > 1.0 => (a / (a + b), b / (a + b)),
+            _ => (a, b),
+        }
+    }
+
+    fn point(&self, (a, b): (f64, f64)) -> Vector3<f64> {
+        self.origin + a * self.edges[0] + b * self.edges[1]
+    }
+
+    /// The corners of the chart's surface, as positions along its edges.
+    fn corners(&self) -> Vec<(f64, f64)> {
+        match self.shape {
+            ChartShape::Parallelogram => vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            _ => vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+        }
+    }
+}
+
+/// The grid of cells the lightmap is split into.
+struct Layout {
+    /// The number of cells across and down.
+    cells: u32,
+    /// The width of each cell, in texels.
+    cell_size: u32,
+}
+
+impl Layout {
+    /// The inside of a cell, within its gutter, in texels.
+    fn inside(&self) -> f64 {
+        (self.cell_size - 2 * GUTTER) as f64
+    }
+
+    /// The gap between two triangles sharing a cell, as a fraction of its inside.
+    fn margin(&self) -> f64 {
+        2.0 * GUTTER as f64 / self.inside()
+    }
+
+    /// The cell containing texel point `(x, y)`, and the point's position in the cell's inside.
+    fn locate(&self, (x, y): (f64, f64)) -> Option<(usize, (f64, f64))> {
+        let (column, row) = (x as u32 / self.cell_size, y as u32 / self.cell_size);
+        if column >= self.cells || row >= self.cells {
+            return None;
+        }
+        let corner = |cell: u32| (cell * self.cell_size + GUTTER) as f64;
+        let position = (
+            (x - corner(column)) / self.inside(),
+            (y - corner(row)) / self.inside(),
+        );
+        Some(((row * self.cells + column) as usize, position))
+    }
+
+    /// The texel point of position `(s, t)` in the inside of `cell`.
+    fn texel_position(&self, cell: usize, (s, t): (f64, f64)) -> (f64, f64) {
+        let (column, row) = (cell as u32 % self.cells, cell as u32 / self.cells);
+        (
+            (column * self.cell_size + GUTTER) as f64 + s * self.inside(),
+            (row * self.cell_size + GUTTER) as f64 + t * self.inside(),
+        )
+    }
+}
+
+/// Handle `cli::Command::Bake`.
+pub fn bake(args: &BakeArgs) -> anyhow::Result<()> {
+    let scene = crate::load_scene(&args.scene)?;
+    scene.validate()?;
+    // Visible ranges stay resolved from the scene's camera, as a lightmap has no viewpoint.
+    let world = scene.create_world(&Plugins::new())?;
+    let camera = scene.create_camera();
+    let seed = camera.seed;
+    let samples = args.samples.unwrap_or(camera.samples_per_pixel).max(1);
+    let renderer = Renderer::new(camera);
+
+    let mut charts = Vec::new();
+    let mut cell = 0;
+    for name in &args.objects {
+        let id = scene
+            .objects
+            .iter()
+            .position(|object| object.name.as_ref() == Some(name))
+            .ok_or_else(|| anyhow::anyhow!("the scene has no object named `{name}`"))?;
+        if world.object_settings[id].transform.is_some() {
+            anyhow::bail!("`{name}` cannot be baked, since it has a transform");
+        }
+        let mut triangles = Vec::new();
+        for (index, object) in world.objects.iter().enumerate() {
+            if world.object_ids[index] != id {
+                continue;
+            }
+            match &*object {
+                Object::Quad { q, u, v, .. } => {
+                    charts.push(Chart {
+                        object: name.clone(),
+                        origin: *q,
+                        edges: [*u, *v],
+                        normal: u.cross(v).try_normalize(1e-12),
+                        shape: ChartShape::Parallelogram,
+                        cell,
+                    });
+                    cell += 1;
+                }
+                Object::Mesh { mesh, .. } => triangles.extend(mesh.triangles()),
+                _ => anyhow::bail!(
+                    "`{name}` cannot be baked; only quads and meshes can be laid out in a lightmap"
+                ),
+            }
+        }
+        for pair in triangles.chunks(2) {
+            for ([a, b, c], shape) in pair
+                .iter()
+                .zip([ChartShape::LowerTriangle, ChartShape::UpperTriangle])
+            {
+                charts.push(Chart {
+                    object: name.clone(),
+                    origin: *a,
+                    edges: [b - a, c - a],
+                    normal: (b - a).cross(&(c - a)).try_normalize(1e-12),
+                    shape,
+                    cell,
+                });
+            }
+            cell += 1;
+        }
+    }
+    if charts.is_empty() {
+        anyhow::bail!("the objects to bake have no surfaces");
+    }
+
+    let orient = |(i, chart): (usize, &mut Chart)| {
+        random::seed_pixel(seed, u32::MAX, i as u32);
+        face_light(&world, &renderer, chart)
+    };
+    let lit = if args.no_parallel {
+        charts
+            .iter_mut()
+            .enumerate()
+            .map(orient)
+            .collect::<Vec<_>>()
+    } else {
+        charts.par_iter_mut().enumerate().map(orient).collect()
+    };
+    let dark = charts
+        .iter()
+        .zip(lit)
+        .filter(|&(_, lit)| !lit)
+        .map(|(chart, _)| &chart.object)
+        .collect::<BTreeSet<_>>();
+    for name in dark {
+        print_warning(&format!(
+            "`{name}` has surfaces that no light falls on from either side, which bake black"
+        ));
+    }
+
+    let cells = (cell as f64).sqrt().ceil() as u32;
+    let layout = Layout {
+        cells,
+        cell_size: args.size / cells,
+    };
+    if layout.cell_size < 2 * GUTTER + 2 {
+        anyhow::bail!(
+            "a {0}x{0} lightmap is too small for {cell} cells; give a larger --size",
+            args.size
+        );
+    }
+    let mut charts_by_cell = vec![Vec::new(); cells as usize * cells as usize];
+    for (i, chart) in charts.iter().enumerate() {
+        charts_by_cell[chart.cell].push(i);
+    }
+
+    let status = Mutex::new((StatusLine::new(), 0, 0));
+    status
+        .lock()
+        .unwrap()
+        .0
+        .update(&ProgressEvent::Stage(Stage::Rendering));
+    let bake_row = |y: u32| {
+        let row = (0..args.size)
+            .map(|x| {
+                let (cell, position) = layout.locate((x as f64 + 0.5, y as f64 + 0.5))?;
+                let chart = charts_by_cell[cell]
+                    .iter()
+                    .map(|&i| &charts[i])
+                    .find(|chart| {
+                        chart.covers(chart.surface_position(position, layout.margin()))
+                    })?;
+                random::seed_pixel(seed, x, y);
+                Some(bake_texel(
+                    &world,
+                    &renderer,
+                    &layout,
+                    chart,
+                    (x, y),
+                    samples,
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        let mut status = status.lock().unwrap();
+        status.1 += 1;
+        let percent = status.1 * 100 / args.size;
+        if percent > status.2 {
+            status.2 = percent;
+            status.0.update(&ProgressEvent::Rendering(percent));
+        }
+        row
+    };
+    let rows: Vec<Vec<Option<Color>>> = if args.no_parallel {
+        (0..args.size).map(bake_row).collect()
+    } else {
+        (0..args.size).into_par_iter().map(bake_row).collect()
+    };
+    let mut status = status.into_inner().unwrap().0;
+
+    let lightmap = dilate(rows, args.size);
+    status.update(&ProgressEvent::Stage(Stage::Writing));
+    let is_exr = args
+        .output
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("exr"));
+    if is_exr {
+        lightmap.save(&args.output)?;
+    } else {
+        render::to_display(&lightmap).save(&args.output)?;
+    }
+    if let Some(path) = &args.obj {
+        std::fs::write(path, obj(&charts, &layout, args.size))?;
+    }
+    status.finish(&format!("Wrote {}", args.output.display()));
+
+    Ok(())
+}
+
+/// Turn `chart` to face the side that more light falls on, judged by rays cast to either side from
+/// points across it, and return whether any light falls on it. The order of a surface's edges says
+/// nothing about where it is lit from, as for a floor whose normal points down.
+fn face_light(world: &rtk::object::World, renderer: &Renderer, chart: &mut Chart) -> bool {
+    const PROBES: u32 = 64;
+
+    let Some(normal) = chart.normal else {
+        return false;
+    };
+    let mut rng = random::rng();
+    let mut light = |normal: Vector3<f64>| {
+        (0..PROBES)
+            .map(|_| {
+                let on_surface = chart.clamp((rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0)));
+                let ray = hemisphere_ray(chart.point(on_surface), normal);
+                renderer.ray_color(world, ray).sum()
+            })
+            .sum::<f64>()
+    };
+    let (front, back) = (light(normal), light(-normal));
+    if back > front {
+        chart.normal = Some(-normal);
+    }
+    front.max(back) > 0.0
+}
+
+/// A ray from `origin` in a random direction of the hemisphere around `normal`, with directions
+/// near the normal more likely, by the cosine of their angle to it.
+fn hemisphere_ray(origin: Vector3<f64>, normal: Vector3<f64>) -> Ray {
+    let mut direction = normal + random_unit_vector();
+    if vector_near_zero(&direction) {
+        direction = normal;
+    }
+    Ray {
+        origin,
+        direction: direction.normalize(),
+        time: 0.0,
+    }
+}
+
+/// The light arriving at the texel at (x, y) of `chart`, averaged over the hemisphere of its baked
+/// side, with each direction weighted by its cosine. This is the irradiance divided by pi, so a
+/// diffuse surface's color times it is the light the surface reflects.
+fn bake_texel(
+    world: &rtk::object::World,
+    renderer: &Renderer,
+    layout: &Layout,
+    chart: &Chart,
+    (x, y): (u32, u32),
+    samples: u32,
+) -> Color {
+    let Some(normal) = chart.normal else {
+        return Color::zeros();
+    };
+    let cell_corner = layout.texel_position(chart.cell, (0.0, 0.0));
+    let mut rng = random::rng();
+
+    let mut sum = Color::zeros();
+    for _ in 0..samples {
+        // Each sample lands somewhere in the texel, which smooths the lightmap's edges.
+        let texel = (
+            x as f64 + rng.gen_range(0.0..1.0),
+            y as f64 + rng.gen_range(0.0..1.0),
+        );
+        let position = (
+            (texel.0 - cell_corner.0) / layout.inside(),
+            (texel.1 - cell_corner.1) / layout.inside(),
+        );
+        let on_surface = chart.clamp(chart.surface_position(position, layout.margin()));
+        sum += renderer.ray_color(world, hemisphere_ray(chart.point(on_surface), normal));
+    }
+
+    sum / samples as f64
+}
+
+/// Turn the baked texels into an image, spreading the texels at the edges of each chart outwards
+/// over the gutters so that filtering across an edge does not blend in black.
+fn dilate(mut rows: Vec<Vec<Option<Color>>>, size: u32) -> image::Rgb32FImage {
+    for _ in 0..GUTTER * 2 {
+        let previous = rows.clone();
+        for (y, row) in rows.iter_mut().enumerate() {
+            for (x, texel) in row.iter_mut().enumerate() {
+                if texel.is_some() {
+                    continue;
+                }
+                let neighbours = (-1..=1)
+                    .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                    .filter_map(|(dx, dy)| {
+                        let row = previous.get(y.checked_add_signed(dy)?)?;
+                        *row.get(x.checked_add_signed(dx)?)?
+                    })
+                    .collect::<Vec<_>>();
+                if !neighbours.is_empty() {
+                    *texel = Some(neighbours.iter().sum::<Color>() / neighbours.len() as f64);
+                }
+            }
+        }
+    }
+
+    image::Rgb32FImage::from_fn(size, size, |x, y| {
+        let color = rows[y as usize][x as usize].unwrap_or_else(Color::zeros);
+        image::Rgb(color.cast::<f32>().into())
+    })
+}
+
+/// The baked surfaces as a Wavefront OBJ file, with their lightmap coordinates, grouped by
+/// object.
+fn obj(charts: &[Chart], layout: &Layout, size: u32) -> String {
+    let mut obj = String::from("# baked by rtk\n");
+    let mut vertices = 0;
+    let mut object = None;
+    for chart in charts {
+        if object != Some(&chart.object) {
+            object = Some(&chart.object);
+            _ = writeln!(obj, "o {}", chart.object);
+        }
+
+        let corners = chart.corners();
+        for &corner in &corners {
+            let p = chart.point(corner);
+            let (x, y) =
+                layout.texel_position(chart.cell, chart.cell_position(corner, layout.margin()));
+            // Texture coordinates start at the bottom left, and texels at the top left.
+            _ = writeln!(obj, "v {} {} {}", p.x, p.y, p.z);
+            _ = writeln!(obj, "vt {} {}", x / size as f64, 1.0 - y / size as f64);
+        }
+        obj.push('f');
+        for i in 1..=corners.len() {
+            _ = write!(obj, " {0}/{0}", vertices + i);
+        }
+        obj.push('\n');
+        vertices += corners.len();
+    }
+
+    obj
+}
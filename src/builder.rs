@@ -0,0 +1,371 @@
+//! Builders for constructing scenes and worlds in Rust, without writing a scene file.
+//!
+//! `WorldBuilder` produces a `World` ready to render, while `SceneBuilder` produces a `Scene`,
+//! the model of a scene file, which can also be serialized to TOML.
+
+use std::sync::Arc;
+
+use nalgebra::{UnitQuaternion, Vector3};
+
+use crate::{
+    light::Light,
+    material::{LightPaths, Material},
+    medium::Atmosphere,
+    mesh::{Mesh, TriangleMesh},
+    metaball::Metaballs,
+    object::{Object, ObjectSettings, Objects, World},
+    plugin::Hittable,
+    points::PointCloud,
+    random::AliasTable,
+    scene,
+    texture::Texture,
+};
+
+/// Worlds with more lights than this cast a shadow ray toward one light at each hit, picked by
+/// power, rather than toward every light.
+const MANY_LIGHTS: usize = 8;
+
+/// A material added to a builder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialHandle(usize);
+
+/// A texture added to a builder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureHandle(usize);
+
+/// An object added to a builder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObjectHandle(usize);
+
+/// Primitives added to a builder to be shared by instances.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GeometryHandle(usize);
+
+impl MaterialHandle {
+    /// The index of the material, as used by `Material::Mix` and `Material::Override`.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl TextureHandle {
+    /// The index of the texture, as used by `Material::Mix` masks and graph texture nodes.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl ObjectHandle {
+    /// The ID of the object, which all primitives the object is made of share.
+    pub fn id(self) -> usize {
+        self.0
+    }
+}
+
+/// Builds a `World` one object, material, texture, and light at a time.
+#[derive(Default)]
+pub struct WorldBuilder {
+    objects: Vec<Object>,
+    object_ids: Vec<usize>,
+    object_settings: Vec<ObjectSettings>,
+    materials: Vec<Material>,
+    light_paths: Vec<LightPaths>,
+    textures: Vec<Texture>,
+    lights: Vec<Light>,
+    atmosphere: Option<Atmosphere>,
+    geometries: Vec<Arc<Objects>>,
+}
+
+impl WorldBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_material(&mut self, material: Material) -> MaterialHandle {
+        self.materials.push(material);
+        MaterialHandle(self.materials.len() - 1)
+    }
+
+    /// Set which light paths see a material, which by default is all of them.
+    pub fn set_light_paths(
+        &mut self,
+        material: MaterialHandle,
+        light_paths: LightPaths,
+    ) -> &mut Self {
+        if self.light_paths.len() <= material.0 {
+            self.light_paths
+                .resize(material.0 + 1, LightPaths::default());
+        }
+        self.light_paths[material.0] = light_paths;
+        self
+    }
+
+    pub fn add_texture(&mut self, texture: Texture) -> TextureHandle {
+        self.textures.push(texture);
+        TextureHandle(self.textures.len() - 1)
+    }
+
+    pub fn add_light(&mut self, light: Light) -> &mut Self {
+        self.lights.push(light);
+        self
+    }
+
+    pub fn set_atmosphere(&mut self, atmosphere: Atmosphere) -> &mut Self {
+        self.atmosphere = Some(atmosphere);
+        self
+    }
+
+    pub fn add_sphere(
+        &mut self,
+        center: Vector3<f64>,
+        radius: f64,
+        material: MaterialHandle,
+    ) -> ObjectHandle {
+        self.add_primitives([Object::sphere(center, radius, material.0)])
+    }
+
+    pub fn add_quad(
+        &mut self,
+        q: Vector3<f64>,
+        u: Vector3<f64>,
+        v: Vector3<f64>,
+        material: MaterialHandle,
+    ) -> ObjectHandle {
+        self.add_primitives([Object::quad(q, u, v, material.0)])
+    }
+
+    /// Add a prism; see `Object::prism`.
+    pub fn add_prism(
+        &mut self,
+        origin: Vector3<f64>,
+        (width, height, depth): (f64, f64, f64),
+        rotation: UnitQuaternion<f64>,
+        material: MaterialHandle,
+    ) -> ObjectHandle {
+        self.add_primitives(Object::prism(
+            &origin, width, height, depth, &rotation, material.0,
+        ))
+    }
+
+    /// Add a shape provided by a plugin.
+    pub fn add_custom(
+        &mut self,
+        hittable: Arc<dyn Hittable>,
+        material: MaterialHandle,
+    ) -> ObjectHandle {
+        self.add_primitives([Object::Custom {
+            hittable,
+            material: material.0,
+        }])
+    }
+
+    pub fn add_point_cloud(&mut self, cloud: PointCloud, material: MaterialHandle) -> ObjectHandle {
+        self.add_primitives([Object::PointCloud {
+            cloud: Arc::new(cloud),
+            material: material.0,
+        }])
+    }
+
+    pub fn add_mesh(&mut self, mesh: TriangleMesh, material: MaterialHandle) -> ObjectHandle {
+        self.add_primitives([Object::Mesh {
+            mesh: Mesh::new(mesh),
+            material: material.0,
+        }])
+    }
+
+    pub fn add_metaballs(
+        &mut self,
+        metaballs: Metaballs,
+        material: MaterialHandle,
+    ) -> ObjectHandle {
+        self.add_primitives([Object::Metaballs {
+            metaballs,
+            material: material.0,
+        }])
+    }
+
+    /// Add primitives to be placed by instances, rather than as an object of their own. However
+    /// many instances there are, the primitives are stored once.
+    pub fn add_geometry(&mut self, primitives: impl IntoIterator<Item = Object>) -> GeometryHandle {
+        let primitives = primitives.into_iter().collect();
+        self.geometries.push(Arc::new(Objects::new(
+            primitives,
+            |_| false,
+            |_, object| object.bounds(),
+        )));
+        GeometryHandle(self.geometries.len() - 1)
+    }
+
+    /// Add an object made of the primitives of `geometry`, shaded with `material`. Place it with
+    /// the `transform` of its settings.
+    pub fn add_instance(
+        &mut self,
+        geometry: GeometryHandle,
+        material: MaterialHandle,
+    ) -> ObjectHandle {
+        self.add_primitives([Object::Instance {
+            geometry: Arc::clone(&self.geometries[geometry.0]),
+            material: material.0,
+        }])
+    }
+
+    /// Add primitives that together form a single object, sharing one ID and one set of settings.
+    pub fn add_primitives(&mut self, primitives: impl IntoIterator<Item = Object>) -> ObjectHandle {
+        let id = self.object_settings.len();
+        self.object_settings.push(ObjectSettings::default());
+        self.objects.extend(primitives);
+        self.object_ids.resize(self.objects.len(), id);
+
+        ObjectHandle(id)
+    }
+
+    /// The settings of an object, which start out as the defaults.
+    pub fn settings_mut(&mut self, object: ObjectHandle) -> &mut ObjectSettings {
+        &mut self.object_settings[object.0]
+    }
+
+    pub fn build(self) -> World {
+        let objects = Objects::new(
+            self.objects,
+            |index| {
+                let settings = &self.object_settings[self.object_ids[index]];
+                !settings.clip_planes.is_empty()
+                    || !settings.motion.is_empty()
+                    || settings.transform.is_some()
+                    || settings.visible_range.is_some()
+            },
+            |index, object| {
+                let settings = &self.object_settings[self.object_ids[index]];
+                Some(settings.swept_bounds(object.bounds()?))
+            },
+        );
+        // Lights are weighed by their power over the world, which objects without bounds do not
+        // add to.
+        let world_radius = objects.bvh().bounds().map_or(1.0, |bounds| {
+            ((bounds.max - bounds.min).magnitude() / 2.0).max(f64::EPSILON)
+        });
+        World {
+            objects,
+            object_ids: self.object_ids,
+            object_settings: self.object_settings,
+            materials: self.materials,
+            light_paths: self.light_paths,
+            textures: self.textures,
+            light_picker: (self.lights.len() > MANY_LIGHTS)
+                .then(|| {
+                    AliasTable::new(
+                        &self
+                            .lights
+                            .iter()
+                            .map(|light| light.power(world_radius))
+                            .collect::<Vec<_>>(),
+                    )
+                })
+                .flatten(),
+            lights: self.lights,
+            atmosphere: self.atmosphere,
+        }
+    }
+}
+
+/// Builds a `Scene` one object, material, texture, and light at a time.
+pub struct SceneBuilder {
+    scene: scene::Scene,
+}
+
+impl SceneBuilder {
+    pub fn new(camera: scene::Camera) -> Self {
+        Self {
+            scene: scene::Scene {
+                version: crate::migrate::VERSION,
+                camera,
+                cameras: Default::default(),
+                materials: Vec::new(),
+                textures: Vec::new(),
+                objects: Vec::new(),
+                lights: Vec::new(),
+                atmosphere: None,
+                clip_planes: Vec::new(),
+                angle_unit: None,
+                script: None,
+            },
+        }
+    }
+
+    pub fn add_material(&mut self, material: scene::Material) -> MaterialHandle {
+        self.scene.materials.push(material.into());
+        MaterialHandle(self.scene.materials.len() - 1)
+    }
+
+    /// A material that has been added, for setting its light path rules.
+    pub fn material_mut(&mut self, material: MaterialHandle) -> &mut scene::MaterialEntry {
+        &mut self.scene.materials[material.0]
+    }
+
+    pub fn add_texture(&mut self, texture: scene::Texture) -> TextureHandle {
+        self.scene.textures.push(texture);
+        TextureHandle(self.scene.textures.len() - 1)
+    }
+
+    pub fn add_light(&mut self, light: scene::Light) -> &mut Self {
+        self.scene.lights.push(light);
+        self
+    }
+
+    pub fn set_atmosphere(&mut self, atmosphere: scene::Atmosphere) -> &mut Self {
+        self.scene.atmosphere = Some(atmosphere);
+        self
+    }
+
+    pub fn add_sphere(
+        &mut self,
+        center: (f64, f64, f64),
+        radius: f64,
+        material: MaterialHandle,
+    ) -> ObjectHandle {
+        self.add_object(
+            material,
+            scene::Shape::Sphere {
+                center,
+                radius,
+                end: None,
+            },
+        )
+    }
+
+    pub fn add_quad(
+        &mut self,
+        q: (f64, f64, f64),
+        u: (f64, f64, f64),
+        v: (f64, f64, f64),
+        material: MaterialHandle,
+    ) -> ObjectHandle {
+        self.add_object(material, scene::Shape::Quad { q, u, v })
+    }
+
+    pub fn add_object(&mut self, material: MaterialHandle, shape: scene::Shape) -> ObjectHandle {
+        self.scene.objects.push(scene::Object {
+            name: None,
+            material: material.0,
+            material_overrides: None,
+            bevel: 0.0,
+            clip_planes: Vec::new(),
+            motion: Vec::new(),
+            transform: None,
+            visible_range: None,
+            shape,
+        });
+        ObjectHandle(self.scene.objects.len() - 1)
+    }
+
+    /// An object that has been added, for setting its other fields.
+    pub fn object_mut(&mut self, object: ObjectHandle) -> &mut scene::Object {
+        &mut self.scene.objects[object.0]
+    }
+
+    /// Finish the scene, checking it like a scene loaded from a file.
+    pub fn build(self) -> anyhow::Result<scene::Scene> {
+        self.scene.validate()?;
+        Ok(self.scene)
+    }
+}
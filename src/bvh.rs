@@ -0,0 +1,150 @@
+//! A bounding volume hierarchy: a tree of boxes over the parts of a shape, such as the triangles of
+//! a mesh, so that a ray only tests the parts in the boxes it passes through.
+
+use std::ops::Range;
+
+use crate::{
+    object::{Aabb, Hit},
+    render::Ray,
+};
+
+/// The most items in a leaf.
+const LEAF_SIZE: usize = 4;
+
+#[derive(Clone)]
+struct Node {
+    bounds: Aabb,
+    /// For a leaf, the range of items it holds; otherwise empty, with the node's first child
+    /// following it and the second at `second_child`.
+    items: Range<usize>,
+    second_child: usize,
+}
+
+/// A hierarchy over a slice of items, which building it reorders.
+#[derive(Clone)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+}
+
+impl Bvh {
+    /// Build a hierarchy over `items`, reordering them so that each leaf holds a range of them. It
+    /// splits at the median along the axis where the items' centers are most spread out.
+    pub fn build<T: Clone>(items: &mut [T], bounds: impl Fn(&T) -> Aabb) -> Self {
+        let mut nodes = Vec::new();
+        if !items.is_empty() {
+            let bounds = items.iter().map(&bounds).collect::<Vec<_>>();
+            let mut order = (0..items.len()).collect::<Vec<_>>();
+            build(&mut order, 0, &bounds, &mut nodes);
+
+            let reordered = order.iter().map(|&i| items[i].clone()).collect::<Vec<_>>();
+            items.clone_from_slice(&reordered);
+        }
+
+        Self { nodes }
+    }
+
+    /// The memory taken by the nodes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.nodes.len() * size_of::<Node>()
+    }
+
+    /// The number of nodes in the hierarchy.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// The box enclosing every item, or `None` if there are none.
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.nodes.first().map(|node| node.bounds)
+    }
+
+    /// The boxes of the nodes down to `max_depth` levels below the root, with the depth of each.
+    pub fn node_bounds(&self, max_depth: usize) -> Vec<(Aabb, usize)> {
+        let mut bounds = Vec::new();
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push((0, 0));
+        }
+
+        while let Some((index, depth)) = stack.pop() {
+            let node = &self.nodes[index];
+            bounds.push((node.bounds, depth));
+            if node.items.is_empty() && depth < max_depth {
+                stack.extend([(node.second_child, depth + 1), (index + 1, depth + 1)]);
+            }
+        }
+
+        bounds
+    }
+
+    /// Find the nearest hit of a ray within `ray_t`, calling `hit_item` with the index of each item
+    /// the ray might hit and the range in which a hit would be the nearest so far.
+    #[inline(always)]
+    pub fn hit(
+        &self,
+        ray: &Ray,
+        mut ray_t: Range<f64>,
+        mut hit_item: impl FnMut(usize, Range<f64>) -> Option<Hit>,
+    ) -> Option<Hit> {
+        let mut hit = None;
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.bounds.hit(ray, ray_t.clone()) {
+                continue;
+            }
+            if node.items.is_empty() {
+                stack.extend([node.second_child, index + 1]);
+                continue;
+            }
+
+            for item in node.items.clone() {
+                if let Some(new_hit) = hit_item(item, ray_t.clone()) {
+                    ray_t.end = new_hit.t;
+                    hit = Some(new_hit);
+                }
+            }
+        }
+
+        hit
+    }
+}
+
+/// Build the tree over the items in `order`, which start at `start` in the final order, and return
+/// the index of its root.
+fn build(order: &mut [usize], start: usize, bounds: &[Aabb], nodes: &mut Vec<Node>) -> usize {
+    let center = |item: usize| (bounds[item].min + bounds[item].max) / 2.0;
+    let node_bounds = order
+        .iter()
+        .map(|&item| bounds[item])
+        .reduce(|a, b| a.union(&b))
+        .expect("nodes are never empty");
+
+    let index = nodes.len();
+    nodes.push(Node {
+        bounds: node_bounds,
+        items: start..start + order.len(),
+        second_child: 0,
+    });
+    if order.len() <= LEAF_SIZE {
+        return index;
+    }
+
+    let centers = order.iter().map(|&item| center(item)).collect::<Vec<_>>();
+    let spread = Aabb::from_points(&centers);
+    let axis = (spread.max - spread.min).imax();
+    let middle = order.len() / 2;
+    order.select_nth_unstable_by(middle, |&a, &b| center(a)[axis].total_cmp(&center(b)[axis]));
+
+    let (first, second) = order.split_at_mut(middle);
+    build(first, start, bounds, nodes);
+    let second_child = build(second, start + middle, bounds, nodes);
+    nodes[index].items = 0..0;
+    nodes[index].second_child = second_child;
+
+    index
+}
@@ -0,0 +1,146 @@
+//! Checkpoint files, which keep the finished tiles of a long render so that it can be resumed after
+//! a crash or an interruption. Each tile is appended to the file as soon as it is finished, after a
+//! header recording the image's size and a fingerprint of the scene and settings, so that an
+//! interruption loses at most the tiles being rendered.
+//!
+//! Every pixel of a tile is finished at once, so its linear color, the average of its samples,
+//! holds all the work done on it.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use image::Rgb32FImage;
+
+/// The bytes that start every checkpoint file, ending with the version of the format.
+const MAGIC: &[u8; 8] = b"rtkckpt1";
+
+/// The bytes before each tile's pixels: its column, row, width, and height.
+const TILE_HEADER_SIZE: usize = 4 * size_of::<u32>();
+
+/// Finished tiles of linear colors, by column and row.
+pub type Tiles = HashMap<(u32, u32), Rgb32FImage>;
+
+pub struct Checkpoint {
+    file: BufWriter<File>,
+}
+
+/// What a checkpoint must match for its tiles to be reused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Layout {
+    /// The size of the output image, in pixels.
+    pub dimensions: (u32, u32),
+    pub tile_size: u32,
+    /// A hash of the scene and the settings that change the image.
+    pub fingerprint: u64,
+}
+
+impl Checkpoint {
+    /// Start a new checkpoint at `path`, replacing any that is there.
+    pub fn create(path: &Path, layout: Layout) -> anyhow::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        for value in [layout.dimensions.0, layout.dimensions.1, layout.tile_size] {
+            file.write_all(&value.to_le_bytes())?;
+        }
+        file.write_all(&layout.fingerprint.to_le_bytes())?;
+        file.flush()?;
+
+        Ok(Self { file })
+    }
+
+    /// Read the tiles of the checkpoint at `path`, and reopen it to add the rest. A tile cut off
+    /// by an interruption while it was written is dropped.
+    pub fn resume(path: &Path, layout: Layout) -> anyhow::Result<(Self, Tiles)> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+
+        let header_size = MAGIC.len() + 3 * size_of::<u32>() + size_of::<u64>();
+        if bytes.len() < header_size || &bytes[..MAGIC.len()] != MAGIC {
+            anyhow::bail!("{} is not an rtk checkpoint", path.display());
+        }
+        let mut reader = &bytes[MAGIC.len()..];
+        let found = Layout {
+            dimensions: (read_u32(&mut reader), read_u32(&mut reader)),
+            tile_size: read_u32(&mut reader),
+            fingerprint: u64::from_le_bytes(take(&mut reader, size_of::<u64>()).try_into()?),
+        };
+        if found != layout {
+            anyhow::bail!(
+                "the checkpoint {} was written for a different scene or different settings",
+                path.display()
+            );
+        }
+
+        let mut tiles = HashMap::new();
+        let mut end = header_size;
+        while reader.len() >= TILE_HEADER_SIZE {
+            let mut header = &reader[..TILE_HEADER_SIZE];
+            let [column, row, width, height] = [(); 4].map(|_| read_u32(&mut header));
+            let size = width as usize * height as usize * 3 * size_of::<f32>();
+            if reader.len() < TILE_HEADER_SIZE + size {
+                break;
+            }
+            reader = &reader[TILE_HEADER_SIZE..];
+            let pixels = take(&mut reader, size)
+                .chunks_exact(size_of::<f32>())
+                .map(|channel| f32::from_le_bytes(channel.try_into().unwrap()))
+                .collect();
+            let tile = Rgb32FImage::from_raw(width, height, pixels)
+                .expect("the tile holds width times height pixels");
+            tiles.insert((column, row), tile);
+            end += TILE_HEADER_SIZE + size;
+        }
+
+        // Cut off a partly written tile, so that the tiles added after it can be read.
+        let file = std::fs::OpenOptions::new().write(true).open(path)?;
+        file.set_len(end as u64)?;
+        let mut file = BufWriter::new(file);
+        file.seek(SeekFrom::End(0))?;
+
+        Ok((Self { file }, tiles))
+    }
+
+    /// Add a finished tile, at `column` and `row` of the image's tiles.
+    pub fn write_tile(&mut self, column: u32, row: u32, tile: &Rgb32FImage) -> anyhow::Result<()> {
+        for value in [column, row, tile.width(), tile.height()] {
+            self.file.write_all(&value.to_le_bytes())?;
+        }
+        for channel in tile.as_raw() {
+            self.file.write_all(&channel.to_le_bytes())?;
+        }
+        self.file.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Whether a checkpoint can be resumed from `path`, because there is a file there.
+pub fn exists(path: &Path) -> anyhow::Result<bool> {
+    match std::fs::metadata(path) {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// A hash of `text` that stays the same across builds of rtk, unlike the standard library's.
+pub fn fingerprint(text: &str) -> u64 {
+    // 64-bit FNV-1a
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn take<'a>(reader: &mut &'a [u8], count: usize) -> &'a [u8] {
+    let (taken, rest) = reader.split_at(count);
+    *reader = rest;
+    taken
+}
+
+fn read_u32(reader: &mut &[u8]) -> u32 {
+    u32::from_le_bytes(take(reader, size_of::<u32>()).try_into().unwrap())
+}
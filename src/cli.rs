@@ -1,33 +1,80 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about)]
+#[command(author, version, about, after_help = EXAMPLES_HELP)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Command,
+    /// The number of threads to render with [default: one for each CPU thread].
+    #[arg(long, global = true, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub threads: Option<u32>,
 }
 
+const EXAMPLES_HELP: &str = "\
+Examples:
+  rtk examples starter -o scene.toml    Write an annotated scene to start from
+  rtk render scene.toml -o scene.png    Render it
+  rtk render scene.toml --checkerboard  Render a quick preview
+  rtk stats scene.toml                  Summarize the scene and check it for mistakes
+  rtk preview-material scene.toml --all Render thumbnails of the scene's materials
+  rtk completions bash > rtk.bash       Generate shell completions";
+
 #[derive(Subcommand, Debug)]
 pub enum Command {
     /// Render a scene.
-    Render {
-        /// Path to the scene to render.
+    Render(Box<RenderArgs>),
+    /// Summarize a scene's contents and point out likely mistakes.
+    Stats {
+        /// Path to the scene to summarize.
+        scene: PathBuf,
+    },
+    /// Show how two scenes differ in what they describe, such as objects added or removed and
+    /// fields changed, with defaults filled in.
+    Diff {
+        /// Path to the first scene.
+        a: PathBuf,
+        /// Path to the scene to compare it with.
+        b: PathBuf,
+    },
+    /// Render a scene's materials on a shader ball under a fixed studio setup.
+    PreviewMaterial(PreviewMaterialArgs),
+    /// Measure how quickly a scene's render converges to a reference image.
+    Converge(ConvergeArgs),
+    /// Render every combination of values of some of a scene's parameters, described by a sweep
+    /// file, for comparing them side by side.
+    Sweep(SweepArgs),
+    /// Bake the light falling on a scene's objects into a lightmap, for real-time engines.
+    Bake(BakeArgs),
+    /// Render light probes at points of a scene, as cubemaps or spherical harmonics, for
+    /// real-time engines.
+    Probe(ProbeArgs),
+    /// List the built-in example scenes, or print or write one of them.
+    Examples {
+        /// The example to print; with `--output`, all of them are written if this is left out.
+        name: Option<String>,
+        /// Write the example to this file, or every example into this directory.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Print a completion script for a shell.
+    Completions { shell: clap_complete::Shell },
+    /// List the devices that rendering and denoising can use on this machine.
+    ListDevices,
+    /// Print a JSON Schema of the scene format, for editors to complete and check scene files.
+    Schema {
+        /// Write the schema to this file instead.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Update a scene file written for an older version of rtk to the current scene format.
+    Migrate {
+        /// Path to the scene to update.
         scene: PathBuf,
-        /// Path to write the output image to.
-        #[arg(short, long, default_value = "image.png")]
-        output: PathBuf,
-        /// Render across all available CPU threads [default].
-        #[arg(long, group = "parallel_option")]
-        parallel: bool,
-        /// Render only on the main thread.
-        #[arg(long, group = "parallel_option")]
-        no_parallel: bool,
-        #[cfg(feature = "denoise")]
-        /// Denoise the image after rendering.
+        /// Write the updated scene here instead of replacing the original.
         #[arg(short, long)]
-        denoise: bool,
+        output: Option<PathBuf>,
     },
     #[cfg(feature = "denoise")]
     /// Denoise an image.
@@ -39,3 +86,305 @@ pub enum Command {
         output: Option<PathBuf>,
     },
 }
+
+#[derive(Args, Debug)]
+pub struct RenderArgs {
+    /// Path to the scene to render, or `-` to read it from standard input.
+    #[arg(required_unless_present = "from_sidecar")]
+    pub scene: Option<PathBuf>,
+    /// Render the scene and settings recorded in the sidecar of an earlier render, instead of a
+    /// scene file. Other options, such as the output path, still apply.
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["scene", "camera", "auto_frame", "frame", "checkerboard", "bounds", "wireframe", "toon"]
+    )]
+    pub from_sidecar: Option<PathBuf>,
+    /// Path to write the output image to, or `-` to write it to standard output as PNG. OpenEXR
+    /// (`.exr`), Radiance HDR (`.hdr`), and PFM (`.pfm`) images keep the linear colors.
+    #[arg(short, long, default_value = "image.png")]
+    pub output: PathBuf,
+    /// The number of this image in an animation, which changes its noise unless the camera's
+    /// `noise-seed-mode` is `fixed`.
+    #[arg(long, default_value_t = 0)]
+    pub frame: u64,
+    /// Render from the scene's camera with this name, instead of its main camera.
+    #[arg(long, value_name = "NAME")]
+    pub camera: Option<String>,
+    /// Move the camera back along its view until the whole scene fits, as with the camera's
+    /// `frame = "all"`.
+    #[arg(long)]
+    pub auto_frame: bool,
+    /// Also write the motion of each pixel since the previous frame to this OpenEXR image, for
+    /// temporal denoising and motion blur in post-processing.
+    #[arg(long, value_name = "PATH", requires = "previous_scene")]
+    pub motion_vectors: Option<PathBuf>,
+    /// Also write each tile of linear colors to this tiled OpenEXR image as soon as it is rendered,
+    /// so that the image can be watched as it fills in, and an interrupted render leaves its
+    /// finished tiles behind.
+    #[arg(long, value_name = "PATH")]
+    pub tiled_exr: Option<PathBuf>,
+    /// Write every sample taken to this file, with its pixel, radiance, and the normal, flat
+    /// color, and depth of the first surface it saw, for experimenting with external denoisers
+    /// and reconstruction. Files grow by 56 bytes a sample.
+    #[arg(long, value_name = "PATH", conflicts_with = "toon")]
+    pub export_samples: Option<PathBuf>,
+    /// Keep each finished tile in this checkpoint file, so that an interrupted render can be
+    /// resumed with `--resume`. It is removed once the image is written.
+    #[arg(long, value_name = "PATH", conflicts_with = "toon")]
+    pub checkpoint: Option<PathBuf>,
+    /// Reuse the tiles kept in the `--checkpoint` file by an earlier run of the same render,
+    /// rendering only the rest. Without a checkpoint file, the render starts from the beginning.
+    #[arg(long, requires = "checkpoint")]
+    pub resume: bool,
+    /// Also write a mask of the pixels that were brighter than white, or not a number, before
+    /// conversion to display colors.
+    #[arg(long, value_name = "PATH")]
+    pub overflow_mask: Option<PathBuf>,
+    /// The scene of the previous frame, whose objects are matched to this scene's by position in
+    /// the file.
+    #[arg(long, value_name = "PATH")]
+    pub previous_scene: Option<PathBuf>,
+    /// The most memory the render may use, like `512M` or `8G`, as estimated from the image, the
+    /// paths being traced, the primitives with their meshes and hierarchies, and the textures. Over
+    /// it, only 8-bit colors are kept, fewer paths are traced at once, and textures are shrunk, and
+    /// if the render still does not fit it is refused.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub max_memory: Option<u64>,
+    /// Render across all available CPU threads [default].
+    #[arg(long, group = "parallel_option")]
+    pub parallel: bool,
+    /// Render only on the main thread.
+    #[arg(long, group = "parallel_option")]
+    pub no_parallel: bool,
+    /// Render only half of the pixels, in a checkerboard pattern, and fill in the rest, for a
+    /// preview in half the time.
+    #[arg(long)]
+    pub checkerboard: bool,
+    /// Render in passes over the whole image, at 1, 2, 4, 8, and so on samples per pixel, so that
+    /// previews show the whole image early. The final image is the same.
+    #[arg(long, conflicts_with_all = ["checkpoint", "toon"])]
+    pub progressive: bool,
+    /// Keep the image's linear colors in half-precision floats, halving the memory of very large
+    /// renders, and write OpenEXR images in half floats. Channels brighter than 65504 are clamped.
+    #[arg(long)]
+    pub half: bool,
+    /// Draw the bounding box of each object, and of the whole scene, over the image.
+    #[arg(long)]
+    pub bounds: bool,
+    /// Also draw the boxes of the hierarchies over the triangles of meshes and the points of point
+    /// clouds, down to this many levels below their roots.
+    #[arg(long, value_name = "DEPTH", requires = "bounds")]
+    pub bvh_depth: Option<usize>,
+    /// Draw the edges of quads and meshes over the image, leaving out those hidden by surfaces.
+    #[arg(long)]
+    pub wireframe: bool,
+    /// Render in a flat, cartoon-like style, with bands of light, a rim light, and ink outlines,
+    /// instead of path tracing.
+    #[arg(long)]
+    pub toon: bool,
+    /// The number of bands of light between shadow and fully lit in a `--toon` render.
+    #[arg(long, value_name = "BANDS", requires = "toon", value_parser = clap::value_parser!(u32).range(2..))]
+    pub toon_bands: Option<u32>,
+    /// Serve the render's progress and a preview of its finished tiles over HTTP at this address,
+    /// like `0.0.0.0:9000`, for watching a render on a headless server from a browser.
+    #[arg(long, value_name = "ADDRESS")]
+    pub progress_listen: Option<String>,
+    /// Show a live map of completed tiles, the sampling rate, and memory use while rendering.
+    #[arg(long)]
+    pub tui: bool,
+    #[cfg(feature = "window")]
+    /// Show the image in a window as its tiles are rendered, and keep it open until it is closed.
+    #[arg(long)]
+    pub preview: bool,
+    #[cfg(feature = "denoise")]
+    /// Denoise the image after rendering.
+    #[arg(short, long)]
+    pub denoise: bool,
+    #[cfg(feature = "denoise")]
+    /// Leave the image noisy, even if the configuration asks to denoise.
+    #[arg(long, conflicts_with = "denoise")]
+    pub no_denoise: bool,
+    /// Print how long each stage of rendering took, summed over all threads.
+    #[arg(long)]
+    pub profile: bool,
+    /// Write the timing of each stage of rendering to this file, in the Chrome tracing format
+    /// (viewable in Perfetto or chrome://tracing).
+    #[arg(long, value_name = "PATH")]
+    pub trace: Option<PathBuf>,
+    /// Show a desktop notification when the render finishes or fails.
+    #[arg(long)]
+    pub notify: bool,
+    /// POST a JSON summary of the render to this URL when it finishes or fails.
+    /// The payload's `text` and `content` fields make it usable with Slack and Discord webhooks.
+    #[arg(long, value_name = "URL")]
+    pub webhook: Option<String>,
+}
+
+impl RenderArgs {
+    /// The file the scene is read from: the scene file, or the sidecar it is replayed from.
+    pub fn source(&self) -> &Path {
+        self.scene
+            .as_deref()
+            .or(self.from_sidecar.as_deref())
+            .expect("clap requires a scene or a sidecar")
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct PreviewMaterialArgs {
+    /// Path to the scene whose materials to preview.
+    pub scene: PathBuf,
+    /// The index of the material to preview, in the order of the scene's materials.
+    #[arg(short, long, value_name = "INDEX", required_unless_present = "all")]
+    pub material: Option<usize>,
+    /// Preview every material, in a grid of thumbnails in the order of the scene's materials.
+    #[arg(long, conflicts_with = "material")]
+    pub all: bool,
+    /// Path to write the output image to.
+    #[arg(short, long, default_value = "material.png")]
+    pub output: PathBuf,
+    /// The width and height of each thumbnail, in pixels.
+    #[arg(long, default_value_t = 256)]
+    pub size: u32,
+    /// The number of samples per pixel of each thumbnail.
+    #[arg(long, default_value_t = 64)]
+    pub samples: u32,
+    /// Keep rendering, refining the image in passes, and start over with new parameters whenever
+    /// this TOML file of material overrides changes, e.g. `[materials.2]` with `ir = 1.45`.
+    #[arg(long, value_name = "PATH")]
+    pub watch: Option<PathBuf>,
+    /// Render only on the main thread.
+    #[arg(long)]
+    pub no_parallel: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ConvergeArgs {
+    /// Path to the scene to render.
+    pub scene: PathBuf,
+    /// Path to the converged image to compare against. OpenEXR and other floating-point images
+    /// are read as linear colors, and 8-bit images as gamma-corrected like rtk's output.
+    #[arg(short, long)]
+    pub reference: PathBuf,
+    /// Path to write the error at each sample count to, as CSV.
+    #[arg(short, long, default_value = "convergence.csv")]
+    pub output: PathBuf,
+    /// Render only on the main thread.
+    #[arg(long)]
+    pub no_parallel: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct SweepArgs {
+    /// Path to the sweep file, which names the scene, the values of each parameter, and where to
+    /// write the renders.
+    pub sweep: PathBuf,
+    /// Render only on the main thread.
+    #[arg(long)]
+    pub no_parallel: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct BakeArgs {
+    /// Path to the scene to bake.
+    pub scene: PathBuf,
+    /// The name of an object to bake, a quad, prism, or mesh. Give it several times to bake
+    /// several objects into one lightmap.
+    #[arg(long = "object", value_name = "NAME", required = true)]
+    pub objects: Vec<String>,
+    /// Path to write the lightmap to. OpenEXR images hold the linear light; other formats are
+    /// gamma-corrected like rendered images.
+    #[arg(short, long, default_value = "lightmap.png")]
+    pub output: PathBuf,
+    /// Write the baked surfaces with their lightmap coordinates to this Wavefront OBJ file.
+    #[arg(long, value_name = "PATH")]
+    pub obj: Option<PathBuf>,
+    /// The width and height of the lightmap, in texels.
+    #[arg(long, default_value_t = 1024)]
+    pub size: u32,
+    /// The number of samples per texel. Defaults to the scene's samples per pixel.
+    #[arg(long)]
+    pub samples: Option<u32>,
+    /// Bake only on the main thread.
+    #[arg(long)]
+    pub no_parallel: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ProbeArgs {
+    /// Path to the scene to render probes of.
+    pub scene: PathBuf,
+    /// The point to place a probe at, like `1.0,2.0,-0.5`. Give it several times for several
+    /// probes.
+    #[arg(long = "at", value_name = "X,Y,Z", required = true, value_parser = parse_point)]
+    pub points: Vec<(f64, f64, f64)>,
+    /// Path to write the probes to: a DDS or KTX2 file holds a cubemap of each in 32-bit floating
+    /// point, and a PLY file a point for each with the spherical harmonics of its light.
+    #[arg(short, long, default_value = "probes.dds")]
+    pub output: PathBuf,
+    /// The width and height of each face of a cube, in pixels.
+    #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u32).range(1..))]
+    pub size: u32,
+    /// The number of samples per pixel. Defaults to the scene's samples per pixel.
+    #[arg(long)]
+    pub samples: Option<u32>,
+    /// Render only on the main thread.
+    #[arg(long)]
+    pub no_parallel: bool,
+}
+
+/// The multipliers of the suffixes accepted by `parse_size`, which are powers of 1024.
+const SIZE_UNITS: [(char, u64); 4] = [
+    ('K', 1 << 10),
+    ('M', 1 << 20),
+    ('G', 1 << 30),
+    ('T', 1 << 40),
+];
+
+/// Parse a number of bytes with an optional unit suffix, like `512M` or `1.5G`.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let upper = s.to_ascii_uppercase();
+    let upper = upper.strip_suffix('B').unwrap_or(&upper);
+    let (number, multiplier) = match SIZE_UNITS
+        .iter()
+        .find(|(suffix, _)| upper.ends_with(*suffix))
+    {
+        Some((_, multiplier)) => (&upper[..upper.len() - 1], *multiplier),
+        None => (upper, 1),
+    };
+
+    let number = number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite() && *number >= 0.0)
+        .ok_or_else(|| format!("`{s}` is not a size, like 512M or 8G"))?;
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Parse a point given as three comma-separated numbers, like `1.0,2.0,-0.5`.
+fn parse_point(s: &str) -> Result<(f64, f64, f64), String> {
+    let coordinates = s
+        .split(',')
+        .map(|coordinate| coordinate.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>();
+    match coordinates.as_deref() {
+        Ok(&[x, y, z]) => Ok((x, y, z)),
+        _ => Err(format!("`{s}` is not a point, like 1.0,2.0,-0.5")),
+    }
+}
+
+/// Format a number of bytes with the largest unit that keeps it at least 1.
+pub fn format_size(bytes: u64) -> String {
+    match SIZE_UNITS
+        .iter()
+        .rev()
+        .find(|(_, multiplier)| bytes >= *multiplier)
+    {
+        Some((suffix, multiplier)) => format!("{:.1}{suffix}", bytes as f64 / *multiplier as f64),
+        None => format!("{bytes}B"),
+    }
+}
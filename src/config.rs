@@ -0,0 +1,112 @@
+//! Defaults for command-line options, so that options wanted on every run need not be given each
+//! time. They are read from `config.toml` in rtk's configuration directory, usually
+//! `~/.config/rtk`, and from `RTK_*` environment variables, which take precedence over the file:
+//!
+//! ```toml
+//! threads = 8
+//! output-dir = "~/renders"
+//! denoise = true
+//! ```
+//!
+//! Options given on the command line take precedence over both.
+
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::cli::RenderArgs;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// The number of threads to render with, instead of one for each CPU thread.
+    pub threads: Option<usize>,
+    /// The directory relative output paths of `rtk render` are taken from, instead of the working
+    /// directory.
+    pub output_dir: Option<PathBuf>,
+    /// Denoise renders unless `--no-denoise` is given.
+    pub denoise: Option<bool>,
+}
+
+impl Config {
+    /// Read the configuration file, if there is one, and the environment variables over it.
+    pub fn load() -> anyhow::Result<Self> {
+        let mut config = match path() {
+            Some(path) if path.exists() => {
+                let toml = std::fs::read_to_string(&path)?;
+                toml::from_str(&toml)
+                    .map_err(|e| anyhow::anyhow!("the configuration {}: {e}", path.display()))?
+            }
+            _ => Config::default(),
+        };
+
+        if let Some(threads) = variable("RTK_THREADS")? {
+            config.threads = Some(threads);
+        }
+        if let Some(output_dir) = std::env::var_os("RTK_OUTPUT_DIR") {
+            config.output_dir = Some(output_dir.into());
+        }
+        if let Some(denoise) = variable("RTK_DENOISE")? {
+            config.denoise = Some(denoise);
+        }
+        if let Some(output_dir) = &mut config.output_dir {
+            *output_dir = expand_home(output_dir);
+        }
+
+        Ok(config)
+    }
+
+    /// Fill in the options of `rtk render` that were left out with the configured defaults.
+    pub fn apply(&self, args: &mut RenderArgs) {
+        if let Some(output_dir) = &self.output_dir {
+            if args.output.is_relative() && !crate::is_standard_stream(&args.output) {
+                args.output = output_dir.join(&args.output);
+            }
+        }
+
+        #[cfg(feature = "denoise")]
+        if self.denoise == Some(true) && !args.no_denoise {
+            args.denoise = true;
+        }
+        #[cfg(not(feature = "denoise"))]
+        if self.denoise == Some(true) {
+            crate::print_warning("the configuration asks to denoise, but this build of rtk cannot");
+        }
+    }
+}
+
+/// The configuration file: `$RTK_CONFIG` if it is set, or `rtk/config.toml` in the user's
+/// configuration directory.
+fn path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("RTK_CONFIG") {
+        return Some(path.into());
+    }
+    let directory = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(directory) if !directory.is_empty() => PathBuf::from(directory),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(directory.join("rtk").join("config.toml"))
+}
+
+/// Parse the environment variable `name`, if it is set.
+fn variable<T: std::str::FromStr>(name: &str) -> anyhow::Result<Option<T>>
+where
+    T::Err: std::fmt::Display,
+{
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("the environment variable {name}: {e}")),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(anyhow::anyhow!("the environment variable {name}: {e}")),
+    }
+}
+
+/// Replace a leading `~` in `path` with the user's home directory.
+fn expand_home(path: &std::path::Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
@@ -0,0 +1,129 @@
+//! Measures the error of renders against a reference image as samples are added.
+
+use std::{
+    fmt::Write as _,
+    time::{Duration, Instant},
+};
+
+use image::{DynamicImage, GenericImage, Rgb32FImage};
+use rtk::{
+    plugin::Plugins,
+    render::{Camera, ProgressEvent, Renderer},
+};
+
+use crate::cli::ConvergeArgs;
+
+/// Handle `cli::Command::Converge`.
+///
+/// The scene is rendered once, progressively, in passes that bring every pixel to each power of
+/// two samples up to the scene's own sample count. The image after each pass is what `rtk render`
+/// produces at that sample count, and is measured as soon as its last tile is finished.
+pub fn converge(args: &ConvergeArgs) -> anyhow::Result<()> {
+    let mut scene = crate::load_scene(&args.scene)?;
+    scene.validate()?;
+
+    let reference = load_reference(&args.reference)?;
+    let mut camera = scene.create_camera();
+    let (width, height) = Renderer::new(camera.clone()).output_dimensions();
+    if reference.dimensions() != (width, height) {
+        anyhow::bail!(
+            "the reference is {}x{}, but the scene renders at {width}x{height}",
+            reference.width(),
+            reference.height(),
+        );
+    }
+
+    let mut world = scene.create_world(&Plugins::new())?;
+    if scene.camera.frame.is_some() {
+        camera.position = scene.frame_camera(&mut world)?;
+    }
+    if let Some(distance) = scene.focus_distance(&world)? {
+        camera.focus_distance = distance;
+    }
+    // The passes end at the largest sample count of any pixel, including those of regions.
+    let max_samples = camera
+        .regions
+        .iter()
+        .map(|region| region.samples_per_pixel)
+        .fold(camera.samples_per_pixel, u32::max);
+    let milestones = milestones(max_samples).collect::<Vec<_>>();
+    let mut renderer = Renderer::new(Camera {
+        // every pixel takes the milestone's samples, so that the error is measured for them
+        adaptive: None,
+        ..camera
+    });
+    renderer.set_progressive(true);
+    let (columns, rows) = renderer.tile_grid();
+
+    let mut image = Rgb32FImage::new(width, height);
+    let mut csv = String::from("samples,seconds,rmse\n");
+    // the pass being rendered and the number of its tiles finished so far
+    let (mut pass, mut tiles) = (0, 0);
+    // the time spent measuring, which is left out of the render's time
+    let mut measuring = Duration::ZERO;
+    let mut result = Ok(());
+    let start = Instant::now();
+    renderer.render_hdr(&world, !args.no_parallel, |event| {
+        let ProgressEvent::TileFinished { x, y, linear, .. } = event else {
+            return;
+        };
+        // Tiles are always within the image, so this cannot fail.
+        _ = image.copy_from(&linear, x, y);
+        tiles += 1;
+        if tiles < columns * rows || result.is_err() {
+            return;
+        }
+
+        let seconds = (start.elapsed() - measuring).as_secs_f64();
+        let measure_start = Instant::now();
+        let samples = milestones[pass];
+        let rmse = rmse(&image, &reference);
+        eprintln!("{samples:>6} spp  {seconds:>9.2}s  rmse {rmse:.6}");
+        _ = writeln!(csv, "{samples},{seconds:.3},{rmse}");
+        // Write after every milestone so that the results so far survive an interrupted run.
+        result = std::fs::write(&args.output, &csv);
+        measuring += measure_start.elapsed();
+        (pass, tiles) = (pass + 1, 0);
+    })?;
+    result?;
+
+    eprintln!("Wrote {}", args.output.display());
+    Ok(())
+}
+
+/// Read a reference image as linear colors.
+fn load_reference(path: &std::path::Path) -> anyhow::Result<Rgb32FImage> {
+    let image = image::io::Reader::open(path)?.decode()?;
+    let is_linear = matches!(
+        image,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    );
+
+    let mut image = image.to_rgb32f();
+    if !is_linear {
+        // Undo the renderer's gamma correction, which takes the square root.
+        for channel in image.iter_mut() {
+            *channel = channel.powi(2);
+        }
+    }
+
+    Ok(image)
+}
+
+/// The sample counts to measure at: the powers of two below `max`, then `max`.
+fn milestones(max: u32) -> impl Iterator<Item = u32> {
+    std::iter::successors(Some(1u32), |n| n.checked_mul(2))
+        .take_while(move |&n| n < max)
+        .chain(std::iter::once(max))
+}
+
+/// The root-mean-square difference of all channels of two images of the same size.
+fn rmse(image: &Rgb32FImage, reference: &Rgb32FImage) -> f64 {
+    let sum: f64 = image
+        .iter()
+        .zip(reference.iter())
+        .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+        .sum();
+
+    (sum / image.len() as f64).sqrt()
+}
@@ -1,9 +1,9 @@
-use image::{ImageBuffer, Rgb};
+use image::{ImageBuffer, Rgb, Rgb32FImage};
 
 pub fn denoise(
     image: &ImageBuffer<Rgb<u8>, Vec<u8>>,
 ) -> anyhow::Result<ImageBuffer<Rgb<u8>, Vec<u8>>> {
-    let device = oidn::Device::new()?;
+    let device = device()?;
     let mut buffer = device.create_buffer(image.pixels().len() * 3)?;
 
     let pfm_image = create_pfm(image);
@@ -28,6 +28,56 @@ pub fn denoise(
     ))
 }
 
+/// Denoise an image of linear colors, which may be brighter than white.
+pub fn denoise_linear(image: &Rgb32FImage) -> anyhow::Result<Rgb32FImage> {
+    let device = device()?;
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let mut buffer = device.create_buffer(image.as_raw().len())?;
+    buffer.as_mut_slice().copy_from_slice(image.as_raw());
+
+    {
+        let mut filter = device.create_filter()?;
+        filter.set_color_image(&buffer, width, height)?;
+        filter.set_output_image(&buffer, width, height)?;
+        filter.set_hdr(true)?;
+
+        filter.execute()?;
+    }
+
+    let pixels = buffer.as_slice().to_vec();
+    Ok(Rgb32FImage::from_raw(image.width(), image.height(), pixels)
+        .expect("the buffer holds every pixel of the image"))
+}
+
+/// The device to denoise on: the default one, which is the fastest the machine supports, or the
+/// CPU if that cannot be created, as when a GPU's runtime is missing.
+fn device() -> anyhow::Result<oidn::Device> {
+    match oidn::Device::new() {
+        Ok(device) => Ok(device),
+        Err(e) => {
+            crate::print_warning(&format!(
+                "the denoiser's default device failed ({e}), so it runs on the CPU"
+            ));
+            Ok(oidn::Device::cpu()?)
+        }
+    }
+}
+
+/// Print the devices the denoiser can use, marking the one it picks by default.
+pub fn print_devices() {
+    let devices = oidn::physical_devices();
+    if devices.is_empty() {
+        println!("  none; the denoiser's library found no supported devices");
+    }
+    for (i, device) in devices.iter().enumerate() {
+        let default = if i == 0 { " (default)" } else { "" };
+        println!(
+            "  {}: {} {}{default}",
+            device.id, device.device_type, device.name
+        );
+    }
+}
+
 fn create_pfm(image: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Vec<f32> {
     let mut result = Vec::new();
 
@@ -0,0 +1,175 @@
+//! Compares two scenes by what they describe rather than how their files are written. Both are read
+//! as they would be rendered, with defaults filled in, so a field written out with its default
+//! value is no difference, and neither are colors written in different ways.
+
+use std::path::Path;
+
+use colored::Colorize;
+use toml::Value;
+
+/// Handle `cli::Command::Diff`.
+pub fn diff(a_path: &Path, b_path: &Path) -> anyhow::Result<()> {
+    let [a, b] = [a_path, b_path].map(|path| -> anyhow::Result<Value> {
+        let scene = crate::load_scene(path)?;
+        Ok(Value::try_from(&scene)?)
+    });
+    let (Value::Table(mut a), Value::Table(mut b)) = (a?, b?) else {
+        unreachable!("scenes are serialized as tables");
+    };
+
+    let mut differences = Vec::new();
+    // Materials are referred to by their index, so they are compared in order, while objects are
+    // matched by name where they have one.
+    let a_objects = a.remove("objects");
+    let b_objects = b.remove("objects");
+    compare("", &Value::Table(a), &Value::Table(b), &mut differences);
+    compare_objects(
+        as_array(a_objects.as_ref()),
+        as_array(b_objects.as_ref()),
+        &mut differences,
+    );
+
+    if differences.is_empty() {
+        println!("The scenes describe the same thing");
+    }
+    for difference in differences {
+        println!("{difference}");
+    }
+
+    Ok(())
+}
+
+fn as_array(value: Option<&Value>) -> &[Value] {
+    value.and_then(Value::as_array).map_or(&[], Vec::as_slice)
+}
+
+/// Add the differences between `a` and `b`, found at `path` in the scenes, to `differences`.
+fn compare(path: &str, a: &Value, b: &Value, differences: &mut Vec<String>) {
+    let join = |key: &str| match path {
+        "" => key.to_string(),
+        path => format!("{path}.{key}"),
+    };
+
+    match (a, b) {
+        (Value::Table(a), Value::Table(b)) if a.get("type") == b.get("type") => {
+            for (key, a_value) in a {
+                match b.get(key) {
+                    Some(b_value) => compare(&join(key), a_value, b_value, differences),
+                    None => differences.push(removed(&join(key), a_value)),
+                }
+            }
+            for (key, b_value) in b {
+                if !a.contains_key(key) {
+                    differences.push(added(&join(key), b_value));
+                }
+            }
+        }
+        // Arrays of tables, such as materials and lights, are compared item by item, and other
+        // arrays, such as positions, as a whole.
+        (Value::Array(a), Value::Array(b)) if a.iter().chain(b).all(Value::is_table) => {
+            for (i, (a_value, b_value)) in a.iter().zip(b).enumerate() {
+                compare(&format!("{path}[{i}]"), a_value, b_value, differences);
+            }
+            for (i, a_value) in a.iter().enumerate().skip(b.len()) {
+                differences.push(removed(&format!("{path}[{i}]"), a_value));
+            }
+            for (i, b_value) in b.iter().enumerate().skip(a.len()) {
+                differences.push(added(&format!("{path}[{i}]"), b_value));
+            }
+        }
+        (a, b) if !same(a, b) => differences.push(format!(
+            "{} {path}: {} -> {}",
+            "~".yellow().bold(),
+            inline(a),
+            inline(b)
+        )),
+        _ => {}
+    }
+}
+
+/// Add the differences between the objects of two scenes. Objects with the same name are compared
+/// with each other, and unnamed objects are matched with identical ones, so that reordering them is
+/// no difference.
+fn compare_objects(a: &[Value], b: &[Value], differences: &mut Vec<String>) {
+    let name = |object: &Value| {
+        object
+            .get("name")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+    };
+    let label = |object: &Value, i: usize| match name(object) {
+        Some(name) => format!("objects.{name}"),
+        None => format!("objects[{i}]"),
+    };
+
+    let mut matched = vec![false; b.len()];
+    let mut unmatched = Vec::new();
+    for (i, a_object) in a.iter().enumerate() {
+        let found = match name(a_object) {
+            Some(a_name) => b
+                .iter()
+                .position(|b_object| name(b_object).as_ref() == Some(&a_name)),
+            None => (0..b.len()).find(|&j| !matched[j] && same(a_object, &b[j])),
+        };
+        match found {
+            Some(j) => {
+                matched[j] = true;
+                compare(&label(a_object, i), a_object, &b[j], differences);
+            }
+            None => unmatched.push(i),
+        }
+    }
+
+    for i in unmatched {
+        differences.push(removed(&label(&a[i], i), &a[i]));
+    }
+    for (j, b_object) in b.iter().enumerate() {
+        if !matched[j] {
+            differences.push(added(&label(b_object, j), b_object));
+        }
+    }
+}
+
+fn added(path: &str, value: &Value) -> String {
+    format!("{} {path}: {}", "+".green().bold(), inline(value))
+}
+
+fn removed(path: &str, value: &Value) -> String {
+    format!("{} {path}: {}", "-".red().bold(), inline(value))
+}
+
+/// Whether two values are equal, taking integers and floats with the same value as equal.
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Integer(a), Value::Float(b)) | (Value::Float(b), Value::Integer(a)) => {
+            *a as f64 == *b
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b))
+        }
+        (Value::Table(a), Value::Table(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key).is_some_and(|b| same(a, b)))
+        }
+        (a, b) => a == b,
+    }
+}
+
+/// A value written on one line, as in an inline table.
+fn inline(value: &Value) -> String {
+    match value {
+        Value::Array(array) => {
+            let items = array.iter().map(inline).collect::<Vec<_>>();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Table(table) => {
+            let entries = table
+                .iter()
+                .map(|(key, value)| format!("{key} = {}", inline(value)))
+                .collect::<Vec<_>>();
+            format!("{{ {} }}", entries.join(", "))
+        }
+        value => value.to_string(),
+    }
+}
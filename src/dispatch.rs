@@ -0,0 +1,104 @@
+//! Runtime dispatch of the hot kernels to the widest instruction set the CPU supports. A kernel
+//! written with `multiversion!` is compiled once for the baseline of the target and again for each
+//! wider instruction set, and each call runs the copy the CPU can execute, so prebuilt binaries run
+//! at full speed without being built for a particular CPU.
+//!
+//! The copies compute exactly the same results: Rust never fuses a multiplication and addition on
+//! its own, so the wider instruction sets only process more values at once.
+
+use std::{fmt, sync::OnceLock};
+
+/// A set of instructions that kernels are compiled for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum InstructionSet {
+    /// What every CPU of the target supports: SSE2 on x86-64, and NEON on AArch64.
+    Baseline,
+    /// AVX2 and FMA, on x86-64 CPUs from 2013 on.
+    Avx2,
+    /// AVX-512, on recent x86-64 server and desktop CPUs.
+    Avx512,
+}
+
+impl fmt::Display for InstructionSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Baseline if cfg!(target_arch = "aarch64") => "NEON",
+            Self::Baseline if cfg!(target_arch = "x86_64") => "SSE2",
+            Self::Baseline => "baseline",
+            Self::Avx2 => "AVX2",
+            Self::Avx512 => "AVX-512",
+        })
+    }
+}
+
+/// The widest instruction set the CPU supports, detected on the first call.
+pub fn instruction_set() -> InstructionSet {
+    static DETECTED: OnceLock<InstructionSet> = OnceLock::new();
+    *DETECTED.get_or_init(detect)
+}
+
+#[cfg(target_arch = "x86_64")]
+fn detect() -> InstructionSet {
+    if is_x86_feature_detected!("avx512f")
+        && is_x86_feature_detected!("avx512bw")
+        && is_x86_feature_detected!("avx512dq")
+        && is_x86_feature_detected!("avx512vl")
+        && is_x86_feature_detected!("avx2")
+        && is_x86_feature_detected!("fma")
+    {
+        InstructionSet::Avx512
+    } else if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        InstructionSet::Avx2
+    } else {
+        InstructionSet::Baseline
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn detect() -> InstructionSet {
+    InstructionSet::Baseline
+}
+
+/// Define a function whose body is compiled for each `InstructionSet`, and which runs the copy for
+/// `instruction_set()`. Everything the body inlines is compiled along with it, so a kernel should
+/// hold its whole loop rather than be called from one, and the functions it calls in that loop
+/// should be `#[inline(always)]`; others are compiled only for the baseline.
+macro_rules! multiversion {
+    (
+        $(#[$attribute:meta])*
+        $visibility:vis fn $name:ident($($argument:ident: $type:ty),* $(,)?) $(-> $output:ty)?
+        $body:block
+    ) => {
+        $(#[$attribute])*
+        $visibility fn $name($($argument: $type),*) $(-> $output)? {
+            #[inline(always)]
+            fn kernel($($argument: $type),*) $(-> $output)? $body
+
+            #[cfg(target_arch = "x86_64")]
+            {
+                #[target_feature(enable = "avx512f,avx512bw,avx512dq,avx512vl,avx2,fma")]
+                fn avx512($($argument: $type),*) $(-> $output)? {
+                    kernel($($argument),*)
+                }
+                #[target_feature(enable = "avx2,fma")]
+                fn avx2($($argument: $type),*) $(-> $output)? {
+                    kernel($($argument),*)
+                }
+
+                match $crate::dispatch::instruction_set() {
+                    // SAFETY: the CPU was found to support the instruction set.
+                    $crate::dispatch::InstructionSet::Avx512 => {
+                        return unsafe { avx512($($argument),*) }
+                    }
+                    // SAFETY: as above.
+                    $crate::dispatch::InstructionSet::Avx2 => return unsafe { avx2($($argument),*) },
+                    $crate::dispatch::InstructionSet::Baseline => {}
+                }
+            }
+
+            kernel($($argument),*)
+        }
+    };
+}
+
+pub(crate) use multiversion;
@@ -0,0 +1,99 @@
+//! The example scenes built into the binary, for `rtk examples`.
+
+use std::path::Path;
+
+/// Each example's name and contents, starting with the annotated scene for new users.
+const EXAMPLES: &[(&str, &str)] = &[
+    ("starter", include_str!("../examples/starter.toml")),
+    ("bevel", include_str!("../examples/bevel.toml")),
+    ("cornell_box", include_str!("../examples/cornell_box.toml")),
+    ("cutaway", include_str!("../examples/cutaway.toml")),
+    ("dirt", include_str!("../examples/dirt.toml")),
+    ("extrusion", include_str!("../examples/extrusion.toml")),
+    ("fog", include_str!("../examples/fog.toml")),
+    ("graph", include_str!("../examples/graph.toml")),
+    ("lathe", include_str!("../examples/lathe.toml")),
+    ("media", include_str!("../examples/media.toml")),
+    ("metaballs", include_str!("../examples/metaballs.toml")),
+    ("metals", include_str!("../examples/metals.toml")),
+    ("mix", include_str!("../examples/mix.toml")),
+    ("overrides", include_str!("../examples/overrides.toml")),
+    (
+        "plane_with_spheres",
+        include_str!("../examples/plane_with_spheres.toml"),
+    ),
+    ("plastic", include_str!("../examples/plastic.toml")),
+    (
+        "point_lights",
+        include_str!("../examples/point_lights.toml"),
+    ),
+    ("prism", include_str!("../examples/prism.toml")),
+    ("quads", include_str!("../examples/quads.toml")),
+    ("quads_light", include_str!("../examples/quads_light.toml")),
+    (
+        "random_color",
+        include_str!("../examples/random_color.toml"),
+    ),
+    ("sphere", include_str!("../examples/sphere.toml")),
+    ("sun", include_str!("../examples/sun.toml")),
+    ("text", include_str!("../examples/text.toml")),
+];
+
+/// Handle `cli::Command::Examples`.
+pub fn examples(name: Option<&str>, output: Option<&Path>) -> anyhow::Result<()> {
+    match (name, output) {
+        (None, None) => {
+            let width = EXAMPLES
+                .iter()
+                .map(|(name, _)| name.len())
+                .max()
+                .unwrap_or(0);
+            for (name, contents) in EXAMPLES {
+                println!("{name:width$}  {}", description(contents));
+            }
+        }
+        (None, Some(directory)) => {
+            std::fs::create_dir_all(directory)?;
+            for (name, contents) in EXAMPLES {
+                std::fs::write(directory.join(format!("{name}.toml")), contents)?;
+            }
+            eprintln!(
+                "Wrote {} examples to {}",
+                EXAMPLES.len(),
+                directory.display()
+            );
+        }
+        (Some(name), output) => {
+            let contents = find(name)?;
+            match output {
+                Some(path) if path.is_dir() => {
+                    std::fs::write(path.join(format!("{name}.toml")), contents)?
+                }
+                Some(path) => std::fs::write(path, contents)?,
+                None => print!("{contents}"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn find(name: &str) -> anyhow::Result<&'static str> {
+    EXAMPLES
+        .iter()
+        .find(|(example, _)| *example == name)
+        .map(|(_, contents)| *contents)
+        .ok_or_else(|| {
+            anyhow::anyhow!("there is no example named `{name}`; run `rtk examples` to list them")
+        })
+}
+
+/// The opening comment of an example, joined into one line.
+fn description(contents: &str) -> String {
+    contents
+        .lines()
+        .map_while(|line| line.strip_prefix('#'))
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
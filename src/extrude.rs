@@ -0,0 +1,263 @@
+//! Solids made by extruding flat outlines, such as polygons, SVG paths, and the glyphs of text.
+
+use lyon_extra::parser::{ParserOptions, PathParser, Source};
+use lyon_tessellation::{
+    math::{point, vector, Point, Vector},
+    path::{iterator::PathIterator, Path, PathEvent},
+    BuffersBuilder, FillOptions, FillRule, FillTessellator, FillVertex, VertexBuffers,
+};
+use nalgebra::Vector3;
+
+use crate::mesh::TriangleMesh;
+
+/// The largest distance between the curves of an outline and the straight edges that approximate
+/// them, as a fraction of the outline's size.
+const TOLERANCE: f32 = 0.001;
+
+/// Extrude a polygon, given by its corners in the XZ plane, upwards by `height`, cutting the edges
+/// of its top and bottom at 45 degrees by `chamfer`.
+pub fn polygon(corners: &[(f64, f64)], height: f64, chamfer: f64) -> anyhow::Result<TriangleMesh> {
+    let mut outline = Path::builder();
+    for (i, &(x, z)) in corners.iter().enumerate() {
+        let corner = point(x as f32, z as f32);
+        if i == 0 {
+            outline.begin(corner);
+        } else {
+            outline.line_to(corner);
+        }
+    }
+    outline.end(true);
+
+    upright(&outline.build(), height, chamfer)
+}
+
+/// Extrude the shape drawn by the data of an SVG path (the `d` attribute of a `<path>`) upwards by
+/// `height`. The path's X and Y run along X and Z, so that it reads as drawn when seen from
+/// above, and it is scaled by `scale`. Every subpath is closed, and filled by the non-zero rule.
+/// The edges of its top and bottom are cut at 45 degrees by `chamfer`, after scaling.
+pub fn svg_path(data: &str, scale: f64, height: f64, chamfer: f64) -> anyhow::Result<TriangleMesh> {
+    let mut outline = Path::builder_with_attributes(0);
+    PathParser::new().parse(
+        &ParserOptions::DEFAULT,
+        &mut Source::new(data.chars()),
+        &mut outline,
+    )?;
+
+    let mut mesh = upright(&outline.build(), height / scale, chamfer / scale)?;
+    for position in &mut mesh.positions {
+        *position *= scale;
+    }
+    Ok(mesh)
+}
+
+/// Extrude an outline in the XZ plane upwards by `height`, chamfered by `chamfer`.
+fn upright(outline: &Path, height: f64, chamfer: f64) -> anyhow::Result<TriangleMesh> {
+    let (mut min, mut max) = (point(f32::MAX, f32::MAX), point(f32::MIN, f32::MIN));
+    for event in outline.iter() {
+        for p in [event.from(), event.to()] {
+            min = min.min(p);
+            max = max.max(p);
+        }
+    }
+    let size = (max - min).x.max((max - min).y);
+    if size.is_nan() || size <= 0.0 {
+        anyhow::bail!("the outline encloses no area");
+    }
+    let tolerance = size * TOLERANCE;
+
+    let mut mesh = TriangleMesh::default();
+    let place = |p: Point| Vector3::new(p.x as f64, p.y as f64, 0.0);
+    extrude_outline(outline, tolerance, height, chamfer, place, &mut mesh)?;
+
+    // Turn the extrusion from -Z to +Y. The outline's Y becomes Z, so the bottom is the front.
+    for position in &mut mesh.positions {
+        *position = Vector3::new(position.x, -position.z, position.y);
+    }
+    Ok(mesh)
+}
+
+/// Add the front, back, and sides of an outline extruded by `depth` to `mesh`. `place` moves a
+/// point of the outline to where it goes in the XY plane, scaling it evenly; the front faces +Z,
+/// and the back is `depth` towards -Z. The outline is filled by the non-zero rule, and its curves
+/// are followed to within `tolerance`, in the outline's units.
+///
+/// A positive `chamfer` cuts the edges around the front and back at 45 degrees: the faces are
+/// inset by `chamfer`, in the units `place` moves to, and slanted strips join them to sides that
+/// are shorter by `chamfer` at each end. It is limited to half the depth.
+pub(crate) fn extrude_outline(
+    outline: &Path,
+    tolerance: f32,
+    depth: f64,
+    chamfer: f64,
+    place: impl Fn(Point) -> Vector3<f64>,
+    mesh: &mut TriangleMesh,
+) -> anyhow::Result<()> {
+    let chamfer = if depth > 0.0 {
+        chamfer.clamp(0.0, depth / 2.0)
+    } else {
+        0.0
+    };
+
+    // The sides follow the straight edges approximating the outline, in closed contours without
+    // repeated points. Each faces away from the filled side of its edge, found by whether a point
+    // just beside the edge is inside the outline.
+    let mut contours: Vec<Vec<Point>> = Vec::new();
+    for event in outline.iter().flattened(tolerance) {
+        match (event, contours.last_mut()) {
+            (PathEvent::Begin { at }, _) => contours.push(vec![at]),
+            (PathEvent::Line { to, .. }, Some(contour)) if contour.last() != Some(&to) => {
+                contour.push(to)
+            }
+            (PathEvent::End { .. }, Some(contour))
+                if contour.len() > 1 && contour.first() == contour.last() =>
+            {
+                contour.pop();
+            }
+            _ => {}
+        }
+    }
+    contours.retain(|contour| contour.len() >= 3);
+    let edges: Vec<(Point, Point)> = contours
+        .iter()
+        .flat_map(|contour| {
+            (0..contour.len()).map(|i| (contour[i], contour[(i + 1) % contour.len()]))
+        })
+        .collect();
+    let outwards: Vec<Vec<Vector>> = contours
+        .iter()
+        .map(|contour| {
+            (0..contour.len())
+                .map(|i| {
+                    let (from, to) = (contour[i], contour[(i + 1) % contour.len()]);
+                    let left = vector(from.y - to.y, to.x - from.x).normalize();
+                    let middle = from.lerp(to, 0.5);
+                    let filled_left = winding_number(&edges, middle + left * tolerance) != 0;
+                    if filled_left {
+                        -left
+                    } else {
+                        left
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    // The chamfer moves each corner inwards along the bisector of its edges' normals, far enough
+    // that both edges move by the inset, but no more than twice that at sharp corners.
+    let unit = (place(point(1.0, 0.0)) - place(point(0.0, 0.0))).norm();
+    let inset = (chamfer / unit) as f32;
+    let insets: Vec<Vec<Point>> = contours
+        .iter()
+        .zip(&outwards)
+        .map(|(contour, outward)| {
+            let n = contour.len();
+            (0..n)
+                .map(|i| {
+                    let (before, after) = (outward[(i + n - 1) % n], outward[i]);
+                    let bisector = before + after;
+                    contour[i] - bisector * (inset / (1.0 + before.dot(after)).max(0.5))
+                })
+                .collect()
+        })
+        .collect();
+
+    let face = if chamfer > 0.0 {
+        let mut face = Path::builder();
+        for contour in &insets {
+            face.begin(contour[0]);
+            for &p in &contour[1..] {
+                face.line_to(p);
+            }
+            face.end(true);
+        }
+        face.build()
+    } else {
+        outline.clone()
+    };
+    let mut fill: VertexBuffers<Point, u32> = VertexBuffers::new();
+    FillTessellator::new()
+        .tessellate_path(
+            &face,
+            &FillOptions::tolerance(tolerance).with_fill_rule(FillRule::NonZero),
+            &mut BuffersBuilder::new(&mut fill, |vertex: FillVertex| vertex.position()),
+        )
+        .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+
+    let back = Vector3::new(0.0, 0.0, -depth);
+    let faces = if depth > 0.0 { 2 } else { 1 };
+    for (offset, facing) in [(Vector3::zeros(), Vector3::z()), (back, -Vector3::z())]
+        .into_iter()
+        .take(faces)
+    {
+        let first = mesh.positions.len();
+        mesh.positions
+            .extend(fill.vertices.iter().map(|&p| place(p) + offset));
+        for triangle in fill.indices.chunks_exact(3) {
+            let triangle = [0, 1, 2].map(|i| first + triangle[i] as usize);
+            push_facing(mesh, triangle, &facing);
+        }
+    }
+    if depth <= 0.0 {
+        return Ok(());
+    }
+
+    let near = Vector3::new(0.0, 0.0, -chamfer);
+    let far = Vector3::new(0.0, 0.0, chamfer - depth);
+    for ((contour, inset), outward) in contours.iter().zip(&insets).zip(&outwards) {
+        for i in 0..contour.len() {
+            let j = (i + 1) % contour.len();
+            let [from, to, inset_from, inset_to] =
+                [contour[i], contour[j], inset[i], inset[j]].map(&place);
+            let facing = Vector3::new(outward[i].x as f64, outward[i].y as f64, 0.0);
+            push_quad(
+                mesh,
+                [from + near, to + near, to + far, from + far],
+                &facing,
+            );
+            if chamfer > 0.0 {
+                let front = [inset_from, inset_to, to + near, from + near];
+                push_quad(mesh, front, &(facing + Vector3::z()));
+                let back = [from + far, to + far, inset_to + back, inset_from + back];
+                push_quad(mesh, back, &(facing - Vector3::z()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Add a flat quadrilateral to a mesh, given its corners in order around it, facing along `facing`.
+fn push_quad(mesh: &mut TriangleMesh, corners: [Vector3<f64>; 4], facing: &Vector3<f64>) {
+    let first = mesh.positions.len();
+    mesh.positions.extend(corners);
+    push_facing(mesh, [first, first + 1, first + 2], facing);
+    push_facing(mesh, [first, first + 2, first + 3], facing);
+}
+
+/// Add a triangle to a mesh, ordering its corners so that its front faces along `facing`.
+fn push_facing(mesh: &mut TriangleMesh, [a, b, c]: [usize; 3], facing: &Vector3<f64>) {
+    let [pa, pb, pc] = [a, b, c].map(|v| mesh.positions[v]);
+    if (pb - pa).cross(&(pc - pa)).dot(facing) >= 0.0 {
+        mesh.triangles.push([a, b, c]);
+    } else {
+        mesh.triangles.push([a, c, b]);
+    }
+}
+
+/// The number of times the closed outline made of `edges` winds around `p`, counting
+/// counter-clockwise turns as positive.
+fn winding_number(edges: &[(Point, Point)], p: Point) -> i32 {
+    edges
+        .iter()
+        .map(|&(from, to)| {
+            let side = (to - from).cross(p - from);
+            if from.y <= p.y && to.y > p.y && side > 0.0 {
+                1
+            } else if from.y > p.y && to.y <= p.y && side < 0.0 {
+                -1
+            } else {
+                0
+            }
+        })
+        .sum()
+}
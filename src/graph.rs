@@ -0,0 +1,343 @@
+//! Node-graph materials: small networks of texture, math, and BSDF nodes evaluated at each hit to
+//! produce the material to shade it with.
+
+use nalgebra::Vector3;
+use rand::Rng;
+
+use crate::{
+    material::{reflectance, Material},
+    object::{Hit, World},
+    random,
+    render::{random_unit_vector, vector_near_zero, Color, Ray},
+    texture::{checker_parity, Texture},
+};
+
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    /// The node producing the material; it must evaluate to a BSDF.
+    pub output: usize,
+}
+
+pub enum Node {
+    /// A texture from the world's texture list.
+    TextureRef(usize),
+    /// A texture owned by this node.
+    Texture(Texture),
+    Checker {
+        scale: f64,
+        even: Input,
+        odd: Input,
+    },
+    Math {
+        op: MathOp,
+        a: Input,
+        b: Input,
+    },
+    /// Schlick's approximation of the fraction of light reflected by a dielectric surface.
+    Fresnel {
+        ir: Input,
+    },
+    /// Linear interpolation between values, or a random choice between BSDFs.
+    Mix {
+        a: Input,
+        b: Input,
+        factor: Input,
+    },
+    Diffuse {
+        albedo: Input,
+    },
+    Metal {
+        albedo: Input,
+    },
+    Dielectric {
+        ir: Input,
+    },
+    Plastic {
+        albedo: Input,
+        ir: Input,
+    },
+    Light {
+        color: Input,
+    },
+    /// The fraction of short rays from the hit that escape without striking anything: 1 in the
+    /// open and less in crevices and corners.
+    AmbientOcclusion {
+        /// How far rays travel looking for occluders.
+        distance: f64,
+        samples: u32,
+    },
+    /// A color varied randomly, but consistently, for each object.
+    RandomColor {
+        color: Input,
+        /// The largest change in hue, as a fraction of the color wheel.
+        hue_jitter: f64,
+        /// The largest change in value, as a fraction of the original value.
+        value_jitter: f64,
+        seed: u64,
+    },
+}
+
+#[derive(Clone)]
+pub enum Input {
+    Constant(Value),
+    Node(usize),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum MathOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+    Minimum,
+    Maximum,
+}
+
+/// The result of evaluating a node.
+#[derive(Clone)]
+pub enum Value {
+    Float(f64),
+    Color(Color),
+    Bsdf(Material),
+}
+
+/// What a node sees of the hit being shaded.
+pub struct ShadingContext<'a> {
+    pub world: &'a World,
+    pub ray: &'a Ray,
+    pub hit: &'a Hit,
+}
+
+impl Graph {
+    /// Evaluate the graph for a hit, producing a material that is not itself a graph or a mix.
+    pub fn evaluate(&self, context: &ShadingContext) -> Material {
+        let mut cache = vec![None; self.nodes.len()];
+        match self.evaluate_node(self.output, context, &mut cache) {
+            Value::Bsdf(material) => material,
+            // Validation ensures the output is a BSDF; fall back to something conspicuous.
+            Value::Float(_) | Value::Color(_) => Material::Light {
+                color: Color::new(1.0, 0.0, 1.0),
+            },
+        }
+    }
+
+    fn evaluate_input(
+        &self,
+        input: &Input,
+        context: &ShadingContext,
+        cache: &mut Vec<Option<Value>>,
+    ) -> Value {
+        match input {
+            Input::Constant(value) => value.clone(),
+            Input::Node(node) => self.evaluate_node(*node, context, cache),
+        }
+    }
+
+    fn evaluate_node(
+        &self,
+        node: usize,
+        context: &ShadingContext,
+        cache: &mut Vec<Option<Value>>,
+    ) -> Value {
+        if let Some(value) = &cache[node] {
+            return value.clone();
+        }
+
+        let mut input = |input: &Input| self.evaluate_input(input, context, cache);
+        let p = &context.hit.p;
+
+        let value = match &self.nodes[node] {
+            Node::TextureRef(texture) => {
+                Value::Color(context.world.textures[*texture].value(context.hit))
+            }
+            Node::Texture(texture) => Value::Color(texture.value(context.hit)),
+            Node::Checker { scale, even, odd } => {
+                if checker_parity(p, *scale) {
+                    input(even)
+                } else {
+                    input(odd)
+                }
+            }
+            Node::Math { op, a, b } => {
+                let (a, b) = (input(a), input(b));
+                op.apply(&a, &b)
+            }
+            Node::Fresnel { ir } => {
+                let ir = input(ir).float();
+                let refraction_ratio = if context.hit.front_face { 1.0 / ir } else { ir };
+                let cos_theta = (-context.ray.direction.normalize())
+                    .dot(&context.hit.normal)
+                    .min(1.0);
+                Value::Float(reflectance(cos_theta, refraction_ratio))
+            }
+            Node::Mix { a, b, factor } => {
+                let factor = input(factor).float();
+                match (input(a), input(b)) {
+                    (Value::Bsdf(a), Value::Bsdf(b)) => {
+                        if random::rng().gen_range(0.0..1.0) < factor {
+                            Value::Bsdf(b)
+                        } else {
+                            Value::Bsdf(a)
+                        }
+                    }
+                    (Value::Float(a), Value::Float(b)) => Value::Float(a + (b - a) * factor),
+                    (a, b) => Value::Color(a.color().lerp(&b.color(), factor)),
+                }
+            }
+            Node::Diffuse { albedo } => Value::Bsdf(Material::Diffuse {
+                albedo: input(albedo).color(),
+            }),
+            Node::Metal { albedo } => Value::Bsdf(Material::Metal {
+                albedo: input(albedo).color(),
+                ior: None,
+                fuzz: 0.0,
+            }),
+            Node::Dielectric { ir } => Value::Bsdf(Material::Dielectric {
+                ir: input(ir).float(),
+                medium: None,
+            }),
+            Node::Plastic { albedo, ir } => Value::Bsdf(Material::Plastic {
+                albedo: input(albedo).color(),
+                ir: input(ir).float(),
+            }),
+            Node::Light { color } => Value::Bsdf(Material::Light {
+                color: input(color).color(),
+            }),
+            Node::AmbientOcclusion { distance, samples } => {
+                Value::Float(ambient_occlusion(context, *distance, *samples))
+            }
+            Node::RandomColor {
+                color,
+                hue_jitter,
+                value_jitter,
+                seed,
+            } => {
+                let id = context.world.object_ids[context.hit.object] as u64;
+                let hash = random::splitmix64(id ^ random::splitmix64(*seed));
+                // two uniform numbers in [-1, 1] from the halves of the hash
+                let r1 = (hash >> 32) as f64 / u32::MAX as f64 * 2.0 - 1.0;
+                let r2 = (hash & 0xFFFF_FFFF) as f64 / u32::MAX as f64 * 2.0 - 1.0;
+
+                let (hue, saturation, value) = rgb_to_hsv(&input(color).color());
+                let hue = (hue + r1 * hue_jitter).rem_euclid(1.0);
+                let value = (value * (1.0 + r2 * value_jitter)).max(0.0);
+                Value::Color(hsv_to_rgb(hue, saturation, value))
+            }
+        };
+
+        cache[node] = Some(value.clone());
+        value
+    }
+}
+
+impl Value {
+    /// Colors are reduced to their mean; BSDFs are treated as zero.
+    pub fn float(&self) -> f64 {
+        match self {
+            Value::Float(f) => *f,
+            Value::Color(c) => c.mean(),
+            Value::Bsdf(_) => 0.0,
+        }
+    }
+
+    /// Floats are broadcast to all channels; BSDFs are treated as black.
+    pub fn color(&self) -> Color {
+        match self {
+            Value::Float(f) => Vector3::repeat(*f),
+            Value::Color(c) => *c,
+            Value::Bsdf(_) => Color::zeros(),
+        }
+    }
+}
+
+impl MathOp {
+    /// Apply the operation to floats, or channel by channel if either operand is a color.
+    fn apply(&self, a: &Value, b: &Value) -> Value {
+        let op = |a: f64, b: f64| match self {
+            MathOp::Add => a + b,
+            MathOp::Subtract => a - b,
+            MathOp::Multiply => a * b,
+            MathOp::Divide => {
+                if b == 0.0 {
+                    0.0
+                } else {
+                    a / b
+                }
+            }
+            MathOp::Power => a.powf(b),
+            MathOp::Minimum => a.min(b),
+            MathOp::Maximum => a.max(b),
+        };
+
+        match (a, b) {
+            (Value::Float(a), Value::Float(b)) => Value::Float(op(*a, *b)),
+            (a, b) => Value::Color(a.color().zip_map(&b.color(), op)),
+        }
+    }
+}
+
+/// Cast `samples` rays distributed around the hit's normal, returning the fraction that travel
+/// `distance` without hitting anything.
+fn ambient_occlusion(context: &ShadingContext, distance: f64, samples: u32) -> f64 {
+    if samples == 0 {
+        return 1.0;
+    }
+
+    let hit = context.hit;
+    let unoccluded = (0..samples)
+        .filter(|_| {
+            // cosine-weighted, like diffuse scattering, so occluders near the normal count most
+            let mut direction = hit.normal + random_unit_vector();
+            if vector_near_zero(&direction) {
+                direction = hit.normal;
+            }
+
+            let ray = Ray {
+                origin: hit.p,
+                direction: direction.normalize(),
+                time: context.ray.time,
+            };
+            context.world.hit(&ray, 0.001, distance).is_none()
+        })
+        .count();
+
+    unoccluded as f64 / samples as f64
+}
+
+/// Convert a color to hue (as a fraction of the color wheel), saturation, and value.
+fn rgb_to_hsv(color: &Color) -> (f64, f64, f64) {
+    let max = color.max();
+    let min = color.min();
+    let delta = max - min;
+
+    let hue = if delta <= 0.0 {
+        0.0
+    } else if max == color.x {
+        ((color.y - color.z) / delta).rem_euclid(6.0) / 6.0
+    } else if max == color.y {
+        ((color.z - color.x) / delta + 2.0) / 6.0
+    } else {
+        ((color.x - color.y) / delta + 4.0) / 6.0
+    };
+    let saturation = if max <= 0.0 { 0.0 } else { delta / max };
+
+    (hue, saturation, max)
+}
+
+fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> Color {
+    let chroma = value * saturation;
+    let sector = hue * 6.0;
+    let x = chroma * (1.0 - (sector.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+
+    Color::new(r + m, g + m, b + m)
+}
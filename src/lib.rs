@@ -0,0 +1,35 @@
+//! rtk, a software ray-tracer.
+//!
+//! A scene is described either by a scene file, deserialized into `scene::Scene`, or built in Rust
+//! with the types in `builder`. Either way it becomes a `object::World` and a `render::Camera`,
+//! which `render::Renderer` turns into an image.
+//!
+//! The command-line interface in `main.rs` is a thin layer over this library, which other programs
+//! can use the same way: rendering returns an image buffer, and nothing reads or writes files
+//! unless a scene refers to them.
+
+pub mod builder;
+pub mod bvh;
+pub mod color;
+pub mod dispatch;
+pub mod extrude;
+pub mod graph;
+pub mod light;
+pub mod material;
+pub mod medium;
+pub mod mesh;
+pub mod metaball;
+pub mod migrate;
+pub mod object;
+pub mod overlay;
+pub mod plugin;
+pub mod points;
+pub mod polarization;
+pub mod profile;
+pub mod random;
+pub mod render;
+pub mod scene;
+#[cfg(feature = "script")]
+pub mod script;
+pub mod text;
+pub mod texture;
@@ -0,0 +1,136 @@
+use nalgebra::{Unit, Vector3};
+use rand::Rng;
+
+use crate::{random, render::Color};
+
+/// A light source that is sampled directly, rather than found by scattered rays.
+pub enum Light {
+    Point {
+        position: Vector3<f64>,
+        color: Color,
+        falloff: Falloff,
+    },
+    /// A light infinitely far away, like the sun.
+    Directional {
+        /// The direction the light travels in.
+        direction: Unit<Vector3<f64>>,
+        color: Color,
+        /// The apparent radius of the light in radians, over which shadow rays are spread to
+        /// produce penumbras.
+        angular_radius: f64,
+    },
+}
+
+/// How a light's intensity decreases with distance.
+#[derive(Clone, Copy, Debug)]
+pub enum Falloff {
+    InverseSquare,
+    Linear,
+    /// Inverse-square, smoothly windowed to reach zero at `range`.
+    Smooth {
+        range: f64,
+    },
+}
+
+/// The light arriving at a point from a light source.
+pub struct LightSample {
+    /// Unit vector from the point toward the light.
+    pub direction: Vector3<f64>,
+    /// The distance to the light, beyond which occluders do not cast shadows.
+    pub distance: f64,
+    pub radiance: Color,
+}
+
+impl Light {
+    /// The power the light gives out in its brightest channel, for choosing between lights in a
+    /// world that fits in a sphere of `world_radius`. A point light with inverse-square falloff
+    /// gives out 4π times its intensity; one with linear falloff does not run out with distance,
+    /// so it is counted as far as the world reaches. A directional light is counted over the
+    /// world's cross-section.
+    pub fn power(&self, world_radius: f64) -> f64 {
+        use std::f64::consts::PI;
+
+        match self {
+            Light::Point { color, falloff, .. } => {
+                let reach = match falloff {
+                    Falloff::InverseSquare | Falloff::Smooth { .. } => 1.0,
+                    Falloff::Linear => world_radius,
+                };
+                4.0 * PI * color.max() * reach
+            }
+            Light::Directional { color, .. } => PI * world_radius.powi(2) * color.max(),
+        }
+    }
+
+    /// Sample the light arriving at `p`, ignoring occlusion.
+    pub fn sample(&self, p: &Vector3<f64>) -> Option<LightSample> {
+        match self {
+            Light::Point {
+                position,
+                color,
+                falloff,
+            } => {
+                let to_light = position - p;
+                let distance = to_light.magnitude();
+                if distance <= 0.0 {
+                    return None;
+                }
+
+                Some(LightSample {
+                    direction: to_light / distance,
+                    distance,
+                    radiance: color * falloff.attenuation(distance),
+                })
+            }
+            Light::Directional {
+                direction,
+                color,
+                angular_radius,
+            } => Some(LightSample {
+                direction: random_direction_in_cone(&-direction.into_inner(), *angular_radius),
+                distance: f64::INFINITY,
+                radiance: *color,
+            }),
+        }
+    }
+}
+
+/// A random unit vector within `angle` radians of `axis`, uniformly distributed over the cone's
+/// solid angle.
+fn random_direction_in_cone(axis: &Vector3<f64>, angle: f64) -> Vector3<f64> {
+    if angle <= 0.0 {
+        return *axis;
+    }
+
+    let mut rng = random::rng();
+    let cos_theta = 1.0 - rng.gen_range(0.0..1.0) * (1.0 - angle.cos());
+    let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
+    let phi = rng.gen_range(0.0..std::f64::consts::TAU);
+
+    // an orthonormal basis around the axis
+    let helper = if axis.x.abs() > 0.9 {
+        Vector3::y()
+    } else {
+        Vector3::x()
+    };
+    let tangent = axis.cross(&helper).normalize();
+    let bitangent = axis.cross(&tangent);
+
+    (tangent * phi.cos() + bitangent * phi.sin()) * sin_theta + axis * cos_theta
+}
+
+impl Falloff {
+    /// The factor by which intensity is scaled at `distance` from the light.
+    pub fn attenuation(&self, distance: f64) -> f64 {
+        match self {
+            Falloff::InverseSquare => 1.0 / distance.powi(2),
+            Falloff::Linear => 1.0 / distance,
+            Falloff::Smooth { range } => {
+                // (1 - (d/r)^4)^2, which has a zero derivative at the range so the cut-off does not
+                // show
+                let window = (1.0 - (distance / range).powi(4)).clamp(0.0, 1.0);
+                window.powi(2) / distance.powi(2)
+            }
+        }
+    }
+}
@@ -1,21 +1,55 @@
+mod bake;
+mod checkpoint;
 mod cli;
+mod config;
+mod converge;
 #[cfg(feature = "denoise")]
 mod denoise;
-mod material;
-mod object;
-mod render;
-mod scene;
-
-use std::{path::Path, thread};
+mod diff;
+mod examples;
+mod monitor;
+mod notify;
+mod output;
+mod preview;
+mod probe;
+mod profiler;
+mod samples;
+mod sidecar;
+mod stats;
+mod status;
+mod sweep;
+mod tiled_exr;
+mod tui;
+#[cfg(feature = "window")]
+mod window;
+
+use std::path::{Path, PathBuf};
+use std::{
+    collections::HashSet,
+    io::Read,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Instant,
+};
 
 use colored::Colorize;
-use nalgebra::{Unit, UnitQuaternion, Vector3};
+use rtk::{
+    overlay::{draw_bounds, draw_wireframe, BoundsOverlay},
+    plugin::Plugins,
+    profile::{self, Scope},
+    render::{self, Camera, ProgressEvent, Renderer, Stage, Toon},
+    scene::{Framing, Scene, StereoLayout},
+};
 
 use crate::{
-    material::Material,
-    object::{Object, World},
-    render::{Camera, Renderer},
-    scene::Scene,
+    checkpoint::{Checkpoint, Layout},
+    monitor::Monitor,
+    notify::RenderReport,
+    samples::SampleFile,
+    sidecar::Sidecar,
+    status::{ProgressDisplay, StatusLine, TICK_INTERVAL},
+    tiled_exr::TiledExr,
+    tui::Tui,
 };
 
 fn main() {
@@ -25,23 +59,47 @@ fn main() {
     }
 }
 
+fn print_warning(message: &str) {
+    eprintln!("{}: {}", "warning".bold().yellow(), message);
+}
+
 fn run_cli() -> anyhow::Result<()> {
     let cli = <cli::Cli as clap::Parser>::parse();
+    let config = config::Config::load()?;
+    if let Some(threads) = cli
+        .threads
+        .map(|threads| threads as usize)
+        .or(config.threads)
+    {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()?;
+    }
 
     match cli.command {
-        cli::Command::Render {
-            scene,
-            output,
-            parallel: _,
-            no_parallel,
-            #[cfg(feature = "denoise")]
-            denoise,
-        } => {
-            #[cfg(feature = "denoise")]
-            render(scene.as_path(), output.as_path(), !no_parallel, denoise)?;
-            #[cfg(not(feature = "denoise"))]
-            render(scene.as_path(), output.as_path(), !no_parallel, false)?;
+        cli::Command::Render(mut args) => {
+            config.apply(&mut args);
+            render(&args)?
+        }
+        cli::Command::Converge(args) => converge::converge(&args)?,
+        cli::Command::Sweep(args) => sweep::sweep(&args)?,
+        cli::Command::Bake(args) => bake::bake(&args)?,
+        cli::Command::Probe(args) => probe::probe(&args)?,
+        cli::Command::Stats { scene } => stats::stats(&scene)?,
+        cli::Command::Diff { a, b } => diff::diff(&a, &b)?,
+        cli::Command::PreviewMaterial(args) => preview::preview_material(&args)?,
+        cli::Command::Examples { name, output } => {
+            examples::examples(name.as_deref(), output.as_deref())?
         }
+        cli::Command::Completions { shell } => clap_complete::generate(
+            shell,
+            &mut <cli::Cli as clap::CommandFactory>::command(),
+            "rtk",
+            &mut std::io::stdout(),
+        ),
+        cli::Command::ListDevices => list_devices(),
+        cli::Command::Schema { output } => schema(output.as_deref())?,
+        cli::Command::Migrate { scene, output } => migrate(&scene, output.as_deref())?,
         #[cfg(feature = "denoise")]
         cli::Command::Denoise { image, output } => denoise(&image, output.as_deref())?,
     }
@@ -49,168 +107,589 @@ fn run_cli() -> anyhow::Result<()> {
     Ok(())
 }
 
-/// Handle `cli::Command::Render`.
-fn render(
-    scene_path: &Path,
-    output_path: &Path,
-    parallel: bool,
-    _denoise: bool,
-) -> anyhow::Result<()> {
-    let scene_source = std::fs::read_to_string(scene_path)?;
-    let scene: Scene = toml::from_str(&scene_source)?;
-
-    let camera = create_camera(&scene);
-    let (renderer, progress_receiver) = Renderer::new(camera);
-
-    let handle = thread::spawn(move || {
-        let materials = collect_materials(&scene);
-        let objects = create_objects(&scene);
-
-        renderer.render(&World { objects, materials }, parallel)
-    });
+/// Whether a path given on the command line is `-`, which stands for standard input or output.
+fn is_standard_stream(path: &Path) -> bool {
+    path == Path::new("-")
+}
 
-    loop {
-        let progress = progress_receiver.recv()?;
-        print_progress_bar(progress);
-        if progress == 100 {
-            eprintln!();
-            break;
-        }
+/// Read the scene file at `path`, or from standard input if it is `-`, warning if it needed
+/// migrating from an older version of the scene format.
+fn load_scene(path: &Path) -> anyhow::Result<Scene> {
+    let toml = if is_standard_stream(path) {
+        let mut toml = String::new();
+        std::io::stdin().read_to_string(&mut toml)?;
+        toml
+    } else {
+        std::fs::read_to_string(path)?
+    };
+    let (mut scene, changes) = Scene::from_toml(&toml)?;
+    if !changes.is_empty() {
+        let fix = if is_standard_stream(path) {
+            "run it through `rtk migrate` to update it".to_string()
+        } else {
+            format!("run `rtk migrate {}` to update it", path.display())
+        };
+        print_warning(&format!(
+            "{} is written for an older version of rtk; {fix}",
+            describe_path(path),
+        ));
     }
+    scene.run_script()?;
 
-    let image = handle
-        .join()
-        .map_err(|e| anyhow::anyhow!("the rendering thread panicked:\n{:#?}", e))?;
+    Ok(scene)
+}
 
-    #[cfg(feature = "denoise")]
-    let image = if _denoise {
-        eprintln!("Denoising...");
-        denoise::denoise(&image)?
+/// How to refer to a path given on the command line in messages.
+fn describe_path(path: &Path) -> String {
+    if is_standard_stream(path) {
+        "the scene from standard input".to_string()
     } else {
-        image
-    };
+        path.display().to_string()
+    }
+}
 
-    eprintln!("Writing to {}...", output_path.display());
-    image.save(output_path)?;
+/// Handle `cli::Command::Migrate`.
+fn migrate(path: &Path, output: Option<&Path>) -> anyhow::Result<()> {
+    let mut document: toml_edit::Document = std::fs::read_to_string(path)?.parse()?;
+    let version = document.get("version").and_then(|item| item.as_integer());
+    let changes = rtk::migrate::migrate(&mut document)?;
+    if version == Some(rtk::migrate::VERSION.into()) {
+        eprintln!(
+            "{} is already at version {} of the scene format",
+            path.display(),
+            rtk::migrate::VERSION
+        );
+        return Ok(());
+    }
+
+    for change in &changes {
+        eprintln!("  {change}");
+    }
+    let output = output.unwrap_or(path);
+    std::fs::write(output, document.to_string())?;
+    eprintln!(
+        "Wrote {} at version {} of the scene format",
+        output.display(),
+        rtk::migrate::VERSION
+    );
 
     Ok(())
 }
 
-#[cfg(feature = "denoise")]
-/// Handle `cli::Command::Denoise`.
-fn denoise(image_path: &Path, output_path: Option<&Path>) -> anyhow::Result<()> {
-    let image = image::io::Reader::open(image_path)?.decode()?.to_rgb8();
-    eprintln!("Denoising {}...", image_path.display());
-    let denoised = denoise::denoise(&image)?;
-    denoised.save(output_path.unwrap_or(image_path))?;
+/// Handle `cli::Command::Render`.
+fn render(args: &cli::RenderArgs) -> anyhow::Result<()> {
+    let start = Instant::now();
+    if args.profile || args.trace.is_some() {
+        profile::enable();
+    }
+    let result = render_to_file(args);
 
-    Ok(())
-}
+    if args.profile || args.trace.is_some() {
+        let events = profile::take_events();
+        if args.profile {
+            profiler::print_summary(&events);
+        }
+        if let Some(path) = &args.trace {
+            if let Err(e) = profiler::write_chrome_trace(&events, path) {
+                print_warning(&format!("failed to write the trace: {e}"));
+            }
+        }
+    }
 
-fn collect_materials(scene: &Scene) -> Vec<Material> {
-    let mut result = Vec::new();
-    for m in scene.materials.iter() {
-        result.push(<scene::Material as Into<Material>>::into(m.clone()));
+    if args.notify || args.webhook.is_some() {
+        let report = RenderReport::new(args, start.elapsed(), &result);
+        if args.notify {
+            if let Err(e) = notify::notify_desktop(&report) {
+                print_warning(&format!("failed to show a notification: {e}"));
+            }
+        }
+        if let Some(url) = &args.webhook {
+            if let Err(e) = notify::post_webhook(url, &report) {
+                print_warning(&format!("failed to call the webhook: {e}"));
+            }
+        }
     }
 
-    result
+    result.map(|_| ())
 }
 
-fn create_objects(scene: &Scene) -> Vec<Object> {
-    let mut result = vec![];
-
-    for obj in scene.objects.iter() {
-        match obj.shape {
-            scene::Shape::Sphere { center, radius } => result.push(Object::sphere(
-                tuple_to_vector(center),
-                radius,
-                obj.material,
-            )),
-            scene::Shape::Quad { q, u, v } => result.push(Object::quad(
-                tuple_to_vector(q),
-                tuple_to_vector(u),
-                tuple_to_vector(v),
-                obj.material,
-            )),
-            scene::Shape::Prism {
-                origin,
-                width,
-                height,
-                depth,
-                ref rotation,
-            } => result.extend(Object::prism(
-                &tuple_to_vector(origin),
-                width,
-                height,
-                depth,
-                &rotation.clone().unwrap_or_default().into(),
-                obj.material,
-            )),
-        }
-    }
-
-    result
-}
+/// Render the scene and write the image, returning the camera that was used.
+fn render_to_file(args: &cli::RenderArgs) -> anyhow::Result<Camera> {
+    let io = profile::scope(Scope::Io);
+    let (mut scene, settings) = match &args.from_sidecar {
+        Some(path) => {
+            let sidecar = Sidecar::load(path)?;
+            (sidecar.scene, sidecar.settings)
+        }
+        None => {
+            let path = args.source();
+            let mut scene = load_scene(path)?;
+            if let Some(name) = &args.camera {
+                scene.select_camera(name)?;
+            }
+            let settings = sidecar::Settings {
+                source: path.to_path_buf(),
+                camera: args.camera.clone(),
+                frame: args.frame,
+                checkerboard: args.checkerboard,
+                #[cfg(feature = "denoise")]
+                denoise: args.denoise,
+                #[cfg(not(feature = "denoise"))]
+                denoise: false,
+                bounds: args.bounds,
+                bvh_depth: args.bvh_depth,
+                wireframe: args.wireframe,
+                toon: args.toon,
+                toon_bands: args.toon_bands,
+                auto_frame: args.auto_frame,
+                half: args.half,
+            };
+            (scene, settings)
+        }
+    };
+    drop(io);
+    // `--auto-frame` frames the camera as the scene's own setting would, so it is validated the
+    // same way.
+    if settings.auto_frame {
+        scene.camera.frame = Some(Framing::All);
+    }
+    scene.validate()?;
+    #[cfg(not(feature = "denoise"))]
+    if settings.denoise {
+        print_warning("the image was denoised, but this build of rtk cannot denoise");
+    }
 
-fn create_camera(scene: &Scene) -> Camera {
-    let p = scene.camera.position.unwrap_or_default();
+    let camera = scene.create_frame_camera(settings.frame);
+    let stereo_layout = scene.camera.stereo.as_ref().map(|stereo| stereo.layout);
+    let mut renderer = Renderer::new(camera.clone());
+    renderer.set_checkerboard(settings.checkerboard);
+    renderer.set_progressive(args.progressive);
+    renderer.set_half_framebuffer(settings.half);
+    if settings.toon {
+        let toon = Toon::default();
+        renderer.set_toon(Some(Toon {
+            bands: settings.toon_bands.unwrap_or(toon.bands),
+            ..toon
+        }));
+    }
+    let sidecar = Sidecar::new(scene.clone(), settings.clone());
 
-    let rotation = if let Some(rotation) = &scene.camera.rotation {
-        match rotation {
-            scene::Rotation::Euler { roll, pitch, yaw } => {
-                UnitQuaternion::from_euler_angles(*roll, *pitch, *yaw)
-            }
-            scene::Rotation::Direction { x, y, z } => {
-                UnitQuaternion::rotation_between(&-Vector3::z(), &Vector3::new(*x, *y, *z))
-                    .unwrap_or_else(|| {
-                        UnitQuaternion::from_axis_angle(
-                            &Unit::new_normalize(Vector3::y()),
-                            std::f64::consts::PI,
-                        )
-                    })
+    let previous_scene = match &args.previous_scene {
+        Some(path) => {
+            let mut previous = load_scene(path)?;
+            if let Some(name) = &settings.camera {
+                previous.select_camera(name)?;
             }
+            previous.validate()?;
+            Some(previous)
         }
+        None => None,
+    };
+    let motion_vectors = args.motion_vectors.is_some();
+    let linear_output = output::is_linear(&args.output);
+    if linear_output && (settings.bounds || settings.wireframe) {
+        anyhow::bail!(
+            "overlays are drawn in display colors, so they cannot be written to {}",
+            args.output.display()
+        );
+    }
+
+    let mut status: Box<dyn ProgressDisplay> = if args.tui {
+        Box::new(Tui::new(renderer.tile_grid()))
     } else {
-        UnitQuaternion::from_euler_angles(0.0, 0.0, 0.0)
+        Box::new(StatusLine::new())
+    };
+    #[cfg(feature = "window")]
+    if args.preview {
+        // A render without a display still goes ahead, just without the window.
+        match window::PreviewWindow::open(renderer.output_dimensions()) {
+            Ok(window) => status = Box::new(vec![status, Box::new(window)]),
+            Err(e) => print_warning(&e.to_string()),
+        }
+    }
+    if let Some(address) = &args.progress_listen {
+        let monitor = Monitor::listen(address, renderer.output_dimensions(), renderer.tile_grid())?;
+        status = Box::new(vec![status, Box::new(monitor)]);
+    }
+    let mut tiled_exr = match &args.tiled_exr {
+        Some(path) => Some(TiledExr::create(
+            path,
+            renderer.output_dimensions(),
+            renderer.tile_size(),
+        )?),
+        None => None,
+    };
+    let mut sample_file = match &args.export_samples {
+        Some(path) => {
+            renderer.set_record_samples(true);
+            Some(SampleFile::create(path, renderer.image_dimensions())?)
+        }
+        None => None,
+    };
+    let mut resumed = HashSet::new();
+    let mut checkpoint = match &args.checkpoint {
+        Some(path) => {
+            // The source is left out, so that a render resumed from a moved scene file matches.
+            let mut fingerprinted = sidecar.clone();
+            fingerprinted.settings.source = PathBuf::new();
+            let layout = Layout {
+                dimensions: renderer.output_dimensions(),
+                tile_size: renderer.tile_size(),
+                fingerprint: checkpoint::fingerprint(&toml::to_string(&fingerprinted)?),
+            };
+            if args.resume && checkpoint::exists(path)? {
+                let (checkpoint, tiles) = Checkpoint::resume(path, layout)?;
+                let (columns, rows) = renderer.tile_grid();
+                eprintln!(
+                    "Resuming with {} of {} tiles finished",
+                    tiles.len(),
+                    columns * rows
+                );
+                resumed = tiles.keys().copied().collect();
+                renderer.set_finished_tiles(tiles);
+                Some(checkpoint)
+            } else {
+                Some(Checkpoint::create(path, layout)?)
+            }
+        }
+        None => None,
     };
+    let (progress_sender, progress_receiver) = mpsc::channel();
+    let parallel = !args.no_parallel;
+
+    // The first interrupt stops the render cleanly, so that notifications are still sent; a second
+    // one exits immediately.
+    let render_handle = renderer.handle();
+    let interrupt = ctrlc::set_handler(move || {
+        if render_handle.is_cancelled() {
+            std::process::exit(130);
+        }
+        render_handle.cancel();
+    });
+    if let Err(e) = interrupt {
+        print_warning(&format!("failed to handle interrupts: {e}"));
+    }
+
+    // A dropped receiver is ignored, so the renderer keeps working even if nobody is listening.
+    let max_memory = args.max_memory;
+    let overlays = settings.bounds || settings.wireframe;
+    let auto_frame = settings.auto_frame;
+    let half = settings.half;
+    let render_thread = thread::spawn(move || {
+        _ = progress_sender.send(ProgressEvent::Stage(Stage::Loading));
+        let build = profile::scope(Scope::Build);
+        let mut world = scene.create_world(&Plugins::new())?;
+        // An interrupt while the scene loads stops the render before anything more is done.
+        if renderer.handle().is_cancelled() {
+            _ = progress_sender.send(ProgressEvent::Cancelled);
+            return Err(render::Cancelled.into());
+        }
+        if auto_frame || scene.camera.frame.is_some() {
+            renderer.set_position(scene.frame_camera(&mut world)?);
+        }
+        if let Some(distance) = scene.focus_distance(&world)? {
+            renderer.set_focus_distance(distance);
+        }
+        drop(build);
+        // A camera pointed the wrong way renders only the background, which is easy to mistake
+        // for a lighting problem.
+        let sees_nothing =
+            !world.object_ids.is_empty() && renderer.visible_objects(&world).is_empty();
+
+        let mut fallbacks = Vec::new();
+        if let Some(budget) = max_memory {
+            let threads = if parallel {
+                rayon::current_num_threads()
+            } else {
+                1
+            };
+            fallbacks = renderer
+                .fit_memory(&mut world, threads, budget)
+                .map_err(|estimate| {
+                    let textures = match estimate.textures {
+                        0 => String::new(),
+                        bytes => format!(", of which textures take {}", cli::format_size(bytes)),
+                    };
+                    anyhow::anyhow!(
+                        "the render needs at least {} of memory{textures}, more than the maximum \
+                         of {}",
+                        cli::format_size(estimate.total()),
+                        cli::format_size(budget)
+                    )
+                })?;
+        }
+
+        let on_progress = |event| _ = progress_sender.send(event);
+        let (image, clipping) = if linear_output && half {
+            let (image, clipping) = renderer.render_half(&world, parallel, on_progress)?;
+            (output::Image::Half(image), clipping)
+        } else if linear_output {
+            let (image, clipping) = renderer.render_exposed(&world, parallel, on_progress)?;
+            (output::Image::Linear(image), clipping)
+        } else {
+            let (image, clipping) = renderer.render_with_clipping(&world, parallel, on_progress)?;
+            (output::Image::Display(image), clipping)
+        };
+
+        let motion = match previous_scene {
+            Some(mut previous_scene) if motion_vectors => {
+                let mut previous_world = previous_scene.create_world(&Plugins::new())?;
+                if previous_world.objects.len() != world.objects.len() {
+                    anyhow::bail!(
+                        "the previous scene has {} primitives, but this one has {}",
+                        previous_world.objects.len(),
+                        world.objects.len()
+                    );
+                }
+                if auto_frame || previous_scene.camera.frame.is_some() {
+                    previous_scene.frame_camera(&mut previous_world)?;
+                }
+                let mut previous = Renderer::new(previous_scene.create_camera());
+                if let Some(distance) = previous_scene.focus_distance(&previous_world)? {
+                    previous.set_focus_distance(distance);
+                }
+                Some(renderer.motion_vectors(&world, &previous, &previous_world))
+            }
+            _ => None,
+        };
+
+        // Overlays are drawn once the image is denoised, so they need the world and renderer.
+        let overlay = overlays.then_some((world, renderer));
 
-    let (focus_distance, defocus_angle) = if let Some(defocus) = &scene.camera.defocus {
-        (defocus.focus_distance, defocus.defocus_angle)
+        anyhow::Ok((image, clipping, motion, fallbacks, overlay, sees_nothing))
+    });
+
+    // The channel disconnects once the rendering thread exits, so this cannot outlive the renderer.
+    // Displays are ticked while no progress arrives, so that the preview window keeps responding.
+    loop {
+        let progress = match progress_receiver.recv_timeout(TICK_INTERVAL) {
+            Ok(progress) => progress,
+            Err(RecvTimeoutError::Timeout) => {
+                status.tick();
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        status.update(&progress);
+        if let (
+            Some(exr),
+            ProgressEvent::TileFinished {
+                column,
+                row,
+                linear,
+                ..
+            },
+        ) = (&mut tiled_exr, &progress)
+        {
+            if let Err(e) = exr.write_tile(*column, *row, linear) {
+                print_warning(&format!(
+                    "failed to write a tile, so no more will be written: {e}"
+                ));
+                tiled_exr = None;
+            }
+        }
+        if let (
+            Some(file),
+            ProgressEvent::TileFinished {
+                column,
+                row,
+                linear,
+                ..
+            },
+        ) = (&mut checkpoint, &progress)
+        {
+            if resumed.contains(&(*column, *row)) {
+                // already in the checkpoint
+            } else if let Err(e) = file.write_tile(*column, *row, linear) {
+                print_warning(&format!(
+                    "failed to add a tile to the checkpoint, so no more will be added: {e}"
+                ));
+                checkpoint = None;
+            }
+        }
+        if let (Some(file), ProgressEvent::Samples(samples)) = (&mut sample_file, &progress) {
+            if let Err(e) = file.write_samples(samples) {
+                print_warning(&format!(
+                    "failed to write samples, so no more will be written: {e}"
+                ));
+                sample_file = None;
+            }
+        }
+        match progress {
+            ProgressEvent::Finished | ProgressEvent::Cancelled => break,
+            ProgressEvent::Failed(message) => {
+                anyhow::bail!("the rendering thread panicked: {message}");
+            }
+            _ => {}
+        }
+    }
+
+    let result = render_thread
+        .join()
+        .map_err(|_| anyhow::anyhow!("the rendering thread panicked"))?;
+    if let (Err(e), Some(path)) = (&result, &args.checkpoint) {
+        if e.is::<render::Cancelled>() {
+            eprintln!(
+                "The finished tiles are kept in {}; run the same command with `--resume` to \
+                 continue",
+                path.display()
+            );
+        }
+    }
+    if let Some(file) = sample_file {
+        if let Err(e) = file.finish() {
+            print_warning(&format!("failed to write samples: {e}"));
+        }
+    }
+    let (image, clipping, motion, fallbacks, overlay, sees_nothing) = result?;
+
+    #[cfg(feature = "denoise")]
+    let image = if settings.denoise {
+        status.update(&ProgressEvent::Stage(Stage::Denoising));
+        let _filter = profile::scope(Scope::Filter);
+        image.map(
+            |image| denoise::denoise(&image),
+            |image| denoise::denoise_linear(&image),
+        )?
     } else {
-        (1.0, 0.0)
+        image
+    };
+
+    let image = match &overlay {
+        Some((world, renderer)) => image.map(
+            |mut image| {
+                if settings.wireframe {
+                    draw_wireframe(&mut image, renderer, world);
+                }
+                if settings.bounds {
+                    let bounds = BoundsOverlay {
+                        bvh_depth: settings.bvh_depth,
+                    };
+                    draw_bounds(&mut image, renderer, world, bounds);
+                }
+                Ok(image)
+            },
+            Ok,
+        )?,
+        None => image,
     };
 
-    let background_color = scene.camera.background_color.unwrap_or_default();
+    // The views are denoised apart, since the denoiser would take an anaglyph's color fringes for
+    // detail.
+    let image = match &stereo_layout {
+        Some(StereoLayout::Anaglyph) => image.map(
+            |image| Ok(render::anaglyph(&image)),
+            |image| Ok(render::anaglyph(&image)),
+        )?,
+        _ => image,
+    };
 
-    Camera {
-        image_width: scene.camera.image_dimensions.0,
-        background_color: tuple_to_vector(background_color),
-        image_height: scene.camera.image_dimensions.1,
-        position: tuple_to_vector(p),
-        rotation,
-        fov: scene.camera.fov,
-        focus_distance,
-        defocus_angle,
-        samples_per_pixel: scene.camera.samples_per_pixel.unwrap_or(100),
+    status.update(&ProgressEvent::Stage(Stage::Writing));
+    let io = profile::scope(Scope::Io);
+    image.save(&args.output)?;
+    if let (Some(path), Some(motion)) = (&args.motion_vectors, motion) {
+        motion.save(path)?;
     }
-}
+    if let Some(path) = &args.overflow_mask {
+        clipping.mask.save(path)?;
+    }
+    // An image written to standard output has nowhere beside it for a sidecar.
+    let to_stdout = is_standard_stream(&args.output);
+    if !to_stdout {
+        if let Err(e) = sidecar.save(&sidecar::path(&args.output)) {
+            print_warning(&format!("failed to write the sidecar: {e}"));
+        }
+    }
+    if let Some(path) = &args.checkpoint {
+        drop(checkpoint);
+        if let Err(e) = std::fs::remove_file(path) {
+            print_warning(&format!("failed to remove the checkpoint: {e}"));
+        }
+    }
+    drop(io);
+    if to_stdout {
+        status.finish("Wrote the image to standard output");
+    } else {
+        status.finish(&format!("Wrote {}", args.output.display()));
+    }
+    print_clipping(&clipping, !linear_output);
+    if let output::Image::Half(image) = &image {
+        let clamped = image.clamped();
+        if clamped > 0 {
+            print_warning(&format!(
+                "{clamped} pixels were brighter than half floats hold and were clamped to 65504; \
+                 render without `--half` to keep them"
+            ));
+        }
+    }
+
+    if sees_nothing {
+        print_warning(
+            "the camera sees none of the objects, so the image shows only the background; check \
+             its position and rotation",
+        );
+    }
+    for fallback in fallbacks {
+        print_warning(&format!(
+            "to stay within the memory limit, rendered by {fallback}"
+        ));
+    }
+    status.wait();
 
-fn tuple_to_vector((x, y, z): (f64, f64, f64)) -> Vector3<f64> {
-    Vector3::new(x, y, z)
+    Ok(camera)
 }
 
-fn print_progress_bar(progress: u32) {
-    const SEGMENTS: u32 = 40;
+/// Report the pixels of a render that were too bright to show, or not a number. Images of linear
+/// colors keep bright pixels, so only `display` images clip them.
+fn print_clipping(clipping: &render::Clipping, display: bool) {
+    let pixels = clipping.mask.len() as f64;
+    if display && clipping.clipped > 0 {
+        eprintln!(
+            "{} pixels ({:.2}%) were brighter than white and clipped; the brightest channel was {:.2}",
+            clipping.clipped,
+            clipping.clipped as f64 / pixels * 100.0,
+            clipping.max
+        );
+    }
+    if clipping.nans > 0 {
+        print_warning(&format!(
+            "{} pixels ({:.2}%) were not a number, which shows as black",
+            clipping.nans,
+            clipping.nans as f64 / pixels * 100.0
+        ));
+    }
+}
 
-    let filled_segments = ((progress as f32 / 100.0) * SEGMENTS as f32) as u32;
-    let empty_segments = SEGMENTS - filled_segments;
+/// Handle `cli::Command::ListDevices`.
+fn list_devices() {
+    let threads = rayon::current_num_threads();
+    let plural = if threads == 1 { "" } else { "s" };
+    println!("Rendering:\n  CPU, {threads} thread{plural}");
+    println!("Denoising:");
+    #[cfg(feature = "denoise")]
+    denoise::print_devices();
+    #[cfg(not(feature = "denoise"))]
+    println!("  none; this build of rtk cannot denoise");
+}
 
-    eprint!("\r[");
-    for _ in 0..filled_segments {
-        eprint!("*");
+/// Handle `cli::Command::Schema`.
+fn schema(output: Option<&std::path::Path>) -> anyhow::Result<()> {
+    let schema = serde_json::to_string_pretty(&schemars::schema_for!(Scene))?;
+    match output {
+        Some(path) => std::fs::write(path, schema)?,
+        None => println!("{schema}"),
     }
-    for _ in 0..empty_segments {
-        eprint!(" ");
-    }
-    eprint!("] {progress}%");
+
+    Ok(())
+}
+
+#[cfg(feature = "denoise")]
+/// Handle `cli::Command::Denoise`.
+fn denoise(image_path: &Path, output_path: Option<&Path>) -> anyhow::Result<()> {
+    let image = image::io::Reader::open(image_path)?.decode()?.to_rgb8();
+    eprintln!("Denoising {}...", image_path.display());
+    let denoised = denoise::denoise(&image)?;
+    denoised.save(output_path.unwrap_or(image_path))?;
+
+    Ok(())
 }
@@ -1,25 +1,187 @@
+use std::sync::Arc;
+
 use nalgebra::Vector3;
 use rand::Rng;
 
 use crate::{
+    graph::Graph,
+    medium::Medium,
     object::Hit,
+    plugin::Bsdf,
+    random,
     render::{random_unit_vector, vector_near_zero, Color, Ray},
 };
 
+#[derive(Clone)]
 pub enum Material {
     Diffuse {
         albedo: Color,
     },
     Metal {
         albedo: Color,
+        /// The complex index of refraction of the conductor. Without one, the metal reflects
+        /// `albedo` at every angle.
+        ior: Option<ComplexIor>,
+        /// How far reflections are blurred, from 0 (a mirror) to 1, as the radius of the sphere
+        /// that reflected directions are offset within.
+        fuzz: f64,
     },
     Dielectric {
         /// Index of refraction.
         ir: f64,
+        /// The medium filling the object, if it is closed.
+        medium: Option<Medium>,
+    },
+    /// A diffuse base under a clear dielectric coat, which reflects more at grazing angles.
+    Plastic {
+        albedo: Color,
+        /// Index of refraction of the coat.
+        ir: f64,
     },
     Light {
         color: Color,
     },
+    /// A blend of materials `a` and `b`; see `World::material`.
+    Mix {
+        a: usize,
+        b: usize,
+        /// The weight of `b`.
+        factor: f64,
+        /// A texture whose value replaces `factor`.
+        mask: Option<usize>,
+    },
+    /// A node graph evaluated at each hit; see `World::material`.
+    Graph(Arc<Graph>),
+    /// Another material with some of its parameters replaced, so that objects can vary a shared
+    /// material without duplicating it in the scene.
+    Override {
+        base: usize,
+        overrides: MaterialOverrides,
+    },
+    /// A diffuse or metal material whose albedo is replaced by the color of a texture from the
+    /// world's list at each hit; see `World::material`.
+    Textured {
+        base: Box<Material>,
+        texture: usize,
+    },
+    /// A material provided by a plugin.
+    Custom(Arc<dyn Bsdf>),
+}
+
+/// The complex index of refraction of a conductor, sampled at the red, green, and blue
+/// wavelengths.
+#[derive(Clone, Copy, Debug)]
+pub struct ComplexIor {
+    /// The real part, or refractive index.
+    pub eta: Color,
+    /// The imaginary part, or extinction coefficient.
+    pub k: Color,
+}
+
+impl ComplexIor {
+    // Measured values at roughly 650, 550, and 450 nm.
+    pub const GOLD: Self = Self::new((0.143, 0.374, 1.442), (3.983, 2.385, 1.603));
+    pub const COPPER: Self = Self::new((0.200, 0.924, 1.102), (3.912, 2.452, 2.142));
+    pub const ALUMINUM: Self = Self::new((1.657, 0.880, 0.521), (9.224, 6.270, 4.837));
+    pub const SILVER: Self = Self::new((0.155, 0.117, 0.138), (4.828, 3.122, 2.147));
+    pub const CHROMIUM: Self = Self::new((3.107, 3.181, 2.323), (3.331, 3.329, 3.135));
+
+    pub const fn new(eta: (f64, f64, f64), k: (f64, f64, f64)) -> Self {
+        Self {
+            eta: Color::new(eta.0, eta.1, eta.2),
+            k: Color::new(k.0, k.1, k.2),
+        }
+    }
+
+    /// The Fresnel reflectance of each channel for light arriving from air at an angle with
+    /// cosine `cos_theta` to the normal.
+    pub fn reflectance(&self, cos_theta: f64) -> Color {
+        Color::from_fn(|i, _| conductor_reflectance(cos_theta, self.eta[i], self.k[i]))
+    }
+}
+
+/// The exact Fresnel reflectance of unpolarized light at an air-conductor interface.
+fn conductor_reflectance(cos_theta: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_theta * cos_theta;
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * cos_theta * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rs + rp)
+}
+
+/// Non-physical rules for which light paths see a material, as in production renderers. A
+/// surface is diffuse if its material is not specular.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LightPaths {
+    /// Paths that have scattered off a diffuse surface end at the material if it is specular, so
+    /// it casts no caustics.
+    pub no_caustics: bool,
+    /// The material casts shadows, blocking shadow rays and the light gathered by diffuse bounces,
+    /// but is invisible to the camera and in reflections.
+    pub shadow_only: bool,
+    /// Only paths that have just scattered off a diffuse surface, and shadow rays, see the
+    /// material, so it lights its surroundings without being seen by the camera or in reflections.
+    pub diffuse_visible_only: bool,
+}
+
+impl LightPaths {
+    /// Whether the camera cannot see the material.
+    pub fn hidden_from_camera(&self) -> bool {
+        self.shadow_only || self.diffuse_visible_only
+    }
+}
+
+/// Replacements for material parameters. Each applies only to materials that have the parameter.
+#[derive(Clone, Debug, Default)]
+pub struct MaterialOverrides {
+    /// Multiplies the albedo of diffuse and metal materials and the color of lights.
+    pub tint: Option<Color>,
+    pub albedo: Option<Color>,
+    pub ir: Option<f64>,
+    pub color: Option<Color>,
+}
+
+impl MaterialOverrides {
+    /// Apply the overrides to a resolved material (not a mix, graph, or override).
+    pub fn apply(&self, material: Material) -> Material {
+        let tint = self.tint.unwrap_or(Color::repeat(1.0));
+
+        match material {
+            Material::Diffuse { albedo } => Material::Diffuse {
+                albedo: self.albedo.unwrap_or(albedo).component_mul(&tint),
+            },
+            Material::Metal { albedo, ior, fuzz } => Material::Metal {
+                albedo: self.albedo.unwrap_or(albedo).component_mul(&tint),
+                ior,
+                fuzz,
+            },
+            Material::Dielectric { ir, medium } => Material::Dielectric {
+                ir: self.ir.unwrap_or(ir),
+                medium,
+            },
+            Material::Plastic { albedo, ir } => Material::Plastic {
+                albedo: self.albedo.unwrap_or(albedo).component_mul(&tint),
+                ir: self.ir.unwrap_or(ir),
+            },
+            Material::Light { color } => Material::Light {
+                color: self.color.unwrap_or(color).component_mul(&tint),
+            },
+            material => material,
+        }
+    }
 }
 
 #[allow(unused)]
@@ -33,34 +195,117 @@ impl Material {
     pub fn metal(r: f64, g: f64, b: f64) -> Self {
         Self::Metal {
             albedo: Vector3::new(r, g, b),
+            ior: None,
+            fuzz: 0.0,
         }
     }
 
     pub fn dielectric(ir: f64) -> Self {
-        Self::Dielectric { ir }
+        Self::Dielectric { ir, medium: None }
     }
 
     /// Scatter a ray according to this material.
-    pub fn scatter(&self, ray: &Ray, hit: &Hit) -> Option<(Color, Ray)> {
+    /// `roughness` blurs mirror-like reflection and refraction, from 0 (sharp) to 1; see
+    /// `Camera::regularization`.
+    pub fn scatter(&self, ray: &Ray, hit: &Hit, roughness: f64) -> Option<(Color, Ray)> {
         match self {
             Material::Diffuse { albedo } => scatter_diffuse(ray, hit, albedo),
-            Material::Metal { albedo } => scatter_metal(ray, hit, albedo),
-            Material::Dielectric { ir } => scatter_dielectric(ray, hit, *ir),
+            Material::Metal { albedo, ior, fuzz } => {
+                scatter_metal(ray, hit, albedo, ior.as_ref(), *fuzz, roughness)
+            }
+            Material::Dielectric { ir, .. } => scatter_dielectric(ray, hit, *ir, roughness),
+            Material::Plastic { albedo, ir } => scatter_plastic(ray, hit, albedo, *ir, roughness),
             Material::Light { color } => None,
+            Material::Custom(bsdf) => bsdf.scatter(ray, hit),
+            Material::Mix { .. }
+            | Material::Graph(_)
+            | Material::Override { .. }
+            | Material::Textured { .. } => {
+                unreachable!(
+                    "mixed, graph, overridden, and textured materials are resolved by the world"
+                )
+            }
+        }
+    }
+
+    /// Evaluate the fraction of light arriving from `direction` (a unit vector) that is reflected
+    /// back along the ray, including the cosine term.
+    /// Materials that only reflect or refract in a single direction return zero, since light from
+    /// a point can never arrive from exactly that direction.
+    pub fn eval(&self, ray: &Ray, hit: &Hit, direction: &Vector3<f64>) -> Color {
+        match self {
+            Material::Diffuse { albedo } => {
+                albedo * (hit.normal.dot(direction).max(0.0) / std::f64::consts::PI)
+            }
+            Material::Plastic { albedo, ir } => {
+                // only the light that passes through the coat reaches the diffuse base
+                let transmitted = 1.0 - coat_reflectance(ray, hit, *ir);
+                albedo * (transmitted * hit.normal.dot(direction).max(0.0) / std::f64::consts::PI)
+            }
+            Material::Custom(bsdf) => bsdf.eval(ray, hit, direction),
+            Material::Metal { .. }
+            | Material::Dielectric { .. }
+            | Material::Light { .. }
+            | Material::Mix { .. }
+            | Material::Graph(_)
+            | Material::Override { .. }
+            | Material::Textured { .. } => Color::zeros(),
+        }
+    }
+
+    /// The medium inside objects made of this material.
+    pub fn medium(&self) -> Option<&Medium> {
+        match self {
+            Material::Dielectric { medium, .. } => medium.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Whether the material only reflects or refracts in a single direction.
+    pub fn is_specular(&self) -> bool {
+        matches!(self, Material::Metal { .. } | Material::Dielectric { .. })
+    }
+
+    /// The flat color of a surface of this material, for non-photoreal shading. Lights are scaled
+    /// to a brightest channel of one, and glass and plugin materials are drawn pale grey.
+    pub fn base_color(&self) -> Color {
+        match self {
+            Material::Diffuse { albedo }
+            | Material::Metal { albedo, .. }
+            | Material::Plastic { albedo, .. } => *albedo,
+            Material::Light { color } => color / color.max().max(1e-9),
+            Material::Dielectric { .. } | Material::Custom(_) => Color::repeat(0.9),
+            Material::Mix { .. }
+            | Material::Graph(_)
+            | Material::Override { .. }
+            | Material::Textured { .. } => {
+                unreachable!(
+                    "mixed, graph, overridden, and textured materials are resolved by the world"
+                )
+            }
         }
     }
 
-    pub fn emit(&self) -> Color {
+    /// The light given off by the surface at a hit, which paths gather wherever they strike it.
+    /// Together with the camera's background color for rays that escape the scene, this is all the
+    /// light there is, so a closed room is lit by its emissive surfaces alone.
+    pub fn emit(&self, hit: &Hit) -> Color {
         match self {
             Material::Diffuse { albedo } => Color::zeros(),
-            Material::Metal { albedo } => Color::zeros(),
-            Material::Dielectric { ir } => Color::zeros(),
+            Material::Metal { .. } => Color::zeros(),
+            Material::Dielectric { .. } => Color::zeros(),
+            Material::Plastic { albedo, ir } => Color::zeros(),
             Material::Light { color } => *color,
+            Material::Custom(bsdf) => bsdf.emit(hit),
+            Material::Mix { .. }
+            | Material::Graph(_)
+            | Material::Override { .. }
+            | Material::Textured { .. } => Color::zeros(),
         }
     }
 }
 
-fn scatter_diffuse(_ray: &Ray, hit: &Hit, albedo: &Color) -> Option<(Color, Ray)> {
+fn scatter_diffuse(ray: &Ray, hit: &Hit, albedo: &Color) -> Option<(Color, Ray)> {
     let mut scatter_direction = hit.normal + random_unit_vector();
 
     // If the scatter direction is too small, it can cause floating point issues
@@ -73,22 +318,48 @@ fn scatter_diffuse(_ray: &Ray, hit: &Hit, albedo: &Color) -> Option<(Color, Ray)
         Ray {
             origin: hit.p,
             direction: scatter_direction,
+            time: ray.time,
         },
     ))
 }
 
-fn scatter_metal(ray: &Ray, hit: &Hit, albedo: &Color) -> Option<(Color, Ray)> {
-    let reflected = reflect(&ray.direction.normalize(), &hit.normal);
+fn scatter_metal(
+    ray: &Ray,
+    hit: &Hit,
+    albedo: &Color,
+    ior: Option<&ComplexIor>,
+    fuzz: f64,
+    roughness: f64,
+) -> Option<(Color, Ray)> {
+    let unit_direction = ray.direction.normalize();
+    let mut reflected = reflect(&unit_direction, &hit.normal);
+    if fuzz > 0.0 {
+        reflected += fuzz * random_unit_vector();
+        // Unlike `roughen`, which absorbs them, fuzzed directions pushed below the surface are
+        // mirrored back above it, so that grazing reflections keep their energy.
+        let below = reflected.dot(&hit.normal);
+        if below < 0.0 {
+            reflected -= 2.0 * below * hit.normal;
+        }
+        reflected = reflected.normalize();
+    }
     let scattered = Ray {
         origin: hit.p,
-        direction: reflected,
+        direction: roughen(&reflected, &hit.normal, roughness)?,
+        time: ray.time,
+    };
+    let attenuation = match ior {
+        Some(ior) => {
+            let cos_theta = (-unit_direction).dot(&hit.normal).clamp(0.0, 1.0);
+            ior.reflectance(cos_theta).component_mul(albedo)
+        }
+        None => *albedo,
     };
-    let attenuation = *albedo;
 
     Some((attenuation, scattered))
 }
 
-fn scatter_dielectric(ray: &Ray, hit: &Hit, ir: f64) -> Option<(Color, Ray)> {
+fn scatter_dielectric(ray: &Ray, hit: &Hit, ir: f64, roughness: f64) -> Option<(Color, Ray)> {
     let attenuation = Vector3::new(1.0, 1.0, 1.0);
     let refraction_ratio = if hit.front_face { 1.0 / ir } else { ir };
 
@@ -99,7 +370,7 @@ fn scatter_dielectric(ray: &Ray, hit: &Hit, ir: f64) -> Option<(Color, Ray)> {
     let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
     let direction = if cannot_refract
-        || reflectance(cos_theta, refraction_ratio) > rand::thread_rng().gen_range(0.0..1.0)
+        || reflectance(cos_theta, refraction_ratio) > random::rng().gen_range(0.0..1.0)
     {
         reflect(&unit_direction, &hit.normal)
     } else {
@@ -108,12 +379,60 @@ fn scatter_dielectric(ray: &Ray, hit: &Hit, ir: f64) -> Option<(Color, Ray)> {
 
     let scattered = Ray {
         origin: hit.p,
-        direction,
+        direction: roughen(&direction, &hit.normal, roughness)?,
+        time: ray.time,
     };
 
     Some((attenuation, scattered))
 }
 
+fn scatter_plastic(
+    ray: &Ray,
+    hit: &Hit,
+    albedo: &Color,
+    ir: f64,
+    roughness: f64,
+) -> Option<(Color, Ray)> {
+    // Choose between the coat and the base in proportion to the coat's reflectance, so each path
+    // carries the full weight of the layer it took.
+    if coat_reflectance(ray, hit, ir) > random::rng().gen_range(0.0..1.0) {
+        let reflected = reflect(&ray.direction.normalize(), &hit.normal);
+        Some((
+            Color::repeat(1.0),
+            Ray {
+                origin: hit.p,
+                direction: roughen(&reflected, &hit.normal, roughness)?,
+                time: ray.time,
+            },
+        ))
+    } else {
+        scatter_diffuse(ray, hit, albedo)
+    }
+}
+
+/// The fraction of light reflected by a dielectric coat for a ray hitting it from outside.
+fn coat_reflectance(ray: &Ray, hit: &Hit, ir: f64) -> f64 {
+    let cos_theta = (-ray.direction.normalize())
+        .dot(&hit.normal)
+        .clamp(0.0, 1.0);
+    reflectance(cos_theta, 1.0 / ir)
+}
+
+/// Blur a reflected or refracted unit `direction` by offsetting it within a sphere of radius
+/// `roughness`. Directions pushed through to the other side of the surface are absorbed.
+fn roughen(
+    direction: &Vector3<f64>,
+    normal: &Vector3<f64>,
+    roughness: f64,
+) -> Option<Vector3<f64>> {
+    if roughness <= 0.0 {
+        return Some(*direction);
+    }
+
+    let roughened = direction + roughness * random_unit_vector();
+    (roughened.dot(normal).signum() == direction.dot(normal).signum()).then_some(roughened)
+}
+
 /// Reflect a vector `v` along a normal `n`.
 fn reflect(v: &Vector3<f64>, n: &Vector3<f64>) -> Vector3<f64> {
     v - 2.0 * v.dot(n) * n
@@ -128,7 +447,7 @@ fn refract(uv: &Vector3<f64>, n: &Vector3<f64>, etai_over_etat: f64) -> Vector3<
 }
 
 /// Schlick's approximation of reflectance.
-fn reflectance(cosine: f64, refraction_ratio: f64) -> f64 {
+pub fn reflectance(cosine: f64, refraction_ratio: f64) -> f64 {
     let mut r0 = (1.0 - refraction_ratio) / (1.0 + refraction_ratio);
     r0 *= r0;
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
@@ -0,0 +1,147 @@
+//! Participating media filling the inside of closed dielectric objects.
+
+use nalgebra::Vector3;
+use rand::Rng;
+
+use crate::{
+    random,
+    render::{Color, Ray},
+};
+
+/// A homogeneous medium that absorbs and scatters light travelling through it.
+/// Coefficients are per unit distance, for each color channel.
+#[derive(Clone, Copy, Debug)]
+pub struct Medium {
+    pub absorption: Color,
+    pub scattering: Color,
+    /// The Henyey-Greenstein asymmetry parameter, from -1 (back-scattering) to 1
+    /// (forward-scattering).
+    pub anisotropy: f64,
+}
+
+/// What happens to a ray travelling through a medium.
+pub enum Interaction {
+    /// The ray scattered inside the medium, continuing as a new ray.
+    Scatter { weight: Color, scattered: Ray },
+    /// The ray reached the surface bounding the medium.
+    Surface { weight: Color },
+}
+
+impl Medium {
+    /// Follow `ray` through the medium until it either scatters or reaches the surface at `t_max`.
+    /// The returned weight accounts for the light lost on the way.
+    pub fn interact(&self, ray: &Ray, t_max: f64) -> Interaction {
+        let speed = ray.direction.magnitude();
+        let surface_distance = t_max * speed;
+        let transmittance =
+            |coefficient: &Color, distance: f64| coefficient.map(|c| (-c * distance).exp());
+
+        // Distances are sampled with the mean scattering coefficient, and the weight corrects
+        // for each channel's actual coefficient.
+        let density = self.scattering.mean();
+        if density <= 0.0 {
+            return Interaction::Surface {
+                weight: transmittance(&self.absorption, surface_distance),
+            };
+        }
+
+        let distance = -(1.0 - random::rng().gen_range(0.0..1.0f64)).ln() / density;
+        if distance < surface_distance {
+            let pdf = density * (-density * distance).exp();
+            let weight = self
+                .scattering
+                .component_mul(&transmittance(&self.scattering, distance))
+                .component_mul(&transmittance(&self.absorption, distance))
+                / pdf;
+            let direction = ray.direction / speed;
+
+            Interaction::Scatter {
+                weight,
+                scattered: Ray {
+                    origin: ray.at(distance / speed),
+                    direction: sample_henyey_greenstein(&direction, self.anisotropy),
+                    time: ray.time,
+                },
+            }
+        } else {
+            let probability = (-density * surface_distance).exp();
+            let weight = transmittance(&self.scattering, surface_distance)
+                .component_mul(&transmittance(&self.absorption, surface_distance))
+                / probability;
+
+            Interaction::Surface { weight }
+        }
+    }
+}
+
+/// Sample a new direction for light travelling along `direction` (a unit vector) according to the
+/// Henyey-Greenstein phase function.
+fn sample_henyey_greenstein(direction: &Vector3<f64>, g: f64) -> Vector3<f64> {
+    let mut rng = random::rng();
+    let u = rng.gen_range(0.0..1.0);
+
+    let cos_theta = if g.abs() < 1e-3 {
+        1.0 - 2.0 * u
+    } else {
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+        ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+    let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
+    let phi = rng.gen_range(0.0..std::f64::consts::TAU);
+
+    // an orthonormal basis around the direction
+    let helper = if direction.x.abs() > 0.9 {
+        Vector3::y()
+    } else {
+        Vector3::x()
+    };
+    let tangent = direction.cross(&helper).normalize();
+    let bitangent = direction.cross(&tangent);
+
+    (tangent * phi.cos() + bitangent * phi.sin()) * sin_theta + direction * cos_theta
+}
+
+/// Haze that fades distant surfaces toward a color, applied to camera rays only.
+#[derive(Clone, Copy, Debug)]
+pub struct Atmosphere {
+    pub color: Color,
+    /// The fog's density at height zero, per unit distance.
+    pub density: f64,
+    /// How quickly the density falls off with height; the density at height `y` is
+    /// `density * exp(-height_falloff * y)`.
+    pub height_falloff: f64,
+}
+
+impl Atmosphere {
+    /// Blend `color`, seen at `distance` along `ray` (infinite for the background), with the fog.
+    pub fn apply(&self, color: &Color, ray: &Ray, distance: f64) -> Color {
+        let transmittance = self.transmittance(ray, distance);
+        color * transmittance + self.color * (1.0 - transmittance)
+    }
+
+    /// The fraction of light that passes through the fog over `distance` along `ray`.
+    pub fn transmittance(&self, ray: &Ray, distance: f64) -> f64 {
+        if self.density <= 0.0 {
+            return 1.0;
+        }
+
+        let direction = ray.direction.normalize();
+        let origin_density = self.density * (-self.height_falloff * ray.origin.y).exp();
+
+        // the integral of the density along the ray
+        let rate = self.height_falloff * direction.y;
+        let optical_depth = if rate.abs() < 1e-8 {
+            origin_density * distance
+        } else if distance.is_infinite() {
+            if rate > 0.0 {
+                origin_density / rate
+            } else {
+                f64::INFINITY
+            }
+        } else {
+            origin_density * (1.0 - (-rate * distance).exp()) / rate
+        };
+
+        (-optical_depth).exp()
+    }
+}
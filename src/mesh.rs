@@ -0,0 +1,514 @@
+//! Triangle meshes, and reducing their detail for rendering.
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashSet},
+    ops::Range,
+    sync::Arc,
+};
+
+use nalgebra::{Matrix4, Vector3, Vector4};
+
+use crate::{
+    bvh::Bvh,
+    dispatch::multiversion,
+    object::{Aabb, Hit},
+    render::Ray,
+};
+
+/// A mesh of triangles that share vertices.
+#[derive(Clone, Debug, Default)]
+pub struct TriangleMesh {
+    pub positions: Vec<Vector3<f64>>,
+    /// The indices into `positions` of each triangle's corners, counter-clockwise when seen from
+    /// the front.
+    pub triangles: Vec<[usize; 3]>,
+}
+
+/// A triangle mesh arranged for rendering. Clones share its triangles, so that an object and the
+/// instances of it store them once.
+#[derive(Clone)]
+pub struct Mesh {
+    positions: Arc<[Vector3<f64>]>,
+    triangles: Arc<[[usize; 3]]>,
+    bvh: Arc<Bvh>,
+}
+
+impl Mesh {
+    pub fn new(mesh: TriangleMesh) -> Self {
+        let TriangleMesh {
+            positions,
+            mut triangles,
+        } = mesh;
+        let bvh = Bvh::build(&mut triangles, |triangle| {
+            Aabb::from_points(&triangle.map(|v| positions[v]))
+        });
+        Self {
+            positions: positions.into(),
+            triangles: triangles.into(),
+            bvh: Arc::new(bvh),
+        }
+    }
+
+    pub fn bvh(&self) -> &Bvh {
+        &self.bvh
+    }
+
+    /// The memory taken by the vertices, triangles, and hierarchy, which clones of the mesh share.
+    pub fn size_in_bytes(&self) -> usize {
+        self.positions.len() * size_of::<Vector3<f64>>()
+            + self.triangles.len() * size_of::<[usize; 3]>()
+            + self.bvh.size_in_bytes()
+    }
+
+    /// The address of the storage the mesh shares with its clones, to tell them apart from other
+    /// meshes.
+    pub fn storage_address(&self) -> usize {
+        Arc::as_ptr(&self.bvh) as usize
+    }
+
+    /// The ends of every edge of the mesh's triangles, each once.
+    pub fn edges(&self) -> Vec<[Vector3<f64>; 2]> {
+        let mut edges = self
+            .triangles
+            .iter()
+            .flat_map(|&[a, b, c]| [(a, b), (b, c), (c, a)])
+            .map(|(a, b)| (a.min(b), a.max(b)))
+            .collect::<Vec<_>>();
+        edges.sort_unstable();
+        edges.dedup();
+
+        edges
+            .into_iter()
+            .map(|(a, b)| [self.positions[a], self.positions[b]])
+            .collect()
+    }
+
+    /// The corners of each triangle, counter-clockwise when seen from the front.
+    pub fn triangles(&self) -> impl Iterator<Item = [Vector3<f64>; 3]> + '_ {
+        self.triangles
+            .iter()
+            .map(|triangle| triangle.map(|v| self.positions[v]))
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    /// The box enclosing the mesh, or `None` if it has no triangles.
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.bvh.bounds()
+    }
+
+    pub fn area(&self) -> f64 {
+        self.triangles
+            .iter()
+            .map(|triangle| {
+                let [a, b, c] = triangle.map(|v| self.positions[v]);
+                (b - a).cross(&(c - a)).magnitude() / 2.0
+            })
+            .sum()
+    }
+
+    /// Find the nearest triangle a ray hits within `ray_t`. Triangles are flat shaded, with their
+    /// front facing the side their corners run counter-clockwise on.
+    pub fn hit(&self, ray: &Ray, ray_t: Range<f64>) -> Option<Hit> {
+        hit_mesh(self, ray, ray_t)
+    }
+}
+
+multiversion! {
+    /// The body of `Mesh::hit`, which spends most of a render's time in large scenes.
+    fn hit_mesh(mesh: &Mesh, ray: &Ray, ray_t: Range<f64>) -> Option<Hit> {
+        mesh.bvh.hit(ray, ray_t, #[inline(always)] |i, ray_t| {
+            let [a, b, c] = mesh.triangles[i].map(|v| mesh.positions[v]);
+            hit_triangle(ray, ray_t, a, b, c)
+        })
+    }
+}
+
+/// Intersect a ray with a triangle (Möller and Trumbore's method).
+#[inline(always)]
+fn hit_triangle(
+    ray: &Ray,
+    ray_t: Range<f64>,
+    a: Vector3<f64>,
+    b: Vector3<f64>,
+    c: Vector3<f64>,
+) -> Option<Hit> {
+    let (ab, ac) = (b - a, c - a);
+    let p = ray.direction.cross(&ac);
+    let determinant = ab.dot(&p);
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+
+    let inverse = 1.0 / determinant;
+    let offset = ray.origin - a;
+    let u = offset.dot(&p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = offset.cross(&ab);
+    let v = ray.direction.dot(&q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = ac.dot(&q) * inverse;
+    if !ray_t.contains(&t) {
+        return None;
+    }
+    let outward_normal = ab.cross(&ac).normalize();
+    Some(Hit::new(ray, t, &outward_normal))
+}
+
+/// How far `TriangleMesh::simplify` may reduce a mesh. It stops at whichever limit is reached
+/// first, and does nothing if neither is set.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SimplifyLimits {
+    /// Stop once the mesh has at most this many triangles.
+    pub target_triangles: Option<usize>,
+    /// Stop before the surface moves further than about this distance from the original. Use
+    /// `render::Camera::pixel_size` to derive it from an error in pixels.
+    pub max_error: Option<f64>,
+}
+
+/// Merging the vertex `from` into `into` at `position`, which would move the surface by about the
+/// square root of `cost`.
+struct Collapse {
+    cost: f64,
+    into: usize,
+    from: usize,
+    position: Vector3<f64>,
+    /// The vertices' versions when the collapse was found; it is stale if either has changed.
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    // reversed, so that the heap yields the cheapest collapse first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+impl TriangleMesh {
+    /// Read the faces of a Wavefront OBJ file, fanning polygons with more than three corners into
+    /// triangles. Texture coordinates, normals, groups, and materials are ignored.
+    pub fn parse_obj(text: &str) -> anyhow::Result<TriangleMesh> {
+        let mut mesh = TriangleMesh::default();
+        for (n, line) in text.lines().enumerate() {
+            let line_number = n + 1;
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("v") => {
+                    let mut coordinate = || -> anyhow::Result<f64> {
+                        let field = fields.next().ok_or_else(|| {
+                            anyhow::anyhow!("line {line_number}: a vertex needs 3 coordinates")
+                        })?;
+                        field.parse().map_err(|_| {
+                            anyhow::anyhow!("line {line_number}: `{field}` is not a number")
+                        })
+                    };
+                    let position = Vector3::new(coordinate()?, coordinate()?, coordinate()?);
+                    mesh.positions.push(position);
+                }
+                Some("f") => {
+                    let corners = fields
+                        .map(|field| {
+                            // `v`, `v/vt`, `v//vn`, or `v/vt/vn`; only the position is used
+                            let index = field.split('/').next().unwrap_or_default();
+                            let index: i64 = index.parse().map_err(|_| {
+                                anyhow::anyhow!("line {line_number}: `{field}` is not a vertex")
+                            })?;
+                            // Negative indices count back from the last vertex read.
+                            let count = mesh.positions.len() as i64;
+                            let index = if index < 0 { count + index } else { index - 1 };
+                            if !(0..count).contains(&index) {
+                                anyhow::bail!(
+                                    "line {line_number}: vertex {field} has not been defined"
+                                );
+                            }
+                            Ok(index as usize)
+                        })
+                        .collect::<anyhow::Result<Vec<_>>>()?;
+                    if corners.len() < 3 {
+                        anyhow::bail!("line {line_number}: a face needs at least 3 vertices");
+                    }
+                    for i in 1..corners.len() - 1 {
+                        mesh.triangles
+                            .push([corners[0], corners[i], corners[i + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if mesh.triangles.is_empty() {
+            anyhow::bail!("there are no faces");
+        }
+        Ok(mesh)
+    }
+
+    /// Revolve a profile around the Y axis in `segments` steps, like turning it on a lathe. Each
+    /// point of the profile is a radius and a height. The surface faces away from the axis where
+    /// the profile runs upwards and towards it where it runs down, so a profile that goes up the
+    /// outside of a glass and back down the inside makes a solid wall.
+    pub fn lathe(profile: &[(f64, f64)], segments: usize) -> TriangleMesh {
+        let mut mesh = TriangleMesh::default();
+
+        // points on the axis are shared by their whole ring
+        let rings = profile
+            .iter()
+            .map(|&(radius, height)| {
+                let first = mesh.positions.len();
+                if radius == 0.0 {
+                    mesh.positions.push(Vector3::new(0.0, height, 0.0));
+                    return vec![first; segments];
+                }
+                for j in 0..segments {
+                    let angle = std::f64::consts::TAU * j as f64 / segments as f64;
+                    mesh.positions.push(Vector3::new(
+                        radius * angle.sin(),
+                        height,
+                        radius * angle.cos(),
+                    ));
+                }
+                (first..first + segments).collect()
+            })
+            .collect::<Vec<_>>();
+
+        for rings in rings.windows(2) {
+            let (below, above) = (&rings[0], &rings[1]);
+            for j in 0..segments {
+                let next = (j + 1) % segments;
+                for triangle in [
+                    [below[j], below[next], above[next]],
+                    [below[j], above[next], above[j]],
+                ] {
+                    if triangle[0] != triangle[1]
+                        && triangle[1] != triangle[2]
+                        && triangle[2] != triangle[0]
+                    {
+                        mesh.triangles.push(triangle);
+                    }
+                }
+            }
+        }
+
+        mesh
+    }
+
+    /// Reduce the number of triangles by repeatedly merging the two ends of the edge whose removal
+    /// changes the shape least, measured by the distance to the planes of the triangles each
+    /// vertex has absorbed (Garland and Heckbert's quadric error metric).
+    pub fn simplify(&self, limits: SimplifyLimits) -> TriangleMesh {
+        if limits.target_triangles.is_none() && limits.max_error.is_none() {
+            return self.clone();
+        }
+        let target = limits.target_triangles.unwrap_or(0);
+        let max_cost = limits
+            .max_error
+            .map_or(f64::INFINITY, |error| error * error);
+
+        let mut positions = self.positions.clone();
+        let mut triangles = self.triangles.iter().copied().map(Some).collect::<Vec<_>>();
+        let mut quadrics = vec![Matrix4::zeros(); positions.len()];
+        let mut vertex_triangles = vec![Vec::new(); positions.len()];
+        for (t, triangle) in self.triangles.iter().enumerate() {
+            let quadric = plane_quadric(triangle.map(|v| positions[v]));
+            for &v in triangle {
+                quadrics[v] += quadric;
+                vertex_triangles[v].push(t);
+            }
+        }
+
+        let mut versions = vec![0u32; positions.len()];
+        let mut removed = vec![false; positions.len()];
+        let mut collapses = BinaryHeap::new();
+        let mut edges = HashSet::new();
+        for triangle in &self.triangles {
+            for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+                let (a, b) = (triangle[a], triangle[b]);
+                if edges.insert((a.min(b), a.max(b))) {
+                    collapses.push(find_collapse(a, b, &positions, &quadrics, &versions));
+                }
+            }
+        }
+
+        let mut live = triangles.iter().flatten().count();
+        while live > target {
+            let Some(collapse) = collapses.pop() else {
+                break;
+            };
+            let Collapse {
+                into, from, cost, ..
+            } = collapse;
+            if removed[into]
+                || removed[from]
+                || collapse.versions != (versions[into], versions[from])
+            {
+                continue;
+            }
+            if cost > max_cost {
+                break;
+            }
+            if flips(&collapse, &positions, &triangles, &vertex_triangles) {
+                continue;
+            }
+
+            positions[into] = collapse.position;
+            quadrics[into] = quadrics[into] + quadrics[from];
+            removed[from] = true;
+            versions[into] += 1;
+
+            for t in std::mem::take(&mut vertex_triangles[from]) {
+                let Some(triangle) = &mut triangles[t] else {
+                    continue;
+                };
+                for v in triangle.iter_mut() {
+                    if *v == from {
+                        *v = into;
+                    }
+                }
+                if triangle[0] == triangle[1]
+                    || triangle[1] == triangle[2]
+                    || triangle[2] == triangle[0]
+                {
+                    triangles[t] = None;
+                    live -= 1;
+                } else {
+                    vertex_triangles[into].push(t);
+                }
+            }
+            vertex_triangles[into].retain(|&t| triangles[t].is_some());
+            vertex_triangles[into].sort_unstable();
+            vertex_triangles[into].dedup();
+
+            let neighbors = vertex_triangles[into]
+                .iter()
+                .filter_map(|&t| triangles[t])
+                .flatten()
+                .filter(|&v| v != into)
+                .collect::<HashSet<_>>();
+            for neighbor in neighbors {
+                versions[neighbor] += 1;
+                collapses.push(find_collapse(
+                    into, neighbor, &positions, &quadrics, &versions,
+                ));
+            }
+        }
+
+        // keep only the vertices still in use, renumbered in their original order
+        let mut remap = vec![usize::MAX; positions.len()];
+        let mut mesh = TriangleMesh::default();
+        for triangle in triangles.into_iter().flatten() {
+            mesh.triangles.push(triangle.map(|v| {
+                if remap[v] == usize::MAX {
+                    remap[v] = mesh.positions.len();
+                    mesh.positions.push(positions[v]);
+                }
+                remap[v]
+            }));
+        }
+
+        mesh
+    }
+}
+
+/// The quadric measuring the squared distance to the plane of a triangle.
+fn plane_quadric([a, b, c]: [Vector3<f64>; 3]) -> Matrix4<f64> {
+    let Some(normal) = (b - a).cross(&(c - a)).try_normalize(1e-12) else {
+        return Matrix4::zeros();
+    };
+    let plane = Vector4::new(normal.x, normal.y, normal.z, -normal.dot(&a));
+    plane * plane.transpose()
+}
+
+/// The squared distance of `p` from the planes summed into `quadric`.
+fn quadric_error(quadric: &Matrix4<f64>, p: &Vector3<f64>) -> f64 {
+    let p = p.push(1.0);
+    (p.transpose() * quadric * p)[0]
+}
+
+/// Find the cheapest position to merge vertices `a` and `b` at.
+fn find_collapse(
+    a: usize,
+    b: usize,
+    positions: &[Vector3<f64>],
+    quadrics: &[Matrix4<f64>],
+    versions: &[u32],
+) -> Collapse {
+    let quadric = quadrics[a] + quadrics[b];
+
+    // The error is a quadratic function of the position, minimized where its gradient is zero,
+    // unless the planes are parallel and there is a line or plane of equally good positions.
+    let mut gradient = quadric;
+    gradient.set_row(3, &Vector4::new(0.0, 0.0, 0.0, 1.0).transpose());
+    let optimal = gradient
+        .try_inverse()
+        .map(|inverse| (inverse * Vector4::w()).xyz())
+        .filter(|p| p.iter().all(|c| c.is_finite()));
+
+    let midpoint = (positions[a] + positions[b]) / 2.0;
+    let (cost, position) = optimal
+        .into_iter()
+        .chain([positions[a], positions[b], midpoint])
+        .map(|p| (quadric_error(&quadric, &p).max(0.0), p))
+        .min_by(|(x, _), (y, _)| x.total_cmp(y))
+        .expect("there are always candidates");
+
+    Collapse {
+        cost,
+        into: a,
+        from: b,
+        position,
+        versions: (versions[a], versions[b]),
+    }
+}
+
+/// Whether a collapse would turn any remaining triangle around its vertices over.
+fn flips(
+    collapse: &Collapse,
+    positions: &[Vector3<f64>],
+    triangles: &[Option<[usize; 3]>],
+    vertex_triangles: &[Vec<usize>],
+) -> bool {
+    let moved = |v: usize| {
+        if v == collapse.into || v == collapse.from {
+            collapse.position
+        } else {
+            positions[v]
+        }
+    };
+
+    [collapse.into, collapse.from]
+        .iter()
+        .flat_map(|&v| &vertex_triangles[v])
+        .filter_map(|&t| triangles[t])
+        // triangles on the collapsed edge disappear rather than flip
+        .filter(|triangle| {
+            !(triangle.contains(&collapse.into) && triangle.contains(&collapse.from))
+        })
+        .any(|triangle| {
+            let [a, b, c] = triangle.map(|v| positions[v]);
+            let before = (b - a).cross(&(c - a));
+            let [a, b, c] = triangle.map(moved);
+            let after = (b - a).cross(&(c - a));
+            before.dot(&after) <= 0.0
+        })
+}
@@ -0,0 +1,143 @@
+//! Metaballs: blobby surfaces where the summed fields of several balls reach a threshold, so that
+//! nearby balls melt into each other.
+
+use std::ops::Range;
+
+use nalgebra::Vector3;
+
+use crate::{
+    object::{Aabb, Hit},
+    render::Ray,
+};
+
+/// The number of steps a ray takes across the smallest ball it passes through while searching for
+/// the surface. Features much thinner than this are missed.
+const STEPS_PER_BALL: f64 = 16.0;
+
+/// The number of times the step containing the surface is halved to find it precisely.
+const BISECTIONS: u32 = 24;
+
+/// A ball contributing to the field.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metaball {
+    pub center: Vector3<f64>,
+    /// The distance beyond which the ball contributes nothing.
+    pub radius: f64,
+    /// The field at the center, which falls smoothly to zero at the radius. Negative strengths
+    /// carve into other balls.
+    pub strength: f64,
+}
+
+impl Metaball {
+    /// The ball's contribution to the field at `p`, and its gradient.
+    fn field(&self, p: &Vector3<f64>) -> (f64, Vector3<f64>) {
+        let offset = p - self.center;
+        let falloff = 1.0 - offset.magnitude_squared() / self.radius.powi(2);
+        if falloff <= 0.0 {
+            return (0.0, Vector3::zeros());
+        }
+
+        let value = self.strength * falloff.powi(3);
+        let gradient = offset * (-6.0 * self.strength * falloff.powi(2) / self.radius.powi(2));
+        (value, gradient)
+    }
+}
+
+/// The surface where the summed field of some balls equals a threshold.
+#[derive(Clone, Debug)]
+pub struct Metaballs {
+    pub balls: Vec<Metaball>,
+    /// The field at the surface; inside, the field is greater.
+    pub threshold: f64,
+}
+
+impl Metaballs {
+    /// The field at `p`, and its gradient.
+    pub fn field(&self, p: &Vector3<f64>) -> (f64, Vector3<f64>) {
+        self.balls
+            .iter()
+            .map(|ball| ball.field(p))
+            .fold((0.0, Vector3::zeros()), |(value, gradient), (v, g)| {
+                (value + v, gradient + g)
+            })
+    }
+
+    /// The box enclosing every ball, or `None` if there are none.
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.balls
+            .iter()
+            .map(|ball| {
+                let extent = Vector3::repeat(ball.radius);
+                Aabb {
+                    min: ball.center - extent,
+                    max: ball.center + extent,
+                }
+            })
+            .reduce(|a, b| a.union(&b))
+    }
+
+    /// Find where a ray first crosses the surface within `ray_t`, by marching along the part of it
+    /// inside the balls and then bisecting the step where the field crosses the threshold.
+    pub fn hit(&self, ray: &Ray, ray_t: Range<f64>) -> Option<Hit> {
+        // the field is zero outside every ball, so only the span through them is searched
+        let mut span = f64::INFINITY..f64::NEG_INFINITY;
+        let mut smallest = f64::INFINITY;
+        for ball in &self.balls {
+            let Some(entry_exit) = sphere_span(ray, &ball.center, ball.radius) else {
+                continue;
+            };
+            span.start = span.start.min(entry_exit.start);
+            span.end = span.end.max(entry_exit.end);
+            smallest = smallest.min(ball.radius);
+        }
+        let start = span.start.max(ray_t.start);
+        let end = span.end.min(ray_t.end);
+        if start >= end {
+            return None;
+        }
+
+        let step = smallest / STEPS_PER_BALL / ray.direction.magnitude();
+        let level = |t: f64| self.field(&ray.at(t)).0 - self.threshold;
+        let inside = level(start) > 0.0;
+
+        let mut t0 = start;
+        while t0 < end {
+            let t1 = (t0 + step).min(end);
+            if (level(t1) > 0.0) != inside {
+                let (mut low, mut high) = (t0, t1);
+                for _ in 0..BISECTIONS {
+                    let middle = (low + high) / 2.0;
+                    if (level(middle) > 0.0) == inside {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+
+                let t = high;
+                let (_, gradient) = self.field(&ray.at(t));
+                // the field falls moving out of the surface
+                let outward_normal = (-gradient).try_normalize(1e-12)?;
+                return Some(Hit::new(ray, t, &outward_normal));
+            }
+            t0 = t1;
+        }
+
+        None
+    }
+}
+
+/// The times at which a ray enters and leaves a sphere, if it meets it.
+fn sphere_span(ray: &Ray, center: &Vector3<f64>, radius: f64) -> Option<Range<f64>> {
+    let oc = ray.origin - center;
+    let a = ray.direction.magnitude_squared();
+    let half_b = oc.dot(&ray.direction);
+    let c = oc.magnitude_squared() - radius.powi(2);
+    let discriminant = half_b.powi(2) - a * c;
+    if discriminant <= 0.0 {
+        return None;
+    }
+
+    let sqrt_d = discriminant.sqrt();
+    Some((-half_b - sqrt_d) / a..(-half_b + sqrt_d) / a)
+}
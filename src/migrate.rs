@@ -0,0 +1,87 @@
+//! Bringing scene files written for older versions of the scene format up to date.
+//!
+//! Each change to the format that would break existing files gets a migration from the version
+//! before it. Migrations edit the TOML document itself, so that comments and formatting survive
+//! when the migrated file is written back.
+
+use toml_edit::{Document, Item, TableLike};
+
+/// The version of the scene format read and written by this version of rtk.
+pub const VERSION: u32 = 2;
+
+/// The version of files that do not give one, which were written before the format had versions.
+const UNVERSIONED: u32 = 1;
+
+/// Upgrade a scene to `VERSION`, applying each migration between its version and the current one,
+/// and return a description of every change made besides setting the version. An up-to-date scene
+/// is left untouched.
+pub fn migrate(scene: &mut Document) -> anyhow::Result<Vec<String>> {
+    let version = match scene.get("version") {
+        Some(item) => item
+            .as_integer()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| anyhow::anyhow!("the scene's version must be a positive integer"))?,
+        None => UNVERSIONED,
+    };
+    if version > VERSION {
+        anyhow::bail!(
+            "the scene is version {version} of the scene format, but this version of rtk only \
+             reads up to version {VERSION}"
+        );
+    }
+    if version == VERSION {
+        return Ok(Vec::new());
+    }
+
+    let mut changes = Vec::new();
+    if version < 2 {
+        kebab_case_defocus(scene, &mut changes);
+    }
+
+    scene["version"] = toml_edit::value(i64::from(VERSION));
+    Ok(changes)
+}
+
+/// Version 2 spells the defocus fields in kebab case, like every other field.
+fn kebab_case_defocus(scene: &mut Document, changes: &mut Vec<String>) {
+    rename_defocus("camera", scene.get_mut("camera"), changes);
+    if let Some(views) = scene.get_mut("cameras").and_then(Item::as_table_like_mut) {
+        for (name, view) in views.iter_mut() {
+            rename_defocus(&format!("cameras.{}", name.get()), Some(view), changes);
+        }
+    }
+}
+
+fn rename_defocus(path: &str, camera: Option<&mut Item>, changes: &mut Vec<String>) {
+    let Some(defocus) = camera
+        .and_then(Item::as_table_like_mut)
+        .and_then(|camera| camera.get_mut("defocus"))
+        .and_then(Item::as_table_like_mut)
+    else {
+        return;
+    };
+
+    for (from, to) in [
+        ("focus_distance", "focus-distance"),
+        ("defocus_angle", "defocus-angle"),
+    ] {
+        if rename(defocus, from, to) {
+            changes.push(format!("renamed `{path}.defocus.{from}` to `{to}`"));
+        }
+    }
+}
+
+/// Rename the key `from` in `table` to `to`, keeping the comments and spacing around it, and
+/// return whether it was there.
+fn rename(table: &mut dyn TableLike, from: &str, to: &str) -> bool {
+    let decor = table.key_decor(from).cloned();
+    let Some(item) = table.remove(from) else {
+        return false;
+    };
+
+    table.insert(to, item);
+    if let (Some(decor), Some(new_decor)) = (decor, table.key_decor_mut(to)) {
+        *new_decor = decor;
+    }
+    true
+}
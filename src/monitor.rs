@@ -0,0 +1,260 @@
+//! Serves the progress of a render over HTTP, with a preview of its finished tiles, so that a
+//! render on a headless server can be watched from a browser.
+//!
+//! `/` is a page that polls `/progress`, a JSON summary, and reloads `/preview.jpg`, a small
+//! image of the tiles finished so far.
+
+use std::{
+    collections::HashSet,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use image::GenericImage;
+use rtk::render::ProgressEvent;
+
+use crate::{print_warning, status::ProgressDisplay};
+
+/// The longest side of the preview image, in pixels.
+const PREVIEW_SIZE: u32 = 512;
+
+/// How long a client may take to send its request before the connection is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>rtk</title>
+<style>
+body { font-family: sans-serif; background: #222; color: #ddd; text-align: center; }
+img { max-width: 90vw; image-rendering: pixelated; }
+progress { width: 512px; max-width: 90vw; }
+</style>
+</head>
+<body>
+<p id="status">Connecting...</p>
+<progress id="bar" max="100" value="0"></progress>
+<p><img id="preview" src="preview.jpg"></p>
+<script>
+async function poll() {
+  try {
+    const progress = await (await fetch("progress")).json();
+    const pass = progress.pass > 1 ? `pass ${progress.pass}, ` : "";
+    document.getElementById("status").textContent =
+      `${progress.status} (${pass}${progress.tiles}/${progress.total_tiles} tiles, ` +
+      `${Math.round(progress.elapsed_seconds)} s)`;
+    document.getElementById("bar").value = progress.percent;
+    document.getElementById("preview").src =
+      `preview.jpg?pass=${progress.pass}&tiles=${progress.tiles}`;
+    if (progress.done) return;
+  } catch (e) {
+    document.getElementById("status").textContent = "Disconnected";
+  }
+  setTimeout(poll, 1000);
+}
+poll();
+</script>
+</body>
+</html>
+"#;
+
+/// What the renderer has done so far, shared with the server's threads.
+struct State {
+    status: String,
+    percent: u32,
+    /// The pass over the image, counting from 1, as a progressive render reports every tile in
+    /// each pass.
+    pass: u32,
+    /// The tiles finished in this pass, by column and row.
+    pass_tiles: HashSet<(u32, u32)>,
+    total_tiles: u32,
+    samples: u64,
+    start: Instant,
+    /// Whether the render has ended, successfully or not.
+    done: bool,
+    /// The display colors of the finished tiles, at full size.
+    image: image::RgbImage,
+}
+
+/// Serves progress over HTTP until it is dropped.
+pub struct Monitor {
+    state: Arc<Mutex<State>>,
+}
+
+impl Monitor {
+    /// Start serving on `address` in the background, for a render of `width` by `height` pixels
+    /// split into `columns` by `rows` tiles.
+    pub fn listen(
+        address: &str,
+        (width, height): (u32, u32),
+        (columns, rows): (u32, u32),
+    ) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(address)
+            .map_err(|e| anyhow::anyhow!("failed to listen on {address}: {e}"))?;
+        let state = Arc::new(Mutex::new(State {
+            status: "Starting".to_string(),
+            percent: 0,
+            pass: 1,
+            pass_tiles: HashSet::new(),
+            total_tiles: columns * rows,
+            samples: 0,
+            start: Instant::now(),
+            done: false,
+            image: image::RgbImage::new(width, height),
+        }));
+        eprintln!("Serving progress at http://{}", listener.local_addr()?);
+
+        let shared = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = Arc::clone(&shared);
+                thread::spawn(move || match respond(stream, &state) {
+                    Err(e) if !is_disconnect(&e) => {
+                        print_warning(&format!("failed to serve progress: {e}"));
+                    }
+                    _ => {}
+                });
+            }
+        });
+
+        Ok(Self { state })
+    }
+}
+
+impl ProgressDisplay for Monitor {
+    fn update(&mut self, progress: &ProgressEvent) {
+        let mut state = self.state.lock().unwrap();
+        match progress {
+            ProgressEvent::Stage(stage) => state.status = stage.to_string(),
+            ProgressEvent::Rendering(percent) => state.percent = *percent,
+            ProgressEvent::TileFinished {
+                column,
+                row,
+                x,
+                y,
+                pixels,
+                samples,
+                ..
+            } => {
+                // A tile finished again belongs to the next pass.
+                if !state.pass_tiles.insert((*column, *row)) {
+                    state.pass += 1;
+                    state.pass_tiles.clear();
+                    state.pass_tiles.insert((*column, *row));
+                }
+                state.samples += samples;
+                // Tiles are always within the image, so this cannot fail.
+                _ = state.image.copy_from(pixels, *x, *y);
+            }
+            ProgressEvent::Finished => {
+                state.percent = 100;
+                state.status = "Finished".to_string();
+            }
+            ProgressEvent::Failed(message) => {
+                state.status = format!("Failed: {message}");
+                state.done = true;
+            }
+            ProgressEvent::Cancelled => {
+                state.status = "Cancelled".to_string();
+                state.done = true;
+            }
+            ProgressEvent::Samples(_) => {}
+        }
+    }
+
+    fn finish(&mut self, message: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.status = message.to_string();
+        state.done = true;
+    }
+}
+
+/// Whether serving a request failed only because the client went away, or never sent it.
+fn is_disconnect(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<io::Error>().is_some_and(|e| {
+        matches!(
+            e.kind(),
+            io::ErrorKind::BrokenPipe
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::WouldBlock
+                | io::ErrorKind::TimedOut
+        )
+    })
+}
+
+/// Answer one request on `stream`.
+fn respond(stream: TcpStream, state: &Mutex<State>) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // The headers are read and ignored.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next(), parts.next().unwrap_or("/"));
+    let path = target.split('?').next().unwrap_or(target);
+    let (status, content_type, body) = match (method, path) {
+        (Some("GET"), "/") => ("200 OK", "text/html", PAGE.as_bytes().to_vec()),
+        (Some("GET"), "/progress") => ("200 OK", "application/json", progress_json(state)),
+        (Some("GET"), "/preview.jpg") => ("200 OK", "image/jpeg", preview_jpeg(state)?),
+        (Some("GET"), _) => ("404 Not Found", "text/plain", b"not found".to_vec()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            b"only GET is allowed".to_vec(),
+        ),
+    };
+
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-store\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    Ok(())
+}
+
+fn progress_json(state: &Mutex<State>) -> Vec<u8> {
+    let state = state.lock().unwrap();
+    serde_json::json!({
+        "status": state.status,
+        "percent": state.percent,
+        "pass": state.pass,
+        "tiles": state.pass_tiles.len(),
+        "total_tiles": state.total_tiles,
+        "samples": state.samples,
+        "elapsed_seconds": state.start.elapsed().as_secs_f64(),
+        "done": state.done,
+    })
+    .to_string()
+    .into_bytes()
+}
+
+/// The finished tiles, shrunk to fit in `PREVIEW_SIZE` pixels.
+fn preview_jpeg(state: &Mutex<State>) -> anyhow::Result<Vec<u8>> {
+    let preview = {
+        let state = state.lock().unwrap();
+        let (width, height) = state.image.dimensions();
+        let scale = (PREVIEW_SIZE as f64 / width.max(height) as f64).min(1.0);
+        image::imageops::thumbnail(
+            &state.image,
+            ((width as f64 * scale) as u32).max(1),
+            ((height as f64 * scale) as u32).max(1),
+        )
+    };
+
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 85).encode_image(&preview)?;
+    Ok(jpeg)
+}
@@ -0,0 +1,87 @@
+//! Reports the outcome of a render to the desktop or to a webhook, for renders that run long
+//! enough to be left unattended.
+
+use std::time::Duration;
+
+use rtk::render::Camera;
+use serde::Serialize;
+
+use crate::{cli::RenderArgs, status::format_duration};
+
+/// The JSON body posted to webhooks, also used to word desktop notifications.
+#[derive(Clone, Debug, Serialize)]
+pub struct RenderReport {
+    pub scene: String,
+    pub output: String,
+    pub succeeded: bool,
+    /// Why the render failed, if it did.
+    pub error: Option<String>,
+    pub duration_seconds: f64,
+    /// `None` if the scene could not be loaded.
+    pub image_dimensions: Option<(u32, u32)>,
+    pub samples_per_pixel: Option<u32>,
+}
+
+impl RenderReport {
+    pub fn new(args: &RenderArgs, duration: Duration, result: &anyhow::Result<Camera>) -> Self {
+        let camera = result.as_ref().ok();
+
+        Self {
+            scene: args.source().display().to_string(),
+            output: args.output.display().to_string(),
+            succeeded: result.is_ok(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            duration_seconds: duration.as_secs_f64(),
+            image_dimensions: camera.map(|c| (c.image_width, c.image_height)),
+            samples_per_pixel: camera.map(|c| c.samples_per_pixel),
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        if self.succeeded {
+            "Render finished"
+        } else {
+            "Render failed"
+        }
+    }
+
+    /// A one-line, human-readable description of the outcome.
+    fn summary(&self) -> String {
+        let duration = format_duration(Duration::from_secs_f64(self.duration_seconds));
+        match (&self.error, self.image_dimensions) {
+            (Some(error), _) => format!("{} failed after {duration}: {error}", self.scene),
+            (None, Some((width, height))) => format!(
+                "{} rendered to {} ({width}x{height}, {} spp) in {duration}",
+                self.scene,
+                self.output,
+                self.samples_per_pixel.unwrap_or_default(),
+            ),
+            (None, None) => format!("{} rendered to {} in {duration}", self.scene, self.output),
+        }
+    }
+}
+
+pub fn notify_desktop(report: &RenderReport) -> anyhow::Result<()> {
+    notify_rust::Notification::new()
+        .appname("rtk")
+        .summary(report.title())
+        .body(&report.summary())
+        .show()?;
+
+    Ok(())
+}
+
+pub fn post_webhook(url: &str, report: &RenderReport) -> anyhow::Result<()> {
+    let summary = format!("{}: {}", report.title(), report.summary());
+    // Slack reads `text` and Discord reads `content`; other consumers can use the structured
+    // report.
+    let payload = serde_json::json!({
+        "text": summary,
+        "content": summary,
+        "render": report,
+    });
+
+    ureq::post(url).send_json(payload)?;
+
+    Ok(())
+}
@@ -1,30 +1,555 @@
-use std::ops::Range;
+use std::{borrow::Cow, ops::Range, sync::Arc};
 
-use nalgebra::{UnitQuaternion, Vector3};
+use nalgebra::{Matrix3, Matrix4, Point3, UnitQuaternion, Vector3};
+use rand::Rng;
 
-use crate::{material::Material, render::Ray};
+use crate::{
+    graph::ShadingContext,
+    light::Light,
+    material::{LightPaths, Material, MaterialOverrides},
+    medium::Atmosphere,
+    mesh::Mesh,
+    metaball::Metaballs,
+    plugin::Hittable,
+    points::PointCloud,
+    random::{self, AliasTable},
+    render::{random_vector_in_unit_disk, Color, Ray},
+    texture::Texture,
+};
+
+mod storage;
+
+pub use storage::Objects;
 
 pub struct World {
-    pub objects: Vec<Object>,
+    /// The primitives, stored by shape. Those of objects with clip planes are kept whole.
+    pub objects: Objects,
+    /// For each object, the index of the scene object it was created from; shapes like prisms are
+    /// made of several objects that share an ID.
+    pub object_ids: Vec<usize>,
+    /// Settings for each scene object, indexed by ID.
+    pub object_settings: Vec<ObjectSettings>,
     pub materials: Vec<Material>,
+    /// The light path rules of each material, indexed like `materials`. Materials past the end
+    /// have none.
+    pub light_paths: Vec<LightPaths>,
+    pub textures: Vec<Texture>,
+    pub lights: Vec<Light>,
+    /// Picks one of `lights` by its power at each hit, in scenes with too many lights to cast
+    /// a shadow ray toward every one; `None` if each is sampled.
+    pub light_picker: Option<AliasTable>,
+    pub atmosphere: Option<Atmosphere>,
+}
+
+/// Settings that apply to a whole scene object, rather than each primitive it is made of.
+#[derive(Clone, Debug, Default)]
+pub struct ObjectSettings {
+    /// The radius of the rounded edges simulated at shading time, or zero for sharp edges.
+    pub bevel_radius: f64,
+    /// Planes cutting away part of the object.
+    pub clip_planes: Vec<ClipPlane>,
+    /// The offsets from its position that the object moves through over the camera's exposure,
+    /// at even intervals, or none if it stays still.
+    pub motion: Vec<Vector3<f64>>,
+    /// Where the object's primitives are placed in the world, if not where they were made.
+    pub transform: Option<Transform>,
+    /// The distances from the camera at which the object can be seen, if not every distance.
+    pub visible_range: Option<VisibleRange>,
+    /// The rays that can hit the object, by their `ray_dither`, if not all of them, as for an
+    /// object hidden or fading out at its distance from the camera. `World::view_from` sets it
+    /// from `visible_range`.
+    pub seen_by: Option<Range<f64>>,
+}
+
+impl ObjectSettings {
+    /// The box that `bounds`, the box of the object's primitives as they were made, covers once
+    /// the object is placed by its transform and over all of its motion.
+    pub fn swept_bounds(&self, bounds: Aabb) -> Aabb {
+        let bounds = match &self.transform {
+            Some(transform) => transform.world_bounds(&bounds),
+            None => bounds,
+        };
+        // The object moves in straight lines between the offsets, so the boxes at them hold it.
+        self.motion
+            .iter()
+            .map(|offset| Aabb {
+                min: bounds.min + offset,
+                max: bounds.max + offset,
+            })
+            .fold(bounds, |a, b| a.union(&b))
+    }
+
+    /// How far the object has moved from its position at moment `time` of the exposure, from 0 to
+    /// 1, moving in a straight line between the offsets of its motion.
+    pub fn offset(&self, time: f64) -> Vector3<f64> {
+        match self.motion.len() {
+            0 => Vector3::zeros(),
+            1 => self.motion[0],
+            n => {
+                let position = time.clamp(0.0, 1.0) * (n - 1) as f64;
+                let i = (position as usize).min(n - 2);
+                self.motion[i].lerp(&self.motion[i + 1], position - i as f64)
+            }
+        }
+    }
+}
+
+/// The distances from the camera at which an object can be seen, so that a detailed object can be
+/// swapped for a simpler stand-in far away.
+///
+/// Over `fade` around either end of the range, except a `near` of zero, the object dissolves
+/// rather than being cut off sharply, seen by some rays and not others. Rays are picked by
+/// `ray_dither`, the same for every object, so an object fading out over the same distances
+/// another fades in over leaves no gap and no overlap between them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VisibleRange {
+    pub near: f64,
+    pub far: f64,
+    pub fade: f64,
+}
+
+impl VisibleRange {
+    /// The rays that see an object at `distance` from the camera, by their dither.
+    pub fn seen_by(&self, distance: f64) -> Range<f64> {
+        // the fraction of rays that see the object past each end, as it fades in or out
+        let beyond = |past: f64| {
+            if self.fade > 0.0 {
+                (past / self.fade + 0.5).clamp(0.0, 1.0)
+            } else if past >= 0.0 {
+                1.0
+            } else {
+                0.0
+            }
+        };
+        let faded_in = if self.near > 0.0 {
+            beyond(distance - self.near)
+        } else {
+            1.0
+        };
+        let faded_out = beyond(self.far - distance);
+
+        1.0 - faded_in..faded_out
+    }
+}
+
+/// A number from 0 to 1 that varies from ray to ray but is always the same for one ray.
+pub fn ray_dither(ray: &Ray) -> f64 {
+    let hash = ray.origin.iter().chain(ray.direction.iter()).fold(
+        0x9e3779b97f4a7c15_u64,
+        |hash, value| {
+            let hash = (hash ^ value.to_bits()).wrapping_mul(0xff51afd7ed558ccd);
+            hash ^ (hash >> 33)
+        },
+    );
+    (hash >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+/// The placement of an object's primitives in the world: scaled along each axis, then rotated,
+/// then moved.
+///
+/// Rays are brought into the primitives' own space to hit them, without normalizing their
+/// direction, so that the distance `t` along the ray is the same in both spaces.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transform {
+    to_world: Matrix4<f64>,
+    to_object: Matrix4<f64>,
+    /// The inverse transpose of the linear part of `to_world`, which keeps normals perpendicular
+    /// to surfaces that are scaled unevenly.
+    normal_to_world: Matrix3<f64>,
+}
+
+impl Transform {
+    /// Scale by `scale`, whose components must not be zero, then rotate by `rotation`, then move
+    /// by `translation`.
+    pub fn new(
+        translation: Vector3<f64>,
+        rotation: UnitQuaternion<f64>,
+        scale: Vector3<f64>,
+    ) -> Self {
+        let to_world = Matrix4::new_translation(&translation)
+            * rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&scale);
+        let to_object = Matrix4::new_nonuniform_scaling(&scale.map(|s| 1.0 / s))
+            * rotation.inverse().to_homogeneous()
+            * Matrix4::new_translation(&-translation);
+        let normal_to_world = to_object.fixed_view::<3, 3>(0, 0).transpose();
+
+        Self {
+            to_world,
+            to_object,
+            normal_to_world,
+        }
+    }
+
+    /// `ray` in the primitives' own space.
+    pub fn object_ray(&self, ray: &Ray) -> Ray {
+        Ray {
+            origin: self
+                .to_object
+                .transform_point(&Point3::from(ray.origin))
+                .coords,
+            direction: self.to_object.transform_vector(&ray.direction),
+            time: ray.time,
+        }
+    }
+
+    /// A hit made in the primitives' own space, brought back into the world.
+    pub fn world_hit(&self, hit: Hit) -> Hit {
+        Hit {
+            p: self.to_world.transform_point(&Point3::from(hit.p)).coords,
+            normal: (self.normal_to_world * hit.normal).normalize(),
+            ..hit
+        }
+    }
+
+    /// `point`, in the primitives' own space, placed in the world.
+    pub fn to_world(&self, point: &Vector3<f64>) -> Vector3<f64> {
+        self.to_world.transform_point(&Point3::from(*point)).coords
+    }
+
+    /// The factor by which the transform scales volumes. Areas grow by its two-thirds power when
+    /// the scale is the same along every axis.
+    pub fn volume_scale(&self) -> f64 {
+        self.to_world.fixed_view::<3, 3>(0, 0).determinant().abs()
+    }
+
+    /// The box around `bounds`, a box in the primitives' own space, once placed in the world.
+    pub fn world_bounds(&self, bounds: &Aabb) -> Aabb {
+        let corners = (0..8)
+            .map(|i| {
+                let corner = Vector3::from_fn(|axis, _| {
+                    if i & (1 << axis) == 0 {
+                        bounds.min[axis]
+                    } else {
+                        bounds.max[axis]
+                    }
+                });
+                self.to_world.transform_point(&Point3::from(corner)).coords
+            })
+            .collect::<Vec<_>>();
+        Aabb::from_points(&corners)
+    }
+}
+
+/// A plane that cuts away the part of an object on the side its normal points to, as in a cutaway
+/// view.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClipPlane {
+    pub point: Vector3<f64>,
+    /// The unit normal, pointing towards the side that is removed.
+    pub normal: Vector3<f64>,
+    /// The material to close the cut with where it passes through the inside of a closed object,
+    /// or `None` to leave the inside open to view.
+    pub cap: Option<usize>,
 }
 
 impl World {
+    /// The settings of the object struck by a hit.
+    pub fn object_settings(&self, hit: &Hit) -> &ObjectSettings {
+        &self.object_settings[self.object_ids[hit.object]]
+    }
+
+    /// The box enclosing the primitives of each scene object over all of its motion, by ID, as
+    /// given by `object_bounds`, found in one pass over the primitives.
+    pub fn bounds_by_object(&self) -> Vec<Option<Aabb>> {
+        let mut bounds = vec![None::<Aabb>; self.object_settings.len()];
+        for (object, &id) in self.objects.iter().zip(&self.object_ids) {
+            if let Some(object_bounds) = object.bounds() {
+                bounds[id] = Some(match bounds[id] {
+                    Some(bounds) => bounds.union(&object_bounds),
+                    None => object_bounds,
+                });
+            }
+        }
+        bounds
+            .into_iter()
+            .zip(&self.object_settings)
+            .map(|(bounds, settings)| Some(settings.swept_bounds(bounds?)))
+            .collect()
+    }
+
+    /// Show or hide the objects with visible ranges by the distance from a camera at `position`
+    /// to the centers of their bounds. Call it again whenever the camera moves.
+    pub fn view_from(&mut self, position: Vector3<f64>) {
+        if self
+            .object_settings
+            .iter()
+            .all(|settings| settings.visible_range.is_none())
+        {
+            return;
+        }
+        let bounds = self.bounds_by_object();
+        for (settings, bounds) in self.object_settings.iter_mut().zip(bounds) {
+            if let Some(range) = &settings.visible_range {
+                let distance =
+                    bounds.map_or(0.0, |bounds| (bounds.center() - position).magnitude());
+                settings.seen_by = Some(range.seen_by(distance));
+            }
+        }
+    }
+
+    /// Each primitive with the ID of the scene object it belongs to, looking through instances to
+    /// the primitives they share. The primitives are where they were made, before the object's
+    /// `transform` places them.
+    pub fn primitives(&self) -> Vec<(usize, Cow<'_, Object>)> {
+        let mut primitives = Vec::new();
+        for (object, &id) in self.objects.iter().zip(&self.object_ids) {
+            match object {
+                Cow::Borrowed(Object::Instance { geometry, .. }) => {
+                    primitives.extend(geometry.iter().map(|primitive| (id, primitive)));
+                }
+                object => primitives.push((id, object)),
+            }
+        }
+        primitives
+    }
+
+    /// The box enclosing the primitives made from scene object `id` over all of its motion, or
+    /// `None` if it has none, or only custom shapes that do not report their bounds.
+    pub fn object_bounds(&self, id: usize) -> Option<Aabb> {
+        let bounds = self
+            .object_ids
+            .iter()
+            .enumerate()
+            .filter(|&(_, &object_id)| object_id == id)
+            .filter_map(|(index, _)| self.objects.get(index)?.bounds())
+            .reduce(|a, b| a.union(&b))?;
+
+        Some(self.object_settings[id].swept_bounds(bounds))
+    }
+
+    /// Round off the edges of the struck object by blending the normals of nearby surfaces of the
+    /// same object into the hit's normal. Nearby surfaces are found with short probe rays cast
+    /// through a disk of `radius` around the hit, along its normal and tangent axes, at the moment
+    /// of `ray`, which made the hit.
+    pub fn bevel(&self, ray: &Ray, hit: &mut Hit, radius: f64) {
+        const PROBES: u32 = 8;
+
+        let id = self.object_ids[hit.object];
+        let outward = |hit: &Hit| {
+            if hit.front_face {
+                hit.normal
+            } else {
+                -hit.normal
+            }
+        };
+        let normal = outward(hit);
+
+        let helper = if normal.x.abs() > 0.9 {
+            Vector3::y()
+        } else {
+            Vector3::x()
+        };
+        let tangent = normal.cross(&helper).normalize();
+        let bitangent = normal.cross(&tangent);
+
+        let mut rng = random::rng();
+        let mut blended = normal;
+
+        for _ in 0..PROBES {
+            // probing along the normal finds nothing new on flat surfaces, so favour the tangents
+            let (axis, u, v) = match rng.gen_range(0..4) {
+                0 => (normal, tangent, bitangent),
+                1 | 2 => (tangent, bitangent, normal),
+                _ => (bitangent, normal, tangent),
+            };
+            let disk = random_vector_in_unit_disk() * radius;
+            let center = hit.p + u * disk.x + v * disk.y;
+
+            for direction in [axis, -axis] {
+                let probe = Ray {
+                    origin: center - direction * radius,
+                    direction,
+                    time: ray.time,
+                };
+                let Some(probe_hit) = self.hit(&probe, 0.0, 2.0 * radius) else {
+                    continue;
+                };
+                if self.object_ids[probe_hit.object] != id {
+                    continue;
+                }
+
+                // closer surfaces contribute more, fading out at the radius
+                let distance = (probe_hit.p - hit.p).magnitude();
+                let weight = (1.0 - distance / radius).max(0.0);
+                blended += outward(&probe_hit) * weight;
+            }
+        }
+
+        let Some(blended) = blended.try_normalize(1e-8) else {
+            return;
+        };
+        hit.normal = if hit.front_face { blended } else { -blended };
+    }
+
+    /// The light path rules of material `material`.
+    pub fn light_paths(&self, material: usize) -> LightPaths {
+        self.light_paths.get(material).copied().unwrap_or_default()
+    }
+
+    /// Get the material to shade a hit with. Mixed materials are resolved to one of their
+    /// components, chosen at random according to the mix weight, which averages out to the blend
+    /// over many samples. Graph materials are evaluated for the hit, overrides are applied to
+    /// whatever their base material resolves to, and textured materials take their albedo from
+    /// their texture at the hit. The hit's color, if any, tints the result.
+    pub fn material(&self, ray: &Ray, hit: &Hit) -> Cow<'_, Material> {
+        let material = self.resolve_material(hit.material, ray, hit);
+        match hit.color {
+            Some(color) => Cow::Owned(
+                MaterialOverrides {
+                    tint: Some(color),
+                    ..Default::default()
+                }
+                .apply(material.into_owned()),
+            ),
+            None => material,
+        }
+    }
+
+    fn resolve_material(&self, index: usize, ray: &Ray, hit: &Hit) -> Cow<'_, Material> {
+        let mut material = &self.materials[index];
+
+        loop {
+            match material {
+                Material::Mix { a, b, factor, mask } => {
+                    let weight = match mask {
+                        Some(mask) => self.textures[*mask].scalar(hit),
+                        None => *factor,
+                    };
+
+                    material = if random::rng().gen_range(0.0..1.0) < weight {
+                        &self.materials[*b]
+                    } else {
+                        &self.materials[*a]
+                    };
+                }
+                Material::Graph(graph) => {
+                    return Cow::Owned(graph.evaluate(&ShadingContext {
+                        world: self,
+                        ray,
+                        hit,
+                    }))
+                }
+                Material::Override { base, overrides } => {
+                    let base = self.resolve_material(*base, ray, hit).into_owned();
+                    return Cow::Owned(overrides.apply(base));
+                }
+                Material::Textured { base, texture } => {
+                    let overrides = MaterialOverrides {
+                        albedo: Some(self.textures[*texture].value(hit)),
+                        ..Default::default()
+                    };
+                    return Cow::Owned(overrides.apply((**base).clone()));
+                }
+                _ => return Cow::Borrowed(material),
+            }
+        }
+    }
+
+    #[inline(always)]
     pub fn hit(&self, ray: &Ray, ray_t_min: f64, ray_t_max: f64) -> Option<Hit> {
-        let mut hit = None;
-        let mut closest = ray_t_max;
+        self.objects.hit(
+            ray,
+            ray_t_min..ray_t_max,
+            #[inline(always)]
+            |index, object, ray_t| {
+                let settings = &self.object_settings[self.object_ids[index]];
+                if let Some(seen_by) = &settings.seen_by {
+                    if !seen_by.contains(&ray_dither(ray)) {
+                        return None;
+                    }
+                }
+                let hit_placed = |ray: &Ray, ray_t: Range<f64>| match &settings.transform {
+                    Some(transform) => object
+                        .hit(&transform.object_ray(ray), ray_t)
+                        .map(|hit| transform.world_hit(hit)),
+                    None => object.hit(ray, ray_t),
+                };
+                let hit = |ray: &Ray| {
+                    if settings.clip_planes.is_empty() {
+                        hit_placed(ray, ray_t)
+                    } else {
+                        hit_clipped(hit_placed, &settings.clip_planes, ray, ray_t)
+                    }
+                };
+                if settings.motion.is_empty() {
+                    return hit(ray);
+                }
 
-        for object in self.objects.iter() {
-            if let Some(new_hit) = object.hit(ray, ray_t_min..closest) {
-                closest = new_hit.t;
-                hit = Some(new_hit);
+                // A moving object is hit by moving the ray the other way.
+                let offset = settings.offset(ray.time);
+                let moved = Ray {
+                    origin: ray.origin - offset,
+                    direction: ray.direction,
+                    time: ray.time,
+                };
+                hit(&moved).map(|hit| Hit {
+                    p: hit.p + offset,
+                    ..hit
+                })
+            },
+        )
+    }
+}
+
+/// Find the nearest hit of a ray with the part of an object that clipping planes leave, or with
+/// the caps closing the cuts. `hit_object` finds the ray's nearest hit with the whole object
+/// within a range.
+fn hit_clipped(
+    hit_object: impl Fn(&Ray, Range<f64>) -> Option<Hit>,
+    planes: &[ClipPlane],
+    ray: &Ray,
+    ray_t: Range<f64>,
+) -> Option<Hit> {
+    // The part of the ray on the kept side of every plane is a single stretch, since that side is
+    // convex. `entry` is the plane the ray crosses to reach it, if it starts out cut away.
+    let mut kept = ray_t.clone();
+    let mut entry = None;
+    for plane in planes {
+        let distance = plane.normal.dot(&(ray.origin - plane.point));
+        let approach = plane.normal.dot(&ray.direction);
+        if approach == 0.0 {
+            if distance > 0.0 {
+                return None;
             }
+            continue;
         }
 
-        hit
+        let t = -distance / approach;
+        if approach < 0.0 {
+            if t > kept.start {
+                kept.start = t;
+                entry = Some(plane);
+            }
+        } else {
+            kept.end = kept.end.min(t);
+        }
+    }
+    if kept.start >= kept.end {
+        return None;
+    }
+
+    let hit = hit_object(ray, kept.clone());
+
+    // Seeing the back of a surface first means the ray entered the inside through the cut.
+    if let Some(ClipPlane {
+        normal,
+        cap: Some(cap),
+        ..
+    }) = entry
+    {
+        if hit.as_ref().is_some_and(|hit| !hit.front_face) {
+            return Some(Hit {
+                material: *cap,
+                ..Hit::new(ray, kept.start, normal)
+            });
+        }
     }
+
+    hit
 }
 
+#[derive(Clone)]
 pub enum Object {
     Sphere {
         center: Vector3<f64>,
@@ -42,6 +567,29 @@ pub enum Object {
         /// Data calculated from the other parameters.
         cached: QuadCached,
     },
+    /// A shape provided by a plugin.
+    Custom {
+        hittable: Arc<dyn Hittable>,
+        material: usize,
+    },
+    PointCloud {
+        cloud: Arc<PointCloud>,
+        material: usize,
+    },
+    Metaballs {
+        metaballs: Metaballs,
+        material: usize,
+    },
+    Mesh {
+        mesh: Mesh,
+        material: usize,
+    },
+    /// Primitives shared by several objects, each placed by its own transform, and shaded with
+    /// `material` in place of the primitives' own.
+    Instance {
+        geometry: Arc<Objects>,
+        material: usize,
+    },
 }
 
 impl Object {
@@ -86,10 +634,11 @@ impl Object {
         let true_origin = origin - (u / 2.0) - (w / 2.0);
         let opposite_true_origin = origin + (u / 2.0) + (w / 2.0) + v;
 
+        // the basis vectors of each face are ordered so that its normal points out of the prism
         let quads = vec![
-            Object::quad(true_origin, u, v, material),
-            Object::quad(true_origin, v, w, material),
-            Object::quad(true_origin, w, u, material),
+            Object::quad(true_origin, v, u, material),
+            Object::quad(true_origin, w, v, material),
+            Object::quad(true_origin, u, w, material),
             Object::quad(opposite_true_origin, -u, -v, material),
             Object::quad(opposite_true_origin, -v, -w, material),
             Object::quad(opposite_true_origin, -w, -u, material),
@@ -99,6 +648,135 @@ impl Object {
     }
 }
 
+/// An axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f64>,
+    pub max: Vector3<f64>,
+}
+
+impl Aabb {
+    /// The smallest box containing all of `points`, which must not be empty.
+    pub fn from_points(points: &[Vector3<f64>]) -> Self {
+        let mut aabb = Self {
+            min: points[0],
+            max: points[0],
+        };
+        for point in &points[1..] {
+            aabb.min = aabb.min.inf(point);
+            aabb.max = aabb.max.sup(point);
+        }
+
+        aabb
+    }
+
+    pub fn center(&self) -> Vector3<f64> {
+        (self.min + self.max) / 2.0
+    }
+
+    /// The smallest box containing both boxes.
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max),
+        }
+    }
+
+    /// Whether a ray passes through the box within `ray_t`.
+    #[inline(always)]
+    pub fn hit(&self, ray: &Ray, ray_t: Range<f64>) -> bool {
+        let (mut t_min, mut t_max) = (ray_t.start, ray_t.end);
+        for axis in 0..3 {
+            let inverse = 1.0 / ray.direction[axis];
+            let t0 = (self.min[axis] - ray.origin[axis]) * inverse;
+            let t1 = (self.max[axis] - ray.origin[axis]) * inverse;
+            let (t0, t1) = if inverse < 0.0 { (t1, t0) } else { (t0, t1) };
+            // written so that NaNs, from rays in the plane of a face, leave the range alone
+            if t0 > t_min {
+                t_min = t0;
+            }
+            if t1 < t_max {
+                t_max = t1;
+            }
+            if t_max < t_min {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Whether the boxes share any points, including on their faces.
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        (0..3).all(|axis| self.min[axis] <= other.max[axis] && other.min[axis] <= self.max[axis])
+    }
+}
+
+impl Object {
+    /// The box enclosing the object, or `None` for custom shapes that do not report one.
+    pub fn bounds(&self) -> Option<Aabb> {
+        match self {
+            Object::Sphere { center, radius, .. } => {
+                let extent = Vector3::repeat(radius.abs());
+                Some(Aabb {
+                    min: center - extent,
+                    max: center + extent,
+                })
+            }
+            Object::Quad { q, u, v, .. } => Some(Aabb::from_points(&[*q, q + u, q + v, q + u + v])),
+            Object::Custom { hittable, .. } => hittable.bounds(),
+            Object::PointCloud { cloud, .. } => Some(cloud.bounds()),
+            Object::Metaballs { metaballs, .. } => metaballs.bounds(),
+            Object::Mesh { mesh, .. } => mesh.bounds(),
+            Object::Instance { geometry, .. } => geometry.bounds(),
+        }
+    }
+
+    /// The point on `other`, the same primitive at another time, corresponding to the point `p` on
+    /// this one. Custom shapes and primitives that have changed shape are assumed not to move.
+    pub fn track(&self, p: &Vector3<f64>, other: &Object) -> Vector3<f64> {
+        match (self, other) {
+            (
+                Object::Sphere { center, radius, .. },
+                Object::Sphere {
+                    center: other_center,
+                    radius: other_radius,
+                    ..
+                },
+            ) => other_center + (p - center) * (other_radius / radius),
+            (
+                Object::Quad {
+                    q, u, v, cached, ..
+                },
+                Object::Quad {
+                    q: other_q,
+                    u: other_u,
+                    v: other_v,
+                    ..
+                },
+            ) => {
+                let planar = p - q;
+                let alpha = cached.w.dot(&planar.cross(v));
+                let beta = cached.w.dot(&u.cross(&planar));
+                other_q + alpha * other_u + beta * other_v
+            }
+            _ => *p,
+        }
+    }
+
+    /// The object's surface area, or `None` for shapes whose area is not easily known.
+    pub fn area(&self) -> Option<f64> {
+        match self {
+            Object::Sphere { radius, .. } => Some(4.0 * std::f64::consts::PI * radius.powi(2)),
+            Object::Quad { u, v, .. } => Some(u.cross(v).magnitude()),
+            Object::Mesh { mesh, .. } => Some(mesh.area()),
+            Object::Instance { geometry, .. } => geometry.iter().map(|object| object.area()).sum(),
+            Object::Custom { .. } | Object::PointCloud { .. } | Object::Metaballs { .. } => None,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct QuadCached {
     normal: Vector3<f64>,
     d: f64,
@@ -120,6 +798,31 @@ impl Object {
                 material,
                 cached,
             } => hit_quad(ray, ray_t, q, u, v, *material, cached),
+            Object::Custom { hittable, material } => hittable.hit(ray, ray_t).map(|hit| Hit {
+                material: *material,
+                ..hit
+            }),
+            Object::PointCloud { cloud, material } => cloud.hit(ray, ray_t).map(|hit| Hit {
+                material: *material,
+                ..hit
+            }),
+            Object::Metaballs {
+                metaballs,
+                material,
+            } => metaballs.hit(ray, ray_t).map(|hit| Hit {
+                material: *material,
+                ..hit
+            }),
+            Object::Mesh { mesh, material } => mesh.hit(ray, ray_t).map(|hit| Hit {
+                material: *material,
+                ..hit
+            }),
+            Object::Instance { geometry, material } => geometry
+                .hit(ray, ray_t, |_, object, ray_t| object.hit(ray, ray_t))
+                .map(|hit| Hit {
+                    material: *material,
+                    ..hit
+                }),
         }
     }
 }
@@ -135,10 +838,38 @@ pub struct Hit {
     pub front_face: bool,
     /// The material of the struck object.
     pub material: usize,
+    /// The index of the struck object in `World::objects`.
+    pub object: usize,
+    /// A color carried by the struck surface itself, such as a point cloud's per-point color,
+    /// which tints its material.
+    pub color: Option<Color>,
+    /// Where on the surface the hit is, from 0 to 1 across it in each direction, for mapping image
+    /// textures. A quad's runs along `u` and `v`, and a sphere's around it from -x and up from its
+    /// bottom.
+    pub uv: (f64, f64),
+}
+
+impl Hit {
+    /// A hit at time `t` along a ray, on a surface with the given unit normal pointing out of the
+    /// object. `material`, `object`, and `uv` are left zero, and `color` empty.
+    pub fn new(ray: &Ray, t: f64, outward_normal: &Vector3<f64>) -> Self {
+        let (normal, front_face) = face_normal(ray, outward_normal);
+        Self {
+            p: ray.at(t),
+            normal,
+            t,
+            front_face,
+            material: 0,
+            object: 0,
+            color: None,
+            uv: (0.0, 0.0),
+        }
+    }
 }
 
 /// Finds the time at which a ray will hit a sphere, or returns `None` if it will not.
-fn hit_sphere(
+#[inline(always)]
+pub(crate) fn hit_sphere(
     ray: &Ray,
     ray_t: Range<f64>,
     center: &Vector3<f64>,
@@ -172,6 +903,8 @@ fn hit_sphere(
     let p = ray.at(t);
     let outward_normal = (p - center) / radius;
     let (normal, front_face) = face_normal(ray, &outward_normal);
+    let theta = (-outward_normal.y).clamp(-1.0, 1.0).acos();
+    let phi = (-outward_normal.z).atan2(outward_normal.x) + std::f64::consts::PI;
 
     Some(Hit {
         p,
@@ -179,9 +912,13 @@ fn hit_sphere(
         t,
         front_face,
         material,
+        object: 0,
+        color: None,
+        uv: (phi / std::f64::consts::TAU, theta / std::f64::consts::PI),
     })
 }
 
+#[inline(always)]
 fn hit_quad(
     ray: &Ray,
     ray_t: Range<f64>,
@@ -219,6 +956,9 @@ fn hit_quad(
         t,
         front_face,
         material,
+        object: 0,
+        color: None,
+        uv: (alpha, beta),
     })
 }
 
@@ -0,0 +1,285 @@
+//! The storage of a world's primitives. Spheres and quads, which scenes are mostly built from, are
+//! kept in structure-of-arrays form, one array per field, so intersecting rays with them reads
+//! only what it needs from contiguous memory instead of striding over large enum values. A
+//! bounding volume hierarchy over all of the primitives picks out the few a ray may hit.
+
+use std::{borrow::Cow, collections::HashSet, ops::Range, sync::Arc};
+
+use nalgebra::Vector3;
+
+use crate::{bvh::Bvh, metaball::Metaball, render::Ray};
+
+use super::{hit_quad, hit_sphere, Aabb, Hit, Object, QuadCached};
+
+/// The primitives of a world, which keep the indices they were added with.
+pub struct Objects {
+    spheres: Spheres,
+    quads: Quads,
+    /// Primitives with structures of their own, like meshes, and primitives that must be hit
+    /// through their `Object`, which are intersected one at a time, with their indices.
+    whole: Vec<(usize, Object)>,
+    /// Where each primitive is stored, by index.
+    slots: Vec<Slot>,
+    /// The indices of the primitives with bounds, in the order of `bvh`'s leaves.
+    bounded: Vec<usize>,
+    bvh: Bvh,
+    /// The indices of custom shapes that do not report their bounds, which every ray is tested
+    /// against.
+    unbounded: Vec<usize>,
+}
+
+#[derive(Clone, Copy)]
+enum Slot {
+    Sphere(usize),
+    Quad(usize),
+    Whole(usize),
+}
+
+#[derive(Default)]
+struct Spheres {
+    centers: Vec<Vector3<f64>>,
+    radii: Vec<f64>,
+    materials: Vec<usize>,
+}
+
+#[derive(Default)]
+struct Quads {
+    q: Vec<Vector3<f64>>,
+    u: Vec<Vector3<f64>>,
+    v: Vec<Vector3<f64>>,
+    cached: Vec<QuadCached>,
+    materials: Vec<usize>,
+}
+
+impl Objects {
+    /// Store `objects`. Those for which `keep_whole` returns true, given their index, are left as
+    /// they are, to be hit through `hit_whole` in `hit`. `bounds` gives the box that each object
+    /// stays within, given its index, or `None` if it has none.
+    pub fn new(
+        objects: Vec<Object>,
+        keep_whole: impl Fn(usize) -> bool,
+        bounds: impl Fn(usize, &Object) -> Option<Aabb>,
+    ) -> Self {
+        let boxes = objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| bounds(index, object))
+            .collect::<Vec<_>>();
+        let (mut bounded, unbounded): (Vec<_>, Vec<_>) =
+            (0..objects.len()).partition(|&index| boxes[index].is_some());
+        let bvh = Bvh::build(&mut bounded, |&index| boxes[index].unwrap());
+
+        let mut spheres = Spheres::default();
+        let mut quads = Quads::default();
+        let mut whole = Vec::new();
+        let mut slots = Vec::with_capacity(objects.len());
+
+        for (index, object) in objects.into_iter().enumerate() {
+            let slot = match object {
+                _ if keep_whole(index) => {
+                    whole.push((index, object));
+                    Slot::Whole(whole.len() - 1)
+                }
+                Object::Sphere {
+                    center,
+                    radius,
+                    material,
+                } => {
+                    spheres.centers.push(center);
+                    spheres.radii.push(radius);
+                    spheres.materials.push(material);
+                    Slot::Sphere(spheres.centers.len() - 1)
+                }
+                Object::Quad {
+                    q,
+                    u,
+                    v,
+                    material,
+                    cached,
+                } => {
+                    quads.q.push(q);
+                    quads.u.push(u);
+                    quads.v.push(v);
+                    quads.cached.push(cached);
+                    quads.materials.push(material);
+                    Slot::Quad(quads.q.len() - 1)
+                }
+                object => {
+                    whole.push((index, object));
+                    Slot::Whole(whole.len() - 1)
+                }
+            };
+            slots.push(slot);
+        }
+
+        Self {
+            spheres,
+            quads,
+            whole,
+            slots,
+            bounded,
+            bvh,
+            unbounded,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// The primitive at `index`. Spheres and quads are put back together from their fields.
+    pub fn get(&self, index: usize) -> Option<Cow<'_, Object>> {
+        Some(match *self.slots.get(index)? {
+            Slot::Sphere(i) => Cow::Owned(Object::Sphere {
+                center: self.spheres.centers[i],
+                radius: self.spheres.radii[i],
+                material: self.spheres.materials[i],
+            }),
+            Slot::Quad(i) => Cow::Owned(Object::Quad {
+                q: self.quads.q[i],
+                u: self.quads.u[i],
+                v: self.quads.v[i],
+                material: self.quads.materials[i],
+                cached: self.quads.cached[i].clone(),
+            }),
+            Slot::Whole(i) => Cow::Borrowed(&self.whole[i].1),
+        })
+    }
+
+    /// The primitives in order of their indices.
+    pub fn iter(&self) -> impl Iterator<Item = Cow<'_, Object>> {
+        (0..self.len()).filter_map(|index| self.get(index))
+    }
+
+    /// The memory taken by the primitives, with the triangles of meshes, the points of point
+    /// clouds, the balls of metaballs, and the geometry of instances. Storage shared between
+    /// primitives, like the geometry of an object and its instances, is counted once. Custom shapes
+    /// count only their `Object`.
+    pub fn size_in_bytes(&self) -> usize {
+        self.unshared_size_in_bytes(&mut HashSet::new())
+    }
+
+    /// `size_in_bytes`, leaving out the shared storage at the addresses in `counted`, and adding
+    /// the addresses of what it counts.
+    fn unshared_size_in_bytes(&self, counted: &mut HashSet<usize>) -> usize {
+        let held = self
+            .whole
+            .iter()
+            .map(|(_, object)| match object {
+                Object::Mesh { mesh, .. } if counted.insert(mesh.storage_address()) => {
+                    mesh.size_in_bytes()
+                }
+                Object::PointCloud { cloud, .. } if counted.insert(Arc::as_ptr(cloud) as usize) => {
+                    cloud.size_in_bytes()
+                }
+                Object::Metaballs { metaballs, .. } => {
+                    metaballs.balls.len() * size_of::<Metaball>()
+                }
+                Object::Instance { geometry, .. }
+                    if counted.insert(Arc::as_ptr(geometry) as usize) =>
+                {
+                    geometry.unshared_size_in_bytes(counted)
+                }
+                _ => 0,
+            })
+            .sum::<usize>();
+
+        let sphere = size_of::<Vector3<f64>>() + size_of::<f64>() + size_of::<usize>();
+        let quad = 3 * size_of::<Vector3<f64>>() + size_of::<QuadCached>() + size_of::<usize>();
+        self.spheres.centers.len() * sphere
+            + self.quads.q.len() * quad
+            + self.whole.len() * size_of::<(usize, Object)>()
+            + self.slots.len() * size_of::<Slot>()
+            + (self.bounded.len() + self.unbounded.len()) * size_of::<usize>()
+            + self.bvh.size_in_bytes()
+            + held
+    }
+
+    /// The box around all of the primitives, or `None` if there are none, or some do not report
+    /// their bounds.
+    pub fn bounds(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.bvh.bounds()
+    }
+
+    /// The hierarchy over the primitives with bounds.
+    pub fn bvh(&self) -> &Bvh {
+        &self.bvh
+    }
+
+    /// Find the nearest hit of a ray within `ray_t`, calling `hit_whole` for each primitive kept
+    /// whole with its index and the range in which a hit would be the nearest so far. The hit's
+    /// `object` is set to the index of the primitive struck.
+    #[inline(always)]
+    pub fn hit(
+        &self,
+        ray: &Ray,
+        ray_t: Range<f64>,
+        mut hit_whole: impl FnMut(usize, &Object, Range<f64>) -> Option<Hit>,
+    ) -> Option<Hit> {
+        let mut hit = self.bvh.hit(
+            ray,
+            ray_t.clone(),
+            #[inline(always)]
+            |item, ray_t| self.hit_primitive(self.bounded[item], ray, ray_t, &mut hit_whole),
+        );
+
+        let mut closest = hit.as_ref().map_or(ray_t.end, |hit| hit.t);
+        for &index in &self.unbounded {
+            let new_hit = self.hit_primitive(index, ray, ray_t.start..closest, &mut hit_whole);
+            if let Some(new_hit) = new_hit {
+                closest = new_hit.t;
+                hit = Some(new_hit);
+            }
+        }
+
+        hit
+    }
+
+    /// Intersect a ray with the primitive at `index` within `ray_t`.
+    #[inline(always)]
+    fn hit_primitive(
+        &self,
+        index: usize,
+        ray: &Ray,
+        ray_t: Range<f64>,
+        hit_whole: &mut impl FnMut(usize, &Object, Range<f64>) -> Option<Hit>,
+    ) -> Option<Hit> {
+        let hit = match self.slots[index] {
+            Slot::Sphere(i) => {
+                let spheres = &self.spheres;
+                hit_sphere(
+                    ray,
+                    ray_t,
+                    &spheres.centers[i],
+                    spheres.radii[i],
+                    spheres.materials[i],
+                )
+            }
+            Slot::Quad(i) => {
+                let quads = &self.quads;
+                hit_quad(
+                    ray,
+                    ray_t,
+                    &quads.q[i],
+                    &quads.u[i],
+                    &quads.v[i],
+                    quads.materials[i],
+                    &quads.cached[i],
+                )
+            }
+            Slot::Whole(i) => hit_whole(index, &self.whole[i].1, ray_t),
+        }?;
+
+        Some(Hit {
+            object: index,
+            ..hit
+        })
+    }
+}
@@ -0,0 +1,192 @@
+//! The images written by `rtk render`: display colors for most formats, or the linear colors
+//! themselves for the floating-point formats OpenEXR (`.exr`), Radiance HDR (`.hdr`), and PFM
+//! (`.pfm`), so that renders can be exposed and graded afterwards without banding. Renders kept in
+//! half floats are written to OpenEXR in half floats.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use image::{codecs::hdr::HdrEncoder, Rgb32FImage, RgbImage};
+use rtk::render::HalfImage;
+
+use crate::{
+    is_standard_stream,
+    tiled_exr::{write_attribute, CHANNELS, MAGIC},
+};
+
+/// The extensions of the formats that are written with linear colors.
+const LINEAR_EXTENSIONS: [&str; 3] = ["exr", "hdr", "pfm"];
+
+pub enum Image {
+    Display(RgbImage),
+    Linear(Rgb32FImage),
+    /// Linear colors in half floats.
+    Half(HalfImage),
+}
+
+/// Whether an image written to `path` keeps linear colors, going by its extension.
+pub fn is_linear(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        LINEAR_EXTENSIONS
+            .iter()
+            .any(|linear| extension.eq_ignore_ascii_case(linear))
+    })
+}
+
+impl Image {
+    /// Apply `display` or `linear` to the image, whichever kind of colors it holds. Half floats
+    /// are converted to full floats for `linear`, and back.
+    pub fn map(
+        self,
+        display: impl FnOnce(RgbImage) -> anyhow::Result<RgbImage>,
+        linear: impl FnOnce(Rgb32FImage) -> anyhow::Result<Rgb32FImage>,
+    ) -> anyhow::Result<Self> {
+        Ok(match self {
+            Image::Display(image) => Image::Display(display(image)?),
+            Image::Linear(image) => Image::Linear(linear(image)?),
+            Image::Half(image) => Image::Half(HalfImage::from_rgb32f(&linear(image.to_rgb32f())?)),
+        })
+    }
+
+    /// Write the image to `path`, in the format its extension names, or as PNG to standard output
+    /// if it is `-`.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let image = match self {
+            Image::Display(image) if is_standard_stream(path) => {
+                // PNG encoding needs to seek, which standard output cannot.
+                let mut png = std::io::Cursor::new(Vec::new());
+                image.write_to(&mut png, image::ImageFormat::Png)?;
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(png.get_ref())?;
+                stdout.flush()?;
+                return Ok(());
+            }
+            Image::Linear(_) | Image::Half(_) if is_standard_stream(path) => {
+                anyhow::bail!("only PNG images can be written to standard output")
+            }
+            image => image,
+        };
+
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+        match (image, extension.as_deref()) {
+            (Image::Display(image), _) => image.save(path)?,
+            (Image::Linear(image), Some("hdr")) => {
+                let file = BufWriter::new(File::create(path)?);
+                let pixels = image.pixels().copied().collect::<Vec<_>>();
+                HdrEncoder::new(file).encode(
+                    &pixels,
+                    image.width() as usize,
+                    image.height() as usize,
+                )?;
+            }
+            (Image::Linear(image), Some("pfm")) => write_pfm(image, path)?,
+            (Image::Linear(image), _) => image.save(path)?,
+            (Image::Half(image), Some("exr")) => write_half_exr(image, path)?,
+            // Radiance HDR and PFM have no half floats.
+            (Image::Half(image), _) => Image::Linear(image.to_rgb32f()).save(path)?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Write a color Portable Float Map: a text header, then the rows from the bottom up as
+/// little-endian floats, which the negative scale in the header announces.
+fn write_pfm(image: &Rgb32FImage, path: &Path) -> anyhow::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write!(file, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+    for row in image.rows().rev() {
+        for pixel in row {
+            for channel in pixel.0 {
+                file.write_all(&channel.to_le_bytes())?;
+            }
+        }
+    }
+    file.flush()?;
+
+    Ok(())
+}
+
+/// Write a scanline OpenEXR image of half floats, uncompressed, one line to a block.
+fn write_half_exr(image: &HalfImage, path: &Path) -> anyhow::Result<()> {
+    /// Version 2 of the file format, for a single scanline part.
+    const VERSION: u32 = 2;
+    /// The pixel type of 16-bit floats.
+    const HALF: i32 = 1;
+    /// The line order in which blocks are written from the top down.
+    const INCREASING_Y: u8 = 0;
+
+    let (width, height) = image.dimensions();
+    let mut header = Vec::new();
+    header.extend(MAGIC.to_le_bytes());
+    header.extend(VERSION.to_le_bytes());
+
+    let mut channels = Vec::new();
+    for name in CHANNELS {
+        channels.extend(name.as_bytes());
+        channels.push(0);
+        channels.extend(HALF.to_le_bytes());
+        // linear, three reserved bytes, and no subsampling
+        channels.extend([0; 4]);
+        channels.extend(1i32.to_le_bytes());
+        channels.extend(1i32.to_le_bytes());
+    }
+    channels.push(0);
+    let window = [0, 0, width as i32 - 1, height as i32 - 1]
+        .into_iter()
+        .flat_map(i32::to_le_bytes)
+        .collect::<Vec<_>>();
+
+    write_attribute(&mut header, "channels", "chlist", &channels);
+    write_attribute(&mut header, "compression", "compression", &[0]);
+    write_attribute(&mut header, "dataWindow", "box2i", &window);
+    write_attribute(&mut header, "displayWindow", "box2i", &window);
+    write_attribute(&mut header, "lineOrder", "lineOrder", &[INCREASING_Y]);
+    write_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    write_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    write_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    // Each line is its number and size, then its blue, green, and red channels in turn.
+    let line_size = width as usize * CHANNELS.len() * size_of::<u16>();
+    let block_size = (2 * size_of::<i32>() + line_size) as u64;
+    let first_block = (header.len() + height as usize * size_of::<u64>()) as u64;
+
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(&header)?;
+    for y in 0..height as u64 {
+        file.write_all(&(first_block + y * block_size).to_le_bytes())?;
+    }
+    for (y, row) in image.as_raw().chunks_exact(width as usize * 3).enumerate() {
+        file.write_all(&(y as i32).to_le_bytes())?;
+        file.write_all(&(line_size as i32).to_le_bytes())?;
+        // the channels are stored in the order of their names, blue, green, then red, which are
+        // indices 2, 1, and 0 of a pixel
+        for channel in [2, 1, 0] {
+            for pixel in row.chunks_exact(3) {
+                file.write_all(&pixel[channel].to_le_bytes())?;
+            }
+        }
+    }
+    file.flush()?;
+
+    Ok(())
+}
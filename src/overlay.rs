@@ -0,0 +1,208 @@
+//! Drawing over a render of a world: the bounding boxes of its objects, for finding misplaced
+//! objects and problems with the hierarchies of meshes and point clouds, and the edges of its quads
+//! and meshes as a wireframe.
+
+use image::{Rgb, RgbImage};
+use nalgebra::{Vector2, Vector3};
+
+use crate::{
+    object::{Aabb, Object, World},
+    render::{Ray, Renderer},
+};
+
+/// The color of the box around each scene object.
+const OBJECT_COLOR: Rgb<u8> = Rgb([255, 220, 0]);
+/// The color of the box around the whole scene.
+const SCENE_COLOR: Rgb<u8> = Rgb([255, 0, 255]);
+/// The colors of hierarchy nodes, cycling with their depth below the root.
+const NODE_COLORS: [Rgb<u8>; 3] = [Rgb([0, 255, 255]), Rgb([0, 255, 0]), Rgb([255, 128, 0])];
+
+/// The color of wireframe edges.
+const WIREFRAME_COLOR: Rgb<u8> = Rgb([16, 16, 16]);
+
+/// Which boxes `draw_bounds` draws.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BoundsOverlay {
+    /// Draw the nodes of the bounding volume hierarchies of meshes and point clouds down to this
+    /// many levels below their roots.
+    pub bvh_depth: Option<usize>,
+}
+
+/// Draw the box around each scene object in `world` and the box around all of them onto `image`,
+/// a render of `world` by `renderer`. Custom shapes that do not report their bounds are left out.
+pub fn draw_bounds(
+    image: &mut RgbImage,
+    renderer: &Renderer,
+    world: &World,
+    overlay: BoundsOverlay,
+) {
+    if let Some(max_depth) = overlay.bvh_depth {
+        for (id, object) in world.primitives() {
+            let bvh = match &*object {
+                Object::Mesh { mesh, .. } => mesh.bvh(),
+                Object::PointCloud { cloud, .. } => cloud.bvh(),
+                _ => continue,
+            };
+            let transform = &world.object_settings[id].transform;
+            for (bounds, depth) in bvh.node_bounds(max_depth) {
+                let bounds = match transform {
+                    Some(transform) => transform.world_bounds(&bounds),
+                    None => bounds,
+                };
+                draw_box(
+                    image,
+                    renderer,
+                    &bounds,
+                    NODE_COLORS[depth % NODE_COLORS.len()],
+                );
+            }
+        }
+    }
+
+    let object_bounds = world
+        .bounds_by_object()
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    for bounds in &object_bounds {
+        draw_box(image, renderer, bounds, OBJECT_COLOR);
+    }
+    if let Some(scene) = object_bounds.into_iter().reduce(|a, b| a.union(&b)) {
+        draw_box(image, renderer, &scene, SCENE_COLOR);
+    }
+}
+
+/// Draw the edges of the quads and mesh triangles of `world` onto `image`, a render of `world` by
+/// `renderer`, leaving out the parts hidden behind surfaces. Other shapes have no edges to draw.
+pub fn draw_wireframe(image: &mut RgbImage, renderer: &Renderer, world: &World) {
+    for (id, object) in world.primitives() {
+        let edges = match &*object {
+            Object::Quad { q, u, v, .. } => {
+                let corners = [*q, q + u, q + u + v, q + v];
+                (0..4).map(|i| [corners[i], corners[(i + 1) % 4]]).collect()
+            }
+            Object::Mesh { mesh, .. } => mesh.edges(),
+            _ => continue,
+        };
+        let transform = &world.object_settings[id].transform;
+        for edge in edges {
+            let edge = match transform {
+                Some(transform) => edge.map(|end| transform.to_world(&end)),
+                None => edge,
+            };
+            draw_visible_edge(image, renderer, world, edge);
+        }
+    }
+}
+
+/// Draw the parts of an edge that are not hidden from the eye by a surface in front of them.
+fn draw_visible_edge(
+    image: &mut RgbImage,
+    renderer: &Renderer,
+    world: &World,
+    [a, b]: [Vector3<f64>; 2],
+) {
+    let (width, height) = (image.width() as f64, image.height() as f64);
+    let length = renderer
+        .project_line(&a, &b)
+        .iter()
+        .map(|[a, b]| {
+            ((b.0 - a.0) * width)
+                .abs()
+                .max(((b.1 - a.1) * height).abs())
+        })
+        .fold(0.0, f64::max);
+    // An edge is followed along its length in the world, rather than across the image, so that
+    // each step can be checked for surfaces in front of it.
+    let steps = length.ceil().clamp(1.0, 4.0 * (width + height)) as u32;
+
+    for step in 0..=steps {
+        let p = a + (b - a) * (step as f64 / steps as f64);
+        for eye in 0..renderer.eye_count() {
+            let Some((x, y)) = renderer.project_point(&p, eye) else {
+                continue;
+            };
+            let (x, y) = (x * width, y * height);
+            if !((0.0..width).contains(&x) && (0.0..height).contains(&y)) {
+                continue;
+            }
+
+            let origin = renderer.eye_center(eye);
+            let ray = Ray {
+                origin,
+                direction: p - origin,
+                time: 0.0,
+            };
+            // The edge's own surfaces are hit at the end of the ray, so only hits well before it
+            // hide the edge.
+            if world.hit(&ray, 0.001, 1.0 - 1e-4).is_none() {
+                image.put_pixel(x as u32, y as u32, WIREFRAME_COLOR);
+            }
+        }
+    }
+}
+
+/// Draw the twelve edges of a box.
+fn draw_box(image: &mut RgbImage, renderer: &Renderer, bounds: &Aabb, color: Rgb<u8>) {
+    let corner = |i: usize| {
+        Vector3::from_fn(|axis, _| {
+            if i & (1 << axis) == 0 {
+                bounds.min[axis]
+            } else {
+                bounds.max[axis]
+            }
+        })
+    };
+
+    // Each edge joins two corners that differ along one axis.
+    for from in 0..8 {
+        for axis in [1, 2, 4] {
+            if from & axis == 0 {
+                for line in renderer.project_line(&corner(from), &corner(from | axis)) {
+                    draw_line(image, line, color);
+                }
+            }
+        }
+    }
+}
+
+/// Draw a line between two points given as fractions of the image's width and height, leaving out
+/// the parts outside the image.
+fn draw_line(image: &mut RgbImage, [a, b]: [(f64, f64); 2], color: Rgb<u8>) {
+    let (width, height) = (image.width() as f64, image.height() as f64);
+    let a = Vector2::new(a.0 * width, a.1 * height);
+    let b = Vector2::new(b.0 * width, b.1 * height);
+
+    // Cut the line to the image (Liang and Barsky's method), since lines from boxes that nearly
+    // reach the camera can be far longer than the image.
+    let delta = b - a;
+    let (mut start, mut end) = (0.0f64, 1.0f64);
+    for (p, q) in [
+        (-delta.x, a.x),
+        (delta.x, width - a.x),
+        (-delta.y, a.y),
+        (delta.y, height - a.y),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return;
+            }
+        } else if p < 0.0 {
+            start = start.max(q / p);
+        } else {
+            end = end.min(q / p);
+        }
+    }
+    if start > end {
+        return;
+    }
+
+    let (a, b) = (a + delta * start, a + delta * end);
+    let steps = (b - a).abs().max().ceil().max(1.0) as u32;
+    for step in 0..=steps {
+        let point = a + (b - a) * (step as f64 / steps as f64);
+        if (0.0..width).contains(&point.x) && (0.0..height).contains(&point.y) {
+            image.put_pixel(point.x as u32, point.y as u32, color);
+        }
+    }
+}
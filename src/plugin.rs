@@ -0,0 +1,209 @@
+//! Extension points for shapes, materials, and image formats defined outside of rtk.
+//!
+//! A plugin implements `Hittable` or `Bsdf` and registers a factory with `Plugins` under a name.
+//! Scene files then refer to it with `type = "custom"` and that name, passing any `params` to the
+//! factory. Plugins are compiled into the program that renders the scene; see
+//! `examples/plugin.rs`.
+//!
+//! Image textures are read by an `ImageLoader` registered for the file's extension, so that other
+//! formats can be added the same way. OpenEXR and TIFF loaders are registered by default, and files
+//! with any other extension are read by the `image` crate, which guesses their format.
+
+use std::{collections::HashMap, fs::File, io::BufReader, ops::Range, path::Path, sync::Arc};
+
+use image::{DynamicImage, ImageFormat};
+
+use nalgebra::Vector3;
+
+use crate::{
+    object::{Aabb, Hit},
+    render::{Color, Ray},
+};
+
+/// A shape that rays can hit.
+pub trait Hittable: Send + Sync {
+    /// Find the nearest intersection of `ray` with the shape within `ray_t`. The `material` and
+    /// `object` of the returned hit are filled in by the world; `Hit::new` leaves them zero.
+    fn hit(&self, ray: &Ray, ray_t: Range<f64>) -> Option<Hit>;
+
+    /// The box enclosing the shape, if it is bounded and known.
+    fn bounds(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+/// How a surface reflects, transmits, and emits light.
+pub trait Bsdf: Send + Sync {
+    /// Choose a direction to continue a path in, returning the attenuation along it, or `None` if
+    /// the path ends here.
+    fn scatter(&self, ray: &Ray, hit: &Hit) -> Option<(Color, Ray)>;
+
+    /// The fraction of light arriving from `direction` (a unit vector) that is reflected back
+    /// along the ray, including the cosine term. This is used to sample lights directly, and may
+    /// be zero for surfaces that only reflect in a single direction.
+    fn eval(&self, _ray: &Ray, _hit: &Hit, _direction: &Vector3<f64>) -> Color {
+        Color::zeros()
+    }
+
+    /// The light emitted by the surface at a hit.
+    fn emit(&self, _hit: &Hit) -> Color {
+        Color::zeros()
+    }
+}
+
+/// Reads texture images from files of a format.
+pub trait ImageLoader: Send + Sync {
+    /// Read the image at `path`. Images of floating-point pixels are taken as linear colors unless
+    /// the texture sets its `color-space`, and others as display colors.
+    fn load(&self, path: &Path) -> anyhow::Result<DynamicImage>;
+}
+
+/// Reads images in one of the formats the `image` crate decodes.
+struct Codec(ImageFormat);
+
+impl ImageLoader for Codec {
+    fn load(&self, path: &Path) -> anyhow::Result<DynamicImage> {
+        let reader = image::io::Reader::with_format(BufReader::new(File::open(path)?), self.0);
+        Ok(reader.decode()?)
+    }
+}
+
+/// Reads TIFF images, including those of floating-point pixels, which the `image` crate cannot.
+struct TiffLoader;
+
+impl ImageLoader for TiffLoader {
+    fn load(&self, path: &Path) -> anyhow::Result<DynamicImage> {
+        let mut decoder = tiff::decoder::Decoder::new(BufReader::new(File::open(path)?))?;
+        let sample_formats = decoder
+            .find_tag_unsigned_vec::<u16>(tiff::tags::Tag::SampleFormat)?
+            .unwrap_or_default();
+        if !sample_formats.contains(&tiff::tags::SampleFormat::IEEEFP.to_u16()) {
+            return Codec(ImageFormat::Tiff).load(path);
+        }
+
+        let (width, height) = decoder.dimensions()?;
+        let color_type = decoder.colortype()?;
+        let values: Vec<f32> = match decoder.read_image()? {
+            tiff::decoder::DecodingResult::F32(values) => values,
+            tiff::decoder::DecodingResult::F64(values) => {
+                values.into_iter().map(|value| value as f32).collect()
+            }
+            _ => anyhow::bail!("mixed integer and floating-point channels are not supported"),
+        };
+        let image = match color_type {
+            tiff::ColorType::Gray(_) => {
+                let values = values.iter().flat_map(|&value| [value; 3]).collect();
+                image::Rgb32FImage::from_raw(width, height, values).map(DynamicImage::ImageRgb32F)
+            }
+            tiff::ColorType::RGB(_) => {
+                image::Rgb32FImage::from_raw(width, height, values).map(DynamicImage::ImageRgb32F)
+            }
+            tiff::ColorType::RGBA(_) => {
+                image::Rgba32FImage::from_raw(width, height, values).map(DynamicImage::ImageRgba32F)
+            }
+            other => anyhow::bail!("floating-point {other:?} images are not supported"),
+        };
+
+        image.ok_or_else(|| anyhow::anyhow!("the image is missing pixels"))
+    }
+}
+
+pub type ShapeFactory =
+    Box<dyn Fn(&toml::Table) -> anyhow::Result<Arc<dyn Hittable>> + Send + Sync>;
+pub type MaterialFactory = Box<dyn Fn(&toml::Table) -> anyhow::Result<Arc<dyn Bsdf>> + Send + Sync>;
+
+/// The custom shapes and materials available to a scene, by name, and the image loaders by file
+/// extension.
+pub struct Plugins {
+    shapes: HashMap<String, ShapeFactory>,
+    materials: HashMap<String, MaterialFactory>,
+    /// Keyed by lowercase extension, without the dot.
+    image_loaders: HashMap<String, Box<dyn ImageLoader>>,
+}
+
+impl Default for Plugins {
+    fn default() -> Self {
+        let mut plugins = Self {
+            shapes: HashMap::new(),
+            materials: HashMap::new(),
+            image_loaders: HashMap::new(),
+        };
+        plugins
+            .register_image_loader("exr", Codec(ImageFormat::OpenExr))
+            .register_image_loader("tif", TiffLoader)
+            .register_image_loader("tiff", TiffLoader);
+        plugins
+    }
+}
+
+impl Plugins {
+    /// The built-in image loaders, with no custom shapes or materials.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a shape, replacing any other with the same name.
+    pub fn register_shape(
+        &mut self,
+        name: impl Into<String>,
+        factory: impl Fn(&toml::Table) -> anyhow::Result<Arc<dyn Hittable>> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.shapes.insert(name.into(), Box::new(factory));
+        self
+    }
+
+    /// Register a material, replacing any other with the same name.
+    pub fn register_material(
+        &mut self,
+        name: impl Into<String>,
+        factory: impl Fn(&toml::Table) -> anyhow::Result<Arc<dyn Bsdf>> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.materials.insert(name.into(), Box::new(factory));
+        self
+    }
+
+    /// Register an image loader for files ending in `.extension`, in any case, replacing any
+    /// other for that extension, including the built-in ones.
+    pub fn register_image_loader(
+        &mut self,
+        extension: &str,
+        loader: impl ImageLoader + 'static,
+    ) -> &mut Self {
+        self.image_loaders
+            .insert(extension.to_lowercase(), Box::new(loader));
+        self
+    }
+
+    /// Create a registered shape.
+    pub fn shape(&self, name: &str, params: &toml::Table) -> anyhow::Result<Arc<dyn Hittable>> {
+        let factory = self
+            .shapes
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("there is no shape plugin named `{name}`"))?;
+        factory(params).map_err(|e| anyhow::anyhow!("shape `{name}`: {e}"))
+    }
+
+    /// Create a registered material.
+    pub fn material(&self, name: &str, params: &toml::Table) -> anyhow::Result<Arc<dyn Bsdf>> {
+        let factory = self
+            .materials
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("there is no material plugin named `{name}`"))?;
+        factory(params).map_err(|e| anyhow::anyhow!("material `{name}`: {e}"))
+    }
+
+    /// Read an image with the loader registered for its extension, or by guessing its format if
+    /// there is none.
+    pub fn load_image(&self, path: &Path) -> anyhow::Result<DynamicImage> {
+        let loader = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| self.image_loaders.get(&extension.to_lowercase()));
+        match loader {
+            Some(loader) => loader.load(path),
+            None => Ok(image::io::Reader::open(path)?
+                .with_guessed_format()?
+                .decode()?),
+        }
+    }
+}
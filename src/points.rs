@@ -0,0 +1,442 @@
+//! Point clouds, such as lidar scans and photogrammetry, loaded from PLY or LAS files and rendered
+//! as a small sphere or disc at each point.
+
+use std::{ops::Range, path::Path};
+
+use anyhow::Context;
+use nalgebra::Vector3;
+
+use crate::{
+    bvh::Bvh,
+    color::ColorSpace,
+    object::{hit_sphere, Aabb, Hit},
+    render::{Color, Ray},
+};
+
+/// A point of a point cloud.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Point {
+    pub position: Vector3<f64>,
+    /// The unit normal of the surface the point was sampled from, if the file has one.
+    pub normal: Option<Vector3<f64>>,
+    /// The point's linear color, if the file has one, which tints the cloud's material.
+    pub color: Option<Color>,
+}
+
+/// The shape drawn at each point.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Splat {
+    #[default]
+    Sphere,
+    /// A flat disc facing along the point's normal, or towards the ray for points without one.
+    Disc,
+}
+
+/// Points drawn as spheres or discs of the same radius.
+pub struct PointCloud {
+    points: Vec<Point>,
+    radius: f64,
+    splat: Splat,
+    bvh: Bvh,
+}
+
+impl PointCloud {
+    /// Arrange `points` for rendering with `radius` and `splat`. There must be at least one point.
+    pub fn new(mut points: Vec<Point>, radius: f64, splat: Splat) -> Self {
+        let bvh = Bvh::build(&mut points, |point| Aabb {
+            min: point.position.add_scalar(-radius),
+            max: point.position.add_scalar(radius),
+        });
+        Self {
+            points,
+            radius,
+            splat,
+            bvh,
+        }
+    }
+
+    /// The memory taken by the points and their hierarchy.
+    pub fn size_in_bytes(&self) -> usize {
+        self.points.len() * size_of::<Point>() + self.bvh.size_in_bytes()
+    }
+
+    /// Load the points of a PLY or LAS file, chosen by its extension, for rendering with `radius`
+    /// and `splat`. The file's colors are converted to linear from `color_space`.
+    pub fn load(
+        path: &Path,
+        radius: f64,
+        splat: Splat,
+        color_space: ColorSpace,
+    ) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        let mut points = match extension.as_deref() {
+            Some("ply") => read_ply(&bytes),
+            Some("las") => read_las(&bytes),
+            _ => anyhow::bail!("{} is not a .ply or .las file", path.display()),
+        }
+        .with_context(|| format!("reading {}", path.display()))?;
+
+        if points.is_empty() {
+            anyhow::bail!("{} has no points", path.display());
+        }
+        for point in &mut points {
+            point.color = point.color.map(|color| color_space.to_linear(color));
+        }
+        Ok(Self::new(points, radius, splat))
+    }
+
+    pub fn bvh(&self) -> &Bvh {
+        &self.bvh
+    }
+
+    pub fn points(&self) -> &[Point] {
+        &self.points
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.bvh.bounds().expect("point clouds are never empty")
+    }
+
+    /// Find the nearest point a ray hits within `ray_t`. The hit carries the point's color.
+    pub fn hit(&self, ray: &Ray, ray_t: Range<f64>) -> Option<Hit> {
+        self.bvh.hit(ray, ray_t, |i, ray_t| {
+            self.hit_point(ray, ray_t, &self.points[i])
+        })
+    }
+
+    fn hit_point(&self, ray: &Ray, ray_t: Range<f64>, point: &Point) -> Option<Hit> {
+        let mut hit = match self.splat {
+            Splat::Sphere => hit_sphere(ray, ray_t, &point.position, self.radius, 0)?,
+            Splat::Disc => {
+                let outward_normal = point.normal.unwrap_or_else(|| -ray.direction.normalize());
+                let denom = outward_normal.dot(&ray.direction);
+                if denom.abs() < 1e-8 {
+                    return None;
+                }
+                let t = (point.position - ray.origin).dot(&outward_normal) / denom;
+                if !ray_t.contains(&t)
+                    || (ray.at(t) - point.position).magnitude_squared() > self.radius.powi(2)
+                {
+                    return None;
+                }
+
+                Hit::new(ray, t, &outward_normal)
+            }
+        };
+        hit.color = point.color;
+
+        Some(hit)
+    }
+}
+
+/// The type of a PLY property.
+#[derive(Clone, Copy)]
+enum PlyType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> anyhow::Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => anyhow::bail!("unknown property type `{name}`"),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    /// The largest value of an integer type, by which colors are divided.
+    fn max(self) -> f64 {
+        match self {
+            Self::U8 => u8::MAX.into(),
+            Self::U16 => u16::MAX.into(),
+            Self::I8 => i8::MAX.into(),
+            Self::I16 => i16::MAX.into(),
+            Self::I32 => i32::MAX.into(),
+            Self::U32 => u32::MAX.into(),
+            Self::F32 | Self::F64 => 1.0,
+        }
+    }
+
+    fn read(self, bytes: &[u8], big_endian: bool) -> f64 {
+        macro_rules! read {
+            ($t:ty) => {{
+                let bytes = bytes[..size_of::<$t>()].try_into().unwrap();
+                let value = if big_endian {
+                    <$t>::from_be_bytes(bytes)
+                } else {
+                    <$t>::from_le_bytes(bytes)
+                };
+                value as f64
+            }};
+        }
+
+        match self {
+            Self::I8 => read!(i8),
+            Self::U8 => read!(u8),
+            Self::I16 => read!(i16),
+            Self::U16 => read!(u16),
+            Self::I32 => read!(i32),
+            Self::U32 => read!(u32),
+            Self::F32 => read!(f32),
+            Self::F64 => read!(f64),
+        }
+    }
+}
+
+struct PlyProperty {
+    name: String,
+    ty: PlyType,
+    /// For list properties, the type of the item count.
+    count: Option<PlyType>,
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+/// Read the `vertex` element of a PLY file, in any of its three formats.
+fn read_ply(bytes: &[u8]) -> anyhow::Result<Vec<Point>> {
+    const END: &[u8] = b"end_header\n";
+    let header_len = bytes
+        .windows(END.len())
+        .position(|window| window == END)
+        .context("the PLY header has no end")?
+        + END.len();
+    let header = std::str::from_utf8(&bytes[..header_len]).context("the PLY header is not text")?;
+
+    let mut format = None;
+    let mut elements = Vec::<PlyElement>::new();
+    for line in header.lines() {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            ["format", name, _] => format = Some(name.to_string()),
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count.parse().context("bad PLY element count")?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, ty, name] => elements
+                .last_mut()
+                .context("a PLY property comes before any element")?
+                .properties
+                .push(PlyProperty {
+                    name: name.to_string(),
+                    ty: PlyType::parse(ty)?,
+                    count: Some(PlyType::parse(count)?),
+                }),
+            ["property", ty, name] => elements
+                .last_mut()
+                .context("a PLY property comes before any element")?
+                .properties
+                .push(PlyProperty {
+                    name: name.to_string(),
+                    ty: PlyType::parse(ty)?,
+                    count: None,
+                }),
+            _ => {}
+        }
+    }
+
+    // Each row of each element is read as a list of values per property, so that elements before
+    // the vertices can be skipped whatever they hold.
+    let mut rows = Vec::new();
+    match format.as_deref() {
+        Some("ascii") => {
+            let body =
+                std::str::from_utf8(&bytes[header_len..]).context("the PLY body is not text")?;
+            let mut values = body.split_whitespace().map(|word| {
+                word.parse::<f64>()
+                    .with_context(|| format!("`{word}` is not a number"))
+            });
+            let mut next = || values.next().context("the PLY file ends early")?;
+            for element in &elements {
+                for _ in 0..element.count {
+                    let mut row = Vec::new();
+                    for property in &element.properties {
+                        let len = match property.count {
+                            Some(_) => next()? as usize,
+                            None => 1,
+                        };
+                        row.push(
+                            (0..len)
+                                .map(|_| next())
+                                .collect::<anyhow::Result<Vec<_>>>()?,
+                        );
+                    }
+                    if element.name == "vertex" {
+                        rows.push(row);
+                    }
+                }
+            }
+        }
+        Some(format @ ("binary_little_endian" | "binary_big_endian")) => {
+            let big_endian = format == "binary_big_endian";
+            let mut body = &bytes[header_len..];
+            let mut next = |ty: PlyType| {
+                if body.len() < ty.size() {
+                    anyhow::bail!("the PLY file ends early");
+                }
+                let value = ty.read(body, big_endian);
+                body = &body[ty.size()..];
+                Ok(value)
+            };
+            for element in &elements {
+                for _ in 0..element.count {
+                    let mut row = Vec::new();
+                    for property in &element.properties {
+                        let len = match property.count {
+                            Some(count) => next(count)? as usize,
+                            None => 1,
+                        };
+                        row.push(
+                            (0..len)
+                                .map(|_| next(property.ty))
+                                .collect::<anyhow::Result<Vec<_>>>()?,
+                        );
+                    }
+                    if element.name == "vertex" {
+                        rows.push(row);
+                    }
+                }
+            }
+        }
+        _ => anyhow::bail!("unknown PLY format"),
+    }
+
+    let vertex = elements
+        .iter()
+        .find(|element| element.name == "vertex")
+        .context("the PLY file has no vertices")?;
+    let find = |names: &[&str]| {
+        vertex
+            .properties
+            .iter()
+            .position(|property| names.contains(&property.name.as_str()))
+    };
+    let find_all = |names: [&[&str]; 3]| -> Option<[usize; 3]> {
+        Some([find(names[0])?, find(names[1])?, find(names[2])?])
+    };
+
+    let position =
+        find_all([&["x"], &["y"], &["z"]]).context("the PLY vertices have no x, y, z")?;
+    let normal = find_all([&["nx"], &["ny"], &["nz"]]);
+    let color = find_all([
+        &["red", "r", "diffuse_red"],
+        &["green", "g", "diffuse_green"],
+        &["blue", "b", "diffuse_blue"],
+    ]);
+    let color_max = color.map(|[red, ..]| vertex.properties[red].ty.max());
+
+    let value = |row: &[Vec<f64>], property: usize| row[property].first().copied().unwrap_or(0.0);
+    let vector = |row: &[Vec<f64>], [x, y, z]: [usize; 3]| {
+        Vector3::new(value(row, x), value(row, y), value(row, z))
+    };
+    Ok(rows
+        .iter()
+        .map(|row| Point {
+            position: vector(row, position),
+            normal: normal.and_then(|normal| vector(row, normal).try_normalize(1e-12)),
+            color: color
+                .zip(color_max)
+                .map(|(color, max)| vector(row, color) / max),
+        })
+        .collect())
+}
+
+/// Read a LAS file of any version from 1.0 to 1.4. Compressed (LAZ) files are not supported.
+fn read_las(bytes: &[u8]) -> anyhow::Result<Vec<Point>> {
+    let field = |offset: usize, len: usize| {
+        bytes
+            .get(offset..offset + len)
+            .context("the LAS file ends early")
+    };
+    let u8_at = |offset| anyhow::Ok(field(offset, 1)?[0]);
+    let u16_at = |offset| anyhow::Ok(u16::from_le_bytes(field(offset, 2)?.try_into()?));
+    let u32_at = |offset| anyhow::Ok(u32::from_le_bytes(field(offset, 4)?.try_into()?));
+    let u64_at = |offset| anyhow::Ok(u64::from_le_bytes(field(offset, 8)?.try_into()?));
+    let f64_at = |offset| anyhow::Ok(f64::from_le_bytes(field(offset, 8)?.try_into()?));
+    let vector_at = |offset| {
+        anyhow::Ok(Vector3::new(
+            f64_at(offset)?,
+            f64_at(offset + 8)?,
+            f64_at(offset + 16)?,
+        ))
+    };
+
+    if field(0, 4)? != b"LASF" {
+        anyhow::bail!("not a LAS file");
+    }
+    let (major, minor) = (u8_at(24)?, u8_at(25)?);
+    let data_offset = u32_at(96)? as usize;
+    let format = u8_at(104)?;
+    let record_len = u16_at(105)? as usize;
+    let mut count = u32_at(107)? as u64;
+    if (major, minor) >= (1, 4) && count == 0 {
+        count = u64_at(247)?;
+    }
+    let scale = vector_at(131)?;
+    let offset = vector_at(155)?;
+
+    if format & 0x80 != 0 {
+        anyhow::bail!("compressed LAS (LAZ) files are not supported");
+    }
+    let color_offset = match format {
+        0 | 1 | 4 | 6 | 9 => None,
+        2 => Some(20),
+        3 | 5 => Some(28),
+        7 | 8 | 10 => Some(30),
+        _ => anyhow::bail!("unknown LAS point format {format}"),
+    };
+
+    (0..count as usize)
+        .map(|i| {
+            let record = data_offset + i * record_len;
+            let coordinate = |axis: usize| {
+                anyhow::Ok(i32::from_le_bytes(field(record + 4 * axis, 4)?.try_into()?) as f64)
+            };
+            let raw = Vector3::new(coordinate(0)?, coordinate(1)?, coordinate(2)?);
+            let color = match color_offset {
+                Some(color) => {
+                    let channel = |c: usize| anyhow::Ok(u16_at(record + color + 2 * c)? as f64);
+                    let rgb = Vector3::new(channel(0)?, channel(1)?, channel(2)?);
+                    Some(rgb / u16::MAX as f64)
+                }
+                None => None,
+            };
+
+            Ok(Point {
+                position: raw.component_mul(&scale) + offset,
+                normal: None,
+                color,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,166 @@
+//! Polarized light, for cameras with a polarizing filter. Light is described by its Stokes vector
+//! `(I, Q, U, V)`: its intensity, how much more of it is polarized along a reference axis than
+//! across it, the same for the diagonals, and its circular polarization. Surfaces change it by a
+//! Mueller matrix.
+//!
+//! Paths are traced from the camera, so rather than the light itself, each path carries the Stokes
+//! vector it is sensitive to: the first row of the product of the filter's and surfaces' Mueller
+//! matrices along it. Lights and the sky are unpolarized, so only the first component of that row,
+//! the weight of unpolarized light, matters where light is gathered.
+
+use nalgebra::{Matrix4, Vector3, Vector4};
+
+use crate::{material::Material, object::Hit};
+
+/// The polarization that the light arriving along a path is weighed by.
+#[derive(Clone, Copy, Debug)]
+pub struct Polarization {
+    /// The Stokes vector whose dot product with the light's gives how much of it reaches the
+    /// camera.
+    sensitivity: Vector4<f64>,
+    /// The unit axis across the path's ray along which `Q` measures polarization.
+    frame: Vector3<f64>,
+}
+
+impl Polarization {
+    /// A linear polarizing filter passing light polarized at `angle` degrees from `right`,
+    /// turning toward the camera's up, for a ray leaving the camera in `direction`.
+    pub fn filter(angle: f64, right: &Vector3<f64>, direction: &Vector3<f64>) -> Self {
+        let (sin, cos) = (2.0 * angle.to_radians()).sin_cos();
+        // The frame's second axis, `direction` × `frame`, points down the image, so an angle
+        // turning up is negative.
+        Self {
+            sensitivity: 0.5 * Vector4::new(1.0, cos, -sin, 0.0),
+            frame: across(right, direction),
+        }
+    }
+
+    /// The fraction of unpolarized light arriving along the path that reaches the camera, apart
+    /// from the path's throughput.
+    pub fn intensity(&self) -> f64 {
+        self.sensitivity.x
+    }
+
+    /// Account for a path turning from `incoming` to `outgoing` at a hit on `material`. Glass
+    /// polarizes the light it reflects and refracts by Fresnel's equations, metals reflect every
+    /// polarization alike, and other materials scatter light evenly into every polarization.
+    ///
+    /// The material has already weighed the path by the share of unpolarized light it scatters,
+    /// so only the difference polarization makes is applied here.
+    pub fn scatter(
+        &mut self,
+        material: &Material,
+        hit: &Hit,
+        incoming: &Vector3<f64>,
+        outgoing: &Vector3<f64>,
+    ) {
+        let incoming = incoming.normalize();
+        let mueller = match material {
+            Material::Dielectric { ir, .. } => {
+                let eta = if hit.front_face { 1.0 / ir } else { *ir };
+                let cos_theta = (-incoming).dot(&hit.normal).clamp(0.0, 1.0);
+                fresnel(cos_theta, eta, outgoing.dot(&hit.normal) > 0.0)
+            }
+            Material::Metal { .. } => Matrix4::from_diagonal(&Vector4::new(1.0, 1.0, -1.0, -1.0)),
+            _ => {
+                self.depolarize(outgoing);
+                return;
+            }
+        };
+
+        // Fresnel's equations are given for polarization across the plane of incidence, along the
+        // normal of that plane.
+        let plane_normal = incoming.cross(&hit.normal);
+        if plane_normal.magnitude_squared() > 1e-12 {
+            self.rotate(&incoming, &plane_normal.normalize());
+        }
+        if mueller.m11 > 1e-12 {
+            self.sensitivity = mueller.tr_mul(&self.sensitivity) / mueller.m11;
+        }
+        self.frame = across(&self.frame, outgoing);
+    }
+
+    /// Account for light scattered evenly into every polarization, and leaving along `outgoing`.
+    pub fn depolarize(&mut self, outgoing: &Vector3<f64>) {
+        self.sensitivity = Vector4::new(self.sensitivity.x, 0.0, 0.0, 0.0);
+        self.frame = across(&self.frame, outgoing);
+    }
+
+    /// Measure polarization along `axis` instead of `frame`, both across `direction`.
+    fn rotate(&mut self, direction: &Vector3<f64>, axis: &Vector3<f64>) {
+        let down = direction.cross(&self.frame);
+        let angle = axis.dot(&down).atan2(axis.dot(&self.frame));
+        let (sin, cos) = (2.0 * angle).sin_cos();
+        let (q, u) = (self.sensitivity.y, self.sensitivity.z);
+        self.sensitivity.y = cos * q + sin * u;
+        self.sensitivity.z = -sin * q + cos * u;
+        self.frame = *axis;
+    }
+}
+
+/// The unit vector nearest `axis` across the unit vector `direction`, or any unit vector across it
+/// if `axis` lies along it.
+fn across(axis: &Vector3<f64>, direction: &Vector3<f64>) -> Vector3<f64> {
+    let direction = direction.normalize();
+    let projected = axis - axis.dot(&direction) * direction;
+    if projected.magnitude_squared() > 1e-12 {
+        return projected.normalize();
+    }
+    let other = if direction.x.abs() < 0.9 {
+        Vector3::x()
+    } else {
+        Vector3::y()
+    };
+    direction.cross(&other).normalize()
+}
+
+/// The Mueller matrix of light reflected, or refracted if `reflected` is false, at an interface
+/// where light arrives at `cos_theta` to the normal and `eta` is the ratio of the index of
+/// refraction it comes from to the one beyond. Polarization is measured across the plane of
+/// incidence.
+fn fresnel(cos_theta: f64, eta: f64, reflected: bool) -> Matrix4<f64> {
+    let sin2_transmitted = eta * eta * (1.0 - cos_theta * cos_theta);
+    if sin2_transmitted >= 1.0 {
+        // Total internal reflection reflects both polarizations fully, but shifts their phases
+        // apart, turning linear polarization partly circular.
+        let sin2 = 1.0 - cos_theta * cos_theta;
+        let ratio = 1.0 / eta;
+        let delta =
+            2.0 * (cos_theta * (sin2 - ratio * ratio).max(0.0).sqrt() / sin2.max(1e-12)).atan();
+        let (sin, cos) = delta.sin_cos();
+        return Matrix4::new(
+            1.0, 0.0, 0.0, 0.0, //
+            0.0, 1.0, 0.0, 0.0, //
+            0.0, 0.0, cos, sin, //
+            0.0, 0.0, -sin, cos,
+        );
+    }
+
+    let cos_transmitted = (1.0 - sin2_transmitted).sqrt();
+    let (s, p, scale) = if reflected {
+        (
+            (eta * cos_theta - cos_transmitted) / (eta * cos_theta + cos_transmitted),
+            (cos_theta - eta * cos_transmitted) / (cos_theta + eta * cos_transmitted),
+            1.0,
+        )
+    } else {
+        (
+            2.0 * eta * cos_theta / (eta * cos_theta + cos_transmitted),
+            2.0 * eta * cos_theta / (cos_theta + eta * cos_transmitted),
+            // the beam is narrowed or widened by refraction, along with the light's speed
+            cos_transmitted / (eta * cos_theta).max(1e-12),
+        )
+    };
+
+    let (sum, difference, product) = (
+        0.5 * scale * (s * s + p * p),
+        0.5 * scale * (s * s - p * p),
+        scale * s * p,
+    );
+    Matrix4::new(
+        sum, difference, 0.0, 0.0, //
+        difference, sum, 0.0, 0.0, //
+        0.0, 0.0, product, 0.0, //
+        0.0, 0.0, 0.0, product,
+    )
+}
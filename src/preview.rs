@@ -0,0 +1,290 @@
+//! Renders a scene's materials on a shader ball under a fixed studio setup, for comparing them
+//! apart from the scenes they are used in.
+
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use image::Rgb32FImage;
+use rtk::{
+    builder::SceneBuilder,
+    plugin::Plugins,
+    render::{self, Renderer},
+    scene::{Camera, Input, Material, MaterialOverrides, Node, Rgb, Scene},
+};
+use serde::Deserialize;
+
+use crate::{cli::PreviewMaterialArgs, print_warning};
+
+/// The samples per pixel of each pass of a watched preview, which are averaged until there are
+/// `--samples` of them.
+const PASS_SAMPLES: u32 = 4;
+
+/// How often a watched preview that has finished accumulating checks for new overrides.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Parameters of previewed materials to replace, read from the file given to `--watch`:
+///
+/// ```toml
+/// [materials.2]
+/// albedo = [0.8, 0.3, 0.1]
+/// ir = 1.45
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Overrides {
+    /// Overrides by the index of the material they apply to.
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialOverrides>,
+}
+
+impl Overrides {
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        let overrides: Self = toml::from_str(&std::fs::read_to_string(path)?)?;
+        for key in overrides.materials.keys() {
+            if key.parse::<usize>().is_err() {
+                anyhow::bail!("`{key}` is not the index of a material");
+            }
+        }
+
+        Ok(overrides)
+    }
+
+    fn get(&self, material: usize) -> Option<&MaterialOverrides> {
+        self.materials.get(&material.to_string())
+    }
+}
+
+/// The studio's camera, looking down slightly at the ball from the front. Its image size and
+/// samples are replaced by the command's options.
+const STUDIO_CAMERA: &str = r#"
+image-dimensions = [256, 256]
+background-color = [0.18, 0.18, 0.2]
+position = [0.0, 1.6, 4.5]
+rotation = { type = "direction", x = 0.0, y = -0.14, z = -1.0 }
+fov = 32.0
+"#;
+
+/// Handle `cli::Command::PreviewMaterial`.
+///
+/// With `--all`, the thumbnails are laid out in a grid, left to right and top to bottom in the
+/// order of the scene's materials.
+pub fn preview_material(args: &PreviewMaterialArgs) -> anyhow::Result<()> {
+    let scene = crate::load_scene(&args.scene)?;
+    scene.validate()?;
+
+    let materials = match args.material {
+        Some(material) if material >= scene.materials.len() => anyhow::bail!(
+            "the scene has no material {material}; it has {} materials",
+            scene.materials.len()
+        ),
+        Some(material) => vec![material],
+        None => (0..scene.materials.len()).collect(),
+    };
+    if materials.is_empty() {
+        anyhow::bail!("the scene has no materials");
+    }
+
+    if let Some(path) = &args.watch {
+        return watch(&scene, &materials, args, path);
+    }
+
+    match materials[..] {
+        [material] => eprintln!("Rendering material {material}..."),
+        _ => eprintln!("Rendering {} materials...", materials.len()),
+    }
+    let grid = render_grid(
+        &scene,
+        &materials,
+        &Overrides::default(),
+        args,
+        args.samples,
+        0,
+    )?;
+    render::to_display(&grid).save(&args.output)?;
+    eprintln!("Wrote {}", args.output.display());
+    Ok(())
+}
+
+/// Render the preview in passes, rewriting the output after each one, and start over whenever the
+/// overrides file at `path` changes. This runs until interrupted.
+fn watch(
+    scene: &Scene,
+    materials: &[usize],
+    args: &PreviewMaterialArgs,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let mut modified: Option<SystemTime> = None;
+    let mut overrides = Overrides::default();
+    let mut sum: Option<Rgb32FImage> = None;
+    let mut passes = 0;
+
+    eprintln!(
+        "Watching {} for overrides; press Ctrl-C to stop",
+        path.display()
+    );
+    loop {
+        let now_modified = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if now_modified != modified {
+            modified = now_modified;
+            // A file that is deleted, or half written and unreadable, keeps the last overrides.
+            match Overrides::load(path) {
+                Ok(new) => {
+                    overrides = new;
+                    (sum, passes) = (None, 0);
+                    eprintln!("Read {}; restarting", path.display());
+                }
+                Err(e) if modified.is_some() => {
+                    print_warning(&format!("failed to read {}: {e}", path.display()));
+                }
+                Err(_) => {}
+            }
+        }
+
+        if passes * PASS_SAMPLES >= args.samples {
+            std::thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+
+        let pass = render_grid(
+            scene,
+            materials,
+            &overrides,
+            args,
+            PASS_SAMPLES,
+            passes.into(),
+        )?;
+        passes += 1;
+        let sum = match &mut sum {
+            Some(sum) => {
+                sum.iter_mut().zip(pass.iter()).for_each(|(a, b)| *a += b);
+                sum
+            }
+            None => sum.insert(pass),
+        };
+
+        let mut average = sum.clone();
+        average.iter_mut().for_each(|c| *c /= passes as f32);
+        render::to_display(&average).save(&args.output)?;
+        eprintln!(
+            "Wrote {} at {} samples per pixel",
+            args.output.display(),
+            passes * PASS_SAMPLES
+        );
+    }
+}
+
+/// Render `materials` of `scene` on the shader ball, laid out in a grid, with `samples` samples
+/// per pixel and the random seed `seed`.
+fn render_grid(
+    scene: &Scene,
+    materials: &[usize],
+    overrides: &Overrides,
+    args: &PreviewMaterialArgs,
+    samples: u32,
+    seed: u64,
+) -> anyhow::Result<Rgb32FImage> {
+    let columns = (materials.len() as f64).sqrt().ceil() as u32;
+    let rows = (materials.len() as u32).div_ceil(columns);
+    let mut grid = Rgb32FImage::new(columns * args.size, rows * args.size);
+    for (i, &material) in materials.iter().enumerate() {
+        let thumbnail = render_thumbnail(
+            scene,
+            material,
+            overrides.get(material),
+            args,
+            samples,
+            seed,
+        )?;
+        let (column, row) = (i as u32 % columns, i as u32 / columns);
+        image::imageops::replace(
+            &mut grid,
+            &thumbnail,
+            (column * args.size).into(),
+            (row * args.size).into(),
+        );
+    }
+
+    Ok(grid)
+}
+
+/// Render material `material` of `scene` on the shader ball, with some of its parameters replaced
+/// by `overrides`.
+fn render_thumbnail(
+    scene: &Scene,
+    material: usize,
+    overrides: Option<&MaterialOverrides>,
+    args: &PreviewMaterialArgs,
+    samples: u32,
+    seed: u64,
+) -> anyhow::Result<Rgb32FImage> {
+    let mut camera: Camera = toml::from_str(STUDIO_CAMERA)?;
+    camera.image_dimensions = (args.size, args.size);
+    camera.samples_per_pixel = Some(samples);
+    camera.seed = seed;
+
+    // The scene's materials and textures keep their indices, which mixes and graphs refer to.
+    let mut builder = SceneBuilder::new(camera);
+    let handles = scene
+        .materials
+        .iter()
+        .map(|entry| builder.add_material(entry.material.clone()))
+        .collect::<Vec<_>>();
+    for texture in &scene.textures {
+        builder.add_texture(texture.clone());
+    }
+
+    let floor = builder.add_material(checker_floor());
+    let light = builder.add_material(Material::Light {
+        color: Rgb(6.0, 6.0, 6.0),
+    });
+    let rim = builder.add_material(Material::Light {
+        color: Rgb(3.0, 3.0, 3.5),
+    });
+
+    let ball = builder.add_sphere((0.0, 1.0, 0.0), 1.0, handles[material]);
+    builder.object_mut(ball).material_overrides = overrides.cloned();
+    builder.add_quad(
+        (-20.0, 0.0, 20.0),
+        (40.0, 0.0, 0.0),
+        (0.0, 0.0, -40.0),
+        floor,
+    );
+    // A large key light above and to the left, and a thin rim light behind to the right.
+    builder.add_quad((-4.0, 5.0, 2.0), (3.0, 0.0, 0.0), (0.0, 0.0, -3.0), light);
+    builder.add_quad((2.5, 0.5, -3.0), (0.0, 3.0, 0.0), (1.0, 0.0, 0.5), rim);
+
+    let studio = builder.build()?;
+    let world = studio.create_world(&Plugins::new())?;
+    let renderer = Renderer::new(studio.create_camera());
+    Ok(renderer.render_hdr(&world, !args.no_parallel, |_| {})?)
+}
+
+/// A grey checkerboard, which shows off reflections and refractions.
+fn checker_floor() -> Material {
+    let nodes = BTreeMap::from([
+        (
+            "checker".to_string(),
+            Node::Checker {
+                scale: 0.5,
+                even: Input::Color(Rgb(0.35, 0.35, 0.35)),
+                odd: Input::Color(Rgb(0.1, 0.1, 0.1)),
+            },
+        ),
+        (
+            "floor".to_string(),
+            Node::Diffuse {
+                albedo: Input::Node("checker".to_string()),
+            },
+        ),
+    ]);
+
+    Material::Graph {
+        output: "floor".to_string(),
+        nodes,
+    }
+}
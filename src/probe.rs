@@ -0,0 +1,371 @@
+//! Renders light probes, the light arriving from every direction at points of a scene, for
+//! real-time engines to light moving objects with. They are written as cubemaps in a DDS or KTX2
+//! file, or as spherical harmonics in a PLY file.
+
+use std::{f64::consts::PI, fmt::Write as _, path::Path, sync::Mutex};
+
+use nalgebra::Vector3;
+use rand::Rng;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rtk::{
+    object::World,
+    plugin::Plugins,
+    random,
+    render::{Color, ProgressEvent, Ray, Renderer, Stage},
+};
+
+use crate::{
+    cli::ProbeArgs,
+    status::{ProgressDisplay, StatusLine},
+};
+
+/// The number of coefficients of spherical harmonics up to band 2, for each color channel.
+const SH_COEFFICIENTS: usize = 9;
+
+/// The faces of a cube around a probe, in the order cubemaps store them: +X, -X, +Y, -Y, +Z, -Z.
+/// Each gives the direction through a point `(u, v)` of the face, from -1 to 1 rightwards and
+/// downwards, as in OpenGL and Direct3D.
+const FACES: [fn(f64, f64) -> Vector3<f64>; 6] = [
+    |u, v| Vector3::new(1.0, -v, -u),
+    |u, v| Vector3::new(-1.0, -v, u),
+    |u, v| Vector3::new(u, 1.0, v),
+    |u, v| Vector3::new(u, -1.0, -v),
+    |u, v| Vector3::new(u, -v, 1.0),
+    |u, v| Vector3::new(-u, -v, -1.0),
+];
+
+/// The formats probes are written in, chosen by the output's extension.
+enum Format {
+    Dds,
+    Ktx2,
+    Ply,
+}
+
+/// The six faces of a cube of light around a probe, each `size` by `size` pixels in rows.
+struct Cubemap {
+    size: u32,
+    faces: [Vec<Color>; 6],
+}
+
+/// Handle `cli::Command::Probe`.
+pub fn probe(args: &ProbeArgs) -> anyhow::Result<()> {
+    let extension = args
+        .output
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    let format = match extension.as_deref() {
+        Some("dds") => Format::Dds,
+        Some("ktx2") => Format::Ktx2,
+        Some("ply") => Format::Ply,
+        _ => anyhow::bail!(
+            "probes are written as cubemaps to .dds or .ktx2 files or spherical harmonics to .ply \
+             files, not to {}",
+            args.output.display()
+        ),
+    };
+
+    let scene = crate::load_scene(&args.scene)?;
+    scene.validate()?;
+    let mut world = scene.create_world(&Plugins::new())?;
+    let camera = scene.create_camera();
+    let seed = camera.seed;
+    let samples = args.samples.unwrap_or(camera.samples_per_pixel).max(1);
+    let renderer = Renderer::new(camera);
+
+    let mut status = StatusLine::new();
+    status.update(&ProgressEvent::Stage(Stage::Rendering));
+    let rows = args.points.len() as u32 * 6 * args.size;
+    let progress = Mutex::new((status, 0, 0));
+    let cubemaps = args
+        .points
+        .iter()
+        .enumerate()
+        .map(|(i, &(x, y, z))| {
+            let origin = Vector3::new(x, y, z);
+            // Objects with visible ranges are shown as they would be seen from the probe.
+            world.view_from(origin);
+            let probe = Probe {
+                world: &world,
+                renderer: &renderer,
+                size: args.size,
+                samples,
+            };
+            let render_row = |face: usize, y: u32| {
+                // Each probe and face is seeded apart, so that they do not repeat each other's
+                // noise.
+                let seed = seed ^ random::splitmix64((i * FACES.len() + face) as u64);
+                let row = probe.render_row(origin, face, y, seed);
+
+                let mut progress = progress.lock().unwrap();
+                progress.1 += 1;
+                let percent = progress.1 * 100 / rows;
+                if percent > progress.2 {
+                    progress.2 = percent;
+                    progress.0.update(&ProgressEvent::Rendering(percent));
+                }
+                row
+            };
+            let render_face = |face: usize| {
+                let rows: Vec<Vec<Color>> = if args.no_parallel {
+                    (0..args.size).map(|y| render_row(face, y)).collect()
+                } else {
+                    (0..args.size)
+                        .into_par_iter()
+                        .map(|y| render_row(face, y))
+                        .collect()
+                };
+                rows.concat()
+            };
+
+            Cubemap {
+                size: args.size,
+                faces: std::array::from_fn(render_face),
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut status = progress.into_inner().unwrap().0;
+
+    status.update(&ProgressEvent::Stage(Stage::Writing));
+    match format {
+        Format::Dds => write_dds(&args.output, &cubemaps)?,
+        Format::Ktx2 => write_ktx2(&args.output, &cubemaps)?,
+        Format::Ply => write_ply(&args.output, &args.points, &cubemaps)?,
+    }
+    status.finish(&format!("Wrote {}", args.output.display()));
+
+    Ok(())
+}
+
+/// What renders the faces of probes.
+struct Probe<'a> {
+    world: &'a World,
+    renderer: &'a Renderer,
+    /// The width and height of each face, in pixels.
+    size: u32,
+    samples: u32,
+}
+
+impl Probe<'_> {
+    /// Render row `y` of face `face` of the cube around `origin`.
+    fn render_row(&self, origin: Vector3<f64>, face: usize, y: u32, seed: u64) -> Vec<Color> {
+        (0..self.size)
+            .map(|x| {
+                random::seed_pixel(seed, x, y);
+                let mut rng = random::rng();
+                let mut color = Color::zeros();
+                for _ in 0..self.samples {
+                    let u = 2.0 * (x as f64 + rng.gen_range(0.0..1.0)) / self.size as f64 - 1.0;
+                    let v = 2.0 * (y as f64 + rng.gen_range(0.0..1.0)) / self.size as f64 - 1.0;
+                    let ray = Ray {
+                        origin,
+                        direction: FACES[face](u, v).normalize(),
+                        time: 0.0,
+                    };
+                    color += self.renderer.ray_color(self.world, ray);
+                }
+                color / self.samples as f64
+            })
+            .collect()
+    }
+}
+
+/// Write the cubemaps as an array of cubes in a DDS file, in 32-bit floating-point RGBA.
+fn write_dds(path: &Path, cubemaps: &[Cubemap]) -> anyhow::Result<()> {
+    const HEADER_SIZE: u32 = 124;
+    const PIXEL_FORMAT_SIZE: u32 = 32;
+    // the caps, height, width, pitch, and pixel format fields are set
+    const FLAGS: u32 = 0x1 | 0x2 | 0x4 | 0x8 | 0x1000;
+    // the pixel format is given by a DX10 header
+    const FOURCC: u32 = 0x4;
+    // a complex texture
+    const CAPS: u32 = 0x1000 | 0x8;
+    // a cubemap with all six faces
+    const CAPS2: u32 = 0x200 | 0xFC00;
+    const DXGI_FORMAT_R32G32B32A32_FLOAT: u32 = 2;
+    const DIMENSION_TEXTURE2D: u32 = 3;
+    const MISC_TEXTURECUBE: u32 = 0x4;
+
+    let size = cubemaps.first().map_or(0, |cubemap| cubemap.size);
+    let mut header = vec![
+        HEADER_SIZE,
+        FLAGS,
+        size,
+        size,
+        size * 4 * size_of::<f32>() as u32,
+        0,
+        1,
+    ];
+    header.extend([0; 11]);
+    header.extend([PIXEL_FORMAT_SIZE, FOURCC, u32::from_le_bytes(*b"DX10")]);
+    header.extend([0; 5]);
+    header.extend([CAPS, CAPS2, 0, 0, 0]);
+    header.extend([
+        DXGI_FORMAT_R32G32B32A32_FLOAT,
+        DIMENSION_TEXTURE2D,
+        MISC_TEXTURECUBE,
+        cubemaps.len() as u32,
+        0,
+    ]);
+
+    let mut data = b"DDS ".to_vec();
+    data.extend(header.into_iter().flat_map(u32::to_le_bytes));
+    for face in cubemaps.iter().flat_map(|cubemap| &cubemap.faces) {
+        for color in face {
+            let rgba = [color.x as f32, color.y as f32, color.z as f32, 1.0];
+            data.extend(rgba.into_iter().flat_map(f32::to_le_bytes));
+        }
+    }
+    std::fs::write(path, data)?;
+
+    Ok(())
+}
+
+/// Write the cubemaps as an array of cubes in a KTX2 file, in 32-bit floating-point RGBA with
+/// linear BT.709 colors.
+fn write_ktx2(path: &Path, cubemaps: &[Cubemap]) -> anyhow::Result<()> {
+    const IDENTIFIER: [u8; 12] = *b"\xABKTX 20\xBB\r\n\x1A\n";
+    const VK_FORMAT_R32G32B32A32_SFLOAT: u32 = 109;
+    // the identifier, the header, the index, and one level's entry in the level index
+    const DESCRIPTOR_OFFSET: u32 = 12 + 9 * 4 + 4 * 4 + 2 * 8 + 3 * 8;
+    // the total size, then a basic block: its 24-byte header and a 16-byte sample per channel
+    const DESCRIPTOR_SIZE: u32 = 4 + 24 + 4 * 16;
+    const MODEL_RGBSDA: u8 = 1;
+    const PRIMARIES_BT709: u8 = 1;
+    const TRANSFER_LINEAR: u8 = 1;
+    // each sample's channel is a signed float
+    const SIGNED_FLOAT: u8 = 0x40 | 0x80;
+    const ALPHA: u8 = 15;
+
+    let size = cubemaps.first().map_or(0, |cubemap| cubemap.size);
+    let texel_size = 4 * size_of::<f32>() as u32;
+    // The level's data is aligned to the size of a texel.
+    let data_offset = (DESCRIPTOR_OFFSET + DESCRIPTOR_SIZE).next_multiple_of(texel_size);
+    let data_size = (cubemaps.len() * 6) as u64 * (size * size * texel_size) as u64;
+    // A single cube is not an array, which is written as no layers.
+    let layers = if cubemaps.len() > 1 {
+        cubemaps.len() as u32
+    } else {
+        0
+    };
+
+    let mut data = IDENTIFIER.to_vec();
+    let header = [
+        VK_FORMAT_R32G32B32A32_SFLOAT,
+        4,
+        size,
+        size,
+        0,
+        layers,
+        6,
+        1,
+        0,
+    ];
+    data.extend(header.into_iter().flat_map(u32::to_le_bytes));
+    // the data format descriptor, and no key-value or supercompression data
+    let index = [DESCRIPTOR_OFFSET, DESCRIPTOR_SIZE, 0, 0];
+    data.extend(index.into_iter().flat_map(u32::to_le_bytes));
+    data.extend([0u64, 0].into_iter().flat_map(u64::to_le_bytes));
+    let levels = [data_offset as u64, data_size, data_size];
+    data.extend(levels.into_iter().flat_map(u64::to_le_bytes));
+
+    data.extend(DESCRIPTOR_SIZE.to_le_bytes());
+    // the vendor and type of the block, 0 for a basic block, its version, and its size
+    data.extend(0u32.to_le_bytes());
+    data.extend(2u16.to_le_bytes());
+    data.extend((DESCRIPTOR_SIZE as u16 - 4).to_le_bytes());
+    data.extend([MODEL_RGBSDA, PRIMARIES_BT709, TRANSFER_LINEAR, 0]);
+    // a texel is one pixel, in one plane of 16 bytes
+    data.extend([0, 0, 0, 0, texel_size as u8, 0, 0, 0, 0, 0, 0, 0]);
+    for (i, channel) in [0, 1, 2, ALPHA].into_iter().enumerate() {
+        let bit_offset = (i * 32) as u16;
+        data.extend(bit_offset.to_le_bytes());
+        data.extend([31, channel | SIGNED_FLOAT, 0, 0, 0, 0]);
+        data.extend((-1f32).to_le_bytes());
+        data.extend(1f32.to_le_bytes());
+    }
+    data.resize(data_offset as usize, 0);
+
+    for face in cubemaps.iter().flat_map(|cubemap| &cubemap.faces) {
+        for color in face {
+            let rgba = [color.x as f32, color.y as f32, color.z as f32, 1.0];
+            data.extend(rgba.into_iter().flat_map(f32::to_le_bytes));
+        }
+    }
+    std::fs::write(path, data)?;
+
+    Ok(())
+}
+
+/// Write a point for each probe to a PLY file, with the spherical harmonics coefficients of the
+/// light arriving at it as properties.
+fn write_ply(path: &Path, points: &[(f64, f64, f64)], cubemaps: &[Cubemap]) -> anyhow::Result<()> {
+    let mut ply = String::from("ply\nformat ascii 1.0\n");
+    ply.push_str(
+        "comment real spherical harmonics of the radiance arriving at each probe, bands 0 to 2\n",
+    );
+    writeln!(ply, "element vertex {}", points.len())?;
+    for axis in ["x", "y", "z"] {
+        writeln!(ply, "property float {axis}")?;
+    }
+    for coefficient in 0..SH_COEFFICIENTS {
+        for channel in ["r", "g", "b"] {
+            writeln!(ply, "property float sh{coefficient}_{channel}")?;
+        }
+    }
+    ply.push_str("end_header\n");
+
+    for (&(x, y, z), cubemap) in points.iter().zip(cubemaps) {
+        write!(ply, "{x} {y} {z}")?;
+        for coefficient in spherical_harmonics(cubemap) {
+            write!(
+                ply,
+                " {} {} {}",
+                coefficient.x, coefficient.y, coefficient.z
+            )?;
+        }
+        ply.push('\n');
+    }
+    std::fs::write(path, ply)?;
+
+    Ok(())
+}
+
+/// Project the light of a cubemap onto the real spherical harmonics of bands 0 to 2.
+fn spherical_harmonics(cubemap: &Cubemap) -> [Color; SH_COEFFICIENTS] {
+    let mut coefficients = [Color::zeros(); SH_COEFFICIENTS];
+    let mut total_weight = 0.0;
+    for (face, colors) in cubemap.faces.iter().enumerate() {
+        for (i, color) in colors.iter().enumerate() {
+            let (x, y) = (i as u32 % cubemap.size, i as u32 / cubemap.size);
+            let u = 2.0 * (x as f64 + 0.5) / cubemap.size as f64 - 1.0;
+            let v = 2.0 * (y as f64 + 0.5) / cubemap.size as f64 - 1.0;
+            // Pixels toward a face's corners cover less of the sphere.
+            let weight = (1.0 + u * u + v * v).powf(-1.5);
+            let direction = FACES[face](u, v).normalize();
+
+            for (coefficient, basis) in coefficients.iter_mut().zip(sh_basis(&direction)) {
+                *coefficient += color * basis * weight;
+            }
+            total_weight += weight;
+        }
+    }
+
+    let scale = 4.0 * PI / total_weight;
+    coefficients.map(|coefficient| coefficient * scale)
+}
+
+/// The real spherical harmonics of bands 0 to 2 in a unit direction.
+fn sh_basis(d: &Vector3<f64>) -> [f64; SH_COEFFICIENTS] {
+    [
+        0.282095,
+        0.488603 * d.y,
+        0.488603 * d.z,
+        0.488603 * d.x,
+        1.092548 * d.x * d.y,
+        1.092548 * d.y * d.z,
+        0.315392 * (3.0 * d.z * d.z - 1.0),
+        1.092548 * d.x * d.z,
+        0.546274 * (d.x * d.x - d.y * d.y),
+    ]
+}
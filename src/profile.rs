@@ -0,0 +1,113 @@
+//! Lightweight timing of the stages of rendering.
+//!
+//! Code marks a stage with `profile::scope`, which records how long the returned guard lives while
+//! profiling is enabled. Scopes are placed around whole batches of work, like tracing a wavefront,
+//! so that recording them costs little next to the work itself. Nothing is recorded until
+//! `enable` is called.
+
+use std::{
+    cell::Cell,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+/// A stage of rendering that is timed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Scope {
+    /// Building the world from the scene, including its acceleration structures.
+    Build,
+    /// Finding what rays hit.
+    Traversal,
+    /// Evaluating materials and lights at hits.
+    Shading,
+    /// Reconstructing pixels from samples, and denoising.
+    Filter,
+    /// Reading scenes and writing images.
+    Io,
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Scope::Build => "build",
+            Scope::Traversal => "traversal",
+            Scope::Shading => "shading",
+            Scope::Filter => "filter",
+            Scope::Io => "io",
+        })
+    }
+}
+
+/// One timed run of a scope.
+#[derive(Clone, Copy, Debug)]
+pub struct Event {
+    pub scope: Scope,
+    /// A small number identifying the thread the scope ran on.
+    pub thread: u32,
+    /// When the scope began, relative to when profiling was enabled.
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+static EVENTS: Mutex<Vec<Event>> = Mutex::new(Vec::new());
+static NEXT_THREAD: AtomicU32 = AtomicU32::new(0);
+
+thread_local! {
+    static THREAD: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+/// Start recording scopes.
+pub fn enable() {
+    EPOCH.get_or_init(Instant::now);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Take the events recorded so far, in the order they ended.
+pub fn take_events() -> Vec<Event> {
+    std::mem::take(&mut EVENTS.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// Time a scope until the returned guard is dropped.
+pub fn scope(scope: Scope) -> ScopeGuard {
+    ScopeGuard {
+        scope,
+        start: ENABLED.load(Ordering::Relaxed).then(Instant::now),
+    }
+}
+
+/// Records a scope when dropped; see `scope`.
+#[must_use = "the scope ends when the guard is dropped"]
+pub struct ScopeGuard {
+    scope: Scope,
+    start: Option<Instant>,
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        let Some(start) = self.start else {
+            return;
+        };
+        let epoch = *EPOCH.get_or_init(Instant::now);
+        let thread = THREAD.with(|thread| match thread.get() {
+            Some(id) => id,
+            None => {
+                let id = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+                thread.set(Some(id));
+                id
+            }
+        });
+
+        let event = Event {
+            scope: self.scope,
+            thread,
+            start: start.saturating_duration_since(epoch),
+            duration: start.elapsed(),
+        };
+        EVENTS.lock().unwrap_or_else(|e| e.into_inner()).push(event);
+    }
+}
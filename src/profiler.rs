@@ -0,0 +1,55 @@
+//! Reports the timings recorded by `rtk::profile`.
+
+use std::{collections::BTreeMap, path::Path, time::Duration};
+
+use rtk::profile::{Event, Scope};
+
+/// Print the total time spent in each scope, and how often it ran.
+pub fn print_summary(events: &[Event]) {
+    let mut totals = BTreeMap::<Scope, (Duration, u64)>::new();
+    for event in events {
+        let (duration, count) = totals.entry(event.scope).or_default();
+        *duration += event.duration;
+        *count += 1;
+    }
+    let total: Duration = totals.values().map(|(duration, _)| *duration).sum();
+
+    eprintln!("Instruction set: {}", rtk::dispatch::instruction_set());
+    eprintln!(
+        "{:<10} {:>10} {:>7} {:>9}",
+        "scope", "time", "share", "calls"
+    );
+    for (scope, (duration, count)) in totals {
+        let share = if total.is_zero() {
+            0.0
+        } else {
+            duration.as_secs_f64() / total.as_secs_f64() * 100.0
+        };
+        eprintln!(
+            "{:<10} {:>9.3}s {share:>6.1}% {count:>9}",
+            scope.to_string(),
+            duration.as_secs_f64()
+        );
+    }
+}
+
+/// Write the events as complete events in the Chrome trace event format.
+pub fn write_chrome_trace(events: &[Event], path: &Path) -> anyhow::Result<()> {
+    let trace_events = events
+        .iter()
+        .map(|event| {
+            serde_json::json!({
+                "name": event.scope.to_string(),
+                "ph": "X",
+                "ts": event.start.as_secs_f64() * 1e6,
+                "dur": event.duration.as_secs_f64() * 1e6,
+                "pid": 1,
+                "tid": event.thread,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let trace = serde_json::json!({ "traceEvents": trace_events });
+    std::fs::write(path, serde_json::to_string(&trace)?)?;
+    Ok(())
+}
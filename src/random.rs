@@ -0,0 +1,207 @@
+//! The random numbers used for sampling.
+//!
+//! Each sample of each pixel is traced with its own random stream, seeded from the pixel's
+//! coordinates, the sample's number, and the scene's seed, so images are identical no matter how
+//! many threads render them or in what order. Anything that samples during rendering, including
+//! plugins, should draw from `rng()`.
+
+use std::cell::RefCell;
+
+use rand::{Rng, RngCore, SeedableRng};
+use rand_pcg::Pcg64Mcg;
+
+thread_local! {
+    static STREAM: RefCell<Pcg64Mcg> = RefCell::new(Pcg64Mcg::seed_from_u64(0));
+}
+
+/// A handle to the current thread's random stream, which the renderer reseeds for each pixel.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StreamRng;
+
+/// Get the current thread's random stream.
+pub fn rng() -> StreamRng {
+    StreamRng
+}
+
+/// Restart the current thread's stream for the pixel at (x, y).
+pub fn seed_pixel(seed: u64, x: u32, y: u32) {
+    let key = splitmix64(seed) ^ ((x as u64) << 32 | y as u64);
+    STREAM.with(|stream| *stream.borrow_mut() = Pcg64Mcg::seed_from_u64(splitmix64(key)));
+}
+
+/// A random stream that can be set aside while others are used, so that each of the paths traced
+/// together on a thread keeps its own.
+pub struct Stream(Pcg64Mcg);
+
+impl Stream {
+    /// The stream for sample `sample` of the pixel at (x, y).
+    pub fn new(seed: u64, x: u32, y: u32, sample: u32) -> Self {
+        let key = splitmix64(seed) ^ ((x as u64) << 32 | y as u64);
+        Self(Pcg64Mcg::seed_from_u64(splitmix64(
+            splitmix64(key) ^ sample as u64,
+        )))
+    }
+}
+
+/// Run `f` with `stream` as the current thread's stream, then set it aside again.
+pub fn with_stream<T>(stream: &mut Stream, f: impl FnOnce() -> T) -> T {
+    STREAM.with(|current| std::mem::swap(&mut *current.borrow_mut(), &mut stream.0));
+    let result = f();
+    STREAM.with(|current| std::mem::swap(&mut *current.borrow_mut(), &mut stream.0));
+    result
+}
+
+impl RngCore for StreamRng {
+    fn next_u32(&mut self) -> u32 {
+        STREAM.with(|stream| stream.borrow_mut().next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        STREAM.with(|stream| stream.borrow_mut().next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        STREAM.with(|stream| stream.borrow_mut().fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        STREAM.with(|stream| stream.borrow_mut().try_fill_bytes(dest))
+    }
+}
+
+/// The bases of the Halton sequence's dimensions; see `PixelSampler`.
+const HALTON_BASES: [u32; 5] = [2, 3, 5, 7, 11];
+
+/// Low-discrepancy points for the camera's sample dimensions: the position within the pixel, on
+/// the lens, and in the exposure. These cover their domain far more evenly than random points,
+/// converging faster.
+///
+/// Every pixel would otherwise use the same points, making the error of neighboring pixels
+/// correlated and showing up as structured patterns, so each pixel's points are shifted by a random
+/// offset from its stream (a Cranley-Patterson rotation).
+pub struct PixelSampler {
+    offsets: [f64; HALTON_BASES.len()],
+}
+
+impl PixelSampler {
+    /// Create the sampler for the current pixel, after its stream has been seeded.
+    pub fn new() -> Self {
+        let mut rng = rng();
+        Self {
+            offsets: std::array::from_fn(|_| rng.gen_range(0.0..1.0)),
+        }
+    }
+
+    /// Coordinate `dimension` of point `index`, in [0, 1).
+    /// Dimensions 0 and 1 are for the pixel, 2 and 3 for the lens, and 4 for the exposure.
+    pub fn sample(&self, index: u32, dimension: usize) -> f64 {
+        let value = radical_inverse(HALTON_BASES[dimension], index) + self.offsets[dimension];
+        value - value.floor()
+    }
+}
+
+impl Default for PixelSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Mirror the digits of `index` in `base` around the radix point.
+fn radical_inverse(base: u32, mut index: u32) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut scale = inverse_base;
+    let mut result = 0.0;
+    while index > 0 {
+        result += (index % base) as f64 * scale;
+        index /= base;
+        scale *= inverse_base;
+    }
+
+    result
+}
+
+/// Picks an index at random in proportion to a weight for each, in constant time (Walker's alias
+/// method, built with Vose's algorithm). Built once when loading something sampled many times
+/// during rendering, such as the lights of a scene by their brightness, it replaces a binary
+/// search through the cumulative weights for every sample.
+#[derive(Clone, Debug)]
+pub struct AliasTable {
+    /// The chance of keeping each index when it is picked uniformly, rather than its alias.
+    keep: Vec<f64>,
+    alias: Vec<usize>,
+    /// The probability of each index.
+    probabilities: Vec<f64>,
+}
+
+impl AliasTable {
+    /// Build the table for `weights`, or return `None` if they do not add up to a positive, finite
+    /// total. Negative and NaN weights count as zero.
+    pub fn new(weights: &[f64]) -> Option<Self> {
+        let weights = weights
+            .iter()
+            .map(|&weight| if weight > 0.0 { weight } else { 0.0 })
+            .collect::<Vec<_>>();
+        let total: f64 = weights.iter().sum();
+        if !(total > 0.0 && total.is_finite()) {
+            return None;
+        }
+
+        let n = weights.len();
+        let probabilities = weights
+            .iter()
+            .map(|weight| weight / total)
+            .collect::<Vec<_>>();
+        // each index's share of a uniform pick, where 1 is an even share
+        let mut scaled = probabilities
+            .iter()
+            .map(|probability| probability * n as f64)
+            .collect::<Vec<_>>();
+        let (mut small, mut large): (Vec<_>, Vec<_>) = (0..n).partition(|&i| scaled[i] < 1.0);
+
+        let mut keep = vec![1.0; n];
+        let mut alias = (0..n).collect::<Vec<_>>();
+        while let (Some(&less), Some(&more)) = (small.last(), large.last()) {
+            small.pop();
+            keep[less] = scaled[less];
+            alias[less] = more;
+            // The larger index fills the rest of the smaller one's column.
+            scaled[more] -= 1.0 - scaled[less];
+            if scaled[more] < 1.0 {
+                large.pop();
+                small.push(more);
+            }
+        }
+        // Whatever is left has an even share, give or take rounding, and keeps its whole column.
+
+        Some(Self {
+            keep,
+            alias,
+            probabilities,
+        })
+    }
+
+    /// Pick an index, given a uniform random number in [0, 1).
+    pub fn sample(&self, u: f64) -> usize {
+        let n = self.keep.len();
+        let scaled = u * n as f64;
+        let column = (scaled as usize).min(n - 1);
+        if scaled - (column as f64) < self.keep[column] {
+            column
+        } else {
+            self.alias[column]
+        }
+    }
+
+    /// The probability of picking `index`.
+    pub fn probability(&self, index: usize) -> f64 {
+        self.probabilities[index]
+    }
+}
+
+/// A well-distributed 64-bit hash, used to derive random-looking values from IDs.
+pub fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
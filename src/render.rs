@@ -1,17 +1,39 @@
 use std::{
+    collections::HashMap,
     ops::Range,
-    sync::{mpsc, Mutex},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
 };
 
+use image::GenericImage;
 use nalgebra::{UnitQuaternion, Vector3};
 use rand::Rng;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
-use crate::object::World;
+use crate::{
+    dispatch::multiversion,
+    material::Material,
+    object::{Hit, World},
+    profile::{self, Scope},
+    random::{self, PixelSampler, Stream},
+};
+
+mod half;
+mod toon;
+mod wavefront;
+
+pub use half::HalfImage;
+pub use toon::Toon;
 
 pub struct Ray {
     pub origin: Vector3<f64>,
     pub direction: Vector3<f64>,
+    /// The moment of the camera's exposure the ray is traced at, from 0 at its start to 1 at its
+    /// end. Moving objects are hit where they are at that moment.
+    pub time: f64,
 }
 
 impl Ray {
@@ -22,6 +44,9 @@ impl Ray {
 
 pub type Color = Vector3<f64>;
 
+/// The width and height of the square tiles the image is divided into for rendering.
+pub const TILE_SIZE: u32 = 32;
+
 #[derive(Clone, Debug, Default)]
 pub struct Camera {
     pub image_width: u32,
@@ -33,13 +58,426 @@ pub struct Camera {
     pub focus_distance: f64,
     pub defocus_angle: f64,
     pub samples_per_pixel: u32,
+    /// Varies the random samples taken; renders with the same seed are identical.
+    pub seed: u64,
+    /// The roughness given to specular bounces after a path's first rough bounce, from 0 (off) to
+    /// 1. This removes fireflies from caustics at the cost of blurring them.
+    pub regularization: f64,
+    /// Scale the brightness of the finished image so that its average luminance is middle grey.
+    pub auto_exposure: bool,
+    /// Parts of the image that take more samples per pixel, such as a portrait's subject.
+    pub regions: Vec<SampleRegion>,
+    /// The part of moving objects' motion, from 0 at its start to 1 at its end, over which the
+    /// shutter is open. Each pixel's samples are spread over it, blurring moving objects and
+    /// drawing trails behind moving lights. Without one, every sample is taken at the start.
+    pub shutter: Option<(f64, f64)>,
+    /// The angle in degrees, from the image's horizontal toward its up, of a linear polarizing
+    /// filter over the lens, which traces the polarization of light along each path.
+    pub polarizer: Option<f64>,
+    /// The number of output pixels across each image pixel. Above 1, each pixel's samples are
+    /// spread over a block of output pixels by where they landed in the pixel, producing a sharper
+    /// image than upscaling for the same number of samples.
+    pub super_resolution: u32,
+    /// Render a view for each of two eyes, the left eye's in the left half of an image twice as
+    /// wide as `image_width`. Regions are given within one eye's view and apply to both.
+    pub stereo: Option<Stereo>,
+    /// Keep sampling each pixel past `samples_per_pixel` until its noise is low enough, instead of
+    /// taking the same number of samples everywhere.
+    pub adaptive: Option<AdaptiveSampling>,
+}
+
+/// Two eyes either side of the camera's position, looking the same way.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stereo {
+    /// The distance between the eyes.
+    pub separation: f64,
+    /// The distance from the camera at which the eyes' views line up. Objects there appear at the
+    /// depth of the screen, with nearer ones in front of it and farther ones behind.
+    pub convergence: f64,
+}
+
+/// When adaptive sampling stops taking samples of a pixel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveSampling {
+    /// The most samples taken of any pixel.
+    pub max_samples: u32,
+    /// The largest error allowed in a pixel's brightness, relative to the brightness. A pixel is
+    /// finished once its 95% confidence interval is within this fraction of its mean.
+    pub tolerance: f64,
+}
+
+/// A rectangle of pixels rendered with its own number of samples per pixel. Where regions overlap,
+/// the largest number is used.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SampleRegion {
+    pub x: Range<u32>,
+    pub y: Range<u32>,
+    pub samples_per_pixel: u32,
+}
+
+impl Camera {
+    /// The width, in world units, of `pixels` pixels on a plane facing the camera at `distance`
+    /// from it. This turns an error measured on screen into one in the world, for example for
+    /// `mesh::SimplifyLimits::max_error`.
+    pub fn pixel_size(&self, distance: f64, pixels: f64) -> f64 {
+        let viewport_height = 2.0 * distance * (self.fov.to_radians() / 2.0).tan();
+        pixels * viewport_height / self.image_height as f64
+    }
+}
+
+/// A step in producing an image, in the order they occur.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Building objects and materials from the scene description.
+    Loading,
+    Rendering,
+    #[cfg(feature = "denoise")]
+    Denoising,
+    Writing,
+}
+
+impl std::fmt::Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Stage::Loading => "Loading scene",
+            Stage::Rendering => "Rendering",
+            #[cfg(feature = "denoise")]
+            Stage::Denoising => "Denoising",
+            Stage::Writing => "Writing image",
+        })
+    }
+}
+
+/// A status update reported by the renderer to the callback given to `Renderer::render`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProgressEvent {
+    /// A new stage has begun.
+    Stage(Stage),
+    /// The percentage of pixels that have been rendered.
+    Rendering(u32),
+    /// A tile of the grid given by `Renderer::tile_grid` has been rendered.
+    TileFinished {
+        column: u32,
+        row: u32,
+        /// The coordinates of the tile's upper-left pixel in the output image.
+        x: u32,
+        y: u32,
+        /// The final pixels of the tile, `TILE_SIZE` times the camera's super-resolution across,
+        /// or smaller at the image's right and bottom edges.
+        pixels: image::RgbImage,
+        /// The linear colors the tile's pixels were converted from.
+        linear: image::Rgb32FImage,
+        /// The number of samples taken across the tile.
+        samples: u64,
+    },
+    /// Samples just taken of a tile, sent in batches as they are traced and before the tile's
+    /// `TileFinished` when samples are recorded; see `Renderer::set_record_samples`.
+    Samples(Vec<SampleRecord>),
+    /// The image is complete.
+    Finished,
+    /// The renderer panicked; the message is the panic payload, if it could be recovered.
+    Failed(String),
+    /// The render was stopped through a `RenderHandle`.
+    Cancelled,
+}
+
+/// A single sample of a render, with what its camera ray first hit, for reconstructing images
+/// outside of rtk; see `Renderer::set_record_samples`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SampleRecord {
+    /// The pixel the sample was taken in, in the image before any super-resolution.
+    pub pixel: (u32, u32),
+    /// Where in the pixel the sample was taken, from 0 to 1 across and down.
+    pub position: (f32, f32),
+    /// The light traced along the sample's path.
+    pub radiance: [f32; 3],
+    /// The normal of the first surface the camera ray hit, or zero if it hit nothing.
+    pub normal: [f32; 3],
+    /// The flat color of the first surface's material, or the background color.
+    pub albedo: [f32; 3],
+    /// The distance from the camera to the first surface, or infinity.
+    pub depth: f32,
+}
+
+/// A handle for stopping a render from another thread; see `Renderer::handle`.
+#[derive(Clone, Debug, Default)]
+pub struct RenderHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl RenderHandle {
+    /// Ask the renderer to stop. Tiles already being rendered are finished, but no new ones are
+    /// started, and `Renderer::render` returns `Err(Cancelled)` unless every tile was already done.
+    /// A cancel asked for before a render starts stops it at once, and lasts until `reset`.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Clear a cancel, so that the renderer can render again.
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// The error returned by `Renderer::render` when the render was cancelled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("the render was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// The sums of the colors splatted onto each output pixel of a tile, and of their weights, in
+/// blocks of each pixel's output pixels.
+struct TileSums {
+    /// The size of the tile, in pixels.
+    width: u32,
+    height: u32,
+    colors: Vec<Color>,
+    weights: Vec<f64>,
+    /// The brightness of each pixel's samples so far, for adaptive sampling; empty otherwise.
+    moments: Vec<Moments>,
+}
+
+impl TileSums {
+    /// Empty sums for a tile of `width` by `height` pixels, each split into `scale` by `scale`
+    /// output pixels.
+    fn new(width: u32, height: u32, scale: u32, adaptive: bool) -> Self {
+        let len = (width * height * scale * scale) as usize;
+        let pixels = if adaptive {
+            (width * height) as usize
+        } else {
+            0
+        };
+        Self {
+            width,
+            height,
+            colors: vec![Color::zeros(); len],
+            weights: vec![0.0; len],
+            moments: vec![Moments::default(); pixels],
+        }
+    }
+}
+
+/// The first surface seen by a recorded sample; see `SampleRecord`.
+struct FirstSurface {
+    normal: [f32; 3],
+    albedo: [f32; 3],
+    depth: f32,
+}
+
+/// The count, sum, and sum of squares of the luminance of a pixel's samples.
+#[derive(Clone, Copy, Debug, Default)]
+struct Moments {
+    count: u32,
+    sum: f64,
+    sum_squares: f64,
+}
+
+impl Moments {
+    fn add(&mut self, color: &Color) {
+        let luminance = 0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z;
+        self.count += 1;
+        self.sum += luminance;
+        self.sum_squares += luminance * luminance;
+    }
+
+    /// The number of samples the pixel needs in all for its error to be within `tolerance`, from
+    /// the spread of its samples so far; at least one more while there are too few to tell.
+    fn samples_needed(&self, tolerance: f64) -> u32 {
+        // Differences in brightness below this hardly show once gamma corrected, so darker pixels
+        // are held to this absolute error instead of chasing their noise.
+        const MIN_BRIGHTNESS: f64 = 0.01;
+        // the half-width of a 95% confidence interval, in standard errors
+        const Z: f64 = 1.96;
+
+        if self.count < 2 {
+            return self.count + 1;
+        }
+        let n = self.count as f64;
+        let mean = self.sum / n;
+        let variance = ((self.sum_squares - self.sum * mean) / (n - 1.0)).max(0.0);
+        let error = tolerance * mean.max(MIN_BRIGHTNESS);
+        // the interval Z * sqrt(variance / n) is within the error once n reaches this
+        let needed = Z * Z * variance / (error * error);
+        needed.ceil().min(u32::MAX as f64) as u32
+    }
+}
+
+/// What the image being rendered keeps for each pixel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Display,
+    Linear,
+    Half,
+}
+
+/// The image being rendered into.
+enum Framebuffer {
+    /// Linear colors.
+    Hdr(image::Rgb32FImage),
+    /// Linear colors in half floats, half the size.
+    Half(HalfImage),
+    /// Gamma-corrected, 8-bit colors, a quarter of the size, and the pixels clipped in converting
+    /// to them.
+    Display(image::RgbImage, Clipping),
+}
+
+/// The pixels of a render whose linear colors are out of the range shown by display colors, found
+/// before they are converted.
+#[derive(Clone, Debug)]
+pub struct Clipping {
+    /// The number of pixels with a channel brighter than 1, which is clipped to white.
+    pub clipped: u64,
+    /// The number of pixels with a channel that is not a number, which usually points to a bug.
+    pub nans: u64,
+    /// The brightest channel of any pixel, ignoring those that are not numbers.
+    pub max: f32,
+    /// White where a pixel is clipped or not a number, and black elsewhere.
+    pub mask: image::GrayImage,
+}
+
+impl Clipping {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            clipped: 0,
+            nans: 0,
+            max: 0.0,
+            mask: image::GrayImage::new(width, height),
+        }
+    }
+
+    /// Find the clipped pixels of a linear image.
+    pub fn measure(image: &image::Rgb32FImage) -> Self {
+        let mut clipping = Self::new(image.width(), image.height());
+        clipping.add_tile(image, 0, 0);
+        clipping
+    }
+
+    /// Find the clipped pixels of a linear image in half floats.
+    pub fn measure_half(image: &HalfImage) -> Self {
+        let mut clipping = Self::new(image.width(), image.height());
+        for (n, pixel) in image.pixels().enumerate() {
+            let n = n as u32;
+            clipping.add_pixel(n % image.width(), n / image.width(), pixel);
+        }
+        clipping
+    }
+
+    /// Add the pixels of a tile of linear colors whose upper-left pixel is at (x, y).
+    fn add_tile(&mut self, tile: &image::Rgb32FImage, x: u32, y: u32) {
+        for (i, j, &pixel) in tile.enumerate_pixels() {
+            self.add_pixel(x + i, y + j, pixel);
+        }
+    }
+
+    fn add_pixel(&mut self, x: u32, y: u32, pixel: image::Rgb<f32>) {
+        let nan = pixel.0.iter().any(|c| c.is_nan());
+        let max = pixel
+            .0
+            .iter()
+            .copied()
+            .filter(|c| !c.is_nan())
+            .fold(0.0, f32::max);
+        self.max = self.max.max(max);
+        if nan {
+            self.nans += 1;
+        } else if max > 1.0 {
+            self.clipped += 1;
+        } else {
+            return;
+        }
+        self.mask.put_pixel(x, y, image::Luma([255]));
+    }
+}
+
+/// An estimate of the memory a render needs, in bytes; see `Renderer::estimate_memory`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryEstimate {
+    /// The image being rendered, and the image it is converted to for display.
+    pub framebuffer: u64,
+    /// The paths and tiles being traced by all threads.
+    pub paths: u64,
+    /// The objects of the world, with their triangles, points, and hierarchies, and its
+    /// materials and lights.
+    pub world: u64,
+    /// The pixels of the world's image textures.
+    pub textures: u64,
+}
+
+impl MemoryEstimate {
+    pub fn total(&self) -> u64 {
+        self.framebuffer + self.paths + self.world + self.textures
+    }
+}
+
+/// A way in which `Renderer::fit_memory` reduced the memory a render needs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryFallback {
+    /// Only the 8-bit display colors of the image are kept, not its linear colors.
+    DisplayFramebuffer,
+    /// Each thread traces this many paths at once instead of `wavefront::WAVE_SIZE`.
+    SmallerWaves(usize),
+    /// Image textures were shrunk to this fraction of their width and height.
+    SmallerTextures(u32),
+}
+
+impl std::fmt::Display for MemoryFallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MemoryFallback::DisplayFramebuffer => {
+                f.write_str("keeping only 8-bit colors for the image")
+            }
+            MemoryFallback::SmallerWaves(size) => {
+                write!(f, "tracing {size} paths at a time on each thread")
+            }
+            MemoryFallback::SmallerTextures(fraction) => {
+                write!(
+                    f,
+                    "shrinking textures to 1/{fraction} of their width and height"
+                )
+            }
+        }
+    }
 }
 
 pub struct Renderer {
     samples_per_pixel: u32,
     background_color: Color,
     max_ray_bounces: u32,
-    progress_sender: mpsc::Sender<u32>,
+    seed: u64,
+    regularization: f64,
+    auto_exposure: bool,
+    regions: Vec<SampleRegion>,
+    super_resolution: u32,
+    shutter: Option<(f64, f64)>,
+    polarizer: Option<f64>,
+    adaptive: Option<AdaptiveSampling>,
+    checkerboard: bool,
+    /// Whether to render in passes over the whole image; see `set_progressive`.
+    progressive: bool,
+    /// Whether `render` keeps only display colors for the whole image, to save memory.
+    display_framebuffer: bool,
+    /// Whether `render` keeps linear colors in half floats, to save memory.
+    half_framebuffer: bool,
+    /// Whether to report every sample taken; see `set_record_samples`.
+    record_samples: bool,
+    /// The number of paths each thread traces at once.
+    wave_size: usize,
+    /// Render in a flat, non-photoreal style instead of tracing paths.
+    toon: Option<Toon>,
+    /// Tiles finished by an earlier render, by column and row, which are used instead of being
+    /// rendered again.
+    finished_tiles: HashMap<(u32, u32), image::Rgb32FImage>,
+    handle: RenderHandle,
 
     // values computed from camera and viewport
     /// In pixels.
@@ -53,10 +491,21 @@ pub struct Renderer {
     defocus_angle: f64,
     defocus_disk_u: Vector3<f64>,
     defocus_disk_v: Vector3<f64>,
+    focus_distance: f64,
+    eyes: Option<Eyes>,
+}
+
+/// The eyes of a stereo render.
+struct Eyes {
+    /// The width of each eye's view, in pixels.
+    width: u32,
+    /// How far the right eye is from the camera's position; the left eye is as far the other way.
+    right: Vector3<f64>,
+    convergence: f64,
 }
 
 impl Renderer {
-    pub fn new(camera: Camera) -> (Self, mpsc::Receiver<u32>) {
+    pub fn new(camera: Camera) -> Self {
         let aspect_ratio = camera.image_width as f64 / camera.image_height as f64;
 
         let viewport_height = 2.0 * camera.focus_distance * (camera.fov.to_radians() / 2.0).tan();
@@ -84,145 +533,1301 @@ impl Renderer {
         let defocus_disk_u = u * defocus_radius;
         let defocus_disk_v = v * defocus_radius;
 
-        let (sender, receiver) = mpsc::channel();
+        let eyes = camera.stereo.map(|stereo| Eyes {
+            width: camera.image_width,
+            right: u * stereo.separation / 2.0,
+            convergence: stereo.convergence,
+        });
+        let mut regions = camera.regions;
+        if eyes.is_some() {
+            let right = regions.iter().map(|region| SampleRegion {
+                x: region.x.start + camera.image_width..region.x.end + camera.image_width,
+                ..region.clone()
+            });
+            regions = regions.iter().cloned().chain(right).collect();
+        }
 
-        (
-            Self {
-                samples_per_pixel: camera.samples_per_pixel,
-                background_color: camera.background_color,
-                image_width: camera.image_width,
-                image_height: camera.image_height,
-                camera_center,
-                pixel_delta_u,
-                pixel_delta_v,
-                pixel_origin,
-                max_ray_bounces: 50,
-                progress_sender: sender,
-                defocus_angle: camera.defocus_angle,
-                defocus_disk_u,
-                defocus_disk_v,
-            },
-            receiver,
-        )
+        Self {
+            samples_per_pixel: camera.samples_per_pixel,
+            background_color: camera.background_color,
+            image_width: camera.image_width * if eyes.is_some() { 2 } else { 1 },
+            image_height: camera.image_height,
+            camera_center,
+            pixel_delta_u,
+            pixel_delta_v,
+            pixel_origin,
+            max_ray_bounces: 50,
+            seed: camera.seed,
+            regularization: camera.regularization,
+            auto_exposure: camera.auto_exposure,
+            regions,
+            super_resolution: camera.super_resolution.max(1),
+            shutter: camera.shutter,
+            polarizer: camera.polarizer,
+            adaptive: camera.adaptive,
+            checkerboard: false,
+            progressive: false,
+            display_framebuffer: false,
+            half_framebuffer: false,
+            record_samples: false,
+            wave_size: wavefront::WAVE_SIZE,
+            toon: None,
+            finished_tiles: HashMap::new(),
+            handle: RenderHandle::default(),
+            defocus_angle: camera.defocus_angle,
+            defocus_disk_u,
+            defocus_disk_v,
+            focus_distance: camera.focus_distance,
+            eyes,
+        }
+    }
+
+    /// Focus at `distance` from the camera instead of the camera's focus distance, keeping the
+    /// field of view and the angle of the cone of defocus blur.
+    pub fn set_focus_distance(&mut self, distance: f64) -> &mut Self {
+        let scale = distance / self.focus_distance;
+        self.pixel_delta_u *= scale;
+        self.pixel_delta_v *= scale;
+        self.pixel_origin = self.camera_center + (self.pixel_origin - self.camera_center) * scale;
+        self.defocus_disk_u *= scale;
+        self.defocus_disk_v *= scale;
+        self.focus_distance = distance;
+        self
+    }
+
+    /// Move the camera to `position`, keeping its rotation, field of view, and focus.
+    pub fn set_position(&mut self, position: Vector3<f64>) -> &mut Self {
+        self.pixel_origin += position - self.camera_center;
+        self.camera_center = position;
+        self
     }
 
-    /// Get the precise color of any ray in the world.
-    pub fn ray_color(&self, world: &World, ray: &Ray, depth: u32) -> Color {
-        if depth == 0 {
-            return Color::zeros();
+    /// The ray from the center of the camera through `(x, y)`, a point of the image given as
+    /// fractions of its width and height from the top left. For stereo renders, this is a point of
+    /// either eye's view, seen from between the eyes.
+    pub fn screen_ray(&self, (x, y): (f64, f64)) -> Ray {
+        let width = self
+            .eyes
+            .as_ref()
+            .map_or(self.image_width, |eyes| eyes.width);
+        let upper_left = self.pixel_origin - 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
+        let point = upper_left
+            + x * width as f64 * self.pixel_delta_u
+            + y * self.image_height as f64 * self.pixel_delta_v;
+
+        Ray {
+            origin: self.camera_center,
+            direction: point - self.camera_center,
+            time: 0.0,
         }
+    }
 
-        if let Some(hit) = world.hit(ray, 0.001, f64::INFINITY) {
-            let material = &world.materials[hit.material];
-            if let Some((attenuation, scattered)) = material.scatter(ray, &hit) {
-                let emission = material.emit();
-                let scatter =
-                    attenuation.component_mul(&self.ray_color(world, &scattered, depth - 1));
-                return emission + scatter;
+    /// Estimate the memory needed to render `world` on `threads` threads. Only the `Object`s of
+    /// custom shapes are counted, not what they hold.
+    pub fn estimate_memory(&self, world: &World, threads: usize) -> MemoryEstimate {
+        let (width, height) = self.output_dimensions();
+        let pixels = width as u64 * height as u64;
+        let linear = if self.half_framebuffer {
+            3 * size_of::<::half::f16>()
+        } else {
+            size_of::<image::Rgb<f32>>()
+        };
+        let mut framebuffer =
+            if self.display_framebuffer && !self.auto_exposure && !self.progressive {
+                pixels * size_of::<image::Rgb<u8>>() as u64
             } else {
-                return material.emit();
+                // the display image is made from the linear one at the end
+                pixels * (linear + size_of::<image::Rgb<u8>>()) as u64
+            };
+        // the brightness of each pixel's samples, for adaptive sampling
+        let moments = if self.adaptive.is_some() {
+            size_of::<Moments>()
+        } else {
+            0
+        };
+        if self.progressive {
+            // the sums of every pixel's samples, kept between passes
+            framebuffer += pixels * (size_of::<Color>() + size_of::<f64>()) as u64
+                + (self.image_width * self.image_height) as u64 * moments as u64;
+        }
+
+        let tile_pixels = (TILE_SIZE * TILE_SIZE * self.super_resolution.pow(2)) as usize;
+        let per_thread = self.wave_size * wavefront::PATH_BYTES
+            + tile_pixels * (size_of::<Color>() + size_of::<f64>() + size_of::<image::Rgb<f32>>())
+            + (TILE_SIZE * TILE_SIZE) as usize * moments;
+
+        let world_bytes = world.objects.size_in_bytes()
+            + world.object_ids.len() * size_of::<usize>()
+            + world.object_settings.len() * size_of::<crate::object::ObjectSettings>()
+            + world.materials.len() * size_of::<Material>()
+            + world.textures.len() * size_of::<crate::texture::Texture>()
+            + world.lights.len() * size_of::<crate::light::Light>();
+        let textures = world
+            .textures
+            .iter()
+            .map(crate::texture::Texture::size_in_bytes)
+            .sum::<usize>();
+
+        MemoryEstimate {
+            framebuffer,
+            paths: (per_thread * threads) as u64,
+            world: world_bytes as u64,
+            textures: textures as u64,
+        }
+    }
+
+    /// Reduce the memory needed to render `world` on `threads` threads to at most `budget` bytes,
+    /// at some cost in speed, precision, or texture detail, returning what was changed. If the
+    /// render cannot fit, the smallest estimate reached is returned as the error.
+    pub fn fit_memory(
+        &mut self,
+        world: &mut World,
+        threads: usize,
+        budget: u64,
+    ) -> Result<Vec<MemoryFallback>, MemoryEstimate> {
+        // Waves smaller than this leave too little work in each stage to be worth batching.
+        const MIN_WAVE_SIZE: usize = 64;
+        // Textures are not shrunk below this many pixels on a side, where they lose their detail.
+        const MIN_TEXTURE_SIZE: u32 = 64;
+
+        let mut fallbacks = Vec::new();
+        if self.estimate_memory(world, threads).total() <= budget {
+            return Ok(fallbacks);
+        }
+
+        if !self.auto_exposure {
+            self.display_framebuffer = true;
+            fallbacks.push(MemoryFallback::DisplayFramebuffer);
+            if self.estimate_memory(world, threads).total() <= budget {
+                return Ok(fallbacks);
+            }
+        }
+
+        let wave_size = self.wave_size;
+        while self.wave_size > MIN_WAVE_SIZE {
+            self.wave_size /= 2;
+            if self.estimate_memory(world, threads).total() <= budget {
+                break;
+            }
+        }
+        if self.wave_size != wave_size {
+            fallbacks.push(MemoryFallback::SmallerWaves(self.wave_size));
+        }
+
+        let mut fraction = 1;
+        while self.estimate_memory(world, threads).total() > budget {
+            let mut shrunk = false;
+            for texture in &mut world.textures {
+                shrunk |= texture.shrink(MIN_TEXTURE_SIZE);
+            }
+            if !shrunk {
+                break;
+            }
+            fraction *= 2;
+        }
+        if fraction > 1 {
+            fallbacks.push(MemoryFallback::SmallerTextures(fraction));
+        }
+
+        let estimate = self.estimate_memory(world, threads);
+        if estimate.total() <= budget {
+            Ok(fallbacks)
+        } else {
+            Err(estimate)
+        }
+    }
+
+    /// Render only every other pixel, in a checkerboard pattern, and fill in the rest from their
+    /// neighbors. This halves the time taken, for quick previews.
+    pub fn set_checkerboard(&mut self, checkerboard: bool) -> &mut Self {
+        self.checkerboard = checkerboard;
+        self
+    }
+
+    /// Render in passes over the whole image, each doubling the samples of every pixel, so that
+    /// after each pass the image is complete, if noisy; tiles are reported through
+    /// `ProgressEvent::TileFinished` after every pass. The final image is the same, but the sums
+    /// of every pixel's samples are kept between passes, and the framebuffer keeps linear colors.
+    pub fn set_progressive(&mut self, progressive: bool) -> &mut Self {
+        self.progressive = progressive;
+        self
+    }
+
+    /// Keep the linear colors of the image in half floats while rendering, in half the memory of
+    /// full floats, for very large images. Each pixel's samples are still summed in full
+    /// precision; see `HalfImage`. `render_half` always does.
+    pub fn set_half_framebuffer(&mut self, half: bool) -> &mut Self {
+        self.half_framebuffer = half;
+        self
+    }
+
+    /// Report every sample taken through `ProgressEvent::Samples`, with its radiance and the
+    /// normal, flat color, and distance of the first surface its camera ray hit, for external
+    /// denoisers and reconstruction. Finding the first surface again makes rendering slower.
+    /// Non-photoreal renders take no samples, and resumed tiles report none.
+    pub fn set_record_samples(&mut self, record: bool) -> &mut Self {
+        self.record_samples = record;
+        self
+    }
+
+    /// Render with flat bands of light, a rim light, and ink outlines instead of tracing paths,
+    /// for stylized images of the same scenes; `None` goes back to path tracing.
+    pub fn set_toon(&mut self, toon: Option<Toon>) -> &mut Self {
+        self.toon = toon;
+        self
+    }
+
+    /// Use these tiles of linear colors, by their column and row in `tile_grid`, instead of
+    /// rendering them, to resume a render that was interrupted. They are reported through
+    /// `ProgressEvent::TileFinished` like rendered tiles, with no samples. Tiles that are not the
+    /// size of their place in the image are ignored.
+    pub fn set_finished_tiles(
+        &mut self,
+        tiles: HashMap<(u32, u32), image::Rgb32FImage>,
+    ) -> &mut Self {
+        self.finished_tiles = tiles;
+        self
+    }
+
+    /// Get a handle for cancelling renders by this renderer from another thread.
+    pub fn handle(&self) -> RenderHandle {
+        self.handle.clone()
+    }
+
+    /// Render a complete world, casting several rays for each pixel and collecting colors into a
+    /// complete image.
+    ///
+    /// Progress is reported to `on_progress`, ending with `ProgressEvent::Finished` on success, or
+    /// `ProgressEvent::Cancelled` if the render is cancelled through a `RenderHandle`; if rendering
+    /// panics, `ProgressEvent::Failed` is reported before the panic is resumed. When rendering in
+    /// parallel, `on_progress` is called from the worker threads, one call at a time.
+    pub fn render(
+        &self,
+        world: &World,
+        parallel: bool,
+        on_progress: impl FnMut(ProgressEvent) + Send,
+    ) -> Result<image::RgbImage, Cancelled> {
+        self.render_with_clipping(world, parallel, on_progress)
+            .map(|(image, _)| image)
+    }
+
+    /// Render like `render`, and also find the pixels that were too bright to show, after any
+    /// auto-exposure.
+    pub fn render_with_clipping(
+        &self,
+        world: &World,
+        parallel: bool,
+        on_progress: impl FnMut(ProgressEvent) + Send,
+    ) -> Result<(image::RgbImage, Clipping), Cancelled> {
+        // Auto-exposure meters the whole image, so it needs the full precision framebuffer, and
+        // the passes of a progressive render would each add their clipped pixels.
+        let format = if self.display_framebuffer && !self.auto_exposure && !self.progressive {
+            Format::Display
+        } else if self.half_framebuffer {
+            Format::Half
+        } else {
+            Format::Linear
+        };
+        match self.render_framebuffer(world, parallel, on_progress, format)? {
+            Framebuffer::Display(image, clipping) => Ok((image, clipping)),
+            Framebuffer::Hdr(mut image) => {
+                if self.auto_exposure {
+                    let exposure = auto_exposure(&image) as f32;
+                    image.iter_mut().for_each(|channel| *channel *= exposure);
+                }
+                Ok((to_display(&image), Clipping::measure(&image)))
+            }
+            Framebuffer::Half(mut image) => {
+                if self.auto_exposure {
+                    image.scale(auto_exposure_half(&image) as f32);
+                }
+                Ok((half_to_display(&image), Clipping::measure_half(&image)))
+            }
+        }
+    }
+
+    /// Render like `render_with_clipping`, but keep the linear colors of the pixels after any
+    /// auto-exposure, without gamma correcting or clamping them, for floating-point images.
+    pub fn render_exposed(
+        &self,
+        world: &World,
+        parallel: bool,
+        on_progress: impl FnMut(ProgressEvent) + Send,
+    ) -> Result<(image::Rgb32FImage, Clipping), Cancelled> {
+        let mut image = self.render_hdr(world, parallel, on_progress)?;
+        if self.auto_exposure {
+            let exposure = auto_exposure(&image) as f32;
+            image.iter_mut().for_each(|channel| *channel *= exposure);
+        }
+        let clipping = Clipping::measure(&image);
+        Ok((image, clipping))
+    }
+
+    /// Render like `render_exposed`, but keep the linear colors in half floats, in half the
+    /// memory, for very large images.
+    pub fn render_half(
+        &self,
+        world: &World,
+        parallel: bool,
+        on_progress: impl FnMut(ProgressEvent) + Send,
+    ) -> Result<(HalfImage, Clipping), Cancelled> {
+        match self.render_framebuffer(world, parallel, on_progress, Format::Half)? {
+            Framebuffer::Half(mut image) => {
+                if self.auto_exposure {
+                    image.scale(auto_exposure_half(&image) as f32);
+                }
+                let clipping = Clipping::measure_half(&image);
+                Ok((image, clipping))
+            }
+            _ => unreachable!("a half framebuffer was requested"),
+        }
+    }
+
+    /// Render like `render`, but keep the linear colors of the pixels without exposing, gamma
+    /// correcting, or clamping them. Tiles reported to `on_progress` are never auto-exposed, since
+    /// the whole image must be rendered to meter it.
+    pub fn render_hdr(
+        &self,
+        world: &World,
+        parallel: bool,
+        on_progress: impl FnMut(ProgressEvent) + Send,
+    ) -> Result<image::Rgb32FImage, Cancelled> {
+        match self.render_framebuffer(world, parallel, on_progress, Format::Linear)? {
+            Framebuffer::Hdr(image) => Ok(image),
+            _ => unreachable!("an HDR framebuffer was requested"),
+        }
+    }
+
+    /// Render into a framebuffer of colors in `format`.
+    fn render_framebuffer(
+        &self,
+        world: &World,
+        parallel: bool,
+        on_progress: impl FnMut(ProgressEvent) + Send,
+        format: Format,
+    ) -> Result<Framebuffer, Cancelled> {
+        let on_progress = Mutex::new(on_progress);
+        let report = |event| (on_progress.lock().unwrap_or_else(|e| e.into_inner()))(event);
+
+        report(ProgressEvent::Stage(Stage::Rendering));
+
+        match panic::catch_unwind(AssertUnwindSafe(|| match &self.toon {
+            Some(toon) => self.render_toon(world, parallel, &report, toon, format),
+            None => self.render_tiles(world, parallel, &report, format),
+        })) {
+            Ok(Some(image)) => {
+                report(ProgressEvent::Finished);
+                Ok(image)
+            }
+            Ok(None) => {
+                report(ProgressEvent::Cancelled);
+                Err(Cancelled)
+            }
+            Err(payload) => {
+                report(ProgressEvent::Failed(panic_message(payload.as_ref())));
+                panic::resume_unwind(payload)
             }
         }
+    }
+
+    /// The width and height of the images produced by `render`.
+    pub fn output_dimensions(&self) -> (u32, u32) {
+        (
+            self.image_width * self.super_resolution,
+            self.image_height * self.super_resolution,
+        )
+    }
+
+    /// The width and height of the image in the pixels samples are taken in, before any
+    /// super-resolution; for stereo renders, both views side by side.
+    pub fn image_dimensions(&self) -> (u32, u32) {
+        (self.image_width, self.image_height)
+    }
+
+    /// The width and height of the tiles reported by `ProgressEvent::TileFinished`, in pixels of
+    /// the output image; tiles at its right and bottom edges may be smaller.
+    pub fn tile_size(&self) -> u32 {
+        TILE_SIZE * self.super_resolution
+    }
 
-        self.background_color
+    /// The number of tile columns and rows covering the image.
+    pub fn tile_grid(&self) -> (u32, u32) {
+        (
+            self.image_width.div_ceil(TILE_SIZE),
+            self.image_height.div_ceil(TILE_SIZE),
+        )
     }
 
-    /// Render a complete world, casting several rays for each pixel and collecting colors into a complete image.
-    pub fn render(&self, world: &World, parallel: bool) -> image::RgbImage {
+    /// Render the image in tiles of `TILE_SIZE` pixels, handed out to rayon's worker threads if
+    /// `parallel` is set. Progress is counted across the threads, so each percentage is reported
+    /// once, in order, whichever thread finishes the tile that reaches it. Returns `None` if the
+    /// render is cancelled before every tile is done.
+    ///
+    /// A progressive render goes over the whole image in passes, each taking samples until the
+    /// pixels have 1, 2, 4, 8, and so on, keeping the sums of each tile's samples between them.
+    fn render_tiles(
+        &self,
+        world: &World,
+        parallel: bool,
+        report: &(impl Fn(ProgressEvent) + Sync),
+        format: Format,
+    ) -> Option<Framebuffer> {
         struct Accumulator {
-            pixels_completed: u32,
+            samples_completed: u64,
             progress_percent: u32,
         }
 
-        let image = image::RgbImage::new(self.image_width, self.image_height);
-        let image = Mutex::new(image);
+        let (width, height) = self.output_dimensions();
+        let framebuffer = Mutex::new(match format {
+            Format::Display => Framebuffer::Display(
+                image::RgbImage::new(width, height),
+                Clipping::new(width, height),
+            ),
+            Format::Linear => Framebuffer::Hdr(image::Rgb32FImage::new(width, height)),
+            Format::Half => Framebuffer::Half(HalfImage::new(width, height)),
+        });
+
+        let (columns, rows) = self.tile_grid();
+        let tile_bounds = |idx: u32| {
+            let (x, y) = ((idx % columns) * TILE_SIZE, (idx / columns) * TILE_SIZE);
+            let width = TILE_SIZE.min(self.image_width - x);
+            let height = TILE_SIZE.min(self.image_height - y);
+            (x, y, width, height)
+        };
+        let samples_in = |(x, y, width, height): (u32, u32, u32, u32), samples: &Range<u32>| {
+            (y..y + height)
+                .flat_map(|j| (x..x + width).map(move |i| (i, j)))
+                .filter(|&(i, j)| !self.is_skipped(i, j))
+                .map(|(i, j)| {
+                    let end = self.pixel_samples(i, j).min(samples.end);
+                    end.saturating_sub(samples.start) as u64
+                })
+                .sum::<u64>()
+        };
 
-        let total_pixels = self.image_width * self.image_height;
+        let passes = self.passes();
+        let all_samples = 0..passes.last().map_or(0, |pass| pass.end);
+        let total_samples = (0..columns * rows)
+            .map(|idx| samples_in(tile_bounds(idx), &all_samples))
+            .sum::<u64>()
+            .max(1);
         let accumulator = Mutex::new(Accumulator {
-            pixels_completed: 0,
+            samples_completed: 0,
             progress_percent: 0,
         });
+        // the sums of each tile's samples so far, kept between passes
+        let sums = (0..columns * rows)
+            .map(|_| Mutex::new(None))
+            .collect::<Vec<Mutex<Option<TileSums>>>>();
+        // whether a tile was left unrendered because the render was cancelled
+        let skipped = AtomicBool::new(false);
+
+        for (pass, samples) in passes.iter().enumerate() {
+            let last_pass = pass + 1 == passes.len();
+            let tile_render_fn = |idx: u32| {
+                if self.handle.is_cancelled() {
+                    skipped.store(true, Ordering::Relaxed);
+                    return;
+                }
+
+                let column = idx % columns;
+                let row = idx / columns;
+                let bounds @ (x, y, width, height) = tile_bounds(idx);
+                let scale = self.super_resolution;
+                let (tile, samples, extra_samples) = match self.finished_tiles.get(&(column, row)) {
+                    Some(tile) if tile.dimensions() == (width * scale, height * scale) => {
+                        // A resumed tile is reported once, with its samples counted as done.
+                        if pass > 0 {
+                            return;
+                        }
+                        accumulator.lock().unwrap().samples_completed +=
+                            samples_in(bounds, &all_samples);
+                        (tile.clone(), 0, 0)
+                    }
+                    _ => {
+                        let mut slot = sums[idx as usize].lock().unwrap();
+                        let tile_sums = slot.get_or_insert_with(|| {
+                            TileSums::new(width, height, scale, self.adaptive.is_some())
+                        });
+                        let extra_samples =
+                            self.render_tile(world, (x, y), samples, tile_sums, last_pass, report);
+                        let tile = self.resolve_tile(x, y, tile_sums);
+                        if last_pass {
+                            *slot = None;
+                        }
+                        drop(slot);
+                        (tile, samples_in(bounds, samples), extra_samples)
+                    }
+                };
+
+                let (x, y) = (x * self.super_resolution, y * self.super_resolution);
+                let display_tile = to_display(&tile);
+                match &mut *framebuffer.lock().unwrap() {
+                    Framebuffer::Hdr(image) => image.copy_from(&tile, x, y).unwrap(),
+                    Framebuffer::Half(image) => image.copy_from(&tile, x, y),
+                    Framebuffer::Display(image, clipping) => {
+                        image.copy_from(&display_tile, x, y).unwrap();
+                        clipping.add_tile(&tile, x, y);
+                    }
+                }
 
-        let pixel_render_fn = |idx| {
-            let i = idx % self.image_width;
-            let j = idx / self.image_width;
+                report(ProgressEvent::TileFinished {
+                    column,
+                    row,
+                    x,
+                    y,
+                    pixels: display_tile,
+                    linear: tile,
+                    samples: samples + extra_samples,
+                });
 
-            let mut pixel_color = Color::zeros();
+                // Progress is measured in the samples every pixel takes, as the extra samples of
+                // adaptive sampling are not known in advance.
+                let mut acc = accumulator.lock().unwrap();
+                acc.samples_completed += samples;
 
-            for _ in 0..self.samples_per_pixel {
-                let ray = self.get_ray(i, j);
-                pixel_color += self.ray_color(world, &ray, self.max_ray_bounces);
+                let percent = (acc.samples_completed * 100 / total_samples) as u32;
+                if percent > acc.progress_percent {
+                    acc.progress_percent = percent;
+                    report(ProgressEvent::Rendering(percent));
+                }
+            };
+
+            if parallel {
+                (0..(columns * rows))
+                    .into_par_iter()
+                    .for_each(tile_render_fn);
+            } else {
+                (0..(columns * rows)).for_each(tile_render_fn);
             }
+        }
 
-            // Divide to compute the average color between all samples
-            pixel_color /= self.samples_per_pixel as f64;
-            // Gamma correct
-            pixel_color = linear_to_gamma(&pixel_color);
-            let rgb = color_to_rgb(&pixel_color);
+        (!skipped.into_inner()).then(|| framebuffer.into_inner().unwrap())
+    }
 
-            let mut image = image.lock().unwrap();
-            image.put_pixel(i, j, image::Rgb(rgb));
+    /// The ranges of sample indices taken in each pass over the image: all of them at once, or
+    /// doubling the samples taken so far in each pass of a progressive render. Pixels take the
+    /// part of each range below their own number of samples.
+    fn passes(&self) -> Vec<Range<u32>> {
+        let max_samples = self
+            .regions
+            .iter()
+            .map(|region| region.samples_per_pixel)
+            .fold(self.samples_per_pixel, u32::max);
 
-            let mut acc = accumulator.lock().unwrap();
-            acc.pixels_completed += 1;
+        let mut passes = Vec::new();
+        let mut start = 0;
+        while start < max_samples {
+            let end = if self.progressive {
+                (start * 2).clamp(1, max_samples)
+            } else {
+                max_samples
+            };
+            passes.push(start..end);
+            start = end;
+        }
+        passes
+    }
+
+    /// Take samples `samples` of each pixel of the tile whose upper-left pixel is (x, y), adding
+    /// them to its `sums`. With adaptive sampling and `adapt`, the pixels whose noise is still
+    /// above the tolerance are then sampled further, and the number of these extra samples is
+    /// returned. Recorded samples are reported to `report` after each wave.
+    fn render_tile(
+        &self,
+        world: &World,
+        (x, y): (u32, u32),
+        samples: &Range<u32>,
+        sums: &mut TileSums,
+        adapt: bool,
+        report: &impl Fn(ProgressEvent),
+    ) -> u64 {
+        let scale = self.super_resolution;
+        let TileSums {
+            width,
+            height,
+            colors,
+            weights,
+            moments,
+        } = sums;
+        let (width, height) = (*width, *height);
+        // the recorded samples of the wave being traced, reported as soon as it is done
+        let mut records = Vec::new();
+
+        // the camera samples waiting to be traced, with their pixels and positions in them, and
+        // what their camera rays first hit if samples are recorded
+        let mut wave = Vec::with_capacity(self.wave_size);
+        let mut wave_samples = Vec::with_capacity(self.wave_size);
+        let mut wave_surfaces = Vec::new();
+
+        // the samples to take of each pixel, by its place in the tile
+        let mut round = (0..height)
+            .flat_map(|tile_j| (0..width).map(move |tile_i| (tile_i, tile_j)))
+            .filter(|&(tile_i, tile_j)| !self.is_skipped(x + tile_i, y + tile_j))
+            .map(|(tile_i, tile_j)| {
+                let end = self.pixel_samples(x + tile_i, y + tile_j).min(samples.end);
+                (tile_i, tile_j, samples.start..end)
+            })
+            .collect::<Vec<_>>();
+        let mut extra_samples = 0;
 
-            if (acc.pixels_completed * 100 / total_pixels) > acc.progress_percent {
-                acc.progress_percent = acc.pixels_completed * 100 / total_pixels;
-                self.progress_sender.send(acc.progress_percent).unwrap();
+        loop {
+            let mut trace_wave =
+                |wave: &mut Vec<_>,
+                 wave_samples: &mut Vec<(usize, (f64, f64))>,
+                 wave_surfaces: &mut Vec<FirstSurface>| {
+                    let traced = self.trace(world, std::mem::take(wave));
+                    let _filter = profile::scope(Scope::Filter);
+                    splat_wave(colors, weights, scale, wave_samples, &traced);
+                    if !moments.is_empty() {
+                        for (&(pixel, _), color) in wave_samples.iter().zip(&traced) {
+                            moments[pixel].add(color);
+                        }
+                    }
+                    for ((&(pixel, (u, v)), color), surface) in wave_samples
+                        .iter()
+                        .zip(&traced)
+                        .zip(wave_surfaces.drain(..))
+                    {
+                        let pixel = pixel as u32;
+                        records.push(SampleRecord {
+                            pixel: (x + pixel % width, y + pixel / width),
+                            position: (u as f32, v as f32),
+                            radiance: color.cast::<f32>().into(),
+                            normal: surface.normal,
+                            albedo: surface.albedo,
+                            depth: surface.depth,
+                        });
+                    }
+                    if !records.is_empty() {
+                        report(ProgressEvent::Samples(std::mem::take(&mut records)));
+                    }
+                    wave_samples.clear();
+                };
+
+            for (tile_i, tile_j, samples) in &round {
+                let (i, j) = (x + tile_i, y + tile_j);
+                random::seed_pixel(self.seed, i, j);
+                let sampler = PixelSampler::new();
+                for sample in samples.clone() {
+                    let position = (sampler.sample(sample, 0), sampler.sample(sample, 1));
+                    let lens = (sampler.sample(sample, 2), sampler.sample(sample, 3));
+                    let time = match self.shutter {
+                        Some((open, close)) => open + (close - open) * sampler.sample(sample, 4),
+                        None => 0.0,
+                    };
+                    let ray = self.get_ray(i, j, position, lens, time);
+                    if self.record_samples {
+                        wave_surfaces.push(self.first_surface(world, &ray));
+                    }
+                    wave.push((ray, Stream::new(self.seed, i, j, sample)));
+                    wave_samples.push(((tile_j * width + tile_i) as usize, position));
+
+                    if wave.len() == self.wave_size {
+                        trace_wave(&mut wave, &mut wave_samples, &mut wave_surfaces);
+                    }
+                }
             }
+            trace_wave(&mut wave, &mut wave_samples, &mut wave_surfaces);
+
+            let Some(adaptive) = self.adaptive.filter(|_| adapt) else {
+                break;
+            };
+            // Each noisy pixel takes the samples its spread so far suggests it needs, at most
+            // doubling its samples so that a poor early estimate is soon corrected.
+            round = round
+                .iter()
+                .filter_map(|&(tile_i, tile_j, _)| {
+                    let pixel = moments[(tile_j * width + tile_i) as usize];
+                    let max_samples = adaptive
+                        .max_samples
+                        .max(self.pixel_samples(x + tile_i, y + tile_j));
+                    let end = pixel
+                        .samples_needed(adaptive.tolerance)
+                        .min(pixel.count.saturating_mul(2))
+                        .min(max_samples);
+                    (end > pixel.count).then_some((tile_i, tile_j, pixel.count..end))
+                })
+                .collect();
+            if round.is_empty() {
+                break;
+            }
+            extra_samples += round
+                .iter()
+                .map(|(_, _, samples)| samples.len() as u64)
+                .sum::<u64>();
+        }
+
+        extra_samples
+    }
+
+    /// What the camera ray of a recorded sample first hits, as in `SampleRecord`.
+    fn first_surface(&self, world: &World, ray: &Ray) -> FirstSurface {
+        let Some(mut hit) = camera_hit(world, ray) else {
+            return FirstSurface {
+                normal: [0.0; 3],
+                albedo: self.background_color.cast::<f32>().into(),
+                depth: f32::INFINITY,
+            };
         };
+        let bevel_radius = world.object_settings(&hit).bevel_radius;
+        if bevel_radius > 0.0 {
+            world.bevel(ray, &mut hit, bevel_radius);
+        }
+
+        FirstSurface {
+            normal: hit.normal.cast::<f32>().into(),
+            albedo: world.material(ray, &hit).base_color().cast::<f32>().into(),
+            depth: (hit.t * ray.direction.magnitude()) as f32,
+        }
+    }
+
+    /// Turn the sums of the samples of a tile, laid out as for `render_tile`, into its colors.
+    fn resolve_tile(&self, x: u32, y: u32, sums: &TileSums) -> image::Rgb32FImage {
+        let scale = self.super_resolution;
+        let block_size = (scale * scale) as usize;
+        let &TileSums {
+            width,
+            height,
+            ref colors,
+            ref weights,
+            ..
+        } = sums;
+
+        let _filter = profile::scope(Scope::Filter);
+        let mut tile = image::Rgb32FImage::new(width * scale, height * scale);
+        for (pixel, (colors, weights)) in colors
+            .chunks(block_size)
+            .zip(weights.chunks(block_size))
+            .enumerate()
+        {
+            let (tile_i, tile_j) = (pixel as u32 % width, pixel as u32 / width);
+            if self.is_skipped(x + tile_i, y + tile_j) {
+                continue;
+            }
 
-        if parallel {
-            (0..(self.image_width * self.image_height))
-                .into_par_iter()
-                .for_each(pixel_render_fn);
+            // Output pixels that no sample landed near fall back to the average of the whole pixel.
+            let average = colors.iter().sum::<Color>() / weights.iter().sum::<f64>();
+            for (n, (color, weight)) in colors.iter().zip(weights).enumerate() {
+                let color = if *weight > 0.0 {
+                    color / *weight
+                } else {
+                    average
+                };
+                tile.put_pixel(
+                    tile_i * scale + n as u32 % scale,
+                    tile_j * scale + n as u32 / scale,
+                    image::Rgb(color.cast::<f32>().into()),
+                );
+            }
+        }
+
+        if self.checkerboard {
+            // Every neighbor of a skipped pixel was rendered, but only those in the tile are known.
+            for tile_j in 0..height {
+                for tile_i in 0..width {
+                    if !self.is_skipped(x + tile_i, y + tile_j) {
+                        continue;
+                    }
+
+                    let neighbors = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                        .into_iter()
+                        .map(|(di, dj)| (tile_i as i64 + di, tile_j as i64 + dj))
+                        .filter(|&(i, j)| {
+                            (0..width as i64).contains(&i) && (0..height as i64).contains(&j)
+                        })
+                        .collect::<Vec<_>>();
+
+                    for block_j in 0..scale {
+                        for block_i in 0..scale {
+                            let sum = neighbors.iter().fold([0.0; 3], |sum, &(i, j)| {
+                                let pixel = tile.get_pixel(
+                                    i as u32 * scale + block_i,
+                                    j as u32 * scale + block_j,
+                                );
+                                std::array::from_fn(|c| sum[c] + pixel[c])
+                            });
+                            let count = neighbors.len().max(1) as f32;
+                            tile.put_pixel(
+                                tile_i * scale + block_i,
+                                tile_j * scale + block_j,
+                                image::Rgb(sum.map(|c| c / count)),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        tile
+    }
+
+    /// Whether the pixel at location (i, j) is filled in from its neighbors instead of rendered.
+    /// A pixel alone in a tile, at the corner of an image one pixel wider and taller than a whole
+    /// number of tiles, has no neighbors to fill it in, so it is always rendered.
+    fn is_skipped(&self, i: u32, j: u32) -> bool {
+        let alone = [(i, self.image_width), (j, self.image_height)]
+            .into_iter()
+            .all(|(n, size)| size - n / TILE_SIZE * TILE_SIZE == 1);
+        self.checkerboard && (i + j) % 2 == 1 && !alone
+    }
+
+    /// Find how far the surface seen through each pixel has moved, in pixels, since an earlier
+    /// frame, given that frame's renderer and world. Its objects are matched to this world's by
+    /// index. The vectors are stored in the red and green channels, pointing right and down.
+    pub fn motion_vectors(
+        &self,
+        world: &World,
+        previous: &Renderer,
+        previous_world: &World,
+    ) -> image::Rgb32FImage {
+        image::Rgb32FImage::from_fn(self.image_width, self.image_height, |i, j| {
+            let (eye, column) = self.eye_column(i);
+            let origin = self.eye_center(eye);
+            let ray = Ray {
+                origin,
+                direction: self.eye_pixel_origin(eye)
+                    + (column as f64 * self.pixel_delta_u)
+                    + (j as f64 * self.pixel_delta_v)
+                    - origin,
+                time: 0.0,
+            };
+
+            // Surfaces are followed to where they were; the background only moves with the camera.
+            // Each eye is compared with the same eye of the earlier frame, if it had two.
+            let previous_eye = if previous.eyes.is_some() { eye } else { 0 };
+            let previous_position = match world.hit(&ray, 0.001, f64::INFINITY) {
+                Some(hit) => {
+                    let p = match (
+                        world.objects.get(hit.object),
+                        previous_world.objects.get(hit.object),
+                    ) {
+                        (Some(object), Some(previous_object)) => {
+                            object.track(&hit.p, &previous_object)
+                        }
+                        _ => hit.p,
+                    };
+                    previous.project(&(p - previous.eye_center(previous_eye)), previous_eye)
+                }
+                None => previous.project(&ray.direction, previous_eye),
+            };
+
+            match previous_position {
+                Some((x, y)) => image::Rgb([(i as f64 - x) as f32, (j as f64 - y) as f32, 0.0]),
+                None => image::Rgb([0.0; 3]),
+            }
+        })
+    }
+
+    /// The IDs of the scene objects in `world` whose bounds lie at least partly within the camera's
+    /// view, found from the bounds alone, without tracing rays. Custom shapes that do not report
+    /// their bounds are taken to be visible.
+    pub fn visible_objects(&self, world: &World) -> Vec<usize> {
+        let frusta = (0..self.eye_count())
+            .map(|eye| self.frustum(eye))
+            .collect::<Vec<_>>();
+        // Primitives are added object by object, so each ID appears in one run.
+        let mut ids = world.object_ids.clone();
+        ids.dedup();
+        let bounds = world.bounds_by_object();
+
+        ids.into_iter()
+            .filter(|&id| match bounds[id] {
+                Some(bounds) => frusta.iter().any(|planes| {
+                    // A box is out of view if all of its corners are outside one of the planes.
+                    !planes.iter().any(|(point, normal)| {
+                        (0..8).all(|corner| {
+                            let corner = Vector3::from_fn(|axis, _| {
+                                if corner & (1 << axis) == 0 {
+                                    bounds.min[axis]
+                                } else {
+                                    bounds.max[axis]
+                                }
+                            });
+                            (corner - point).dot(normal) > 0.0
+                        })
+                    })
+                }),
+                None => true,
+            })
+            .collect()
+    }
+
+    /// The planes bounding what eye `eye` sees, as a point on each and its normal, which points
+    /// out of view: one through each edge of the image, and one through the eye facing backwards.
+    fn frustum(&self, eye: usize) -> Vec<(Vector3<f64>, Vector3<f64>)> {
+        let center = self.eye_center(eye);
+        let width = self
+            .eyes
+            .as_ref()
+            .map_or(self.image_width, |eyes| eyes.width);
+        let upper_left =
+            self.eye_pixel_origin(eye) - 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
+        let across = width as f64 * self.pixel_delta_u;
+        let down = self.image_height as f64 * self.pixel_delta_v;
+        let corners = [
+            upper_left,
+            upper_left + across,
+            upper_left + across + down,
+            upper_left + down,
+        ];
+        let middle = upper_left + (across + down) / 2.0 - center;
+
+        let mut planes = (0..4)
+            .map(|i| {
+                let normal = (corners[i] - center).cross(&(corners[(i + 1) % 4] - center));
+                let normal = if normal.dot(&middle) > 0.0 {
+                    -normal
+                } else {
+                    normal
+                };
+                (center, normal)
+            })
+            .collect::<Vec<_>>();
+        planes.push((center, -middle));
+        planes
+    }
+
+    /// The number of views in the image: two for a stereo camera, or one.
+    pub fn eye_count(&self) -> usize {
+        if self.eyes.is_some() {
+            2
         } else {
-            (0..(self.image_width * self.image_height)).for_each(pixel_render_fn);
+            1
+        }
+    }
+
+    /// Where point `p` appears in eye `eye`'s view, as fractions of the image's width and height
+    /// from the top left, or `None` if it is behind the eye.
+    pub fn project_point(&self, p: &Vector3<f64>, eye: usize) -> Option<(f64, f64)> {
+        self.project(&(p - self.eye_center(eye)), eye)
+            .map(|pixel| self.pixel_to_fraction(pixel))
+    }
+
+    /// The ends of the line from `a` to `b` in each eye's view, as fractions of the image's width
+    /// and height from the top left, cut short where the line passes behind the eye. Eyes it is
+    /// entirely behind are left out.
+    pub fn project_line(&self, a: &Vector3<f64>, b: &Vector3<f64>) -> Vec<[(f64, f64); 2]> {
+        let forward = self.pixel_delta_u.cross(&self.pixel_delta_v).normalize();
+        (0..self.eye_count())
+            .filter_map(|eye| {
+                let center = self.eye_center(eye);
+                let near = 1e-3 * (self.eye_pixel_origin(eye) - center).dot(&forward);
+                let (depth_a, depth_b) = ((a - center).dot(&forward), (b - center).dot(&forward));
+                if depth_a < near && depth_b < near {
+                    return None;
+                }
+
+                let cut = |from: &Vector3<f64>, depth_from: f64, to: &Vector3<f64>, depth_to| {
+                    if depth_from >= near {
+                        *from
+                    } else {
+                        from + (to - from) * (near - depth_from) / (depth_to - depth_from)
+                    }
+                };
+                let (a, b) = (cut(a, depth_a, b, depth_b), cut(b, depth_b, a, depth_a));
+                Some([self.project_point(&a, eye)?, self.project_point(&b, eye)?])
+            })
+            .collect()
+    }
+
+    /// Convert pixel coordinates with pixel centers at whole numbers to fractions of the image's
+    /// width and height.
+    fn pixel_to_fraction(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (
+            (x + 0.5) / self.image_width as f64,
+            (y + 0.5) / self.image_height as f64,
+        )
+    }
+
+    /// The pixel coordinates at which a direction from eye `eye` crosses its viewport, with
+    /// pixel centers at whole numbers, or `None` if it points away from the viewport.
+    fn project(&self, direction: &Vector3<f64>, eye: usize) -> Option<(f64, f64)> {
+        let center = self.eye_center(eye);
+        let pixel_origin = self.eye_pixel_origin(eye);
+        let normal = self.pixel_delta_u.cross(&self.pixel_delta_v);
+        let t = (pixel_origin - center).dot(&normal) / direction.dot(&normal);
+        if !(t > 0.0 && t.is_finite()) {
+            return None;
         }
 
-        image.into_inner().unwrap()
+        let offset = center + t * direction - pixel_origin;
+        let eye_start = self.eyes.as_ref().map_or(0, |eyes| eyes.width * eye as u32);
+        Some((
+            eye_start as f64
+                + offset.dot(&self.pixel_delta_u) / self.pixel_delta_u.magnitude_squared(),
+            offset.dot(&self.pixel_delta_v) / self.pixel_delta_v.magnitude_squared(),
+        ))
     }
 
-    /// Get a randomly sampled camera ray for the pixel at location (i, j).
-    fn get_ray(&self, i: u32, j: u32) -> Ray {
-        let pixel_center =
-            self.pixel_origin + (i as f64 * self.pixel_delta_u) + (j as f64 * self.pixel_delta_v);
-        let pixel_sample = pixel_center + self.pixel_sample_square();
+    /// The eye that sees pixel column `i`, 0 for the left eye or a single view and 1 for the
+    /// right, and the column within that eye's view.
+    fn eye_column(&self, i: u32) -> (usize, u32) {
+        match &self.eyes {
+            Some(eyes) if i >= eyes.width => (1, i - eyes.width),
+            _ => (0, i),
+        }
+    }
+
+    /// How far eye `eye` is from the camera's position.
+    fn eye_offset(&self, eye: usize) -> Vector3<f64> {
+        match &self.eyes {
+            Some(eyes) if eye == 1 => eyes.right,
+            Some(eyes) => -eyes.right,
+            None => Vector3::zeros(),
+        }
+    }
+
+    /// The position eye `eye` sees from.
+    pub fn eye_center(&self, eye: usize) -> Vector3<f64> {
+        self.camera_center + self.eye_offset(eye)
+    }
+
+    /// The center of the upper-left pixel of eye `eye`'s view. Each eye's view is an off-center
+    /// part of a wider one, so that the views share an image plane and line up at the convergence
+    /// distance.
+    fn eye_pixel_origin(&self, eye: usize) -> Vector3<f64> {
+        let shift = self
+            .eyes
+            .as_ref()
+            .map_or(0.0, |eyes| 1.0 - self.focus_distance / eyes.convergence);
+        self.pixel_origin + self.eye_offset(eye) * shift
+    }
+
+    /// The number of samples to take for the pixel at location (i, j).
+    fn pixel_samples(&self, i: u32, j: u32) -> u32 {
+        self.regions
+            .iter()
+            .filter(|region| region.x.contains(&i) && region.y.contains(&j))
+            .map(|region| region.samples_per_pixel)
+            .fold(self.samples_per_pixel, u32::max)
+    }
+
+    /// Get the camera ray through the point `(u, v)` of the unit square covering the pixel at
+    /// location (i, j), from the point `lens` of the unit square mapped onto the lens, at moment
+    /// `time` of the exposure.
+    fn get_ray(&self, i: u32, j: u32, (u, v): (f64, f64), lens: (f64, f64), time: f64) -> Ray {
+        let (eye, i) = self.eye_column(i);
+        let pixel_center = self.eye_pixel_origin(eye)
+            + (i as f64 * self.pixel_delta_u)
+            + (j as f64 * self.pixel_delta_v);
+        let pixel_sample = pixel_center + self.pixel_sample_square(u, v);
 
         let origin = if self.defocus_angle <= 0.0 {
-            self.camera_center
+            self.eye_center(eye)
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(eye, lens.0, lens.1)
         };
         let ray_direction = pixel_sample - origin;
 
         Ray {
             origin,
             direction: ray_direction,
+            time,
         }
     }
 
-    /// Get a random location within the size of a pixel on the viewport.
-    fn pixel_sample_square(&self) -> Vector3<f64> {
-        let mut thread_rng = rand::thread_rng();
-        let px = -0.5 + thread_rng.gen_range(0.0..1.0);
-        let py = -0.5 + thread_rng.gen_range(0.0..1.0);
+    /// Get the location within the size of a pixel on the viewport for the point (u, v) in the unit
+    /// square.
+    fn pixel_sample_square(&self, u: f64, v: f64) -> Vector3<f64> {
+        let px = -0.5 + u;
+        let py = -0.5 + v;
 
         (px * self.pixel_delta_u) + (py * self.pixel_delta_v)
     }
 
-    fn defocus_disk_sample(&self) -> Vector3<f64> {
-        let p = random_vector_in_unit_disk();
-        self.camera_center + (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v)
+    /// Get the location on the lens for the point (u, v) in the unit square, mapped uniformly onto
+    /// the disk.
+    fn defocus_disk_sample(&self, eye: usize, u: f64, v: f64) -> Vector3<f64> {
+        let radius = u.sqrt();
+        let theta = v * std::f64::consts::TAU;
+        let (x, y) = (radius * theta.cos(), radius * theta.sin());
+        self.eye_center(eye) + (x * self.defocus_disk_u) + (y * self.defocus_disk_v)
     }
 }
 
-fn linear_to_gamma(linear_color: &Vector3<f64>) -> Vector3<f64> {
-    Vector3::new(
-        linear_color.x.sqrt(),
-        linear_color.y.sqrt(),
-        linear_color.z.sqrt(),
-    )
+/// Sum the light reflected from `world.lights` at a hit, casting a shadow ray toward each light,
+/// or estimate it from one light picked by `world.light_picker`.
+fn direct_light(world: &World, ray: &Ray, material: &Material, hit: &Hit) -> Color {
+    if let Some(picker) = &world.light_picker {
+        let i = picker.sample(random::rng().gen_range(0.0..1.0));
+        return reflected_light(world, ray, material, hit, &world.lights[i])
+            / picker.probability(i);
+    }
+
+    world
+        .lights
+        .iter()
+        .map(|light| reflected_light(world, ray, material, hit, light))
+        .sum()
+}
+
+/// The light from `light` reflected at a hit, if nothing shadows it.
+fn reflected_light(
+    world: &World,
+    ray: &Ray,
+    material: &Material,
+    hit: &Hit,
+    light: &crate::light::Light,
+) -> Color {
+    let Some(sample) = light.sample(&hit.p) else {
+        return Color::zeros();
+    };
+
+    let reflected = material.eval(ray, hit, &sample.direction);
+    if vector_near_zero(&reflected) {
+        return Color::zeros();
+    }
+
+    let shadow_ray = Ray {
+        origin: hit.p,
+        direction: sample.direction,
+        time: ray.time,
+    };
+    if world.hit(&shadow_ray, 0.001, sample.distance).is_some() {
+        return Color::zeros();
+    }
+    reflected.component_mul(&sample.radiance)
+}
+
+/// Recover the message from a panic payload, which is a `&str` or `String` for panics raised with
+/// `panic!`.
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// The first surface along a camera ray that the camera can see.
+fn camera_hit(world: &World, ray: &Ray) -> Option<Hit> {
+    let mut t_min = 0.001;
+    loop {
+        let hit = world.hit(ray, t_min, f64::INFINITY)?;
+        if !world.light_paths(hit.material).hidden_from_camera() {
+            return Some(hit);
+        }
+        t_min = hit.t + 0.001;
+    }
+}
+
+/// The factor that brings the log-average luminance of a linear image to middle grey.
+/// The logarithmic average is less swayed by small, very bright areas, like lights, than the mean.
+pub fn auto_exposure(image: &image::Rgb32FImage) -> f64 {
+    log_average_exposure(image.pixels().copied())
+}
+
+/// The factor that brings the log-average luminance of a linear image in half floats to middle
+/// grey; see `auto_exposure`.
+pub fn auto_exposure_half(image: &HalfImage) -> f64 {
+    log_average_exposure(image.pixels())
+}
+
+fn log_average_exposure(pixels: impl Iterator<Item = image::Rgb<f32>>) -> f64 {
+    const MIDDLE_GREY: f64 = 0.18;
+    // keeps black pixels from taking the logarithm of zero
+    const DELTA: f64 = 1e-4;
+
+    let (count, log_sum) = pixels.fold((0, 0.0), |(count, log_sum), pixel| {
+        let [r, g, b] = pixel.0.map(f64::from);
+        (
+            count + 1,
+            log_sum + (DELTA + 0.2126 * r + 0.7152 * g + 0.0722 * b).ln(),
+        )
+    });
+    if count == 0 {
+        return 1.0;
+    }
+    let log_average = (log_sum / count as f64).exp();
+
+    MIDDLE_GREY / log_average
+}
+
+/// Combine the side-by-side views of a stereo render into a red-cyan anaglyph, taking red from the
+/// left eye's view and green and blue from the right eye's.
+pub fn anaglyph<P: image::Pixel>(
+    image: &image::ImageBuffer<P, Vec<P::Subpixel>>,
+) -> image::ImageBuffer<P, Vec<P::Subpixel>> {
+    let width = image.width() / 2;
+    image::ImageBuffer::from_fn(width, image.height(), |x, y| {
+        let mut pixel = *image.get_pixel(x + width, y);
+        pixel.channels_mut()[0] = image.get_pixel(x, y).channels()[0];
+        pixel
+    })
+}
+
+/// Gamma correct and quantize a linear image for display.
+pub fn to_display(image: &image::Rgb32FImage) -> image::RgbImage {
+    let mut display = image::RgbImage::new(image.width(), image.height());
+    tonemap(image.as_raw(), &mut display);
+    display
+}
+
+/// Gamma correct and quantize a linear image in half floats for display, a row at a time so that
+/// it is never held in full floats.
+pub fn half_to_display(image: &HalfImage) -> image::RgbImage {
+    let mut display = image::RgbImage::new(image.width(), image.height());
+    let row_len = image.width() as usize * 3;
+    let mut row = vec![0.0; row_len];
+    for (half, display) in image
+        .as_raw()
+        .chunks_exact(row_len)
+        .zip(display.chunks_exact_mut(row_len))
+    {
+        for (full, half) in row.iter_mut().zip(half) {
+            *full = half.to_f32();
+        }
+        tonemap(&row, display);
+    }
+    display
+}
+
+multiversion! {
+    /// Add the color traced for each sample of a wave, with the pixel it was taken in and its
+    /// position there, to the sums of the output pixels of the pixel, in blocks of `scale` by
+    /// `scale`.
+    fn splat_wave(
+        colors: &mut [Color],
+        weights: &mut [f64],
+        scale: u32,
+        samples: &[(usize, (f64, f64))],
+        traced: &[Color],
+    ) {
+        let block_size = (scale * scale) as usize;
+        for (&(pixel, (u, v)), color) in samples.iter().zip(traced) {
+            let block = pixel * block_size..(pixel + 1) * block_size;
+            let (colors, weights) = (&mut colors[block.clone()], &mut weights[block]);
+            if scale == 1 {
+                colors[0] += color;
+                weights[0] += 1.0;
+                continue;
+            }
+
+            // A tent filter one output pixel in radius overlaps neighboring output pixels, so that
+            // each receives samples even at low sample counts.
+            let (u, v) = (u * scale as f64, v * scale as f64);
+            for (n, (color_sum, weight_sum)) in colors.iter_mut().zip(weights).enumerate() {
+                let center_u = (n as u32 % scale) as f64 + 0.5;
+                let center_v = (n as u32 / scale) as f64 + 0.5;
+                let weight =
+                    (1.0 - (u - center_u).abs()).max(0.0) * (1.0 - (v - center_v).abs()).max(0.0);
+                *color_sum += color * weight;
+                *weight_sum += weight;
+            }
+        }
+    }
+}
+
+multiversion! {
+    /// Convert linear channel values to display values, with a gamma of 2.
+    fn tonemap(linear: &[f32], display: &mut [u8]) {
+        for (value, display) in linear.iter().zip(display) {
+            *display = ((*value as f64).sqrt() * 255.999) as u8;
+        }
+    }
 }
 
 pub fn vector_near_zero(v: &Vector3<f64>) -> bool {
@@ -231,11 +1836,11 @@ pub fn vector_near_zero(v: &Vector3<f64>) -> bool {
 }
 
 pub fn random_vector_range(range: Range<f64>) -> Vector3<f64> {
-    let mut thread_rng = rand::thread_rng();
+    let mut rng = random::rng();
     Vector3::new(
-        thread_rng.gen_range(range.clone()),
-        thread_rng.gen_range(range.clone()),
-        thread_rng.gen_range(range),
+        rng.gen_range(range.clone()),
+        rng.gen_range(range.clone()),
+        rng.gen_range(range),
     )
 }
 
@@ -253,23 +1858,11 @@ pub fn random_unit_vector() -> Vector3<f64> {
 }
 
 pub fn random_vector_in_unit_disk() -> Vector3<f64> {
-    let mut thread_rng = rand::thread_rng();
+    let mut rng = random::rng();
     loop {
-        let vec = Vector3::new(
-            thread_rng.gen_range(-1.0..1.0),
-            thread_rng.gen_range(-1.0..1.0),
-            0.0,
-        );
+        let vec = Vector3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
         if vec.magnitude_squared() <= 1.0 {
             return vec;
         }
     }
 }
-
-fn color_to_rgb(c: &Vector3<f64>) -> [u8; 3] {
-    [
-        (c.x * 255.999) as u8,
-        (c.y * 255.999) as u8,
-        (c.z * 255.999) as u8,
-    ]
-}
@@ -0,0 +1,121 @@
+//! Linear images stored in half-precision floats, which take half the memory of `Rgb32FImage` for
+//! very large renders.
+//!
+//! Samples are still summed in full precision, and only each pixel's finished color is rounded to
+//! a half float. A half float keeps 11 significant bits, so the rounding error is at most 1/2048 of
+//! the color, while even at thousands of samples per pixel the noise of a render is around a
+//! percent: the rounding never shows. Debug builds check that bound against the full-float tiles
+//! as they are copied in. Only the range is a limit, as half floats hold values up to
+//! 65504, and brighter channels are clamped to that.
+
+use half::f16;
+use image::{Rgb, Rgb32FImage};
+
+/// An image of linear colors in half-precision floats.
+#[derive(Clone, Debug)]
+pub struct HalfImage {
+    width: u32,
+    height: u32,
+    /// The red, green, and blue channels of each pixel, row by row.
+    channels: Vec<f16>,
+}
+
+impl HalfImage {
+    /// A black image.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            channels: vec![f16::ZERO; width as usize * height as usize * 3],
+        }
+    }
+
+    /// Round an image of full floats.
+    pub fn from_rgb32f(image: &Rgb32FImage) -> Self {
+        let mut half = Self::new(image.width(), image.height());
+        half.copy_from(image, 0, 0);
+        half
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// The channels of each pixel, red, green, and blue, row by row.
+    pub fn as_raw(&self) -> &[f16] {
+        &self.channels
+    }
+
+    /// Write the pixels of `tile` with its upper-left pixel at (x, y), clamping channels too
+    /// bright for half floats.
+    pub fn copy_from(&mut self, tile: &Rgb32FImage, x: u32, y: u32) {
+        let max = f16::MAX.to_f32();
+        for (i, j, pixel) in tile.enumerate_pixels() {
+            let start = ((y + j) as usize * self.width as usize + (x + i) as usize) * 3;
+            for (channel, value) in self.channels[start..start + 3].iter_mut().zip(pixel.0) {
+                // `clamp` keeps NaNs, so that they are still found in the image
+                let clamped = value.clamp(-max, max);
+                *channel = f16::from_f32(clamped);
+                debug_assert!(
+                    clamped.is_nan() || rounding_error(clamped, *channel) <= 1.0 / 2048.0,
+                    "{value} was rounded to {channel}, further than half floats round"
+                );
+            }
+        }
+    }
+
+    /// The colors of the pixels, row by row, in full floats.
+    pub fn pixels(&self) -> impl Iterator<Item = Rgb<f32>> + '_ {
+        self.channels
+            .chunks_exact(3)
+            .map(|pixel| Rgb([pixel[0], pixel[1], pixel[2]].map(f16::to_f32)))
+    }
+
+    /// Multiply every channel by `factor`, as for exposure.
+    pub fn scale(&mut self, factor: f32) {
+        let max = f16::MAX.to_f32();
+        for channel in &mut self.channels {
+            *channel = f16::from_f32((channel.to_f32() * factor).clamp(-max, max));
+        }
+    }
+
+    /// The number of pixels with a channel at the largest half float, which were likely brighter
+    /// and clamped.
+    pub fn clamped(&self) -> u64 {
+        self.channels
+            .chunks_exact(3)
+            .filter(|pixel| {
+                pixel
+                    .iter()
+                    .any(|channel| *channel == f16::MAX || *channel == f16::MIN)
+            })
+            .count() as u64
+    }
+
+    /// Convert the image to full floats.
+    pub fn to_rgb32f(&self) -> Rgb32FImage {
+        let channels = self
+            .channels
+            .iter()
+            .map(|channel| channel.to_f32())
+            .collect();
+        Rgb32FImage::from_raw(self.width, self.height, channels)
+            .expect("the image holds width times height pixels")
+    }
+}
+
+/// How far `rounded` is from `value`, relative to `value`. Half floats below 2^-14 are subnormal
+/// and keep a fixed absolute precision of 2^-24 instead, so the error of those is relative to
+/// 2^-14, the smallest normal half float.
+fn rounding_error(value: f32, rounded: f16) -> f32 {
+    let smallest_normal = f16::MIN_POSITIVE.to_f32();
+    (rounded.to_f32() - value).abs() / value.abs().max(smallest_normal)
+}
@@ -0,0 +1,371 @@
+//! The non-photoreal renderer. Instead of tracing paths, it looks up each surface's flat color,
+//! lights it in a few hard bands from the scene's lights, brightens its silhouette with a rim
+//! light, and inks outlines where the depth or normal seen by neighbouring pixels jumps.
+
+use std::sync::Mutex;
+
+use nalgebra::Vector3;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    material::Material,
+    object::{Object, World},
+    random,
+};
+
+use super::{
+    camera_hit, Clipping, Color, Format, Framebuffer, HalfImage, ProgressEvent, Ray, Renderer,
+};
+
+/// Settings for non-photoreal rendering; see `Renderer::set_toon`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Toon {
+    /// The number of bands of light between shadow and fully lit, at least two.
+    pub bands: u32,
+    /// The brightness of the rim light along silhouettes, or zero for none.
+    pub rim: f64,
+    /// Whether to ink silhouettes and creases.
+    pub outlines: bool,
+}
+
+impl Default for Toon {
+    fn default() -> Self {
+        Self {
+            bands: 3,
+            rim: 0.3,
+            outlines: true,
+        }
+    }
+}
+
+/// The rows and columns of samples averaged for each pixel, which smooth the edges of bands and
+/// shapes.
+const SUBSAMPLES: u32 = 3;
+/// The brightness of surfaces in the darkest band, as a fraction of their color.
+const AMBIENT: f64 = 0.3;
+/// How far a surface must face away from the eye, as one minus the cosine of the angle, to be lit
+/// by the rim light.
+const RIM_THRESHOLD: f64 = 0.6;
+/// The largest cosine of the angle between the normals of neighbouring pixels at which a crease is
+/// inked.
+const CREASE_COSINE: f64 = 0.7;
+/// The largest change in the slope of depth across a pixel, as a fraction of its depth, that is
+/// not inked.
+const DEPTH_THRESHOLD: f64 = 0.05;
+const INK: Color = Color::new(0.02, 0.02, 0.02);
+
+/// A light that picks out surfaces facing it.
+struct KeyLight {
+    position: KeyLightPosition,
+    /// The light's brightness relative to the brightest key light.
+    strength: f64,
+    /// The object that gives off the light, which does not shadow it.
+    object: Option<usize>,
+}
+
+enum KeyLightPosition {
+    At(Vector3<f64>),
+    /// Infinitely far away in this direction.
+    Toward(Vector3<f64>),
+}
+
+/// What the center of a pixel sees, for finding outlines.
+#[derive(Clone, Copy)]
+struct Surface {
+    /// The scene object seen.
+    object: usize,
+    /// The distance along the ray.
+    depth: f64,
+    normal: Vector3<f64>,
+}
+
+impl Renderer {
+    /// Render `world` in the style set by `set_toon`, into a framebuffer of colors in `format`, or
+    /// `None` if the render is cancelled first.
+    pub(super) fn render_toon(
+        &self,
+        world: &World,
+        parallel: bool,
+        report: &(impl Fn(ProgressEvent) + Sync),
+        toon: &Toon,
+        format: Format,
+    ) -> Option<Framebuffer> {
+        let (width, height) = self.output_dimensions();
+        let lights = key_lights(world);
+        let rows_completed = Mutex::new((0, 0));
+
+        let render_row = |y: u32| {
+            if self.handle.is_cancelled() {
+                return None;
+            }
+            let row = (0..width)
+                .map(|x| self.toon_pixel(world, &lights, toon, x, y))
+                .collect::<Vec<_>>();
+
+            let mut completed = rows_completed.lock().unwrap();
+            completed.0 += 1;
+            let percent = completed.0 * 100 / height;
+            if percent > completed.1 {
+                completed.1 = percent;
+                report(ProgressEvent::Rendering(percent));
+            }
+            Some(row)
+        };
+        let rows = if parallel {
+            (0..height)
+                .into_par_iter()
+                .map(render_row)
+                .collect::<Option<Vec<_>>>()
+        } else {
+            (0..height).map(render_row).collect()
+        }?;
+
+        let image = image::Rgb32FImage::from_fn(width, height, |x, y| {
+            let (color, _) = rows[y as usize][x as usize];
+            let color = if toon.outlines && self.is_outline(&rows, x, y) {
+                INK
+            } else {
+                color
+            };
+            image::Rgb(color.cast::<f32>().into())
+        });
+
+        // Outlines need the whole image, so its tiles are only reported once it is done.
+        let (columns, tile_rows) = self.tile_grid();
+        let size = self.tile_size();
+        for row in 0..tile_rows {
+            for column in 0..columns {
+                let (x, y) = (column * size, row * size);
+                let tile = image::imageops::crop_imm(
+                    &image,
+                    x,
+                    y,
+                    size.min(width - x),
+                    size.min(height - y),
+                )
+                .to_image();
+                report(ProgressEvent::TileFinished {
+                    column,
+                    row,
+                    x,
+                    y,
+                    pixels: super::to_display(&tile),
+                    samples: (tile.width() * tile.height() * SUBSAMPLES * SUBSAMPLES).into(),
+                    linear: tile,
+                });
+            }
+        }
+
+        Some(match format {
+            Format::Display => {
+                Framebuffer::Display(super::to_display(&image), Clipping::measure(&image))
+            }
+            Format::Linear => Framebuffer::Hdr(image),
+            Format::Half => Framebuffer::Half(HalfImage::from_rgb32f(&image)),
+        })
+    }
+
+    /// The color of output pixel (x, y), and what its center sees.
+    fn toon_pixel(
+        &self,
+        world: &World,
+        lights: &[KeyLight],
+        toon: &Toon,
+        x: u32,
+        y: u32,
+    ) -> (Color, Option<Surface>) {
+        random::seed_pixel(self.seed, x, y);
+        let scale = self.super_resolution;
+        let (i, j) = (x / scale, y / scale);
+        // the position of a point of the output pixel within the camera pixel containing it
+        let position = |u: f64, v: f64| {
+            (
+                ((x % scale) as f64 + u) / scale as f64,
+                ((y % scale) as f64 + v) / scale as f64,
+            )
+        };
+
+        let mut color = Color::zeros();
+        for n in 0..SUBSAMPLES * SUBSAMPLES {
+            let u = ((n % SUBSAMPLES) as f64 + 0.5) / SUBSAMPLES as f64;
+            let v = ((n / SUBSAMPLES) as f64 + 0.5) / SUBSAMPLES as f64;
+            let ray = self.get_ray(i, j, position(u, v), (0.5, 0.5), 0.0);
+            color += self.toon_color(world, lights, toon, &ray);
+        }
+        color /= (SUBSAMPLES * SUBSAMPLES) as f64;
+
+        let ray = self.get_ray(i, j, position(0.5, 0.5), (0.5, 0.5), 0.0);
+        let surface = camera_hit(world, &ray).map(|hit| Surface {
+            object: world.object_ids[hit.object],
+            depth: hit.t * ray.direction.magnitude(),
+            normal: hit.normal,
+        });
+
+        (color, surface)
+    }
+
+    /// The shaded color seen along a camera ray.
+    fn toon_color(&self, world: &World, lights: &[KeyLight], toon: &Toon, ray: &Ray) -> Color {
+        let Some(mut hit) = camera_hit(world, ray) else {
+            return self.background_color;
+        };
+        let bevel_radius = world.object_settings(&hit).bevel_radius;
+        if bevel_radius > 0.0 {
+            world.bevel(ray, &mut hit, bevel_radius);
+        }
+
+        let material = world.material(ray, &hit);
+        let base = material.base_color();
+        if let Material::Light { .. } = *material {
+            return base;
+        }
+
+        let view = -ray.direction.normalize();
+        let mut light = 0.0;
+        if lights.is_empty() {
+            // Scenes lit only by their background are lit from the eye.
+            light = hit.normal.dot(&view).max(0.0);
+        }
+        for key in lights {
+            let (to_light, distance) = match key.position {
+                KeyLightPosition::At(position) => (position - hit.p, 1.0),
+                KeyLightPosition::Toward(direction) => (direction, f64::INFINITY),
+            };
+            let facing = hit.normal.dot(&to_light.normalize());
+            if facing <= 0.0 {
+                continue;
+            }
+            let shadow_ray = Ray {
+                origin: hit.p,
+                direction: to_light,
+                time: 0.0,
+            };
+            let shadowed = world
+                .hit(&shadow_ray, 0.001, distance)
+                .is_some_and(|blocker| Some(world.object_ids[blocker.object]) != key.object);
+            if !shadowed {
+                light += facing * key.strength;
+            }
+        }
+
+        let bands = toon.bands.max(2);
+        let band = (light.min(1.0) * bands as f64)
+            .floor()
+            .min((bands - 1) as f64);
+        let brightness = AMBIENT + (1.0 - AMBIENT) * band / (bands - 1) as f64;
+        let mut color = base * brightness;
+        if 1.0 - hit.normal.dot(&view).abs() > RIM_THRESHOLD {
+            color += Color::repeat(toon.rim);
+        }
+
+        color
+    }
+
+    /// Whether output pixel (x, y) lies on an outline: the near side of a silhouette, or a crease
+    /// or fold within an object.
+    fn is_outline(&self, rows: &[Vec<(Color, Option<Surface>)>], x: u32, y: u32) -> bool {
+        let surface = |x: i64, y: i64| {
+            rows.get(usize::try_from(y).ok()?)?
+                .get(usize::try_from(x).ok()?)
+                .map(|(_, surface)| *surface)
+        };
+        let Some(center) = surface(x.into(), y.into()).flatten() else {
+            return false;
+        };
+        // Neighbours in the other view of a stereo image are not next to each other in the world.
+        let eye = self.eye_column(x / self.super_resolution).0;
+        let same_eye = |x: i64| {
+            u32::try_from(x).is_ok_and(|x| self.eye_column(x / self.super_resolution).0 == eye)
+        };
+
+        for (dx, dy) in [(1, 0), (0, 1)] {
+            let (x, y) = (i64::from(x), i64::from(y));
+            let before = surface(x - dx, y - dy).filter(|_| same_eye(x - dx));
+            let after = surface(x + dx, y + dy).filter(|_| same_eye(x + dx));
+
+            for neighbour in [before, after].into_iter().flatten() {
+                let Some(neighbour) = neighbour else {
+                    return true;
+                };
+                if neighbour.object != center.object && neighbour.depth > center.depth {
+                    return true;
+                }
+            }
+            // Creases are inked on one side, so their lines are as thin as silhouettes'.
+            if let Some(Some(after)) = after {
+                if after.object == center.object && after.normal.dot(&center.normal) < CREASE_COSINE
+                {
+                    return true;
+                }
+            }
+            if let (Some(Some(before)), Some(Some(after))) = (before, after) {
+                let curvature = before.depth + after.depth - 2.0 * center.depth;
+                if curvature.abs() > DEPTH_THRESHOLD * center.depth
+                    && before.object == center.object
+                    && after.object == center.object
+                    && center.depth < before.depth.max(after.depth)
+                {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+}
+
+/// The lights of `world` that shade surfaces: its point and directional lights, and its spheres
+/// and quads made of light materials.
+fn key_lights(world: &World) -> Vec<KeyLight> {
+    let mut lights = Vec::new();
+    for light in &world.lights {
+        lights.push(match light {
+            crate::light::Light::Point {
+                position, color, ..
+            } => KeyLight {
+                position: KeyLightPosition::At(*position),
+                strength: color.max(),
+                object: None,
+            },
+            crate::light::Light::Directional {
+                direction, color, ..
+            } => KeyLight {
+                position: KeyLightPosition::Toward(-direction.into_inner()),
+                strength: color.max(),
+                object: None,
+            },
+        });
+    }
+    for (object, &id) in world.objects.iter().zip(&world.object_ids) {
+        // An instance of a light is one light, at the center of the primitives it places.
+        let material = match &*object {
+            Object::Sphere { material, .. }
+            | Object::Quad { material, .. }
+            | Object::Instance { material, .. } => *material,
+            _ => continue,
+        };
+        let (Material::Light { color }, Some(bounds)) =
+            (&world.materials[material], object.bounds())
+        else {
+            continue;
+        };
+        lights.push(KeyLight {
+            position: KeyLightPosition::At(world.object_settings[id].swept_bounds(bounds).center()),
+            strength: color.max(),
+            object: Some(id),
+        });
+    }
+
+    let brightest = lights
+        .iter()
+        .map(|light| light.strength)
+        .fold(0.0, f64::max);
+    for light in &mut lights {
+        light.strength = if brightest > 0.0 {
+            light.strength / brightest
+        } else {
+            1.0
+        };
+    }
+    lights
+}
@@ -0,0 +1,266 @@
+//! The wavefront path tracer. Rather than following each path to its end before starting the next,
+//! a batch of paths is advanced one bounce at a time, through a stage that finds what every path
+//! hits and then one that shades every hit. Each stage runs the same code over many paths in a
+//! row, which keeps it in cache, and the batches map directly onto how a GPU would trace them.
+
+use rand::Rng;
+
+use crate::{
+    dispatch::multiversion,
+    medium::Interaction,
+    object::{Hit, World},
+    polarization::Polarization,
+    profile::{self, Scope},
+    random::{self, Stream},
+};
+
+use super::{direct_light, Color, Ray, Renderer};
+
+/// The largest number of paths traced together, which bounds the memory used by each thread.
+pub const WAVE_SIZE: usize = 4096;
+
+/// The memory used for each path in a wave while it is traced, including its camera ray and the
+/// color it produces.
+pub const PATH_BYTES: usize = size_of::<Path>()
+    + size_of::<Option<Hit>>()
+    + size_of::<bool>()
+    + size_of::<(Ray, Stream)>()
+    + size_of::<(usize, (f64, f64))>()
+    + size_of::<Color>();
+
+/// The number of bounces after which paths may be ended by Russian roulette.
+const ROULETTE_START_BOUNCE: u32 = 3;
+
+/// A path from the camera being traced.
+struct Path {
+    /// The index of the camera sample the path started from.
+    sample: usize,
+    state: PathState,
+    stream: Stream,
+}
+
+/// Where a path is and what it has gathered so far.
+pub(super) struct PathState {
+    /// The ray the path continues along.
+    pub ray: Ray,
+    /// The light gathered so far.
+    pub radiance: Color,
+    /// The fraction of the light arriving along `ray` that reaches the camera.
+    pub throughput: Color,
+    /// The number of rays the path may still cast.
+    pub depth: u32,
+    /// The roughness applied to specular bounces; it starts at zero and is raised to the camera's
+    /// regularization once the path has scattered off a rough surface.
+    pub roughness: f64,
+    /// Whether the path has scattered off a diffuse surface, after which a specular surface would
+    /// start a caustic.
+    pub diffuse: bool,
+    /// Whether the path's last scatter was off a diffuse surface.
+    pub last_diffuse: bool,
+    /// The polarization the path is sensitive to, if the camera has a polarizing filter.
+    pub polarization: Option<Polarization>,
+}
+
+impl PathState {
+    /// The fraction of unpolarized light, such as that of lights and the sky, arriving along `ray`
+    /// that reaches the camera.
+    fn unpolarized_throughput(&self) -> Color {
+        self.throughput * self.polarization.map_or(1.0, |p| p.intensity())
+    }
+}
+
+impl Renderer {
+    /// Trace a path from each camera ray, drawing its random numbers from the stream given with
+    /// it, and return the color each brings to the camera.
+    pub(super) fn trace(&self, world: &World, camera_rays: Vec<(Ray, Stream)>) -> Vec<Color> {
+        let mut colors = vec![Color::zeros(); camera_rays.len()];
+        let mut paths = camera_rays
+            .into_iter()
+            .enumerate()
+            .map(|(sample, (ray, stream))| Path {
+                sample,
+                state: self.start_path(ray),
+                stream,
+            })
+            .collect::<Vec<_>>();
+
+        while !paths.is_empty() {
+            let traversal = profile::scope(Scope::Traversal);
+            let hits = find_hits(world, &paths);
+            drop(traversal);
+
+            let _shading = profile::scope(Scope::Shading);
+            let continues = paths
+                .iter_mut()
+                .zip(hits)
+                .map(|(path, hit)| {
+                    random::with_stream(&mut path.stream, || {
+                        self.shade(world, &mut path.state, hit)
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let mut continues = continues.into_iter();
+            paths.retain(|path| {
+                let continues = continues.next().unwrap_or(false);
+                if !continues {
+                    colors[path.sample] = path.state.radiance;
+                }
+                continues
+            });
+        }
+
+        colors
+    }
+
+    /// Get the color of the light arriving along a camera ray, by following a single path from it
+    /// with the current thread's random stream. `render` traces many paths at once instead.
+    pub fn ray_color(&self, world: &World, ray: Ray) -> Color {
+        let mut path = self.start_path(ray);
+        loop {
+            let hit = world.hit(&path.ray, 0.001, f64::INFINITY);
+            if !self.shade(world, &mut path, hit) {
+                return path.radiance;
+            }
+        }
+    }
+
+    /// The state of a path leaving the camera along `ray`.
+    pub(super) fn start_path(&self, ray: Ray) -> PathState {
+        let polarization = self.polarizer.map(|angle| {
+            Polarization::filter(angle, &self.pixel_delta_u.normalize(), &ray.direction)
+        });
+        PathState {
+            ray,
+            radiance: Color::zeros(),
+            throughput: Color::repeat(1.0),
+            depth: self.max_ray_bounces,
+            roughness: 0.0,
+            diffuse: false,
+            last_diffuse: false,
+            polarization,
+        }
+    }
+
+    /// Gather the light at what a path's ray hit, if anything, and continue the path from there.
+    /// Returns whether the path goes on.
+    pub(super) fn shade(&self, world: &World, path: &mut PathState, hit: Option<Hit>) -> bool {
+        // Camera rays pass through the atmosphere, which adds its own color over the distance.
+        if path.depth == self.max_ray_bounces {
+            if let Some(atmosphere) = &world.atmosphere {
+                let distance = hit
+                    .as_ref()
+                    .map_or(f64::INFINITY, |hit| hit.t * path.ray.direction.magnitude());
+                let transmittance = atmosphere.transmittance(&path.ray, distance);
+                path.radiance += path
+                    .unpolarized_throughput()
+                    .component_mul(&atmosphere.color)
+                    * (1.0 - transmittance);
+                path.throughput *= transmittance;
+            }
+        }
+        path.depth -= 1;
+
+        let Some(mut hit) = hit else {
+            path.radiance += path
+                .unpolarized_throughput()
+                .component_mul(&self.background_color);
+            return false;
+        };
+
+        // Surfaces hidden from this path by their light path rules are passed through, without
+        // using up a bounce. Shadow-only surfaces still stop the light that diffuse bounces gather,
+        // which is what casts their shadows.
+        let light_paths = world.light_paths(hit.material);
+        if light_paths.shadow_only && path.last_diffuse {
+            return false;
+        }
+        if light_paths.shadow_only || (light_paths.diffuse_visible_only && !path.last_diffuse) {
+            path.ray = Ray {
+                origin: hit.p,
+                direction: path.ray.direction,
+                time: path.ray.time,
+            };
+            path.depth += 1;
+            return true;
+        }
+
+        // A ray reaching the back of a surface has travelled through the object's interior.
+        if !hit.front_face {
+            if let Some(medium) = world.material(&path.ray, &hit).medium() {
+                match medium.interact(&path.ray, hit.t) {
+                    Interaction::Scatter { weight, scattered } => {
+                        path.throughput.component_mul_assign(&weight);
+                        if let Some(polarization) = &mut path.polarization {
+                            polarization.depolarize(&scattered.direction);
+                        }
+                        path.ray = scattered;
+                        path.roughness = self.regularization;
+                        path.diffuse = true;
+                        path.last_diffuse = true;
+                        return path.depth > 0;
+                    }
+                    Interaction::Surface { weight } => {
+                        path.throughput.component_mul_assign(&weight)
+                    }
+                }
+            }
+        }
+
+        let bevel_radius = world.object_settings(&hit).bevel_radius;
+        if bevel_radius > 0.0 {
+            world.bevel(&path.ray, &mut hit, bevel_radius);
+        }
+
+        let material = world.material(&path.ray, &hit);
+        if light_paths.no_caustics && path.diffuse && material.is_specular() {
+            return false;
+        }
+        let emission = material.emit(&hit) + direct_light(world, &path.ray, &material, &hit);
+        path.radiance += path.unpolarized_throughput().component_mul(&emission);
+
+        let Some((attenuation, scattered)) = material.scatter(&path.ray, &hit, path.roughness)
+        else {
+            return false;
+        };
+
+        // Light that reaches a rough surface through a chain of sharp reflections or refractions (a
+        // caustic) is found by few paths, which show up as fireflies. Blurring later bounces
+        // spreads it over many more.
+        path.last_diffuse = !material.is_specular();
+        if path.last_diffuse {
+            path.roughness = self.regularization;
+            path.diffuse = true;
+        }
+        path.throughput.component_mul_assign(&attenuation);
+        if let Some(polarization) = &mut path.polarization {
+            polarization.scatter(&material, &hit, &path.ray.direction, &scattered.direction);
+        }
+        path.ray = scattered;
+
+        // Paths carrying little light are ended at random, and the survivors are brightened to
+        // make up for them, which spends time on the paths that matter without changing the
+        // average.
+        if self.max_ray_bounces - path.depth >= ROULETTE_START_BOUNCE {
+            let survival = path.throughput.max().min(0.95);
+            if random::rng().gen_range(0.0..1.0) >= survival {
+                return false;
+            }
+            path.throughput /= survival;
+        }
+
+        path.depth > 0
+    }
+}
+
+multiversion! {
+    /// Find what each path's ray hits.
+    fn find_hits(world: &World, paths: &[Path]) -> Vec<Option<Hit>> {
+        // a loop rather than `collect`, which would not be inlined into each copy
+        let mut hits = Vec::with_capacity(paths.len());
+        for path in paths {
+            hits.push(world.hit(&path.state.ray, 0.001, f64::INFINITY));
+        }
+        hits
+    }
+}
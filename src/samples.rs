@@ -0,0 +1,60 @@
+//! Sample files, which keep every sample of a render for denoisers and reconstruction algorithms
+//! outside of rtk. A file starts with a header, then holds one record for each sample, in the order
+//! they were taken, with every number little-endian:
+//!
+//! - The header: the bytes `rtksmpl1`, then the width and height of the image in pixels, before
+//!   any super-resolution, as `u32`s.
+//! - Each sample: its pixel's column and row as `u32`s; then, as `f32`s, its position in the pixel
+//!   from 0 to 1 across and down, its red, green, and blue radiance, and the normal, flat color,
+//!   and distance from the camera of the first surface its camera ray hit. A ray that hit nothing
+//!   has a zero normal, the background color, and an infinite distance.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use rtk::render::SampleRecord;
+
+/// The bytes that start every sample file, ending with the version of the format.
+const MAGIC: &[u8; 8] = b"rtksmpl1";
+
+pub struct SampleFile {
+    file: BufWriter<File>,
+}
+
+impl SampleFile {
+    /// Start a sample file for an image of `width` by `height` pixels.
+    pub fn create(path: &Path, (width, height): (u32, u32)) -> anyhow::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&width.to_le_bytes())?;
+        file.write_all(&height.to_le_bytes())?;
+
+        Ok(Self { file })
+    }
+
+    pub fn write_samples(&mut self, samples: &[SampleRecord]) -> anyhow::Result<()> {
+        for sample in samples {
+            self.file.write_all(&sample.pixel.0.to_le_bytes())?;
+            self.file.write_all(&sample.pixel.1.to_le_bytes())?;
+            let floats = [sample.position.0, sample.position.1]
+                .into_iter()
+                .chain(sample.radiance)
+                .chain(sample.normal)
+                .chain(sample.albedo)
+                .chain([sample.depth]);
+            for value in floats {
+                self.file.write_all(&value.to_le_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write out what is left buffered.
+    pub fn finish(mut self) -> anyhow::Result<()> {
+        Ok(self.file.flush()?)
+    }
+}
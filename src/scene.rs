@@ -1,124 +1,2645 @@
 //! This module describes the model of a scene file.
 
-use nalgebra::{UnitQuaternion, Vector3};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use nalgebra::{Unit, UnitQuaternion, Vector3};
 use serde::{Deserialize, Serialize};
 
+use crate::{builder::WorldBuilder, plugin::Plugins, points::PointCloud};
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Scene {
+    /// The version of the scene format the scene is written in. Files without one are from before
+    /// the format had versions, and are migrated when read.
+    #[serde(default = "current_version")]
+    pub version: u32,
     pub camera: Camera,
+    /// Other views of the scene, by name, which `select_camera` switches to.
+    #[serde(default)]
+    pub cameras: BTreeMap<String, NamedCamera>,
+    #[serde(default)]
+    pub materials: Vec<MaterialEntry>,
     #[serde(default)]
-    pub materials: Vec<Material>,
+    pub textures: Vec<Texture>,
     #[serde(default)]
     pub objects: Vec<Object>,
+    #[serde(default)]
+    pub lights: Vec<Light>,
+    pub atmosphere: Option<Atmosphere>,
+    /// Planes cutting away part of every object, in addition to each object's own.
+    #[serde(default, rename = "clip-planes")]
+    pub clip_planes: Vec<ClipPlane>,
+    /// The unit of angles written as bare numbers. Without it, each angle keeps the unit it has
+    /// always had: degrees for `fov`, `defocus-angle`, and `angular-radius`, and radians for Euler
+    /// rotations.
+    #[serde(default, rename = "angle-unit")]
+    pub angle_unit: Option<AngleUnit>,
+    /// A script generating more of the scene, run by `run_script`.
+    pub script: Option<Script>,
+}
+
+fn current_version() -> u32 {
+    crate::migrate::VERSION
+}
+
+/// The number of samples per pixel taken by a camera that does not give one.
+pub const DEFAULT_SAMPLES_PER_PIXEL: u32 = 100;
+
+/// A color in a scene file: either a linear `[r, g, b]`, a string holding a hex code like
+/// `"#ffcc00"` or a CSS color name like `"rebeccapurple"`, or a table giving the space of its
+/// components, like `{ rgb = [1.0, 0.8, 0.0], color-space = "srgb" }`. Strings are display colors,
+/// as picked in an image editor, and are converted to linear with the same gamma of 2 used for
+/// output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Rgb(pub f64, pub f64, pub f64);
+
+impl Rgb {
+    /// The linear color of a hex code or CSS color name, if `text` is one.
+    pub fn parse(text: &str) -> Option<Self> {
+        let [r, g, b] = crate::color::parse(text)?;
+        let display = Vector3::new(r, g, b).map(|c| c as f64 / 255.0);
+        Some(crate::color::display_to_linear(display).into())
+    }
+}
+
+impl From<(f64, f64, f64)> for Rgb {
+    fn from((r, g, b): (f64, f64, f64)) -> Self {
+        Self(r, g, b)
+    }
+}
+
+impl From<Vector3<f64>> for Rgb {
+    fn from(color: Vector3<f64>) -> Self {
+        Self(color.x, color.y, color.z)
+    }
+}
+
+impl From<Rgb> for Vector3<f64> {
+    fn from(Rgb(r, g, b): Rgb) -> Self {
+        Vector3::new(r, g, b)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rgb {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RgbVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for RgbVisitor {
+            type Value = Rgb;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("an [r, g, b] array, a hex code like \"#ffcc00\", or a color name")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, text: &str) -> Result<Rgb, E> {
+                Rgb::parse(text).ok_or_else(|| {
+                    E::custom(format!(
+                        "`{text}` is not a hex code like \"#ffcc00\" or a CSS color name"
+                    ))
+                })
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(self, seq: A) -> Result<Rgb, A::Error> {
+                let components: (f64, f64, f64) =
+                    Deserialize::deserialize(serde::de::value::SeqAccessDeserializer::new(seq))?;
+                Ok(components.into())
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<Rgb, A::Error> {
+                #[derive(Deserialize)]
+                #[serde(rename_all = "kebab-case", deny_unknown_fields)]
+                struct InSpace {
+                    rgb: (f64, f64, f64),
+                    color_space: ColorSpace,
+                }
+
+                let InSpace { rgb, color_space } =
+                    Deserialize::deserialize(serde::de::value::MapAccessDeserializer::new(map))?;
+                let color_space = crate::color::ColorSpace::from(color_space);
+                Ok(color_space.to_linear(Rgb::from(rgb).into()).into())
+            }
+        }
+
+        deserializer.deserialize_any(RgbVisitor)
+    }
+}
+
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for Rgb {
+    fn schema_name() -> String {
+        "Color".to_string()
+    }
+
+    fn json_schema(generator: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        #[derive(schemars::JsonSchema)]
+        #[schemars(untagged)]
+        #[allow(dead_code)]
+        enum Color {
+            /// A linear color.
+            Linear((f64, f64, f64)),
+            /// A hex code like `#ffcc00` or a CSS color name, converted to linear.
+            Display(String),
+            /// A color in the given space, converted to linear.
+            InSpace {
+                rgb: (f64, f64, f64),
+                #[schemars(rename = "color-space")]
+                color_space: ColorSpace,
+            },
+        }
+
+        Color::json_schema(generator)
+    }
+}
+
+/// The space a color's components are given in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorSpace {
+    /// Linear, as the renderer works in.
+    #[default]
+    Linear,
+    /// Stored for display, like 8-bit images and colors picked in an image editor, and converted
+    /// to linear with the same gamma of 2 used for output.
+    Srgb,
+}
+
+impl From<ColorSpace> for crate::color::ColorSpace {
+    fn from(value: ColorSpace) -> Self {
+        match value {
+            ColorSpace::Linear => Self::Linear,
+            ColorSpace::Srgb => Self::Srgb,
+        }
+    }
+}
+
+/// A color of a texture: `[r, g, b]` in the texture's `color-space`, or a hex code, color name, or
+/// table that gives its own.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(untagged)]
+pub enum TextureColor {
+    Components((f64, f64, f64)),
+    // written back with its space, so that it is not read in the texture's
+    #[serde(serialize_with = "serialize_in_linear")]
+    Color(Rgb),
+}
+
+fn serialize_in_linear<S: serde::Serializer>(
+    color: &Rgb,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    use serde::ser::SerializeStruct;
+
+    let mut table = serializer.serialize_struct("Color", 2)?;
+    table.serialize_field("rgb", color)?;
+    table.serialize_field("color-space", &ColorSpace::Linear)?;
+    table.end()
+}
+
+impl TextureColor {
+    /// The linear color, reading `[r, g, b]` in `color_space`.
+    pub fn to_linear(self, color_space: ColorSpace) -> crate::render::Color {
+        match self {
+            Self::Components(rgb) => {
+                crate::color::ColorSpace::from(color_space).to_linear(Rgb::from(rgb).into())
+            }
+            Self::Color(color) => color.into(),
+        }
+    }
+}
+
+/// The unit of an angle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum AngleUnit {
+    #[serde(rename = "deg", alias = "degrees")]
+    Degrees,
+    #[serde(rename = "rad", alias = "radians")]
+    Radians,
+}
+
+impl AngleUnit {
+    fn suffix(self) -> &'static str {
+        match self {
+            Self::Degrees => "deg",
+            Self::Radians => "rad",
+        }
+    }
+}
+
+/// An angle in a scene file: either a number, in the scene's `angle-unit` or otherwise the unit of
+/// the field it is in, or a string with a unit suffix like `"45deg"` or `"0.5rad"`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Angle {
+    pub value: f64,
+    /// The unit the angle was written with, or `None` for a bare number.
+    pub unit: Option<AngleUnit>,
+}
+
+impl Angle {
+    pub fn degrees(value: f64) -> Self {
+        Self {
+            value,
+            unit: Some(AngleUnit::Degrees),
+        }
+    }
+
+    pub fn radians(value: f64) -> Self {
+        Self {
+            value,
+            unit: Some(AngleUnit::Radians),
+        }
+    }
+
+    /// The angle of a number followed by `deg` or `rad`, if `text` is one.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        [AngleUnit::Degrees, AngleUnit::Radians]
+            .into_iter()
+            .find_map(|unit| {
+                let value = text.strip_suffix(unit.suffix())?.trim_end().parse().ok()?;
+                Some(Self {
+                    value,
+                    unit: Some(unit),
+                })
+            })
+    }
+
+    /// The angle in radians, reading a bare number as being in `unit`.
+    pub fn to_radians(self, unit: AngleUnit) -> f64 {
+        match self.unit.unwrap_or(unit) {
+            AngleUnit::Degrees => self.value.to_radians(),
+            AngleUnit::Radians => self.value,
+        }
+    }
+
+    /// The angle in degrees, reading a bare number as being in `unit`.
+    pub fn to_degrees(self, unit: AngleUnit) -> f64 {
+        match self.unit.unwrap_or(unit) {
+            AngleUnit::Degrees => self.value,
+            AngleUnit::Radians => self.value.to_degrees(),
+        }
+    }
+}
+
+impl From<f64> for Angle {
+    fn from(value: f64) -> Self {
+        Self { value, unit: None }
+    }
+}
+
+impl Serialize for Angle {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.unit {
+            Some(unit) => serializer.serialize_str(&format!("{}{}", self.value, unit.suffix())),
+            None => serializer.serialize_f64(self.value),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Angle {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct AngleVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for AngleVisitor {
+            type Value = Angle;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a number, or an angle with a unit like \"45deg\" or \"0.5rad\"")
+            }
+
+            fn visit_f64<E: serde::de::Error>(self, value: f64) -> Result<Angle, E> {
+                Ok(value.into())
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, value: i64) -> Result<Angle, E> {
+                Ok((value as f64).into())
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Angle, E> {
+                Ok((value as f64).into())
+            }
+
+            fn visit_str<E: serde::de::Error>(self, text: &str) -> Result<Angle, E> {
+                Angle::parse(text).ok_or_else(|| {
+                    E::custom(format!(
+                        "`{text}` is not an angle with a unit like \"45deg\" or \"0.5rad\""
+                    ))
+                })
+            }
+        }
+
+        deserializer.deserialize_any(AngleVisitor)
+    }
+}
+
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for Angle {
+    fn schema_name() -> String {
+        "Angle".to_string()
+    }
+
+    fn json_schema(generator: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        #[derive(schemars::JsonSchema)]
+        #[schemars(untagged)]
+        #[allow(dead_code)]
+        enum Angle {
+            /// An angle in the scene's `angle-unit`, or otherwise the unit of the field.
+            Number(f64),
+            /// An angle with a unit, like `45deg` or `0.5rad`.
+            WithUnit(String),
+        }
+
+        Angle::json_schema(generator)
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "kebab-case")]
 pub struct Camera {
     pub image_dimensions: (u32, u32),
-    pub background_color: Option<(f64, f64, f64)>,
+    pub background_color: Option<Rgb>,
     pub samples_per_pixel: Option<u32>,
     pub position: Option<(f64, f64, f64)>,
     pub rotation: Option<Rotation>,
-    pub fov: f64,
+    /// Place the camera to fit objects into view, instead of at `position`.
+    pub frame: Option<Framing>,
+    /// The vertical field of view, in degrees unless given another unit. It stays the same
+    /// wherever the camera focuses, so animating the focus never changes the framing, as with a
+    /// lens corrected for focus breathing.
+    pub fov: Angle,
+    pub defocus: Option<Defocus>,
+    /// Focus on the center of the object with this name, instead of at the defocus block's focus
+    /// distance.
+    pub focus_target: Option<String>,
+    /// Focus on this point, instead of at the defocus block's focus distance.
+    pub focus_point: Option<(f64, f64, f64)>,
+    /// Focus on whatever is seen at a point of the image, instead of at the defocus block's
+    /// focus distance.
+    pub focus: Option<Focus>,
+    /// Varies the random samples taken; renders with the same seed are identical.
+    #[serde(default)]
+    pub seed: u64,
+    /// Blur reflections and refractions after a path's first diffuse bounce by this roughness,
+    /// from 0 (off) to 1, to remove fireflies from caustics.
+    #[serde(default)]
+    pub regularization: f64,
+    /// Adjust the brightness of the image to its average luminance, instead of leaving it as lit.
+    #[serde(default)]
+    pub auto_exposure: bool,
+    /// Parts of the image to render with more samples than the rest.
+    #[serde(default)]
+    pub regions: Vec<SampleRegion>,
+    /// Keep the shutter open while objects move through their `motion`, so that moving lights
+    /// paint trails across the image. Otherwise, objects are seen where their motion starts.
+    #[serde(default)]
+    pub long_exposure: bool,
+    /// The part of objects' motion, as `[open, close]` fractions from 0 at its start to 1 at its
+    /// end, over which the shutter is open, blurring what moves. `long-exposure` is the same as
+    /// `[0.0, 1.0]`.
+    pub shutter: Option<(f64, f64)>,
+    /// Put a linear polarizing filter over the lens, passing light polarized at this angle from
+    /// the image's horizontal, counter-clockwise, in degrees unless given another unit. Reflections
+    /// off glass and water are polarized, so turning the filter dims or brightens them.
+    pub polarizer: Option<Angle>,
+    /// Output this many pixels across each of `image-dimensions`' pixels, spreading each pixel's
+    /// samples over them. This is sharper than upscaling a render, for the same render time.
+    #[serde(default = "default_super_resolution")]
+    pub super_resolution: u32,
+    /// Whether the seed changes between the frames of an animation.
+    #[serde(default)]
+    pub noise_seed_mode: NoiseSeedMode,
+    /// Render a view for each eye, for viewing in 3D.
+    pub stereo: Option<Stereo>,
+    /// How many samples each pixel takes. By default, every pixel takes `samples-per-pixel`.
+    pub sampling: Option<Sampling>,
+}
+
+/// What the camera is placed to fit into view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub enum Framing {
+    /// Every object with bounds. The camera backs away along its view from the center of their
+    /// bounds until they all fit, keeping its rotation and field of view.
+    All,
+}
+
+/// Two eyes either side of the camera's position, along its right-hand direction.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub struct Stereo {
+    /// The distance between the eyes.
+    pub separation: f64,
+    /// The distance from the camera at which objects appear at the depth of the screen.
+    pub convergence: f64,
+    #[serde(default)]
+    pub layout: StereoLayout,
+}
+
+/// How the views of a stereo camera are put in one image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub enum StereoLayout {
+    /// The left eye's view, then the right's, in an image twice as wide as `image-dimensions`.
+    #[default]
+    SideBySide,
+    /// A red-cyan anaglyph, for glasses with a red filter over the left eye.
+    Anaglyph,
+}
+
+/// A view of the scene that replaces the main camera's placement and lens. Anything left out, and
+/// every other setting, is kept from the main camera.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub struct NamedCamera {
+    pub image_dimensions: Option<(u32, u32)>,
+    pub position: Option<(f64, f64, f64)>,
+    pub rotation: Option<Rotation>,
+    pub fov: Option<Angle>,
     pub defocus: Option<Defocus>,
+    pub focus_target: Option<String>,
+    pub focus_point: Option<(f64, f64, f64)>,
+    pub focus: Option<Focus>,
+}
+
+/// How the camera finds its focus distance.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "mode", rename_all = "kebab-case")]
+pub enum Focus {
+    /// Cast a ray through `at`, a point of the image given as fractions of its width and height
+    /// from the top left, and focus where it hits. If it hits nothing, the defocus block's focus
+    /// distance is kept.
+    Auto {
+        #[serde(default = "default_focus_at")]
+        at: (f64, f64),
+    },
+}
+
+/// How many samples each pixel takes.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(
+    tag = "mode",
+    rename_all = "kebab-case",
+    rename_all_fields = "kebab-case"
+)]
+pub enum Sampling {
+    /// Every pixel takes `samples-per-pixel` samples, or a region's number.
+    Fixed,
+    /// Every pixel takes `samples-per-pixel` samples, or a region's number, then more until the
+    /// noise in its brightness is within `tolerance`, so that smooth areas finish early and the
+    /// time goes to noisy ones.
+    Adaptive {
+        /// The most samples taken of any pixel.
+        #[cfg_attr(feature = "schema", schemars(rename = "max-samples"))]
+        max_samples: u32,
+        /// The largest error allowed in a pixel's brightness, as a fraction of it: a pixel is
+        /// finished once its 95% confidence interval is within this fraction of its mean.
+        tolerance: f64,
+    },
+}
+
+fn default_focus_at() -> (f64, f64) {
+    (0.5, 0.5)
+}
+
+fn default_super_resolution() -> u32 {
+    1
+}
+
+/// How the random seed varies with the frame number given to `Scene::create_frame_camera`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub enum NoiseSeedMode {
+    /// Each frame has different noise, which averages out in motion instead of looking like a
+    /// pattern stuck to the screen.
+    #[default]
+    PerFrame,
+    /// Every frame has the same noise, which some denoisers handle more stably.
+    Fixed,
+}
+
+/// A rectangle of the image, given as fractions of its width and height from the top left, that
+/// takes its own number of samples per pixel.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub struct SampleRegion {
+    pub min: (f64, f64),
+    pub max: (f64, f64),
+    pub samples_per_pixel: u32,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(tag = "type", rename_all = "kebab-case")]
 pub enum Rotation {
-    Euler { roll: f64, pitch: f64, yaw: f64 },
-    Direction { x: f64, y: f64, z: f64 },
+    /// Angles in radians unless given another unit.
+    Euler {
+        roll: Angle,
+        pitch: Angle,
+        yaw: Angle,
+    },
+    Direction {
+        x: f64,
+        y: f64,
+        z: f64,
+    },
+}
+
+/// A Rhai script that adds materials, objects, and lights to a scene when it is loaded, for
+/// patterns that need loops and math. The script calls `material`, `object`, and `light` with maps
+/// written like the tables of a scene file, and `material` returns the new material's index.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub struct Script {
+    /// The path of the script.
+    pub file: PathBuf,
+}
+
+/// Haze that fades distant surfaces toward `color`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub struct Atmosphere {
+    pub color: Rgb,
+    /// The fog's density at height zero, per unit distance.
+    pub density: f64,
+    /// How quickly the fog thins out with height; zero for uniform fog.
+    #[serde(default)]
+    pub height_falloff: f64,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
 pub struct Defocus {
-    pub focus_distance: f64,
-    pub defocus_angle: f64,
+    /// The distance along the view direction to the plane in focus. It may be left out when the
+    /// camera has a `focus-target` or `focus-point`.
+    pub focus_distance: Option<f64>,
+    /// In degrees unless given another unit.
+    pub defocus_angle: Angle,
+}
+
+/// A material of a scene, with the rules for which light paths see it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MaterialEntry {
+    #[serde(flatten)]
+    pub material: Material,
+    /// Non-physical tricks that hide the material from some light paths, like
+    /// `light-paths = ["no-caustics"]`.
+    #[serde(default, rename = "light-paths", skip_serializing_if = "Vec::is_empty")]
+    pub light_paths: Vec<LightPath>,
+}
+
+impl From<Material> for MaterialEntry {
+    fn from(material: Material) -> Self {
+        Self {
+            material,
+            light_paths: Vec::new(),
+        }
+    }
+}
+
+/// A rule for which light paths see a material.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub enum LightPath {
+    /// Paths that have scattered off a diffuse surface end at the material if it is a mirror or
+    /// glass, so it casts no caustics, which take many samples to converge.
+    NoCaustics,
+    /// The material casts shadows, but is invisible to the camera and in reflections.
+    ShadowOnly,
+    /// Only paths that have just scattered off a diffuse surface see the material, besides shadow
+    /// rays, so it lights its surroundings without being seen by the camera or in reflections.
+    DiffuseVisibleOnly,
+}
+
+impl FromIterator<LightPath> for crate::material::LightPaths {
+    fn from_iter<I: IntoIterator<Item = LightPath>>(rules: I) -> Self {
+        let mut light_paths = Self::default();
+        for rule in rules {
+            match rule {
+                LightPath::NoCaustics => light_paths.no_caustics = true,
+                LightPath::ShadowOnly => light_paths.shadow_only = true,
+                LightPath::DiffuseVisibleOnly => light_paths.diffuse_visible_only = true,
+            }
+        }
+        light_paths
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(tag = "type", rename_all = "kebab-case")]
 pub enum Material {
+    /// A matte surface. It needs an `albedo`, a `texture`, or both, in which case the texture is
+    /// used.
     Diffuse {
-        albedo: (f64, f64, f64),
+        albedo: Option<Rgb>,
+        /// A texture whose color at each hit replaces `albedo`.
+        texture: Option<usize>,
     },
+    /// A mirror-like conductor. With a `preset` or a custom `ior`, its color comes from the
+    /// conductor's Fresnel reflectance, which tends to white at grazing angles, and `albedo` is a
+    /// tint on top of it.
     Metal {
-        albedo: (f64, f64, f64),
+        #[serde(default = "default_metal_albedo")]
+        albedo: Rgb,
+        preset: Option<MetalPreset>,
+        ior: Option<ComplexIor>,
+        /// How blurry reflections are, from 0 (a mirror, the default) to 1 (brushed).
+        #[serde(default)]
+        fuzz: f64,
+        /// A texture whose color at each hit replaces `albedo`.
+        texture: Option<usize>,
     },
     Dielectric {
         /// Index of refraction.
         ir: f64,
+        /// A medium filling the inside of objects with this material. Objects must be closed
+        /// (spheres or prisms) for the medium to make sense.
+        medium: Option<Medium>,
+    },
+    /// A diffuse base under a clear, glossy coat.
+    Plastic {
+        albedo: Rgb,
+        /// Index of refraction of the coat.
+        #[serde(default = "default_plastic_ir")]
+        ir: f64,
     },
     Light {
-        color: (f64, f64, f64),
+        color: Rgb,
+    },
+    /// A light glowing with the color and brightness of a body heated to `temperature`, in kelvin,
+    /// by Planck's law: the warm white of a tungsten filament at 2800 K is as bright as a light
+    /// with a `color` of about 17, while the red of 1000 K is a million times fainter and needs a
+    /// large `strength` to be seen.
+    Incandescent {
+        temperature: f64,
+        /// Multiplies the brightness.
+        #[serde(default = "default_incandescent_strength")]
+        strength: f64,
+    },
+    /// A blend of two other materials, chosen between at each hit.
+    /// With a `mask`, the texture's value at the hit is used as the weight of `b` instead of
+    /// `factor`.
+    Mix {
+        a: usize,
+        b: usize,
+        #[serde(default = "default_mix_factor")]
+        factor: f64,
+        mask: Option<usize>,
     },
+    /// A material computed at each hit by a graph of nodes.
+    Graph {
+        /// The name of the node producing the material, which must be a BSDF node or a mix of them.
+        output: String,
+        nodes: BTreeMap<String, Node>,
+    },
+    /// A material provided by a plugin registered under `name`.
+    Custom {
+        name: String,
+        #[serde(default)]
+        #[cfg_attr(
+            feature = "schema",
+            schemars(with = "BTreeMap<String, serde_json::Value>")
+        )]
+        params: toml::Table,
+    },
+}
+
+fn default_metal_albedo() -> Rgb {
+    Rgb(1.0, 1.0, 1.0)
 }
 
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub enum MetalPreset {
+    Gold,
+    Copper,
+    Aluminum,
+    Silver,
+    Chromium,
+}
+
+/// A complex index of refraction, given for the red, green, and blue channels.
 #[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct Object {
-    pub material: usize,
-    pub shape: Shape,
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ComplexIor {
+    pub eta: (f64, f64, f64),
+    pub k: (f64, f64, f64),
 }
 
+/// A homogeneous participating medium. Coefficients are per unit distance.
 #[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(tag = "type", rename_all = "kebab-case")]
-pub enum Shape {
-    Sphere {
-        center: (f64, f64, f64),
-        radius: f64,
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Medium {
+    /// How quickly each color channel is absorbed, tinting light that passes through.
+    #[serde(default)]
+    pub absorption: (f64, f64, f64),
+    /// How quickly each color channel is scattered, making the medium cloudy.
+    #[serde(default)]
+    pub scattering: (f64, f64, f64),
+    /// The Henyey-Greenstein asymmetry parameter: positive values scatter light forward and
+    /// negative values scatter it back.
+    #[serde(default)]
+    pub anisotropy: f64,
+}
+
+fn default_mix_factor() -> f64 {
+    0.5
+}
+
+fn default_plastic_ir() -> f64 {
+    1.5
+}
+
+fn default_incandescent_strength() -> f64 {
+    1.0
+}
+
+/// A node in a graph material. Inputs may be constants or the name of another node.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(
+    tag = "type",
+    rename_all = "kebab-case",
+    rename_all_fields = "kebab-case"
+)]
+pub enum Node {
+    /// A texture from the scene's `textures`.
+    Texture {
+        texture: usize,
     },
-    Quad {
-        q: (f64, f64, f64),
-        u: (f64, f64, f64),
-        v: (f64, f64, f64),
+    Checker {
+        scale: f64,
+        even: Input,
+        odd: Input,
     },
-    Prism {
-        /// The center of the bottom face.
-        origin: (f64, f64, f64),
-        width: f64,
-        height: f64,
-        depth: f64,
-        rotation: Option<Rotation>,
+    Noise {
+        scale: f64,
+    },
+    Math {
+        op: MathOp,
+        a: Input,
+        b: Input,
+    },
+    /// The fraction of light reflected by a dielectric surface at the angle of the hit.
+    Fresnel {
+        ir: Input,
+    },
+    /// Blends two values, or chooses between two BSDFs.
+    Mix {
+        a: Input,
+        b: Input,
+        factor: Input,
+    },
+    Diffuse {
+        albedo: Input,
+    },
+    Metal {
+        albedo: Input,
+    },
+    Dielectric {
+        ir: Input,
+    },
+    Plastic {
+        albedo: Input,
+        ir: Input,
+    },
+    Light {
+        color: Input,
+    },
+    /// The fraction of short rays from the hit that escape, for darkening crevices and corners.
+    AmbientOcclusion {
+        /// How far rays travel looking for occluders.
+        distance: f64,
+        #[serde(default = "default_ambient_occlusion_samples")]
+        samples: u32,
+    },
+    /// `color` with its hue and value varied randomly for each object, so that many objects
+    /// sharing a material do not look identical.
+    RandomColor {
+        color: Input,
+        /// The largest change in hue, as a fraction of the color wheel.
+        #[serde(default)]
+        // schemars ignores `rename_all_fields`, so fields of several words are renamed for it
+        // one by one
+        #[cfg_attr(feature = "schema", schemars(rename = "hue-jitter"))]
+        hue_jitter: f64,
+        /// The largest change in value, as a fraction of the original value.
+        #[serde(default)]
+        #[cfg_attr(feature = "schema", schemars(rename = "value-jitter"))]
+        value_jitter: f64,
+        /// Changes the colors chosen for every object.
+        #[serde(default)]
+        seed: u64,
     },
 }
 
-impl Default for Rotation {
-    fn default() -> Self {
-        Self::Euler {
-            roll: 0.0,
-            pitch: 0.0,
-            yaw: 0.0,
-        }
-    }
+fn default_ambient_occlusion_samples() -> u32 {
+    8
 }
 
-impl From<Rotation> for UnitQuaternion<f64> {
-    fn from(rotation: Rotation) -> Self {
-        match rotation {
-            Rotation::Euler { roll, pitch, yaw } => {
-                UnitQuaternion::from_euler_angles(roll, pitch, yaw)
-            }
-            Rotation::Direction { x, y, z } => {
-                UnitQuaternion::rotation_between(&-Vector3::z(), &Vector3::new(x, y, z)).unwrap()
-            }
-        }
-    }
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(untagged)]
+pub enum Input {
+    Float(f64),
+    /// The name of another node, or a hex code or color name if no node has that name.
+    Node(String),
+    Color(Rgb),
 }
 
-impl From<Material> for crate::material::Material {
-    fn from(value: Material) -> Self {
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub enum MathOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+    Minimum,
+    Maximum,
+}
+
+/// What a graph node evaluates to, for checking that nodes are connected sensibly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum NodeKind {
+    Value,
+    Bsdf,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Texture {
+    Solid {
+        color: TextureColor,
+        /// The space the texture's `[r, g, b]` colors are given in.
+        #[serde(default, rename = "color-space")]
+        color_space: ColorSpace,
+    },
+    Checker {
+        scale: f64,
+        even: TextureColor,
+        odd: TextureColor,
+        #[serde(default, rename = "color-space")]
+        color_space: ColorSpace,
+    },
+    Noise {
+        scale: f64,
+    },
+    /// An image file wrapped over the surfaces of objects, such as a quad from its `q` corner
+    /// along `u` and `v`, or a sphere around its vertical axis.
+    Image {
+        path: PathBuf,
+        /// The space of the image's colors. Floating-point images, such as OpenEXR, are linear by
+        /// default, and others are display colors.
+        #[serde(rename = "color-space")]
+        color_space: Option<ColorSpace>,
+    },
+}
+
+/// A light that is not an object in the world; it cannot be seen directly, only by what it lights.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(
+    tag = "type",
+    rename_all = "kebab-case",
+    rename_all_fields = "kebab-case"
+)]
+pub enum Light {
+    Point {
+        position: (f64, f64, f64),
+        color: Rgb,
+        #[serde(default)]
+        falloff: Falloff,
+    },
+    /// A light infinitely far away, like the sun, shining in `direction`.
+    Directional {
+        direction: (f64, f64, f64),
+        color: Rgb,
+        /// The apparent radius of the light, in degrees unless given another unit; larger lights
+        /// cast softer shadows.
+        #[serde(default)]
+        #[cfg_attr(feature = "schema", schemars(rename = "angular-radius"))]
+        angular_radius: Angle,
+    },
+}
+
+/// How a light's intensity decreases with distance.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Falloff {
+    /// Physically based: intensity is divided by the square of the distance.
+    #[default]
+    InverseSquare,
+    /// Intensity is divided by the distance.
+    Linear,
+    /// Inverse-square, smoothly windowed to reach zero at `range`.
+    Smooth { range: f64 },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub struct Object {
+    /// A name to refer to the object by, such as in the camera's `focus-target`.
+    pub name: Option<String>,
+    pub material: usize,
+    /// Parameters of `material` to replace for this object only.
+    pub material_overrides: Option<MaterialOverrides>,
+    /// Round the object's edges with this radius when shading, without changing its geometry.
+    #[serde(default)]
+    pub bevel: f64,
+    /// Planes cutting away part of the object.
+    #[serde(default)]
+    pub clip_planes: Vec<ClipPlane>,
+    /// Offsets from the object's position that it moves through, at even intervals, over the
+    /// exposure of a `long-exposure` camera or one with a `shutter`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub motion: Vec<(f64, f64, f64)>,
+    /// Where to place the shape, if not where it is described.
+    pub transform: Option<Transform>,
+    /// The distances from the camera's `position` to the center of the object at which it can be
+    /// seen, for swapping it with a simpler stand-in far away.
+    pub visible_range: Option<VisibleRange>,
+    pub shape: Shape,
+}
+
+/// Distances at which an object can be seen: from `near` to `far`, dissolving in and out over
+/// `fade` around each end.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub struct VisibleRange {
+    #[serde(default)]
+    pub near: f64,
+    pub far: Option<f64>,
+    #[serde(default)]
+    pub fade: f64,
+}
+
+/// How far to reduce the triangles of a loaded mesh, stopping at whichever limit is reached first.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub struct Simplify {
+    /// Stop once the mesh has at most this many triangles.
+    pub triangles: Option<usize>,
+    /// Stop before the surface moves by more than this many pixels, seen from the camera's
+    /// `position` at the mesh's nearest point after the object's `transform`. A mesh that
+    /// instances share keeps to the smallest error of all the places it is drawn.
+    pub pixel_error: Option<f64>,
+}
+
+impl From<VisibleRange> for crate::object::VisibleRange {
+    fn from(range: VisibleRange) -> Self {
+        Self {
+            near: range.near,
+            far: range.far.unwrap_or(f64::INFINITY),
+            fade: range.fade,
+        }
+    }
+}
+
+/// A placement of an object's shape: scaled around the origin, then rotated, then moved by
+/// `translation`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub struct Transform {
+    #[serde(default)]
+    pub translation: (f64, f64, f64),
+    pub rotation: Option<Rotation>,
+    #[serde(default)]
+    pub scale: Scale,
+}
+
+/// A scale: one factor for every axis, or `[x, y, z]` factors for each.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(untagged)]
+pub enum Scale {
+    Uniform(f64),
+    Axes((f64, f64, f64)),
+}
+
+impl Default for Scale {
+    fn default() -> Self {
+        Self::Uniform(1.0)
+    }
+}
+
+impl Scale {
+    pub fn vector(self) -> Vector3<f64> {
+        match self {
+            Self::Uniform(factor) => Vector3::repeat(factor),
+            Self::Axes(axes) => tuple_to_vector(axes),
+        }
+    }
+}
+
+impl Transform {
+    pub fn create(&self, angle_unit: Option<AngleUnit>) -> crate::object::Transform {
+        crate::object::Transform::new(
+            tuple_to_vector(self.translation),
+            self.rotation
+                .clone()
+                .unwrap_or_default()
+                .quaternion(angle_unit),
+            self.scale.vector(),
+        )
+    }
+}
+
+/// A plane through `point` that cuts away the side `normal` points to. Where the cut passes
+/// through the inside of a closed object, it is closed with the material `cap`, or left open.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ClipPlane {
+    pub point: (f64, f64, f64),
+    pub normal: (f64, f64, f64),
+    pub cap: Option<usize>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MaterialOverrides {
+    /// Multiplies the albedo of diffuse and metal materials and the color of lights.
+    pub tint: Option<Rgb>,
+    pub albedo: Option<Rgb>,
+    pub ir: Option<f64>,
+    pub color: Option<Rgb>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(
+    tag = "type",
+    rename_all = "kebab-case",
+    rename_all_fields = "kebab-case"
+)]
+pub enum Shape {
+    Sphere {
+        center: (f64, f64, f64),
+        radius: f64,
+        /// Where the center moves to in a straight line over the motion, to blur the sphere with
+        /// the camera's `shutter`. This is the same as a `motion` of `[0, 0, 0]` and `end -
+        /// center`.
+        end: Option<(f64, f64, f64)>,
+    },
+    Quad {
+        q: (f64, f64, f64),
+        u: (f64, f64, f64),
+        v: (f64, f64, f64),
+    },
+    Prism {
+        /// The center of the bottom face.
+        origin: (f64, f64, f64),
+        width: f64,
+        height: f64,
+        depth: f64,
+        rotation: Option<Rotation>,
+    },
+    /// A shape provided by a plugin registered under `name`.
+    Custom {
+        name: String,
+        #[serde(default)]
+        #[cfg_attr(
+            feature = "schema",
+            schemars(with = "BTreeMap<String, serde_json::Value>")
+        )]
+        params: toml::Table,
+    },
+    /// `text` in a font loaded from `font`, relative to the working directory, extruded by
+    /// `depth`. Unrotated, it reads along X from `origin`, which is on its first baseline, and
+    /// faces +Z. `size` is the height of the font's em square. `chamfer` cuts the edges around
+    /// the front and back of the letters at 45 degrees, insetting their faces by that much; unlike
+    /// the object's `bevel`, which rounds edges in shading only, it changes their silhouettes.
+    Text {
+        text: String,
+        font: PathBuf,
+        #[serde(default)]
+        origin: (f64, f64, f64),
+        #[serde(default = "default_text_size")]
+        size: f64,
+        #[serde(default = "default_text_depth")]
+        depth: f64,
+        #[serde(default)]
+        chamfer: f64,
+        rotation: Option<Rotation>,
+    },
+    /// A triangle mesh read from a Wavefront OBJ file at `path`, relative to the working directory.
+    /// Its vertices are multiplied by `scale`, rotated, and moved to `origin`.
+    Mesh {
+        path: PathBuf,
+        #[serde(default)]
+        origin: (f64, f64, f64),
+        #[serde(default = "default_mesh_scale")]
+        scale: f64,
+        rotation: Option<Rotation>,
+        simplify: Option<Simplify>,
+    },
+    /// A surface of revolution: `profile`, a polyline of radii and heights, turned around the
+    /// vertical axis through `origin` in `segments` steps. See `mesh::TriangleMesh::lathe` for
+    /// which way it faces.
+    Lathe {
+        profile: Vec<(f64, f64)>,
+        #[serde(default)]
+        origin: (f64, f64, f64),
+        #[serde(default = "default_lathe_segments")]
+        segments: usize,
+        rotation: Option<Rotation>,
+    },
+    /// A flat outline extruded upwards by `height` from the XZ plane through `origin`: either a
+    /// polygon given by its `corners`, or the data of an SVG path scaled by `scale`, seen from
+    /// above. `chamfer` cuts the edges around its top and bottom at 45 degrees, insetting them by
+    /// that much; unlike the object's `bevel`, which rounds edges in shading only, it changes the
+    /// silhouette.
+    Extrusion {
+        corners: Option<Vec<(f64, f64)>>,
+        #[cfg_attr(feature = "schema", schemars(rename = "svg-path"))]
+        svg_path: Option<String>,
+        #[serde(default = "default_svg_scale")]
+        scale: f64,
+        height: f64,
+        #[serde(default)]
+        chamfer: f64,
+        #[serde(default)]
+        origin: (f64, f64, f64),
+        rotation: Option<Rotation>,
+    },
+    /// A blobby surface where the summed fields of `balls` reach `threshold`.
+    Metaballs {
+        balls: Vec<Metaball>,
+        #[serde(default = "default_metaball_threshold")]
+        threshold: f64,
+    },
+    /// The points of a PLY or LAS file, relative to the working directory, each drawn as a sphere
+    /// or disc of `radius`. Points with colors tint the object's material.
+    PointCloud {
+        path: PathBuf,
+        radius: f64,
+        #[serde(default)]
+        splat: Splat,
+        /// The space the file's colors are stored in.
+        #[serde(default = "default_point_color_space")]
+        #[cfg_attr(feature = "schema", schemars(rename = "color-space"))]
+        color_space: ColorSpace,
+    },
+    /// The shape of the scene's object number `object`, shared rather than copied, so that a mesh
+    /// or prism can be placed many times with `transform` without storing it again. Only the
+    /// shape is shared; the other object's material, transform, and other settings are not.
+    Instance { object: usize },
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Metaball {
+    pub center: (f64, f64, f64),
+    /// The distance beyond which the ball has no effect.
+    pub radius: f64,
+    /// The field at the center, falling to zero at the radius; negative to carve into other balls.
+    #[serde(default = "default_metaball_strength")]
+    pub strength: f64,
+}
+
+fn default_text_size() -> f64 {
+    1.0
+}
+
+fn default_text_depth() -> f64 {
+    0.2
+}
+
+fn default_mesh_scale() -> f64 {
+    1.0
+}
+
+fn default_lathe_segments() -> usize {
+    64
+}
+
+fn default_svg_scale() -> f64 {
+    1.0
+}
+
+fn default_metaball_threshold() -> f64 {
+    0.5
+}
+
+fn default_metaball_strength() -> f64 {
+    1.0
+}
+
+fn default_point_color_space() -> ColorSpace {
+    ColorSpace::Srgb
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub enum Splat {
+    #[default]
+    Sphere,
+    /// A disc facing along the point's normal, or towards the camera for points without normals.
+    Disc,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Self::Euler {
+            roll: Angle::default(),
+            pitch: Angle::default(),
+            yaw: Angle::default(),
+        }
+    }
+}
+
+impl Rotation {
+    /// The rotation as a quaternion, reading bare numbers in `angle_unit`, the scene's
+    /// `angle-unit`, or radians without one.
+    pub fn quaternion(&self, angle_unit: Option<AngleUnit>) -> UnitQuaternion<f64> {
+        match *self {
+            Rotation::Euler { roll, pitch, yaw } => {
+                let [roll, pitch, yaw] = [roll, pitch, yaw]
+                    .map(|angle| angle.to_radians(angle_unit.unwrap_or(AngleUnit::Radians)));
+                UnitQuaternion::from_euler_angles(roll, pitch, yaw)
+            }
+            Rotation::Direction { x, y, z } => {
+                UnitQuaternion::rotation_between(&-Vector3::z(), &Vector3::new(x, y, z)).unwrap()
+            }
+        }
+    }
+}
+
+impl From<MetalPreset> for crate::material::ComplexIor {
+    fn from(preset: MetalPreset) -> Self {
+        match preset {
+            MetalPreset::Gold => Self::GOLD,
+            MetalPreset::Copper => Self::COPPER,
+            MetalPreset::Aluminum => Self::ALUMINUM,
+            MetalPreset::Silver => Self::SILVER,
+            MetalPreset::Chromium => Self::CHROMIUM,
+        }
+    }
+}
+
+impl From<Atmosphere> for crate::medium::Atmosphere {
+    fn from(value: Atmosphere) -> Self {
+        Self {
+            color: value.color.into(),
+            density: value.density,
+            height_falloff: value.height_falloff,
+        }
+    }
+}
+
+impl From<Medium> for crate::medium::Medium {
+    fn from(value: Medium) -> Self {
+        Self {
+            absorption: Vector3::new(value.absorption.0, value.absorption.1, value.absorption.2),
+            scattering: Vector3::new(value.scattering.0, value.scattering.1, value.scattering.2),
+            anisotropy: value.anisotropy,
+        }
+    }
+}
+
+impl From<Material> for crate::material::Material {
+    fn from(value: Material) -> Self {
         match value {
-            Material::Diffuse { albedo } => crate::material::Material::Diffuse {
-                albedo: Vector3::new(albedo.0, albedo.1, albedo.2),
+            Material::Diffuse { albedo, texture } => textured(
+                crate::material::Material::Diffuse {
+                    albedo: albedo.unwrap_or_default().into(),
+                },
+                texture,
+            ),
+            Material::Metal {
+                albedo,
+                preset,
+                ior,
+                fuzz,
+                texture,
+            } => textured(
+                crate::material::Material::Metal {
+                    albedo: Vector3::new(albedo.0, albedo.1, albedo.2),
+                    ior: preset
+                        .map(Into::into)
+                        .or(ior.map(|ior| crate::material::ComplexIor::new(ior.eta, ior.k))),
+                    fuzz,
+                },
+                texture,
+            ),
+            Material::Dielectric { ir, medium } => crate::material::Material::Dielectric {
+                ir,
+                medium: medium.map(Into::into),
             },
-            Material::Metal { albedo } => crate::material::Material::Metal {
+            Material::Plastic { albedo, ir } => crate::material::Material::Plastic {
                 albedo: Vector3::new(albedo.0, albedo.1, albedo.2),
+                ir,
             },
-            Material::Dielectric { ir } => crate::material::Material::Dielectric { ir },
             Material::Light { color } => crate::material::Material::Light {
                 color: Vector3::new(color.0, color.1, color.2),
             },
+            Material::Incandescent {
+                temperature,
+                strength,
+            } => crate::material::Material::Light {
+                color: crate::color::blackbody(temperature) * strength,
+            },
+            Material::Mix { a, b, factor, mask } => {
+                crate::material::Material::Mix { a, b, factor, mask }
+            }
+            Material::Graph { output, nodes } => {
+                crate::material::Material::Graph(Arc::new(create_graph(&output, &nodes)))
+            }
+            Material::Custom { .. } => {
+                unreachable!("custom materials are created by plugins in Scene::create_world")
+            }
+        }
+    }
+}
+
+/// `material` with its albedo taken from `texture`, if it has one.
+fn textured(
+    material: crate::material::Material,
+    texture: Option<usize>,
+) -> crate::material::Material {
+    match texture {
+        Some(texture) => crate::material::Material::Textured {
+            base: Box::new(material),
+            texture,
+        },
+        None => material,
+    }
+}
+
+/// Convert a validated graph, replacing node names with indices.
+fn create_graph(output: &str, nodes: &BTreeMap<String, Node>) -> crate::graph::Graph {
+    use crate::graph::{Input as I, Node as N, Value};
+
+    let names: Vec<&String> = nodes.keys().collect();
+    let index = |name: &str| names.iter().position(|n| *n == name).unwrap();
+    let input = |input: &Input| match input {
+        Input::Float(f) => I::Constant(Value::Float(*f)),
+        Input::Color(c) => I::Constant(Value::Color(Vector3::new(c.0, c.1, c.2))),
+        Input::Node(name) => match names.iter().position(|n| *n == name) {
+            Some(node) => I::Node(node),
+            None => I::Constant(Value::Color(Rgb::parse(name).unwrap().into())),
+        },
+    };
+
+    let nodes = nodes
+        .values()
+        .map(|node| match node {
+            Node::Texture { texture } => N::TextureRef(*texture),
+            Node::Checker { scale, even, odd } => N::Checker {
+                scale: *scale,
+                even: input(even),
+                odd: input(odd),
+            },
+            Node::Noise { scale } => N::Texture(crate::texture::Texture::noise(*scale)),
+            Node::Math { op, a, b } => N::Math {
+                op: (*op).into(),
+                a: input(a),
+                b: input(b),
+            },
+            Node::Fresnel { ir } => N::Fresnel { ir: input(ir) },
+            Node::Mix { a, b, factor } => N::Mix {
+                a: input(a),
+                b: input(b),
+                factor: input(factor),
+            },
+            Node::Diffuse { albedo } => N::Diffuse {
+                albedo: input(albedo),
+            },
+            Node::Metal { albedo } => N::Metal {
+                albedo: input(albedo),
+            },
+            Node::Dielectric { ir } => N::Dielectric { ir: input(ir) },
+            Node::Plastic { albedo, ir } => N::Plastic {
+                albedo: input(albedo),
+                ir: input(ir),
+            },
+            Node::Light { color } => N::Light {
+                color: input(color),
+            },
+            Node::AmbientOcclusion { distance, samples } => N::AmbientOcclusion {
+                distance: *distance,
+                samples: *samples,
+            },
+            Node::RandomColor {
+                color,
+                hue_jitter,
+                value_jitter,
+                seed,
+            } => N::RandomColor {
+                color: input(color),
+                hue_jitter: *hue_jitter,
+                value_jitter: *value_jitter,
+                seed: *seed,
+            },
+        })
+        .collect();
+
+    crate::graph::Graph {
+        nodes,
+        output: index(output),
+    }
+}
+
+impl From<Splat> for crate::points::Splat {
+    fn from(value: Splat) -> Self {
+        match value {
+            Splat::Sphere => Self::Sphere,
+            Splat::Disc => Self::Disc,
+        }
+    }
+}
+
+impl From<MathOp> for crate::graph::MathOp {
+    fn from(value: MathOp) -> Self {
+        match value {
+            MathOp::Add => crate::graph::MathOp::Add,
+            MathOp::Subtract => crate::graph::MathOp::Subtract,
+            MathOp::Multiply => crate::graph::MathOp::Multiply,
+            MathOp::Divide => crate::graph::MathOp::Divide,
+            MathOp::Power => crate::graph::MathOp::Power,
+            MathOp::Minimum => crate::graph::MathOp::Minimum,
+            MathOp::Maximum => crate::graph::MathOp::Maximum,
+        }
+    }
+}
+
+impl From<MaterialOverrides> for crate::material::MaterialOverrides {
+    fn from(value: MaterialOverrides) -> Self {
+        Self {
+            tint: value.tint.map(Into::into),
+            albedo: value.albedo.map(Into::into),
+            ir: value.ir,
+            color: value.color.map(Into::into),
+        }
+    }
+}
+
+impl Texture {
+    /// Create the texture, reading its image file with `plugins`' loaders if it has one.
+    pub fn create(&self, plugins: &Plugins) -> anyhow::Result<crate::texture::Texture> {
+        Ok(match *self {
+            Texture::Solid { color, color_space } => crate::texture::Texture::Solid {
+                color: color.to_linear(color_space),
+            },
+            Texture::Checker {
+                scale,
+                even,
+                odd,
+                color_space,
+            } => crate::texture::Texture::Checker {
+                scale,
+                even: even.to_linear(color_space),
+                odd: odd.to_linear(color_space),
+            },
+            Texture::Noise { scale } => crate::texture::Texture::noise(scale),
+            Texture::Image {
+                ref path,
+                color_space,
+            } => {
+                let image = plugins.load_image(path)?;
+                let is_float = matches!(
+                    image,
+                    image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_)
+                );
+                let color_space = color_space.unwrap_or(if is_float {
+                    ColorSpace::Linear
+                } else {
+                    ColorSpace::Srgb
+                });
+
+                // Textures are albedos, which reflect at most all of the light, so the brighter
+                // and negative channels that floating-point images can hold are clamped, and NaNs
+                // made black.
+                let mut image = image.to_rgb32f();
+                for pixel in image.pixels_mut() {
+                    let [r, g, b] = pixel.0.map(f64::from);
+                    let color = crate::color::ColorSpace::from(color_space)
+                        .to_linear(Vector3::new(r, g, b))
+                        .map(|c| if c.is_nan() { 0.0 } else { c.clamp(0.0, 1.0) });
+                    pixel.0 = [color.x as f32, color.y as f32, color.z as f32];
+                }
+                crate::texture::Texture::Image(image)
+            }
+        })
+    }
+}
+
+impl Scene {
+    /// Read a scene from the contents of a scene file, first migrating it from an older version of
+    /// the scene format if needed. The changes made by migration are returned too, so that the
+    /// file can be reported as out of date.
+    pub fn from_toml(source: &str) -> anyhow::Result<(Self, Vec<String>)> {
+        let mut document: toml_edit::Document = source.parse()?;
+        let changes = crate::migrate::migrate(&mut document)?;
+        // An unchanged document prints as the original source, so errors point at its lines.
+        let scene = toml::from_str(&document.to_string())?;
+        Ok((scene, changes))
+    }
+
+    /// Run the scene's `script`, if it has one, adding what it generates to the scene. The script
+    /// is removed afterwards, so the scene can be saved with what it generated and loaded again.
+    pub fn run_script(&mut self) -> anyhow::Result<()> {
+        let Some(script) = self.script.take() else {
+            return Ok(());
+        };
+
+        #[cfg(feature = "script")]
+        return crate::script::run(self, &script.file);
+        #[cfg(not(feature = "script"))]
+        anyhow::bail!(
+            "the scene's script {} cannot be run; rtk was built without the `script` feature",
+            script.file.display()
+        );
+    }
+
+    /// Replace the main camera's view with the named camera `name`.
+    pub fn select_camera(&mut self, name: &str) -> anyhow::Result<()> {
+        let Some(view) = self.cameras.get(name) else {
+            let names = self.cameras.keys().cloned().collect::<Vec<_>>();
+            anyhow::bail!(
+                "the scene has no camera named `{name}`; its cameras are: {}",
+                if names.is_empty() {
+                    "none".to_string()
+                } else {
+                    names.join(", ")
+                }
+            );
+        };
+
+        let view = view.clone();
+        let camera = &mut self.camera;
+        camera.image_dimensions = view.image_dimensions.unwrap_or(camera.image_dimensions);
+        if view.position.is_some() {
+            camera.position = view.position;
+            camera.frame = None;
+        }
+        camera.rotation = view.rotation.or(camera.rotation.take());
+        camera.fov = view.fov.unwrap_or(camera.fov);
+        camera.defocus = view.defocus.or(camera.defocus.take());
+        if view.focus_target.is_some() || view.focus_point.is_some() || view.focus.is_some() {
+            camera.focus_target = view.focus_target;
+            camera.focus_point = view.focus_point;
+            camera.focus = view.focus;
+        }
+
+        Ok(())
+    }
+
+    /// Create the camera described by the scene. A camera with a `focus-target`, `focus-point`, or
+    /// `focus` is not focused until `focus_distance` is given to `Renderer::set_focus_distance`.
+    pub fn create_camera(&self) -> crate::render::Camera {
+        let p = self.camera.position.unwrap_or_default();
+        let rotation = match &self.camera.rotation {
+            Some(Rotation::Direction { x, y, z }) => {
+                UnitQuaternion::rotation_between(&-Vector3::z(), &Vector3::new(*x, *y, *z))
+                    .unwrap_or_else(|| {
+                        UnitQuaternion::from_axis_angle(
+                            &Unit::new_normalize(Vector3::y()),
+                            std::f64::consts::PI,
+                        )
+                    })
+            }
+            Some(rotation) => rotation.quaternion(self.angle_unit),
+            None => UnitQuaternion::identity(),
+        };
+
+        let degrees = self.angle_unit.unwrap_or(AngleUnit::Degrees);
+        let (focus_distance, defocus_angle) = if let Some(defocus) = &self.camera.defocus {
+            (
+                defocus.focus_distance.unwrap_or(1.0),
+                defocus.defocus_angle.to_degrees(degrees),
+            )
+        } else {
+            (1.0, 0.0)
+        };
+
+        let background_color = self.camera.background_color.unwrap_or_default();
+
+        let (width, height) = self.camera.image_dimensions;
+        let to_pixels = |from: f64, to: f64, size: u32| {
+            (from * size as f64).round() as u32..(to * size as f64).round() as u32
+        };
+        let regions = self
+            .camera
+            .regions
+            .iter()
+            .map(|region| crate::render::SampleRegion {
+                x: to_pixels(region.min.0, region.max.0, width),
+                y: to_pixels(region.min.1, region.max.1, height),
+                samples_per_pixel: region.samples_per_pixel,
+            })
+            .collect();
+
+        crate::render::Camera {
+            image_width: self.camera.image_dimensions.0,
+            background_color: background_color.into(),
+            image_height: self.camera.image_dimensions.1,
+            position: tuple_to_vector(p),
+            rotation,
+            fov: self.camera.fov.to_degrees(degrees),
+            focus_distance,
+            defocus_angle,
+            samples_per_pixel: self
+                .camera
+                .samples_per_pixel
+                .unwrap_or(DEFAULT_SAMPLES_PER_PIXEL),
+            seed: self.camera.seed,
+            regularization: self.camera.regularization,
+            auto_exposure: self.camera.auto_exposure,
+            regions,
+            shutter: self
+                .camera
+                .shutter
+                .or(self.camera.long_exposure.then_some((0.0, 1.0))),
+            polarizer: self.camera.polarizer.map(|angle| angle.to_degrees(degrees)),
+            super_resolution: self.camera.super_resolution,
+            stereo: self
+                .camera
+                .stereo
+                .as_ref()
+                .map(|stereo| crate::render::Stereo {
+                    separation: stereo.separation,
+                    convergence: stereo.convergence,
+                }),
+            adaptive: match self.camera.sampling {
+                Some(Sampling::Adaptive {
+                    max_samples,
+                    tolerance,
+                }) => Some(crate::render::AdaptiveSampling {
+                    max_samples,
+                    tolerance,
+                }),
+                Some(Sampling::Fixed) | None => None,
+            },
+        }
+    }
+
+    /// Create the camera for frame `frame` of an animation of the scene. Frame zero is the same as
+    /// `create_camera`.
+    pub fn create_frame_camera(&self, frame: u64) -> crate::render::Camera {
+        let mut camera = self.create_camera();
+        if frame != 0 && self.camera.noise_seed_mode == NoiseSeedMode::PerFrame {
+            camera.seed ^= crate::random::splitmix64(frame);
+        }
+
+        camera
+    }
+
+    /// The focus distance that brings the camera's `focus-point`, the center of its `focus-target`
+    /// in `world`, or what its autofocus sees in `world` into focus, or `None` if it has none of
+    /// them or autofocus sees nothing.
+    pub fn focus_distance(&self, world: &crate::object::World) -> anyhow::Result<Option<f64>> {
+        let camera = self.create_camera();
+        let point = match (&self.camera.focus_point, &self.camera.focus_target) {
+            (Some(point), _) => tuple_to_vector(*point),
+            (None, Some(name)) => {
+                let id = self
+                    .objects
+                    .iter()
+                    .position(|object| object.name.as_ref() == Some(name))
+                    .ok_or_else(|| anyhow::anyhow!("the scene has no object named `{name}`"))?;
+                let bounds = world.object_bounds(id).ok_or_else(|| {
+                    anyhow::anyhow!("the focus target `{name}` has no bounds to focus on")
+                })?;
+                (bounds.min + bounds.max) / 2.0
+            }
+            (None, None) => match &self.camera.focus {
+                Some(Focus::Auto { at }) => {
+                    let ray = crate::render::Renderer::new(camera.clone()).screen_ray(*at);
+                    match world.hit(&ray, 0.001, f64::INFINITY) {
+                        Some(hit) => hit.p,
+                        None => return Ok(None),
+                    }
+                }
+                None => return Ok(None),
+            },
+        };
+
+        let forward = camera.rotation * -Vector3::z();
+        let distance = (point - camera.position).dot(&forward);
+        if distance <= 0.0 {
+            anyhow::bail!("the camera's focus is behind it");
+        }
+
+        Ok(Some(distance))
+    }
+
+    /// Move the camera so that it sees every object of `world` with bounds, keeping its rotation
+    /// and field of view, and return its new position. Objects with visible ranges in `world` are
+    /// shown or hidden as seen from there.
+    pub fn frame_camera(
+        &mut self,
+        world: &mut crate::object::World,
+    ) -> anyhow::Result<Vector3<f64>> {
+        // the fraction of the field of view left empty around the objects
+        const MARGIN: f64 = 0.05;
+
+        let bounds = world
+            .bounds_by_object()
+            .into_iter()
+            .flatten()
+            .reduce(|a, b| a.union(&b))
+            .ok_or_else(|| anyhow::anyhow!("the scene has no objects with bounds to frame"))?;
+        if !bounds
+            .min
+            .iter()
+            .chain(bounds.max.iter())
+            .all(|c| c.is_finite())
+        {
+            anyhow::bail!("the scene's bounds are infinite, so it cannot be framed");
+        }
+
+        let camera = self.create_camera();
+        let right = camera.rotation * Vector3::x();
+        let up = camera.rotation * Vector3::y();
+        let forward = camera.rotation * -Vector3::z();
+        let (width, height) = self.camera.image_dimensions;
+        let tan_vertical = (camera.fov.to_radians() / 2.0).tan() * (1.0 - MARGIN);
+        let tan_horizontal = tan_vertical * width as f64 / height as f64;
+
+        // Aim at the center of the bounds, then back away until each corner is inside the view.
+        let center = (bounds.min + bounds.max) / 2.0;
+        let mut distance = 0.0_f64;
+        for corner in 0..8 {
+            let point = Vector3::from_fn(|axis, _| {
+                if corner >> axis & 1 == 0 {
+                    bounds.min[axis]
+                } else {
+                    bounds.max[axis]
+                }
+            });
+            let offset = point - center;
+            let depth = offset.dot(&forward);
+            distance = distance
+                .max(offset.dot(&right).abs() / tan_horizontal - depth)
+                .max(offset.dot(&up).abs() / tan_vertical - depth);
+        }
+
+        let position = center - forward * distance;
+        self.camera.position = Some((position.x, position.y, position.z));
+        world.view_from(position);
+        Ok(position)
+    }
+
+    /// Create the objects, materials, textures, and lights of a validated scene, with custom shapes
+    /// and materials from `plugins`.
+    /// Objects with material overrides each get a new material, added after the scene's own.
+    pub fn create_world(&self, plugins: &Plugins) -> anyhow::Result<crate::object::World> {
+        let mut builder = WorldBuilder::new();
+        let mut materials = Vec::new();
+        for (i, entry) in self.materials.iter().enumerate() {
+            let material = match &entry.material {
+                Material::Custom { name, params } => crate::material::Material::Custom(
+                    plugins
+                        .material(name, params)
+                        .map_err(|e| anyhow::anyhow!("material {i}: {e}"))?,
+                ),
+                material => material.clone().into(),
+            };
+            let handle = builder.add_material(material);
+            if !entry.light_paths.is_empty() {
+                builder.set_light_paths(handle, entry.light_paths.iter().copied().collect());
+            }
+            materials.push(handle);
+        }
+        for (i, texture) in self.textures.iter().enumerate() {
+            let texture = texture
+                .create(plugins)
+                .map_err(|e| anyhow::anyhow!("texture {i}: {e}"))?;
+            builder.add_texture(texture);
+        }
+        for light in &self.lights {
+            builder.add_light(light.create(self.angle_unit));
+        }
+        if let Some(atmosphere) = &self.atmosphere {
+            builder.set_atmosphere(atmosphere.clone().into());
+        }
+
+        let object_materials = self
+            .objects
+            .iter()
+            .map(|obj| match &obj.material_overrides {
+                Some(overrides) => {
+                    let handle = builder.add_material(crate::material::Material::Override {
+                        base: obj.material,
+                        overrides: overrides.clone().into(),
+                    });
+                    let light_paths = &self.materials[obj.material].light_paths;
+                    builder.set_light_paths(handle, light_paths.iter().copied().collect());
+                    handle
+                }
+                None => materials[obj.material],
+            })
+            .collect::<Vec<_>>();
+
+        // The shapes that instances refer to are made once and shared. The objects they come from
+        // keep their own primitives, which share the storage of meshes with the instances.
+        let shared = self
+            .objects
+            .iter()
+            .filter_map(|obj| match obj.shape {
+                Shape::Instance { object } => Some(object),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+        let mut geometries = BTreeMap::new();
+        let mut sources = BTreeMap::new();
+        for object in shared {
+            let primitives = self.primitives(object, object_materials[object].index(), plugins)?;
+            geometries.insert(object, builder.add_geometry(primitives.clone()));
+            sources.insert(object, primitives);
+        }
+
+        for (i, obj) in self.objects.iter().enumerate() {
+            let material = object_materials[i];
+            let handle = match (&obj.shape, sources.remove(&i)) {
+                (&Shape::Instance { object }, _) => {
+                    builder.add_instance(geometries[&object], material)
+                }
+                (_, Some(primitives)) => builder.add_primitives(primitives),
+                _ => builder.add_primitives(self.primitives(i, material.index(), plugins)?),
+            };
+            let settings = builder.settings_mut(handle);
+            settings.bevel_radius = obj.bevel;
+            settings.clip_planes = obj
+                .clip_planes
+                .iter()
+                .chain(&self.clip_planes)
+                .map(|plane| crate::object::ClipPlane {
+                    point: tuple_to_vector(plane.point),
+                    normal: tuple_to_vector(plane.normal).normalize(),
+                    cap: plane.cap.map(|cap| materials[cap].index()),
+                })
+                .collect();
+            settings.motion = match obj.shape {
+                Shape::Sphere {
+                    center,
+                    end: Some(end),
+                    ..
+                } => vec![
+                    Vector3::zeros(),
+                    tuple_to_vector(end) - tuple_to_vector(center),
+                ],
+                _ => obj
+                    .motion
+                    .iter()
+                    .map(|&offset| tuple_to_vector(offset))
+                    .collect(),
+            };
+            settings.transform = obj
+                .transform
+                .as_ref()
+                .map(|transform| transform.create(self.angle_unit));
+            settings.visible_range = obj.visible_range.clone().map(Into::into);
+        }
+
+        let mut world = builder.build();
+        world.view_from(tuple_to_vector(self.camera.position.unwrap_or_default()));
+        Ok(world)
+    }
+
+    /// The distance that `pixels` pixels of error in the simplified mesh of object `i` cover, in
+    /// the mesh's own units. The mesh is shared by the instances of the object, so it is the
+    /// smallest of those at each place the mesh is drawn, after its transform.
+    fn simplify_error(&self, i: usize, mesh: &crate::mesh::TriangleMesh, pixels: f64) -> f64 {
+        let camera = self.create_camera();
+        self.objects
+            .iter()
+            .enumerate()
+            .filter(|(j, obj)| {
+                *j == i || matches!(obj.shape, Shape::Instance { object } if object == i)
+            })
+            .map(|(_, obj)| {
+                let (distance, scale) = match &obj.transform {
+                    None => (nearest_distance(&mesh.positions, camera.position), 1.0),
+                    Some(transform) => {
+                        let placed = transform.create(self.angle_unit);
+                        let positions = mesh
+                            .positions
+                            .iter()
+                            .map(|p| placed.to_world(p))
+                            .collect::<Vec<_>>();
+                        let scale = transform.scale.vector().abs().max();
+                        (nearest_distance(&positions, camera.position), scale)
+                    }
+                };
+                camera.pixel_size(distance, pixels) / scale
+            })
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /// Make the primitives of object `i`'s shape, which must not be an instance, with `material`.
+    fn primitives(
+        &self,
+        i: usize,
+        material: usize,
+        plugins: &Plugins,
+    ) -> anyhow::Result<Vec<crate::object::Object>> {
+        use crate::object::Object;
+
+        let placed_mesh = |mesh, origin, rotation: &Option<Rotation>| Object::Mesh {
+            mesh: crate::mesh::Mesh::new(place_mesh(mesh, origin, rotation, self.angle_unit)),
+            material,
+        };
+        Ok(match self.objects[i].shape {
+            Shape::Sphere { center, radius, .. } => {
+                vec![Object::sphere(tuple_to_vector(center), radius, material)]
+            }
+            Shape::Quad { q, u, v } => vec![Object::quad(
+                tuple_to_vector(q),
+                tuple_to_vector(u),
+                tuple_to_vector(v),
+                material,
+            )],
+            Shape::Prism {
+                origin,
+                width,
+                height,
+                depth,
+                ref rotation,
+            } => Object::prism(
+                &tuple_to_vector(origin),
+                width,
+                height,
+                depth,
+                &rotation
+                    .clone()
+                    .unwrap_or_default()
+                    .quaternion(self.angle_unit),
+                material,
+            ),
+            Shape::Custom {
+                ref name,
+                ref params,
+            } => {
+                let hittable = plugins
+                    .shape(name, params)
+                    .map_err(|e| anyhow::anyhow!("object {i}: {e}"))?;
+                vec![Object::Custom { hittable, material }]
+            }
+            Shape::Text {
+                ref text,
+                ref font,
+                origin,
+                size,
+                depth,
+                chamfer,
+                ref rotation,
+            } => {
+                let mesh = std::fs::read(font)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|font| crate::text::extrude(&font, text, size, depth, chamfer))
+                    .map_err(|e| anyhow::anyhow!("object {i}: {}: {e:#}", font.display()))?;
+                vec![placed_mesh(mesh, origin, rotation)]
+            }
+            Shape::Mesh {
+                ref path,
+                origin,
+                scale,
+                ref rotation,
+                ref simplify,
+            } => {
+                let mut mesh = std::fs::read_to_string(path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|text| crate::mesh::TriangleMesh::parse_obj(&text))
+                    .map_err(|e| anyhow::anyhow!("object {i}: {}: {e:#}", path.display()))?;
+                for position in &mut mesh.positions {
+                    *position *= scale;
+                }
+                let mut mesh = place_mesh(mesh, origin, rotation, self.angle_unit);
+                if let Some(simplify) = simplify {
+                    let max_error = simplify
+                        .pixel_error
+                        .map(|pixels| self.simplify_error(i, &mesh, pixels));
+                    mesh = mesh.simplify(crate::mesh::SimplifyLimits {
+                        target_triangles: simplify.triangles,
+                        max_error,
+                    });
+                }
+                vec![Object::Mesh {
+                    mesh: crate::mesh::Mesh::new(mesh),
+                    material,
+                }]
+            }
+            Shape::Lathe {
+                ref profile,
+                origin,
+                segments,
+                ref rotation,
+            } => {
+                let mesh = crate::mesh::TriangleMesh::lathe(profile, segments);
+                vec![placed_mesh(mesh, origin, rotation)]
+            }
+            Shape::Extrusion {
+                ref corners,
+                ref svg_path,
+                scale,
+                height,
+                chamfer,
+                origin,
+                ref rotation,
+            } => {
+                let mesh = match (corners, svg_path) {
+                    (Some(corners), _) => crate::extrude::polygon(corners, height, chamfer),
+                    (_, Some(data)) => crate::extrude::svg_path(data, scale, height, chamfer),
+                    (None, None) => unreachable!("checked by `validate`"),
+                }
+                .map_err(|e| anyhow::anyhow!("object {i}: {e}"))?;
+                vec![placed_mesh(mesh, origin, rotation)]
+            }
+            Shape::Metaballs {
+                ref balls,
+                threshold,
+            } => vec![Object::Metaballs {
+                metaballs: crate::metaball::Metaballs {
+                    balls: balls
+                        .iter()
+                        .map(|ball| crate::metaball::Metaball {
+                            center: tuple_to_vector(ball.center),
+                            radius: ball.radius,
+                            strength: ball.strength,
+                        })
+                        .collect(),
+                    threshold,
+                },
+                material,
+            }],
+            Shape::PointCloud {
+                ref path,
+                radius,
+                splat,
+                color_space,
+            } => {
+                let cloud = PointCloud::load(path, radius, splat.into(), color_space.into())
+                    .map_err(|e| anyhow::anyhow!("object {i}: {e:#}"))?;
+                vec![Object::PointCloud {
+                    cloud: Arc::new(cloud),
+                    material,
+                }]
+            }
+            Shape::Instance { .. } => unreachable!("instances are made by `create_world`"),
+        })
+    }
+
+    /// Check that every material and texture index refers to something that exists, and that mixed
+    /// materials do not contain themselves.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(script) = &self.script {
+            anyhow::bail!(
+                "the scene's script {} has not been run; see `Scene::run_script`",
+                script.file.display()
+            );
+        }
+        if !(0.0..=1.0).contains(&self.camera.regularization) {
+            anyhow::bail!("the camera's regularization must be between 0 and 1");
+        }
+        if let Some((open, close)) = self.camera.shutter {
+            if !(0.0 <= open && open <= close && close <= 1.0) {
+                anyhow::bail!("the camera's shutter must open and close between 0 and 1, in order");
+            }
+        }
+        if self.camera.super_resolution == 0 {
+            anyhow::bail!("the camera's super-resolution must be at least 1");
+        }
+        for (i, region) in self.camera.regions.iter().enumerate() {
+            let in_bounds = |min: f64, max: f64| 0.0 <= min && min < max && max <= 1.0;
+            if !in_bounds(region.min.0, region.max.0) || !in_bounds(region.min.1, region.max.1) {
+                anyhow::bail!(
+                    "the camera's region {i} must lie between 0 and 1, with min below max"
+                );
+            }
+            if region.samples_per_pixel == 0 {
+                anyhow::bail!("the camera's region {i} must take at least one sample per pixel");
+            }
+        }
+        if let Some(Sampling::Adaptive {
+            max_samples,
+            tolerance,
+        }) = self.camera.sampling
+        {
+            if max_samples == 0 {
+                anyhow::bail!("the camera's adaptive sampling must allow at least one sample");
+            }
+            if tolerance <= 0.0 || tolerance.is_nan() {
+                anyhow::bail!("the camera's adaptive sampling tolerance must be positive");
+            }
+        }
+        let focus_settings = [
+            self.camera.focus_target.is_some(),
+            self.camera.focus_point.is_some(),
+            self.camera.focus.is_some(),
+        ];
+        let focused = focus_settings.contains(&true);
+        if focus_settings.iter().filter(|&&set| set).count() > 1 {
+            anyhow::bail!(
+                "the camera can have only one of a focus target, a focus point, and autofocus"
+            );
+        }
+        if let Some(Focus::Auto { at }) = &self.camera.focus {
+            if !(0.0..=1.0).contains(&at.0) || !(0.0..=1.0).contains(&at.1) {
+                anyhow::bail!("the camera's autofocus point must lie between 0 and 1");
+            }
+        }
+        if let Some(name) = &self.camera.focus_target {
+            if !self
+                .objects
+                .iter()
+                .any(|object| object.name.as_ref() == Some(name))
+            {
+                anyhow::bail!("the camera's focus target `{name}` is not the name of an object");
+            }
+        }
+        if let Some(defocus) = &self.camera.defocus {
+            match defocus.focus_distance {
+                Some(distance) if distance <= 0.0 => {
+                    anyhow::bail!("the camera's focus distance must be positive")
+                }
+                None if !focused => anyhow::bail!(
+                    "the camera needs a focus distance, focus target, focus point, or autofocus \
+                     to defocus"
+                ),
+                _ => {}
+            }
+        }
+        for (i, object) in self.objects.iter().enumerate() {
+            let Some(name) = &object.name else {
+                continue;
+            };
+            if let Some(j) = self.objects[..i]
+                .iter()
+                .position(|other| other.name.as_ref() == Some(name))
+            {
+                anyhow::bail!("objects {j} and {i} are both named `{name}`");
+            }
+        }
+        if let Some(stereo) = &self.camera.stereo {
+            if stereo.separation < 0.0 || stereo.separation.is_nan() {
+                anyhow::bail!("the camera's stereo separation must not be negative");
+            }
+            if stereo.convergence <= 0.0 || stereo.convergence.is_nan() {
+                anyhow::bail!("the camera's stereo convergence distance must be positive");
+            }
+        }
+        if let Some(atmosphere) = &self.atmosphere {
+            if atmosphere.density < 0.0 || atmosphere.height_falloff < 0.0 {
+                anyhow::bail!("the atmosphere's density and height falloff must not be negative");
+            }
+        }
+
+        for plane in &self.clip_planes {
+            self.validate_clip_plane(plane)?;
+        }
+
+        for (i, object) in self.objects.iter().enumerate() {
+            if object.material >= self.materials.len() {
+                anyhow::bail!(
+                    "object {i} uses material {}, but there are only {} materials",
+                    object.material,
+                    self.materials.len()
+                );
+            }
+            for plane in &object.clip_planes {
+                self.validate_clip_plane(plane)
+                    .map_err(|e| anyhow::anyhow!("object {i}: {e}"))?;
+            }
+            if let Some(transform) = &object.transform {
+                let scale = transform.scale.vector();
+                if scale
+                    .iter()
+                    .any(|&factor| factor == 0.0 || !factor.is_finite())
+                {
+                    anyhow::bail!("object {i} has a transform whose scale is zero or not finite");
+                }
+            }
+            if let Some(range) = &object.visible_range {
+                let far = range.far.unwrap_or(f64::INFINITY);
+                if !(range.near >= 0.0 && far > range.near) {
+                    anyhow::bail!(
+                        "object {i}'s visible range must not be negative, with near below far"
+                    );
+                }
+                if range.fade < 0.0 || range.fade.is_nan() {
+                    anyhow::bail!(
+                        "object {i}'s visible range must not fade over a negative distance"
+                    );
+                }
+            }
+            match &object.shape {
+                Shape::Sphere { end: Some(_), .. } if !object.motion.is_empty() => {
+                    anyhow::bail!("object {i} is a sphere with both an end and a motion");
+                }
+                Shape::Instance { object: j } => match self.objects.get(*j) {
+                    None => anyhow::bail!(
+                        "object {i} is an instance of object {j}, but there are only {} objects",
+                        self.objects.len()
+                    ),
+                    Some(Object {
+                        shape: Shape::Instance { .. },
+                        ..
+                    }) => anyhow::bail!(
+                        "object {i} is an instance of object {j}, which is itself an instance"
+                    ),
+                    Some(_) => {}
+                },
+                Shape::PointCloud { radius, .. } if *radius <= 0.0 => {
+                    anyhow::bail!("object {i} is a point cloud whose radius is not positive");
+                }
+                Shape::Mesh { scale, .. } if *scale <= 0.0 => {
+                    anyhow::bail!("object {i} is a mesh whose scale is not positive");
+                }
+                Shape::Mesh {
+                    simplify: Some(simplify),
+                    ..
+                } => {
+                    if simplify.pixel_error.is_some_and(|e| e < 0.0 || e.is_nan()) {
+                        anyhow::bail!(
+                            "object {i} is a mesh whose simplify pixel-error is negative"
+                        );
+                    }
+                    // The error is measured from where the camera is when the mesh is loaded,
+                    // before framing moves it.
+                    if simplify.pixel_error.is_some() && self.camera.frame.is_some() {
+                        anyhow::bail!(
+                            "object {i} simplifies its mesh by pixel-error, which needs the \
+                             camera's position, so the camera cannot be framed"
+                        );
+                    }
+                }
+                Shape::Text { size, depth, .. } if *size <= 0.0 || *depth < 0.0 => {
+                    anyhow::bail!(
+                        "object {i} is text whose size is not positive or whose depth is negative"
+                    );
+                }
+                Shape::Text { depth, chamfer, .. } if *chamfer < 0.0 || *chamfer > depth / 2.0 => {
+                    anyhow::bail!(
+                        "object {i} is text whose chamfer is negative or more than half its depth"
+                    );
+                }
+                Shape::Lathe {
+                    profile, segments, ..
+                } => {
+                    if profile.len() < 2 || *segments < 3 {
+                        anyhow::bail!(
+                            "object {i} is a lathe that needs at least 2 profile points and 3 \
+                             segments"
+                        );
+                    }
+                    if profile.iter().any(|&(radius, _)| radius < 0.0) {
+                        anyhow::bail!("object {i} is a lathe with a negative radius");
+                    }
+                }
+                Shape::Extrusion {
+                    corners,
+                    svg_path,
+                    scale,
+                    height,
+                    chamfer,
+                    ..
+                } => {
+                    match (corners, svg_path) {
+                        (Some(corners), None) if corners.len() < 3 => {
+                            anyhow::bail!("object {i} is an extrusion with fewer than 3 corners")
+                        }
+                        (Some(_), None) | (None, Some(_)) => {}
+                        _ => anyhow::bail!(
+                            "object {i} is an extrusion that needs either corners or an svg-path"
+                        ),
+                    }
+                    if *height <= 0.0 || *scale <= 0.0 {
+                        anyhow::bail!(
+                            "object {i} is an extrusion whose height or scale is not positive"
+                        );
+                    }
+                    if *chamfer < 0.0 || *chamfer > height / 2.0 {
+                        anyhow::bail!(
+                            "object {i} is an extrusion whose chamfer is negative or more than \
+                             half its height"
+                        );
+                    }
+                }
+                Shape::Metaballs { threshold, .. } if *threshold <= 0.0 => {
+                    anyhow::bail!("object {i} is metaballs whose threshold is not positive");
+                }
+                Shape::Metaballs { balls, .. } if balls.iter().any(|ball| ball.radius <= 0.0) => {
+                    anyhow::bail!("object {i} has a metaball whose radius is not positive");
+                }
+                _ => {}
+            }
+        }
+
+        for (i, MaterialEntry { material, .. }) in self.materials.iter().enumerate() {
+            if let Material::Graph { output, nodes } = material {
+                validate_graph(output, nodes, self.textures.len())
+                    .map_err(|e| anyhow::anyhow!("material {i}: {e}"))?;
+                continue;
+            }
+
+            if let Material::Metal {
+                preset: Some(_),
+                ior: Some(_),
+                ..
+            } = material
+            {
+                anyhow::bail!("material {i} has both a preset and an ior, but only one is allowed");
+            }
+            if let Material::Metal { fuzz, .. } = material {
+                if !(0.0..=1.0).contains(fuzz) {
+                    anyhow::bail!("material {i} has a fuzz outside 0 to 1");
+                }
+            }
+
+            match material {
+                Material::Diffuse {
+                    albedo: None,
+                    texture: None,
+                } => anyhow::bail!("material {i} needs an albedo or a texture"),
+                Material::Diffuse {
+                    texture: Some(texture),
+                    ..
+                }
+                | Material::Metal {
+                    texture: Some(texture),
+                    ..
+                } if *texture >= self.textures.len() => anyhow::bail!(
+                    "material {i} uses texture {texture}, but there are only {} textures",
+                    self.textures.len()
+                ),
+                _ => {}
+            }
+
+            if let Material::Dielectric {
+                medium: Some(medium),
+                ..
+            } = material
+            {
+                let (a, s) = (medium.absorption, medium.scattering);
+                if [a.0, a.1, a.2, s.0, s.1, s.2].iter().any(|c| *c < 0.0) {
+                    anyhow::bail!("material {i} has a medium with negative coefficients");
+                }
+                if !(-1.0..=1.0).contains(&medium.anisotropy) {
+                    anyhow::bail!("material {i} has a medium with anisotropy outside -1 to 1");
+                }
+            }
+
+            if let Material::Incandescent {
+                temperature,
+                strength,
+            } = material
+            {
+                if *temperature <= 0.0 || temperature.is_nan() {
+                    anyhow::bail!("material {i} must have a temperature above 0 K");
+                }
+                if *strength < 0.0 {
+                    anyhow::bail!("material {i} must not have a negative strength");
+                }
+            }
+
+            let Material::Mix { a, b, mask, .. } = material else {
+                continue;
+            };
+
+            for component in [a, b] {
+                if *component >= self.materials.len() {
+                    anyhow::bail!(
+                        "material {i} mixes material {component}, but there are only {} materials",
+                        self.materials.len()
+                    );
+                }
+            }
+            if let Some(mask) = mask {
+                if *mask >= self.textures.len() {
+                    anyhow::bail!(
+                        "material {i} is masked by texture {mask}, but there are only {} textures",
+                        self.textures.len()
+                    );
+                }
+            }
+
+            if self.mix_contains(i, i, 0) {
+                anyhow::bail!("material {i} is a mix that contains itself");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_clip_plane(&self, plane: &ClipPlane) -> anyhow::Result<()> {
+        if tuple_to_vector(plane.normal).magnitude() == 0.0 {
+            anyhow::bail!("a clipping plane has no normal");
+        }
+        if let Some(cap) = plane.cap.filter(|&cap| cap >= self.materials.len()) {
+            anyhow::bail!(
+                "a clipping plane is capped with material {cap}, but there are only {} materials",
+                self.materials.len()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Whether `target` is a component of the mixed material `material`, at any depth.
+    fn mix_contains(&self, material: usize, target: usize, depth: usize) -> bool {
+        if depth > self.materials.len() {
+            return true;
+        }
+
+        match &self.materials[material].material {
+            Material::Mix { a, b, .. } => [a, b]
+                .into_iter()
+                .any(|&c| c == target || self.mix_contains(c, target, depth + 1)),
+            _ => false,
+        }
+    }
+}
+
+impl Light {
+    /// Create the light, reading bare angles in `angle_unit`, the scene's `angle-unit`, or
+    /// degrees without one.
+    pub fn create(&self, angle_unit: Option<AngleUnit>) -> crate::light::Light {
+        match *self {
+            Light::Point {
+                position,
+                color,
+                ref falloff,
+            } => crate::light::Light::Point {
+                position: Vector3::new(position.0, position.1, position.2),
+                color: Vector3::new(color.0, color.1, color.2),
+                falloff: falloff.clone().into(),
+            },
+            Light::Directional {
+                direction,
+                color,
+                angular_radius,
+            } => crate::light::Light::Directional {
+                direction: Unit::new_normalize(Vector3::new(direction.0, direction.1, direction.2)),
+                color: Vector3::new(color.0, color.1, color.2),
+                angular_radius: angular_radius.to_radians(angle_unit.unwrap_or(AngleUnit::Degrees)),
+            },
+        }
+    }
+}
+
+impl From<Falloff> for crate::light::Falloff {
+    fn from(value: Falloff) -> Self {
+        match value {
+            Falloff::InverseSquare => crate::light::Falloff::InverseSquare,
+            Falloff::Linear => crate::light::Falloff::Linear,
+            Falloff::Smooth { range } => crate::light::Falloff::Smooth { range },
         }
     }
 }
+
+/// Check that a graph's node names exist, it has no cycles, and BSDFs and values are not confused.
+fn validate_graph(
+    output: &str,
+    nodes: &BTreeMap<String, Node>,
+    texture_count: usize,
+) -> anyhow::Result<()> {
+    fn kind(
+        name: &str,
+        nodes: &BTreeMap<String, Node>,
+        texture_count: usize,
+        visiting: &mut Vec<String>,
+    ) -> anyhow::Result<NodeKind> {
+        if visiting.iter().any(|n| n == name) {
+            anyhow::bail!("node `{name}` depends on itself");
+        }
+        let node = nodes
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("there is no node named `{name}`"))?;
+
+        visiting.push(name.to_string());
+        let mut input_kind = |input: &Input| match input {
+            Input::Float(_) | Input::Color(_) => Ok(NodeKind::Value),
+            Input::Node(name) if !nodes.contains_key(name) && Rgb::parse(name).is_some() => {
+                Ok(NodeKind::Value)
+            }
+            Input::Node(name) => kind(name, nodes, texture_count, visiting),
+        };
+        let mut expect_value = |input: &Input| -> anyhow::Result<()> {
+            if input_kind(input)? == NodeKind::Bsdf {
+                anyhow::bail!("node `{name}` takes a value, but is given a BSDF");
+            }
+            Ok(())
+        };
+
+        let result = match node {
+            Node::Texture { texture } => {
+                if *texture >= texture_count {
+                    anyhow::bail!(
+                        "node `{name}` uses texture {texture}, but there are only {texture_count} textures"
+                    );
+                }
+                NodeKind::Value
+            }
+            Node::Noise { .. } | Node::AmbientOcclusion { .. } => NodeKind::Value,
+            Node::Checker {
+                even: a, odd: b, ..
+            }
+            | Node::Math { a, b, .. } => {
+                expect_value(a)?;
+                expect_value(b)?;
+                NodeKind::Value
+            }
+            Node::Fresnel { ir: input } | Node::RandomColor { color: input, .. } => {
+                expect_value(input)?;
+                NodeKind::Value
+            }
+            Node::Plastic { albedo, ir } => {
+                expect_value(albedo)?;
+                expect_value(ir)?;
+                NodeKind::Bsdf
+            }
+            Node::Diffuse { albedo: input }
+            | Node::Metal { albedo: input }
+            | Node::Dielectric { ir: input }
+            | Node::Light { color: input } => {
+                expect_value(input)?;
+                NodeKind::Bsdf
+            }
+            Node::Mix { a, b, factor } => {
+                expect_value(factor)?;
+                let a = input_kind(a)?;
+                if a != input_kind(b)? {
+                    anyhow::bail!("node `{name}` mixes a BSDF with a value");
+                }
+                a
+            }
+        };
+        visiting.pop();
+
+        Ok(result)
+    }
+
+    for name in nodes.keys() {
+        kind(name, nodes, texture_count, &mut Vec::new())?;
+    }
+
+    if kind(output, nodes, texture_count, &mut Vec::new())? != NodeKind::Bsdf {
+        anyhow::bail!("the output node `{output}` is not a BSDF");
+    }
+
+    Ok(())
+}
+
+/// Move a mesh made around the origin to `origin`, after rotating it.
+fn place_mesh(
+    mut mesh: crate::mesh::TriangleMesh,
+    origin: (f64, f64, f64),
+    rotation: &Option<Rotation>,
+    angle_unit: Option<AngleUnit>,
+) -> crate::mesh::TriangleMesh {
+    let rotation = rotation.clone().unwrap_or_default().quaternion(angle_unit);
+    for position in &mut mesh.positions {
+        *position = rotation * *position + tuple_to_vector(origin);
+    }
+    mesh
+}
+
+/// The distance from `point` to the closest point of the box around `positions`, zero inside it.
+fn nearest_distance(positions: &[Vector3<f64>], point: Vector3<f64>) -> f64 {
+    let Some(first) = positions.first() else {
+        return 0.0;
+    };
+    let (min, max) = positions
+        .iter()
+        .fold((*first, *first), |(min, max), p| (min.inf(p), max.sup(p)));
+    (min - point)
+        .sup(&(point - max))
+        .sup(&Vector3::zeros())
+        .norm()
+}
+
+fn tuple_to_vector((x, y, z): (f64, f64, f64)) -> Vector3<f64> {
+    Vector3::new(x, y, z)
+}
@@ -0,0 +1,80 @@
+//! Scene scripts, which generate materials, objects, and lights with the loops and math that TOML
+//! lacks. Scripts are written in Rhai, and describe what they add with maps laid out like the
+//! tables of a scene file:
+//!
+//! ```rhai
+//! let gold = material(#{ type: "metal", albedo: "#d4af37" });
+//! for i in 0..12 {
+//!     let angle = i * PI() / 6.0;
+//!     object(#{
+//!         material: gold,
+//!         shape: #{ type: "sphere", center: [cos(angle), 0.2, sin(angle)], radius: 0.2 },
+//!     });
+//! }
+//! ```
+
+use std::{cell::RefCell, path::Path, rc::Rc};
+
+use rhai::{Dynamic, Engine, EvalAltResult, Map};
+use serde::de::DeserializeOwned;
+
+use crate::scene::{Light, MaterialEntry, Object, Scene};
+
+/// What a script has generated so far.
+#[derive(Default)]
+struct Generated {
+    materials: Vec<MaterialEntry>,
+    objects: Vec<Object>,
+    lights: Vec<Light>,
+}
+
+/// Run the script at `path`, adding what it generates to `scene` after what the scene file holds.
+pub fn run(scene: &mut Scene, path: &Path) -> anyhow::Result<()> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("the scene's script {}: {e}", path.display()))?;
+
+    let generated = Rc::new(RefCell::new(Generated::default()));
+    let mut engine = Engine::new();
+
+    let materials = scene.materials.len();
+    let state = generated.clone();
+    engine.register_fn(
+        "material",
+        move |map: Map| -> Result<i64, Box<EvalAltResult>> {
+            let mut generated = state.borrow_mut();
+            generated.materials.push(from_map(map)?);
+            Ok((materials + generated.materials.len() - 1) as i64)
+        },
+    );
+    let state = generated.clone();
+    engine.register_fn(
+        "object",
+        move |map: Map| -> Result<(), Box<EvalAltResult>> {
+            state.borrow_mut().objects.push(from_map(map)?);
+            Ok(())
+        },
+    );
+    let state = generated.clone();
+    engine.register_fn("light", move |map: Map| -> Result<(), Box<EvalAltResult>> {
+        state.borrow_mut().lights.push(from_map(map)?);
+        Ok(())
+    });
+
+    engine
+        .run(&source)
+        .map_err(|e| anyhow::anyhow!("the scene's script {}: {e}", path.display()))?;
+
+    drop(engine);
+    let generated = generated.take();
+    scene.materials.extend(generated.materials);
+    scene.objects.extend(generated.objects);
+    scene.lights.extend(generated.lights);
+
+    Ok(())
+}
+
+/// Read a map given to a script function as the scene table it stands for.
+fn from_map<T: DeserializeOwned>(map: Map) -> Result<T, Box<EvalAltResult>> {
+    let value: toml::Value = rhai::serde::from_dynamic(&Dynamic::from_map(map))?;
+    value.try_into().map_err(|error| error.to_string().into())
+}
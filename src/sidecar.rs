@@ -0,0 +1,80 @@
+//! Sidecar files, written next to each rendered image, that record everything needed to render it
+//! again: the scene as it was rendered and the command-line settings that changed the image.
+
+use std::path::{Path, PathBuf};
+
+use rtk::scene::{Scene, DEFAULT_SAMPLES_PER_PIXEL};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Sidecar {
+    pub settings: Settings,
+    /// The scene with the chosen camera in place of its main camera, and the defaults that were
+    /// used filled in.
+    pub scene: Scene,
+}
+
+/// The settings given on the command line that change the rendered image.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Settings {
+    /// The scene file that was rendered, for reference; the scene is read from the sidecar.
+    pub source: PathBuf,
+    /// The camera that was chosen with `--camera`, for reference.
+    pub camera: Option<String>,
+    pub frame: u64,
+    pub checkerboard: bool,
+    #[serde(default)]
+    pub denoise: bool,
+    /// Whether bounding boxes were drawn over the image, with `--bounds`.
+    #[serde(default)]
+    pub bounds: bool,
+    #[serde(default)]
+    pub bvh_depth: Option<usize>,
+    /// Whether edges were drawn over the image, with `--wireframe`.
+    #[serde(default)]
+    pub wireframe: bool,
+    /// Whether the image was rendered in a flat style, with `--toon`.
+    #[serde(default)]
+    pub toon: bool,
+    #[serde(default)]
+    pub toon_bands: Option<u32>,
+    /// Whether the camera was moved to fit the scene into view, with `--auto-frame`.
+    #[serde(default)]
+    pub auto_frame: bool,
+    /// Whether the image was kept and written in half-precision floats, with `--half`.
+    #[serde(default)]
+    pub half: bool,
+}
+
+impl Sidecar {
+    pub fn new(mut scene: Scene, settings: Settings) -> Self {
+        let camera = &mut scene.camera;
+        camera.samples_per_pixel = Some(
+            camera
+                .samples_per_pixel
+                .unwrap_or(DEFAULT_SAMPLES_PER_PIXEL),
+        );
+        camera.background_color = Some(camera.background_color.unwrap_or_default());
+        camera.position = Some(camera.position.unwrap_or_default());
+
+        Self { settings, scene }
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        Ok(std::fs::write(path, toml::to_string(self)?)?)
+    }
+}
+
+/// The path of the sidecar for the image at `output`, which is the image's path with `.toml`
+/// appended, so that it never replaces a scene file of the same name.
+pub fn path(output: &Path) -> PathBuf {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".toml");
+    PathBuf::from(path)
+}
@@ -0,0 +1,377 @@
+//! Summarizes the contents of a scene.
+
+use std::{
+    collections::{hash_map::Entry, BTreeSet, HashMap},
+    path::Path,
+};
+
+use nalgebra::{Vector2, Vector3};
+use rtk::{
+    object::{Aabb, Object, Transform, World},
+    plugin::Plugins,
+    render::Renderer,
+    scene::{Material, Scene, Shape},
+};
+
+use crate::print_warning;
+
+/// Handle `cli::Command::Stats`.
+pub fn stats(scene_path: &Path) -> anyhow::Result<()> {
+    let mut scene = crate::load_scene(scene_path)?;
+    scene.validate()?;
+    let mut world = scene.create_world(&Plugins::new())?;
+    if scene.camera.frame.is_some() {
+        scene.frame_camera(&mut world)?;
+    }
+
+    let (mut spheres, mut quads, mut metaballs, mut custom, mut instances) = (0, 0, 0, 0, 0);
+    let (mut meshes, mut triangles, mut point_clouds, mut points) = (0, 0, 0, 0);
+    for object in world.objects.iter() {
+        match &*object {
+            Object::Sphere { .. } => spheres += 1,
+            Object::Quad { .. } => quads += 1,
+            Object::Metaballs { .. } => metaballs += 1,
+            Object::Mesh { mesh, .. } => {
+                meshes += 1;
+                triangles += mesh.triangle_count();
+            }
+            Object::Custom { .. } => custom += 1,
+            Object::Instance { .. } => instances += 1,
+            Object::PointCloud { cloud, .. } => {
+                point_clouds += 1;
+                points += cloud.points().len();
+            }
+        }
+    }
+
+    println!(
+        "Objects:        {} ({} primitives: {spheres} spheres, {quads} quads, {metaballs} metaballs, \
+         {custom} custom, {instances} instances)",
+        scene.objects.len(),
+        world.objects.len()
+    );
+    if meshes > 0 {
+        println!("Triangles:      {triangles} in {meshes} meshes");
+    }
+    if point_clouds > 0 {
+        println!("Points:         {points} in {point_clouds} point clouds");
+    }
+    println!(
+        "Materials:      {} ({} including per-object overrides)",
+        scene.materials.len(),
+        world.materials.len()
+    );
+    println!("Textures:       {}", scene.textures.len());
+    println!("Lights:         {}", scene.lights.len());
+
+    let emitters = emitters(&scene, &world);
+    let emissive_area = emitters
+        .iter()
+        .filter_map(|&i| placed_area(&world, i))
+        .fold(0.0, |sum, area| sum + area);
+    println!(
+        "Emissive area:  {emissive_area:.3} ({} primitives)",
+        emitters.len()
+    );
+
+    match (0..world.objects.len())
+        .filter_map(|i| placed_bounds(&world, i))
+        .reduce(|a, b| a.union(&b))
+    {
+        Some(bounds) => println!(
+            "World bounds:   ({:.3}, {:.3}, {:.3}) to ({:.3}, {:.3}, {:.3})",
+            bounds.min.x, bounds.min.y, bounds.min.z, bounds.max.x, bounds.max.y, bounds.max.z
+        ),
+        None => println!("World bounds:   none"),
+    }
+
+    let visible = Renderer::new(scene.create_camera()).visible_objects(&world);
+    println!(
+        "In view:        {} of {} objects",
+        visible.len(),
+        scene.objects.len()
+    );
+
+    let bvh = world.objects.bvh();
+    println!(
+        "BVH memory:     {:.1} KiB ({} nodes)",
+        bvh.size_in_bytes() as f64 / 1024.0,
+        bvh.node_count()
+    );
+
+    if visible.is_empty() && !scene.objects.is_empty() {
+        print_warning(
+            "the camera sees none of the objects, so renders will show only the background",
+        );
+    }
+    for problem in problems(&scene, &world, &emitters) {
+        print_warning(&problem);
+    }
+
+    Ok(())
+}
+
+/// The indices of the primitives in `world` whose scene material emits light.
+fn emitters(scene: &Scene, world: &World) -> Vec<usize> {
+    world
+        .object_ids
+        .iter()
+        .enumerate()
+        .filter(|(_, &id)| emits(scene, scene.objects[id].material))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Whether a scene material is a light, or a mix that may resolve to one.
+fn emits(scene: &Scene, material: usize) -> bool {
+    match &scene.materials[material].material {
+        Material::Light { .. } | Material::Incandescent { .. } => true,
+        Material::Mix { a, b, .. } => emits(scene, *a) || emits(scene, *b),
+        _ => false,
+    }
+}
+
+/// The box around primitive `i` where its object places it, over all of its motion.
+fn placed_bounds(world: &World, i: usize) -> Option<Aabb> {
+    let bounds = world.objects.get(i)?.bounds()?;
+    Some(world.object_settings[world.object_ids[i]].swept_bounds(bounds))
+}
+
+/// The area of primitive `i` where its object places it.
+fn placed_area(world: &World, i: usize) -> Option<f64> {
+    fn area(transform: &Transform, object: &Object) -> Option<f64> {
+        let triangle = |[a, b, c]: [Vector3<f64>; 3]| {
+            let [a, b, c] = [a, b, c].map(|p| transform.to_world(&p));
+            (b - a).cross(&(c - a)).magnitude() / 2.0
+        };
+        match object {
+            // Flat shapes are measured once placed, which is exact however they are scaled.
+            Object::Quad { q, u, v, .. } => Some(2.0 * triangle([*q, q + u, q + v])),
+            Object::Mesh { mesh, .. } => Some(mesh.triangles().map(triangle).sum()),
+            Object::Instance { geometry, .. } => {
+                geometry.iter().map(|object| area(transform, &object)).sum()
+            }
+            object => Some(object.area()? * transform.volume_scale().powf(2.0 / 3.0)),
+        }
+    }
+
+    let object = world.objects.get(i)?;
+    match &world.object_settings[world.object_ids[i]].transform {
+        Some(transform) => area(transform, &object),
+        None => object.area(),
+    }
+}
+
+/// Describe things in the scene that are probably mistakes.
+fn problems(scene: &Scene, world: &World, emitters: &[usize]) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    // Emitters inside one another waste light and make the overlap hard to sample.
+    let mut overlapping = BTreeSet::new();
+    let bounds = |i: usize| placed_bounds(world, i);
+    for (n, &i) in emitters.iter().enumerate() {
+        for &j in &emitters[n + 1..] {
+            let (a, b) = (world.object_ids[i], world.object_ids[j]);
+            if a == b {
+                continue;
+            }
+            let (Some(bounds_i), Some(bounds_j)) = (bounds(i), bounds(j)) else {
+                continue;
+            };
+            if bounds_i.overlaps(&bounds_j) {
+                overlapping.insert((a, b));
+            }
+        }
+    }
+    for (a, b) in overlapping {
+        problems.push(format!("objects {a} and {b} emit light and overlap"));
+    }
+
+    problems.extend(degenerate_primitives(scene, world));
+    let duplicates = duplicate_objects(scene);
+    for &(a, b) in &duplicates {
+        problems.push(format!("objects {a} and {b} are identical"));
+    }
+    for (a, b) in coplanar_quads(scene, world) {
+        if !duplicates.contains(&(a, b)) {
+            problems.push(format!(
+                "objects {a} and {b} have overlapping quads in the same plane, which will flicker \
+                 between each other"
+            ));
+        }
+    }
+
+    let mut used = vec![false; scene.materials.len()];
+    for object in &scene.objects {
+        mark_used(scene, object.material, &mut used);
+    }
+    for (i, used) in used.into_iter().enumerate() {
+        if !used {
+            problems.push(format!("material {i} is not used by any object"));
+        }
+    }
+
+    problems
+}
+
+/// Mark a material as used, along with the components it mixes.
+fn mark_used(scene: &Scene, material: usize, used: &mut [bool]) {
+    if used[material] {
+        return;
+    }
+    used[material] = true;
+
+    if let Material::Mix { a, b, .. } = &scene.materials[material].material {
+        mark_used(scene, *a, used);
+        mark_used(scene, *b, used);
+    }
+}
+
+/// Describe the primitives that have no size, or are inside out by mistake.
+fn degenerate_primitives(scene: &Scene, world: &World) -> Vec<String> {
+    let mut flat_quads = BTreeSet::new();
+    let mut empty_spheres = BTreeSet::new();
+    let mut inside_out_spheres = BTreeSet::new();
+    for (i, object) in world.objects.iter().enumerate() {
+        let id = world.object_ids[i];
+        match &*object {
+            Object::Quad { u, v, .. } => {
+                let area = u.cross(v).norm();
+                if area <= 1e-9 * u.norm() * v.norm() {
+                    flat_quads.insert(id);
+                }
+            }
+            Object::Sphere { radius, .. } => {
+                // Glass spheres with a negative radius are a common way to model hollow glass,
+                // with their normals pointing inwards.
+                let glass = matches!(
+                    scene.materials[scene.objects[id].material].material,
+                    Material::Dielectric { .. }
+                );
+                if *radius == 0.0 || radius.is_nan() {
+                    empty_spheres.insert(id);
+                } else if *radius < 0.0 && !glass {
+                    inside_out_spheres.insert(id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let flat_quads = flat_quads.into_iter().map(|id| {
+        format!("object {id} has a quad with no area, since its `u` and `v` are zero or parallel")
+    });
+    let empty_spheres = empty_spheres
+        .into_iter()
+        .map(|id| format!("object {id} has a sphere with a radius of zero"));
+    let inside_out_spheres = inside_out_spheres.into_iter().map(|id| {
+        format!("object {id} has a sphere with a negative radius, which turns it inside out")
+    });
+    flat_quads
+        .chain(empty_spheres)
+        .chain(inside_out_spheres)
+        .collect()
+}
+
+/// The pairs of scene objects that are the same apart from their names, so one hides the other.
+fn duplicate_objects(scene: &Scene) -> BTreeSet<(usize, usize)> {
+    // Objects are grouped by their serialized form, so each is compared with the first of the
+    // same form rather than with every object before it.
+    let mut first = HashMap::new();
+    let mut duplicates = BTreeSet::new();
+    for (b, object) in scene.objects.iter().enumerate() {
+        let mut object = object.clone();
+        object.name = None;
+        let Ok(key) = toml::to_string(&object) else {
+            continue;
+        };
+        match first.entry(key) {
+            Entry::Occupied(entry) => {
+                duplicates.insert((*entry.get(), b));
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(b);
+            }
+        }
+    }
+
+    duplicates
+}
+
+/// The pairs of quad objects that lie in the same plane and overlap, which renders as noise where
+/// either surface wins from pixel to pixel. The faces of prisms are left out, since a box resting
+/// on a floor or against another box hides the faces they share, and so are transformed quads.
+fn coplanar_quads(scene: &Scene, world: &World) -> BTreeSet<(usize, usize)> {
+    let quads = world
+        .objects
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| {
+            let object = &scene.objects[world.object_ids[i]];
+            matches!(object.shape, Shape::Quad { .. }) && object.transform.is_none()
+        })
+        .filter_map(|(i, object)| match &*object {
+            Object::Quad { q, u, v, .. } => {
+                let normal = u.cross(v).try_normalize(0.0)?;
+                Some((world.object_ids[i], *q, *u, *v, normal, object.bounds()?))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut pairs = BTreeSet::new();
+    for (n, &(a, q_a, u_a, v_a, normal, ref bounds_a)) in quads.iter().enumerate() {
+        for &(b, q_b, u_b, v_b, normal_b, ref bounds_b) in &quads[n + 1..] {
+            if a == b || !bounds_a.overlaps(bounds_b) {
+                continue;
+            }
+            let size = u_a.norm() + v_a.norm() + u_b.norm() + v_b.norm();
+            let tolerance = 1e-6 * size;
+            if normal.cross(&normal_b).norm() > 1e-6 || (q_b - q_a).dot(&normal).abs() > tolerance {
+                continue;
+            }
+
+            // Compare the quads as parallelograms in the plane.
+            let x = u_a.normalize();
+            let y = normal.cross(&x);
+            let corners = |q: Vector3<f64>, u: Vector3<f64>, v: Vector3<f64>| {
+                [q, q + u, q + u + v, q + v].map(|corner| {
+                    let corner = corner - q_a;
+                    Vector2::new(corner.dot(&x), corner.dot(&y))
+                })
+            };
+            if overlap(&corners(q_a, u_a, v_a), &corners(q_b, u_b, v_b), tolerance) {
+                pairs.insert((a.min(b), a.max(b)));
+            }
+        }
+    }
+
+    pairs
+}
+
+/// Whether two convex polygons overlap by more than `tolerance`, rather than only touching.
+fn overlap(a: &[Vector2<f64>], b: &[Vector2<f64>], tolerance: f64) -> bool {
+    let edges = |polygon: &[Vector2<f64>]| {
+        (0..polygon.len())
+            .map(|i| polygon[(i + 1) % polygon.len()] - polygon[i])
+            .collect::<Vec<_>>()
+    };
+    let project = |polygon: &[Vector2<f64>], axis: &Vector2<f64>| {
+        polygon
+            .iter()
+            .map(|point| point.dot(axis))
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), d| {
+                (min.min(d), max.max(d))
+            })
+    };
+
+    // The polygons are apart if their shadows on the normal of any edge are.
+    edges(a).into_iter().chain(edges(b)).all(|edge| {
+        let Some(axis) = Vector2::new(-edge.y, edge.x).try_normalize(0.0) else {
+            return true;
+        };
+        let (min_a, max_a) = project(a, &axis);
+        let (min_b, max_b) = project(b, &axis);
+        max_a.min(max_b) - min_a.max(min_b) > tolerance
+    })
+}
@@ -0,0 +1,131 @@
+//! Displays renderer progress on stderr.
+
+use std::{
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use rtk::render::{ProgressEvent, Stage};
+
+/// How often displays are ticked while no progress arrives.
+pub const TICK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Something that shows the user what the renderer is doing.
+pub trait ProgressDisplay {
+    fn update(&mut self, progress: &ProgressEvent);
+    /// Leave a final message and stop displaying progress.
+    fn finish(&mut self, message: &str);
+    /// Called every `TICK_INTERVAL` while no progress arrives, for displays that must keep
+    /// responding.
+    fn tick(&mut self) {}
+    /// Wait until the user is done with the display, once everything else is finished.
+    fn wait(&mut self) {}
+}
+
+/// Several displays, each shown every update.
+impl ProgressDisplay for Vec<Box<dyn ProgressDisplay>> {
+    fn update(&mut self, progress: &ProgressEvent) {
+        self.iter_mut().for_each(|display| display.update(progress));
+    }
+
+    fn finish(&mut self, message: &str) {
+        self.iter_mut().for_each(|display| display.finish(message));
+    }
+
+    fn tick(&mut self) {
+        self.iter_mut().for_each(|display| display.tick());
+    }
+
+    fn wait(&mut self) {
+        self.iter_mut().for_each(|display| display.wait());
+    }
+}
+
+/// A single, continuously rewritten status line.
+pub struct StatusLine {
+    /// When the rendering stage began, used to estimate the time remaining.
+    render_start: Option<Instant>,
+    /// The length of the last line written, so that shorter lines can fully overwrite it.
+    last_len: usize,
+}
+
+impl StatusLine {
+    pub fn new() -> Self {
+        Self {
+            render_start: None,
+            last_len: 0,
+        }
+    }
+
+    fn write(&mut self, line: &str) {
+        let padding = self.last_len.saturating_sub(line.len());
+        eprint!("\r{line}{}", " ".repeat(padding));
+        _ = std::io::stderr().flush();
+        self.last_len = line.len();
+    }
+}
+
+impl ProgressDisplay for StatusLine {
+    fn update(&mut self, progress: &ProgressEvent) {
+        match progress {
+            ProgressEvent::Stage(Stage::Rendering) => {
+                self.render_start = Some(Instant::now());
+                self.write(&progress_bar(0, None));
+            }
+            ProgressEvent::Stage(stage) => self.write(&format!("{stage}...")),
+            ProgressEvent::Rendering(percent) => {
+                let eta = self
+                    .render_start
+                    .and_then(|start| estimate_remaining(start.elapsed(), *percent));
+                self.write(&progress_bar(*percent, eta));
+            }
+            ProgressEvent::TileFinished { .. }
+            | ProgressEvent::Samples(_)
+            | ProgressEvent::Finished => {}
+            ProgressEvent::Failed(_) | ProgressEvent::Cancelled => self.finish(""),
+        }
+    }
+
+    fn finish(&mut self, message: &str) {
+        self.write(message);
+        eprintln!();
+        self.last_len = 0;
+    }
+}
+
+fn progress_bar(percent: u32, eta: Option<Duration>) -> String {
+    const SEGMENTS: u32 = 40;
+
+    let filled_segments = ((percent as f32 / 100.0) * SEGMENTS as f32) as u32;
+    let empty_segments = SEGMENTS - filled_segments;
+
+    let mut bar = format!(
+        "[{}{}] {percent}%",
+        "*".repeat(filled_segments as usize),
+        " ".repeat(empty_segments as usize)
+    );
+    if let Some(eta) = eta {
+        bar.push_str(&format!(" (ETA {})", format_duration(eta)));
+    }
+
+    bar
+}
+
+/// Extrapolate the time remaining from the time taken so far, or `None` if there is too little
+/// progress to say.
+pub fn estimate_remaining(elapsed: Duration, percent: u32) -> Option<Duration> {
+    if percent == 0 {
+        return None;
+    }
+
+    Some(elapsed.mul_f64((100 - percent.min(100)) as f64 / percent as f64))
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=59 => format!("{seconds}s"),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, (seconds % 3600) / 60),
+    }
+}
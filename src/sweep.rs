@@ -0,0 +1,294 @@
+//! Renders every combination of values of some of a scene's parameters, described by a sweep file:
+//!
+//! ```toml
+//! scene = "scene.toml"
+//! output = "sweep/fov-{camera.fov}-ir-{materials.4.ir}.png"
+//! contact-sheet = "sheet.png"
+//!
+//! [parameters]
+//! "camera.fov" = [20, 35, 50]
+//! "materials.4.ir" = "1.3..1.7 step 0.1"
+//! ```
+//!
+//! Each parameter is the dotted path of a value in the scene file, with numbers indexing arrays.
+
+use std::{collections::BTreeSet, path::PathBuf};
+
+use image::RgbImage;
+use indexmap::IndexMap;
+use rtk::{
+    plugin::Plugins,
+    render::{self, Renderer},
+    scene::Scene,
+};
+use serde::Deserialize;
+use toml::Value;
+
+use crate::{cli::SweepArgs, print_warning};
+
+/// The space between the images of a contact sheet, in pixels.
+const SHEET_GAP: u32 = 4;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct Sweep {
+    /// The scene to render variations of.
+    scene: PathBuf,
+    /// Where to write each render. `{index}` is replaced by the render's number, counting from 0,
+    /// and the path of a parameter in braces by its value.
+    #[serde(default = "default_output")]
+    output: String,
+    /// Write every render into one image, with a row for each value of the first parameter.
+    contact_sheet: Option<PathBuf>,
+    /// The values of each parameter, by its path in the scene, in the order they are written.
+    parameters: IndexMap<String, Values>,
+}
+
+fn default_output() -> String {
+    "sweep-{index}.png".to_string()
+}
+
+/// The values a parameter takes.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Values {
+    List(Vec<Value>),
+    /// Evenly spaced numbers, written like `"0..1 step 0.1"`, including both ends. Whole numbers
+    /// give integers.
+    Range(String),
+}
+
+impl Values {
+    fn expand(&self) -> anyhow::Result<Vec<Value>> {
+        let range = match self {
+            Values::List(values) => return Ok(values.clone()),
+            Values::Range(range) => range,
+        };
+
+        let invalid = || anyhow::anyhow!("`{range}` is not a range like `0..1 step 0.1`");
+        let (bounds, step) = range.split_once("step").ok_or_else(invalid)?;
+        let (start, end) = bounds.split_once("..").ok_or_else(invalid)?;
+        // Ranges of whole numbers give integers, for parameters like the samples per pixel.
+        let whole = [start, end, step].map(|number| number.trim().parse::<i64>().ok());
+        if let [Some(start), Some(end), Some(step)] = whole {
+            if step <= 0 || end < start {
+                return Err(invalid());
+            }
+            return Ok((start..=end)
+                .step_by(step as usize)
+                .map(Value::Integer)
+                .collect());
+        }
+
+        let parse = |number: &str| number.trim().parse::<f64>().map_err(|_| invalid());
+        let (start, end, step) = (parse(start)?, parse(end)?, parse(step)?);
+        if step <= 0.0 || step.is_nan() || end < start {
+            return Err(invalid());
+        }
+
+        // The count is rounded so that an end reached by adding up steps is kept despite rounding
+        // errors, and each value is rounded to keep them out of output names.
+        let count = ((end - start) / step + 1e-9).floor() as usize + 1;
+        Ok((0..count)
+            .map(|i| Value::Float(((start + i as f64 * step) * 1e9).round() / 1e9))
+            .collect())
+    }
+}
+
+/// Handle `cli::Command::Sweep`.
+pub fn sweep(args: &SweepArgs) -> anyhow::Result<()> {
+    let sweep: Sweep = toml::from_str(&std::fs::read_to_string(&args.sweep)?)?;
+    if sweep.parameters.is_empty() {
+        anyhow::bail!("the sweep has no parameters");
+    }
+    let base: Value = toml::from_str(&std::fs::read_to_string(&sweep.scene)?)?;
+    let parameters = sweep
+        .parameters
+        .iter()
+        .map(|(path, values)| {
+            let values = values.expand()?;
+            if values.is_empty() {
+                anyhow::bail!("the parameter `{path}` has no values");
+            }
+            Ok((path.as_str(), values))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let total = parameters.iter().map(|(_, values)| values.len()).product();
+    let combinations = (0..total)
+        .map(|index| combination(&parameters, index))
+        .collect::<Vec<_>>();
+    let outputs = combinations
+        .iter()
+        .enumerate()
+        .map(|(index, combination)| output_path(&sweep.output, index, combination))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if outputs.iter().collect::<BTreeSet<_>>().len() < outputs.len() {
+        anyhow::bail!(
+            "the output `{}` names the same file for several renders; include `{{index}}` or every \
+             parameter in it",
+            sweep.output
+        );
+    }
+
+    let mut renders = Vec::new();
+    for (index, (combination, output)) in combinations.iter().zip(&outputs).enumerate() {
+        let mut document = base.clone();
+        for &(path, value) in combination {
+            set(&mut document, path, value.clone())?;
+        }
+
+        let (mut scene, changes) = Scene::from_toml(&toml::to_string(&document)?)?;
+        if index == 0 && !changes.is_empty() {
+            print_warning(&format!(
+                "{} is written for an older version of rtk; run `rtk migrate {}` to update it",
+                sweep.scene.display(),
+                sweep.scene.display()
+            ));
+        }
+        let image = render(&mut scene, !args.no_parallel)?;
+        if let Some(directory) = output.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        image.save(output)?;
+        eprintln!("[{}/{total}] Wrote {}", index + 1, output.display());
+
+        if sweep.contact_sheet.is_some() {
+            renders.push(image);
+        }
+    }
+
+    if let Some(path) = &sweep.contact_sheet {
+        contact_sheet(&renders, parameters[0].1.len()).save(path)?;
+        eprintln!("Wrote {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// The value of each parameter in the `index`th combination, counting through the last
+/// parameter's values fastest.
+fn combination<'a>(
+    parameters: &'a [(&'a str, Vec<Value>)],
+    index: usize,
+) -> Vec<(&'a str, &'a Value)> {
+    let mut rest = index;
+    let mut combination = parameters
+        .iter()
+        .rev()
+        .map(|(path, values)| {
+            let value = &values[rest % values.len()];
+            rest /= values.len();
+            (*path, value)
+        })
+        .collect::<Vec<_>>();
+    combination.reverse();
+    combination
+}
+
+/// Replace the value at the dotted `path` in a scene document, adding the last key if it is
+/// missing.
+fn set(document: &mut Value, path: &str, value: Value) -> anyhow::Result<()> {
+    let missing = || anyhow::anyhow!("the scene has nothing at `{path}`");
+    let (parents, last) = match path.rsplit_once('.') {
+        Some((parents, last)) => (Some(parents), last),
+        None => (None, path),
+    };
+
+    let mut parent = document;
+    for key in parents.into_iter().flat_map(|parents| parents.split('.')) {
+        parent = match parent {
+            Value::Table(table) => table.get_mut(key),
+            Value::Array(array) => key.parse::<usize>().ok().and_then(|i| array.get_mut(i)),
+            _ => None,
+        }
+        .ok_or_else(missing)?;
+    }
+
+    match parent {
+        Value::Table(table) => {
+            table.insert(last.to_string(), value);
+        }
+        Value::Array(array) => {
+            let slot = last
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| array.get_mut(i))
+                .ok_or_else(missing)?;
+            *slot = value;
+        }
+        _ => return Err(missing()),
+    }
+
+    Ok(())
+}
+
+/// Fill in an output path template for the `index`th render.
+fn output_path(
+    template: &str,
+    index: usize,
+    combination: &[(&str, &Value)],
+) -> anyhow::Result<PathBuf> {
+    let mut output = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow::anyhow!("the output `{template}` has an unclosed `{{`"))?;
+        let name = &rest[start + 1..start + end];
+        if name == "index" {
+            output.push_str(&index.to_string());
+        } else {
+            let (_, value) = combination
+                .iter()
+                .find(|(path, _)| *path == name)
+                .ok_or_else(|| anyhow::anyhow!("the output names `{name}`, which is not swept"))?;
+            match value {
+                Value::String(string) => output.push_str(string),
+                value => output.push_str(&value.to_string()),
+            }
+        }
+        rest = &rest[start + end + 1..];
+    }
+    output.push_str(rest);
+
+    Ok(PathBuf::from(output))
+}
+
+/// Render a scene as `rtk render` does with no options, to display colors.
+fn render(scene: &mut Scene, parallel: bool) -> anyhow::Result<RgbImage> {
+    scene.run_script()?;
+    scene.validate()?;
+    let mut world = scene.create_world(&Plugins::new())?;
+    let mut camera = scene.create_camera();
+    if scene.camera.frame.is_some() {
+        camera.position = scene.frame_camera(&mut world)?;
+    }
+    if let Some(distance) = scene.focus_distance(&world)? {
+        camera.focus_distance = distance;
+    }
+
+    let image = Renderer::new(camera).render_hdr(&world, parallel, |_| {})?;
+    Ok(render::to_display(&image))
+}
+
+/// Lay renders out in a grid of `rows` rows, each as large as the largest render.
+fn contact_sheet(renders: &[RgbImage], rows: usize) -> RgbImage {
+    let columns = renders.len().div_ceil(rows) as u32;
+    let rows = rows as u32;
+    let width = renders.iter().map(RgbImage::width).max().unwrap_or(0);
+    let height = renders.iter().map(RgbImage::height).max().unwrap_or(0);
+
+    let mut sheet = RgbImage::new(
+        columns * (width + SHEET_GAP) + SHEET_GAP,
+        rows * (height + SHEET_GAP) + SHEET_GAP,
+    );
+    for (i, render) in renders.iter().enumerate() {
+        let (column, row) = (i as u32 % columns, i as u32 / columns);
+        let x = SHEET_GAP + column * (width + SHEET_GAP);
+        let y = SHEET_GAP + row * (height + SHEET_GAP);
+        image::imageops::replace(&mut sheet, render, x.into(), y.into());
+    }
+
+    sheet
+}
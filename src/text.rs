@@ -0,0 +1,114 @@
+//! Turning text into triangles, by filling the outlines of its glyphs in a TrueType or OpenType
+//! font and extruding them.
+
+use anyhow::Context;
+use lyon_tessellation::{
+    math::{point, Point},
+    path::Path,
+};
+use nalgebra::Vector3;
+
+use crate::{extrude::extrude_outline, mesh::TriangleMesh};
+
+/// The largest distance between a glyph's curves and the straight edges that approximate them, as
+/// a fraction of the font size.
+const TOLERANCE: f32 = 0.002;
+
+/// Fill the glyphs of `text` from `font` (the contents of a font file) and extrude them by `depth`,
+/// cutting the edges of their front and back at 45 degrees by `chamfer`.
+///
+/// The text lies in the XY plane, reading along X from the origin with its first baseline on the
+/// X axis, and faces +Z; it is extruded towards -Z. `size` is the height of the font's em square,
+/// and lines are spaced by the font's line height. With no depth, only the front faces are made.
+pub fn extrude(
+    font: &[u8],
+    text: &str,
+    size: f64,
+    depth: f64,
+    chamfer: f64,
+) -> anyhow::Result<TriangleMesh> {
+    let face = ttf_parser::Face::parse(font, 0).context("reading the font")?;
+    let em = f32::from(face.units_per_em());
+    let scale = size / em as f64;
+    let line_height = f64::from(face.ascender() - face.descender() + face.line_gap()) * scale;
+
+    let mut mesh = TriangleMesh::default();
+    let mut pen = (0.0, 0.0);
+    for c in text.chars() {
+        if c == '\n' {
+            pen = (0.0, pen.1 - line_height);
+            continue;
+        }
+
+        let glyph = face.glyph_index(c).unwrap_or(ttf_parser::GlyphId(0));
+        let mut outline = Outline::new();
+        if face.outline_glyph(glyph, &mut outline).is_some() {
+            let origin = Vector3::new(pen.0, pen.1, 0.0);
+            let place = |p: Point| origin + Vector3::new(p.x as f64, p.y as f64, 0.0) * scale;
+            extrude_outline(
+                &outline.finish(),
+                em * TOLERANCE,
+                depth,
+                chamfer,
+                place,
+                &mut mesh,
+            )
+            .with_context(|| format!("filling the glyph for `{c}`"))?;
+        }
+        pen.0 += f64::from(face.glyph_hor_advance(glyph).unwrap_or(0)) * scale;
+    }
+
+    Ok(mesh)
+}
+
+/// Collects the contours of a glyph into a path.
+struct Outline {
+    path: lyon_tessellation::path::path::Builder,
+    open: bool,
+}
+
+impl Outline {
+    fn new() -> Self {
+        Self {
+            path: Path::builder(),
+            open: false,
+        }
+    }
+
+    fn finish(mut self) -> Path {
+        if self.open {
+            self.path.end(true);
+        }
+        self.path.build()
+    }
+}
+
+impl ttf_parser::OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        if self.open {
+            self.path.end(true);
+        }
+        self.path.begin(point(x, y));
+        self.open = true;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.path.line_to(point(x, y));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.path.quadratic_bezier_to(point(x1, y1), point(x, y));
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.path
+            .cubic_bezier_to(point(x1, y1), point(x2, y2), point(x, y));
+    }
+
+    fn close(&mut self) {
+        if self.open {
+            self.path.end(true);
+            self.open = false;
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use image::imageops::FilterType;
+use nalgebra::Vector3;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{object::Hit, render::Color};
+
+/// A color that varies over the surface of an object.
+pub enum Texture {
+    Solid {
+        color: Color,
+    },
+    /// A 3D checkerboard of cubes with side length `scale`.
+    Checker {
+        scale: f64,
+        even: Color,
+        odd: Color,
+    },
+    /// Smooth Perlin noise between black and white, with features about `1 / scale` in size.
+    Noise {
+        scale: f64,
+        perlin: Box<Perlin>,
+    },
+    /// An image in linear colors, wrapped over surfaces by the `uv` of their hits, with its bottom
+    /// left corner at (0, 0).
+    Image(image::Rgb32FImage),
+}
+
+impl Texture {
+    pub fn noise(scale: f64) -> Self {
+        Self::Noise {
+            scale,
+            perlin: Box::new(Perlin::new()),
+        }
+    }
+
+    /// The color of the texture at a hit.
+    pub fn value(&self, hit: &Hit) -> Color {
+        let p = &hit.p;
+        match self {
+            Texture::Solid { color } => *color,
+            Texture::Checker { scale, even, odd } => {
+                if checker_parity(p, *scale) {
+                    *even
+                } else {
+                    *odd
+                }
+            }
+            Texture::Noise { scale, perlin } => {
+                Color::repeat(0.5 * (1.0 + perlin.noise(&(p * *scale))))
+            }
+            Texture::Image(image) => {
+                let (u, v) = hit.uv;
+                let pixel = |coordinate: f64, size: u32| {
+                    ((coordinate.clamp(0.0, 1.0) * size as f64) as u32).min(size - 1)
+                };
+                let x = pixel(u, image.width());
+                let y = pixel(1.0 - v, image.height());
+                let [r, g, b] = image.get_pixel(x, y).0;
+                Color::new(r.into(), g.into(), b.into())
+            }
+        }
+    }
+
+    /// The memory taken by the pixels of an image texture.
+    pub fn size_in_bytes(&self) -> usize {
+        match self {
+            Texture::Image(image) => image.as_raw().len() * size_of::<f32>(),
+            _ => 0,
+        }
+    }
+
+    /// Halve the width and height of an image texture, averaging its pixels, unless that would
+    /// make it smaller than `min_size` pixels on a side. Returns whether it was shrunk.
+    pub fn shrink(&mut self, min_size: u32) -> bool {
+        let Texture::Image(image) = self else {
+            return false;
+        };
+        let (width, height) = (image.width() / 2, image.height() / 2);
+        if width.min(height) < min_size {
+            return false;
+        }
+        *image = image::imageops::resize(image, width, height, FilterType::Triangle);
+        true
+    }
+
+    /// The texture at a hit as a single value, e.g. for use as a mask.
+    pub fn scalar(&self, hit: &Hit) -> f64 {
+        self.value(hit).mean()
+    }
+}
+
+/// Whether `p` falls in an even cell of a 3D checkerboard of cubes with side length `scale`.
+pub fn checker_parity(p: &Vector3<f64>, scale: f64) -> bool {
+    let cell = (p / scale).map(|c| c.floor() as i64);
+    (cell.x + cell.y + cell.z).rem_euclid(2) == 0
+}
+
+/// Gradient noise as described in "Ray Tracing: The Next Week".
+pub struct Perlin {
+    gradients: Vec<Vector3<f64>>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Perlin {
+    const POINT_COUNT: usize = 256;
+
+    pub fn new() -> Self {
+        // A fixed seed keeps textures identical between renders of the same scene.
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let gradients = (0..Self::POINT_COUNT)
+            .map(|_| {
+                Vector3::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+                .normalize()
+            })
+            .collect();
+
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..Self::POINT_COUNT).collect();
+            p.shuffle(&mut rng);
+            p
+        };
+
+        Self {
+            gradients,
+            perm_x: permutation(),
+            perm_y: permutation(),
+            perm_z: permutation(),
+        }
+    }
+
+    /// Noise in the range [-1, 1] at point `p`.
+    pub fn noise(&self, p: &Vector3<f64>) -> f64 {
+        let floor = p.map(f64::floor);
+        let fraction = p - floor;
+        let (i, j, k) = (floor.x as i64, floor.y as i64, floor.z as i64);
+
+        // Hermite smoothing to avoid grid artifacts
+        let smooth = fraction.map(|f| f * f * (3.0 - 2.0 * f));
+
+        let mask = Self::POINT_COUNT as i64 - 1;
+        let mut accumulated = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.perm_x[((i + di) & mask) as usize]
+                        ^ self.perm_y[((j + dj) & mask) as usize]
+                        ^ self.perm_z[((k + dk) & mask) as usize];
+                    let weight = fraction - Vector3::new(di as f64, dj as f64, dk as f64);
+
+                    let (fi, fj, fk) = (di as f64, dj as f64, dk as f64);
+                    accumulated += (fi * smooth.x + (1.0 - fi) * (1.0 - smooth.x))
+                        * (fj * smooth.y + (1.0 - fj) * (1.0 - smooth.y))
+                        * (fk * smooth.z + (1.0 - fk) * (1.0 - smooth.z))
+                        * self.gradients[index].dot(&weight);
+                }
+            }
+        }
+
+        accumulated
+    }
+}
@@ -0,0 +1,186 @@
+//! Writes a tiled OpenEXR image one tile at a time, as a render finishes them. The file is laid
+//! out in full, with every tile black, when it is created, and each finished tile is written over
+//! its place. Since the tiles are uncompressed, they keep their sizes, so the file is a complete
+//! image at every point, and a render that is interrupted leaves its finished tiles behind.
+
+use std::{
+    fs::File,
+    io::{Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// The magic number that starts every OpenEXR file.
+pub const MAGIC: u32 = 20000630;
+/// Version 2 of the file format, with the flag for a single tiled part.
+const VERSION: u32 = 2 | 0x200;
+/// The pixel type of 32-bit floats.
+const FLOAT: i32 = 2;
+/// The line order in which tiles may be written in any order.
+const RANDOM_Y: u8 = 2;
+/// Channels are stored in alphabetical order.
+pub const CHANNELS: [&str; 3] = ["B", "G", "R"];
+
+/// The bytes before each tile's pixels: its column, row, level across and down, and size.
+const TILE_HEADER_SIZE: u64 = 5 * size_of::<i32>() as u64;
+
+pub struct TiledExr {
+    file: File,
+    width: u32,
+    height: u32,
+    tile_size: u32,
+    /// Where each tile starts in the file, in bytes, in rows of tiles.
+    offsets: Vec<u64>,
+}
+
+impl TiledExr {
+    /// Create an image of `width` by `height` pixels with no tiles, split into square tiles
+    /// `tile_size` pixels across.
+    pub fn create(
+        path: &Path,
+        (width, height): (u32, u32),
+        tile_size: u32,
+    ) -> anyhow::Result<Self> {
+        let mut header = Vec::new();
+        header.extend(MAGIC.to_le_bytes());
+        header.extend(VERSION.to_le_bytes());
+
+        let mut channels = Vec::new();
+        for name in CHANNELS {
+            channels.extend(name.as_bytes());
+            channels.push(0);
+            channels.extend(FLOAT.to_le_bytes());
+            // linear, three reserved bytes, and no subsampling
+            channels.extend([0; 4]);
+            channels.extend(1i32.to_le_bytes());
+            channels.extend(1i32.to_le_bytes());
+        }
+        channels.push(0);
+        let window = [0, 0, width as i32 - 1, height as i32 - 1]
+            .into_iter()
+            .flat_map(i32::to_le_bytes)
+            .collect::<Vec<_>>();
+        let mut tiles = Vec::new();
+        tiles.extend(tile_size.to_le_bytes());
+        tiles.extend(tile_size.to_le_bytes());
+        // a single level, rounded down
+        tiles.push(0);
+
+        write_attribute(&mut header, "channels", "chlist", &channels);
+        write_attribute(&mut header, "compression", "compression", &[0]);
+        write_attribute(&mut header, "dataWindow", "box2i", &window);
+        write_attribute(&mut header, "displayWindow", "box2i", &window);
+        write_attribute(&mut header, "lineOrder", "lineOrder", &[RANDOM_Y]);
+        write_attribute(
+            &mut header,
+            "pixelAspectRatio",
+            "float",
+            &1f32.to_le_bytes(),
+        );
+        write_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+        write_attribute(
+            &mut header,
+            "screenWindowWidth",
+            "float",
+            &1f32.to_le_bytes(),
+        );
+        write_attribute(&mut header, "tiles", "tiledesc", &tiles);
+        header.push(0);
+
+        let (columns, rows) = (width.div_ceil(tile_size), height.div_ceil(tile_size));
+        let tile_sizes = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                (
+                    column,
+                    row,
+                    tile_size.min(width - column * tile_size),
+                    tile_size.min(height - row * tile_size),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut offsets = Vec::with_capacity(tile_sizes.len());
+        let mut end = (header.len() + tile_sizes.len() * size_of::<u64>()) as u64;
+        for &(_, _, width, height) in &tile_sizes {
+            offsets.push(end);
+            end += TILE_HEADER_SIZE + pixel_bytes(width, height);
+        }
+        header.extend(offsets.iter().flat_map(|offset| offset.to_le_bytes()));
+
+        let mut file = File::create(path)?;
+        file.write_all(&header)?;
+        for (&(column, row, width, height), &offset) in tile_sizes.iter().zip(&offsets) {
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(&tile_header(column, row, pixel_bytes(width, height)))?;
+        }
+        // The pixels between the tiles' headers are left as zeros, which are black.
+        file.set_len(end)?;
+
+        Ok(Self {
+            file,
+            width,
+            height,
+            tile_size,
+            offsets,
+        })
+    }
+
+    /// Write the tile in column `column` and row `row` of the grid of tiles, given its linear
+    /// colors.
+    pub fn write_tile(
+        &mut self,
+        column: u32,
+        row: u32,
+        tile: &image::Rgb32FImage,
+    ) -> anyhow::Result<()> {
+        let (x, y) = (column * self.tile_size, row * self.tile_size);
+        let expected = (
+            self.tile_size.min(self.width - x),
+            self.tile_size.min(self.height - y),
+        );
+        if tile.dimensions() != expected {
+            anyhow::bail!(
+                "tile ({column}, {row}) is {:?} pixels, but should be {expected:?}",
+                tile.dimensions()
+            );
+        }
+
+        let mut data = Vec::with_capacity(pixel_bytes(expected.0, expected.1) as usize);
+        for line in tile.rows() {
+            let line = line.collect::<Vec<_>>();
+            // the channels are stored in the order of their names, blue, green, then red, which
+            // are indices 2, 1, and 0 of a pixel
+            for channel in [2, 1, 0] {
+                data.extend(line.iter().flat_map(|pixel| pixel[channel].to_le_bytes()));
+            }
+        }
+
+        let index = (row * self.width.div_ceil(self.tile_size) + column) as usize;
+        self.file
+            .seek(SeekFrom::Start(self.offsets[index] + TILE_HEADER_SIZE))?;
+        self.file.write_all(&data)?;
+
+        Ok(())
+    }
+}
+
+/// The size of the pixels of a tile.
+fn pixel_bytes(width: u32, height: u32) -> u64 {
+    width as u64 * height as u64 * (CHANNELS.len() * size_of::<f32>()) as u64
+}
+
+fn tile_header(column: u32, row: u32, size: u64) -> Vec<u8> {
+    [column as i32, row as i32, 0, 0, size as i32]
+        .into_iter()
+        .flat_map(i32::to_le_bytes)
+        .collect()
+}
+
+pub fn write_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    for text in [name, kind] {
+        header.extend(text.as_bytes());
+        header.push(0);
+    }
+    header.extend((value.len() as i32).to_le_bytes());
+    header.extend(value);
+}
@@ -0,0 +1,225 @@
+//! A multi-line progress display for long renders: an overall progress bar, sampling statistics,
+//! and a mini-map of the tile grid showing which parts of the image are complete.
+
+use std::{collections::HashSet, time::Instant};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use rtk::render::{ProgressEvent, Stage};
+
+use crate::status::{estimate_remaining, format_duration, ProgressDisplay};
+
+/// The mini-map is never drawn larger than this many characters in either dimension; larger tile
+/// grids are downscaled so that each character covers several tiles.
+const MAX_MAP_COLUMNS: u32 = 64;
+const MAX_MAP_ROWS: u32 = 24;
+
+pub struct Tui {
+    _multi: MultiProgress,
+    main_bar: ProgressBar,
+    stats_bar: ProgressBar,
+    map_bars: Vec<ProgressBar>,
+
+    /// The tile grid's dimensions.
+    columns: u32,
+    rows: u32,
+    /// The mini-map's dimensions.
+    map_columns: u32,
+    map_rows: u32,
+    /// For each mini-map cell, the number of tiles it covers that are complete in this pass.
+    completed_tiles: Vec<u32>,
+    /// The pass over the image, counting from 1, as a progressive render reports every tile in
+    /// each pass, and the tiles finished in it, by column and row.
+    pass: u32,
+    pass_tiles: HashSet<(u32, u32)>,
+
+    render_start: Option<Instant>,
+    samples: u64,
+    percent: u32,
+}
+
+impl Tui {
+    /// `tile_grid` is the number of tile columns and rows, as given by `Renderer::tile_grid`.
+    pub fn new((columns, rows): (u32, u32)) -> Self {
+        let multi = MultiProgress::with_draw_target(ProgressDrawTarget::stderr());
+
+        let main_bar = multi.add(ProgressBar::new(100));
+        main_bar.set_style(ProgressStyle::with_template("{spinner} {msg}").unwrap());
+
+        let stats_bar = multi.add(ProgressBar::new(0));
+        stats_bar.set_style(ProgressStyle::with_template("  {msg}").unwrap());
+
+        let map_columns = columns.clamp(1, MAX_MAP_COLUMNS);
+        let map_rows = rows.clamp(1, MAX_MAP_ROWS);
+
+        let map_bars = (0..map_rows)
+            .map(|_| {
+                let bar = multi.add(ProgressBar::new(0));
+                bar.set_style(ProgressStyle::with_template("  {msg}").unwrap());
+                bar
+            })
+            .collect();
+
+        let tui = Self {
+            _multi: multi,
+            main_bar,
+            stats_bar,
+            map_bars,
+            columns,
+            rows,
+            map_columns,
+            map_rows,
+            completed_tiles: vec![0; (map_columns * map_rows) as usize],
+            pass: 1,
+            pass_tiles: HashSet::new(),
+            render_start: None,
+            samples: 0,
+            percent: 0,
+        };
+        tui.draw_map();
+
+        tui
+    }
+
+    /// The mini-map cell that a tile falls into.
+    fn map_cell(&self, column: u32, row: u32) -> usize {
+        let map_column = column * self.map_columns / self.columns.max(1);
+        let map_row = row * self.map_rows / self.rows.max(1);
+        (map_row * self.map_columns + map_column) as usize
+    }
+
+    /// The number of tiles covered by a mini-map cell.
+    fn cell_capacity(&self, cell: usize) -> u32 {
+        let map_column = cell as u32 % self.map_columns;
+        let map_row = cell as u32 / self.map_columns;
+
+        let tiles_in = |map_index: u32, map_len: u32, len: u32| {
+            // the tiles t for which t * map_len / len == map_index
+            let first = (map_index * len).div_ceil(map_len);
+            let end = ((map_index + 1) * len).div_ceil(map_len);
+            end.min(len) - first.min(len)
+        };
+
+        tiles_in(map_column, self.map_columns, self.columns)
+            * tiles_in(map_row, self.map_rows, self.rows)
+    }
+
+    fn draw_map(&self) {
+        for (map_row, bar) in self.map_bars.iter().enumerate() {
+            let line: String = (0..self.map_columns)
+                .map(|map_column| {
+                    let cell = map_row * self.map_columns as usize + map_column as usize;
+                    let capacity = self.cell_capacity(cell);
+                    match self.completed_tiles[cell] {
+                        0 => '·',
+                        n if n >= capacity => '█',
+                        _ => '▒',
+                    }
+                })
+                .collect();
+            bar.set_message(line);
+        }
+    }
+
+    fn draw_stats(&self) {
+        let Some(start) = self.render_start else {
+            return;
+        };
+
+        let elapsed = start.elapsed();
+        let samples_per_second = self.samples as f64 / elapsed.as_secs_f64().max(1e-3);
+
+        let mut stats = format!(
+            "{} samples/s | elapsed {}",
+            format_count(samples_per_second),
+            format_duration(elapsed)
+        );
+        if self.pass > 1 {
+            stats.push_str(&format!(" | pass {}", self.pass));
+        }
+        if let Some(eta) = estimate_remaining(elapsed, self.percent) {
+            stats.push_str(&format!(" | ETA {}", format_duration(eta)));
+        }
+        if let Some(memory) = resident_memory() {
+            stats.push_str(&format!(" | memory {:.1} MiB", memory as f64 / 1048576.0));
+        }
+
+        self.stats_bar.set_message(stats);
+    }
+}
+
+impl ProgressDisplay for Tui {
+    fn update(&mut self, progress: &ProgressEvent) {
+        match progress {
+            ProgressEvent::Stage(Stage::Rendering) => {
+                self.render_start = Some(Instant::now());
+                self.main_bar.set_style(
+                    ProgressStyle::with_template("{spinner} Rendering [{bar:40}] {pos}%")
+                        .unwrap()
+                        .progress_chars("=> "),
+                );
+                self.main_bar
+                    .enable_steady_tick(std::time::Duration::from_millis(100));
+            }
+            ProgressEvent::Stage(stage) => {
+                self.main_bar
+                    .set_style(ProgressStyle::with_template("{spinner} {msg}").unwrap());
+                self.main_bar.set_message(format!("{stage}..."));
+            }
+            ProgressEvent::Rendering(percent) => {
+                self.percent = *percent;
+                self.main_bar.set_position(*percent as u64);
+                self.draw_stats();
+            }
+            ProgressEvent::TileFinished {
+                column,
+                row,
+                samples,
+                ..
+            } => {
+                // A tile finished again belongs to the next pass, which starts the map over.
+                if !self.pass_tiles.insert((*column, *row)) {
+                    self.pass += 1;
+                    self.pass_tiles.clear();
+                    self.pass_tiles.insert((*column, *row));
+                    self.completed_tiles.fill(0);
+                }
+                let cell = self.map_cell(*column, *row);
+                self.completed_tiles[cell] += 1;
+                self.samples += samples;
+                self.draw_map();
+                self.draw_stats();
+            }
+            ProgressEvent::Samples(_) => {}
+            ProgressEvent::Finished => self.draw_stats(),
+            ProgressEvent::Failed(_) | ProgressEvent::Cancelled => self.main_bar.abandon(),
+        }
+    }
+
+    fn finish(&mut self, message: &str) {
+        self.main_bar
+            .set_style(ProgressStyle::with_template("{msg}").unwrap());
+        self.main_bar.finish_with_message(message.to_string());
+        self.stats_bar.finish();
+        for bar in &self.map_bars {
+            bar.finish();
+        }
+    }
+}
+
+/// Format a large count compactly, e.g. `12.3M`.
+fn format_count(count: f64) -> String {
+    match count {
+        c if c >= 1e9 => format!("{:.1}G", c / 1e9),
+        c if c >= 1e6 => format!("{:.1}M", c / 1e6),
+        c if c >= 1e3 => format!("{:.1}K", c / 1e3),
+        c => format!("{c:.0}"),
+    }
+}
+
+/// The resident set size of this process in bytes, where the platform makes it available.
+fn resident_memory() -> Option<u64> {
+    // the second field of statm is the resident set size, in pages
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    Some(pages * 4096)
+}
@@ -0,0 +1,123 @@
+//! A window showing the image as its tiles are rendered, for `rtk render --preview`.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use minifb::{ScaleMode, Window, WindowOptions};
+use rtk::render::ProgressEvent;
+
+use crate::status::ProgressDisplay;
+
+/// The shortest time between redraws of the window, so that small tiles finishing quickly do not
+/// slow the render down.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(50);
+
+pub struct PreviewWindow {
+    /// `None` once the window has been closed.
+    window: Option<Window>,
+    width: u32,
+    height: u32,
+    /// The image so far, in 0RGB pixels.
+    buffer: Vec<u32>,
+    last_redraw: Instant,
+}
+
+impl PreviewWindow {
+    /// Open a window for an image of `width` by `height` pixels.
+    pub fn open((width, height): (u32, u32)) -> anyhow::Result<Self> {
+        let options = WindowOptions {
+            resize: true,
+            scale_mode: ScaleMode::AspectRatioStretch,
+            ..WindowOptions::default()
+        };
+        let mut window = Window::new("rtk", width as usize, height as usize, options)
+            .map_err(|e| anyhow::anyhow!("failed to open the preview window: {e}"))?;
+        // Redraws are spaced out here, since the window would otherwise sleep to keep its rate.
+        window.set_target_fps(0);
+
+        let mut preview = Self {
+            window: Some(window),
+            width,
+            height,
+            buffer: vec![0; width as usize * height as usize],
+            last_redraw: Instant::now(),
+        };
+        preview.redraw();
+        Ok(preview)
+    }
+
+    /// Show the image so far, or notice that the window was closed.
+    fn redraw(&mut self) {
+        let Some(window) = &mut self.window else {
+            return;
+        };
+        if !window.is_open() {
+            self.window = None;
+            return;
+        }
+
+        let (width, height) = (self.width as usize, self.height as usize);
+        if window
+            .update_with_buffer(&self.buffer, width, height)
+            .is_err()
+        {
+            self.window = None;
+        }
+        self.last_redraw = Instant::now();
+    }
+}
+
+impl ProgressDisplay for PreviewWindow {
+    fn update(&mut self, progress: &ProgressEvent) {
+        match progress {
+            ProgressEvent::Rendering(percent) => {
+                if let Some(window) = &mut self.window {
+                    window.set_title(&format!("rtk ({percent}%)"));
+                }
+            }
+            ProgressEvent::TileFinished { x, y, pixels, .. } => {
+                for (i, j, pixel) in pixels.enumerate_pixels() {
+                    let [r, g, b] = pixel.0.map(u32::from);
+                    let index = (y + j) as usize * self.width as usize + (x + i) as usize;
+                    self.buffer[index] = r << 16 | g << 8 | b;
+                }
+            }
+            _ => {}
+        }
+
+        // The last tiles are always shown, however soon after the previous redraw they finish.
+        let last = matches!(
+            progress,
+            ProgressEvent::Finished | ProgressEvent::Cancelled | ProgressEvent::Failed(_)
+        );
+        if last || self.last_redraw.elapsed() >= REDRAW_INTERVAL {
+            self.redraw();
+        }
+    }
+
+    fn finish(&mut self, _message: &str) {
+        if let Some(window) = &mut self.window {
+            window.set_title("rtk (finished)");
+        }
+        self.redraw();
+    }
+
+    fn tick(&mut self) {
+        if self.last_redraw.elapsed() >= REDRAW_INTERVAL {
+            self.redraw();
+        }
+    }
+
+    /// Keep the window open with the finished image until the user closes it.
+    fn wait(&mut self) {
+        if self.window.is_some() {
+            eprintln!("Close the preview window to exit");
+        }
+        while self.window.is_some() {
+            thread::sleep(REDRAW_INTERVAL);
+            self.redraw();
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Renders rtk scenes in the browser. See `index.html` for the page that uses this.
+
+use rtk::{plugin::Plugins, render::Renderer, scene::Scene};
+use wasm_bindgen::prelude::*;
+
+/// A rendered image, as RGBA pixels ready for a canvas `ImageData`.
+#[wasm_bindgen]
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl Image {
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Take the pixels out of the image, leaving it empty.
+    pub fn take_pixels(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.pixels)
+    }
+}
+
+/// Render a scene file's contents. Rendering happens on the calling thread, so large scenes
+/// should be rendered in a web worker.
+#[wasm_bindgen]
+pub fn render(scene_source: &str) -> Result<Image, JsError> {
+    let (mut scene, _) =
+        Scene::from_toml(scene_source).map_err(|e| JsError::new(&e.to_string()))?;
+    scene
+        .run_script()
+        .map_err(|e| JsError::new(&e.to_string()))?;
+    scene.validate().map_err(|e| JsError::new(&e.to_string()))?;
+
+    let mut renderer = Renderer::new(scene.create_camera());
+    let mut world = scene
+        .create_world(&Plugins::new())
+        .map_err(|e| JsError::new(&e.to_string()))?;
+    if scene.camera.frame.is_some() {
+        let position = scene
+            .frame_camera(&mut world)
+            .map_err(|e| JsError::new(&e.to_string()))?;
+        renderer.set_position(position);
+    }
+    let focus_distance = scene
+        .focus_distance(&world)
+        .map_err(|e| JsError::new(&e.to_string()))?;
+    if let Some(distance) = focus_distance {
+        renderer.set_focus_distance(distance);
+    }
+    let image = renderer.render(&world, false, |_| {})?;
+    let (width, height) = image.dimensions();
+    let pixels = image
+        .pixels()
+        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+        .collect();
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}